@@ -1,4 +1,5 @@
 use std::io::{stdin, stdout, Write};
+use std::net::ToSocketAddrs;
 
 use futures::future::lazy;
 use futures::stream::Stream;
@@ -8,7 +9,7 @@ use new_tokio_smtp::{command, Connection, ConnectionConfig, Domain};
 use vec1::vec1;
 
 struct Request {
-    config: ConnectionConfig<command::auth::Plain>,
+    config: ConnectionConfig<command::RequireSecure<command::auth::Plain>>,
     mails: Vec<MailEnvelop>,
 }
 
@@ -27,7 +28,7 @@ fn main() {
         println!("[start connect_send_quit]");
         Connection::connect_send_quit(config, mails)
             //Stream::for_each is design wise broken in futures v0.1
-            .then(|result| Ok(result))
+            .then(Ok)
             .for_each(|result| {
                 if let Err(err) = result {
                     println!("[sending mail failed]: {}", err);
@@ -45,8 +46,15 @@ fn read_request() -> Request {
     let passwd = read_password();
 
     // The `from_unchecked` will turn into a `.parse()` in the future.
-    let config = ConnectionConfig::builder(Domain::from_unchecked("smtp.ethereal.email"))
-        .expect("resolving domain failed")
+    let domain = Domain::from_unchecked("smtp.ethereal.email");
+    // Resolving the host blocks, which is fine as tokio isn't running yet,
+    // from inside of a future use `ConnectionBuilder::new_with_resolver`.
+    let addr = (domain.as_str(), 587)
+        .to_socket_addrs()
+        .ok()
+        .and_then(|mut addrs| addrs.next())
+        .expect("resolving domain failed");
+    let config = ConnectionConfig::builder_with_addr(addr, domain)
         .auth(
             command::auth::Plain::from_username(sender.clone(), passwd)
                 .expect("username/password can not contain \\0 bytes"),
@@ -45,16 +45,16 @@ impl From<String> for IgnoreAsciiCaseString {
     }
 }
 
-impl Into<String> for IgnoreAsciiCaseString {
-    fn into(self) -> String {
-        let IgnoreAsciiCaseString { inner } = self;
+impl From<IgnoreAsciiCaseString> for String {
+    fn from(v: IgnoreAsciiCaseString) -> Self {
+        let IgnoreAsciiCaseString { inner } = v;
         inner
     }
 }
 
 impl Hash for IgnoreAsciiCaseString {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        ignore_ascii_hash(&*self.inner, state)
+        ignore_ascii_hash(&self.inner, state)
     }
 }
 
@@ -95,15 +95,15 @@ impl AsRef<str> for IgnoreAsciiCaseStr {
     }
 }
 
-impl<'a> Into<&'a str> for &'a IgnoreAsciiCaseStr {
-    fn into(self) -> &'a str {
-        &self.inner
+impl<'a> From<&'a IgnoreAsciiCaseStr> for &'a str {
+    fn from(v: &'a IgnoreAsciiCaseStr) -> &'a str {
+        &v.inner
     }
 }
 
-impl<'a> Into<String> for &'a IgnoreAsciiCaseStr {
-    fn into(self) -> String {
-        self.inner.to_owned()
+impl From<&IgnoreAsciiCaseStr> for String {
+    fn from(v: &IgnoreAsciiCaseStr) -> Self {
+        v.inner.to_owned()
     }
 }
 
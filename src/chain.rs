@@ -1,11 +1,11 @@
-//! Provides the `smtp_chain` macro and the `chain` function
+//! Provides the `smtp_chain` macro and the `chain`/`chain_typed`/`chain_collect` functions
 //!
 //! see their respective documentation for more information.
 use futures::future::{self, Either, Future, Loop};
 use std::io as std_io;
 use std::sync::Arc;
 
-use crate::{command, error::LogicError, BoxedCmd, Connection};
+use crate::{error::LogicError, io::SmtpResult, BoxedCmd, Cmd, Connection};
 
 /// creates a chain of commands and them to the given connection
 ///
@@ -83,23 +83,83 @@ pub fn chain<H>(
 ) -> impl Future<Item = (Connection, Result<(), (usize, LogicError)>), Error = std_io::Error> + Send
 where
     H: HandleErrorInChain,
+{
+    chain_typed(con, chain, on_error)
+}
+
+/// send all commands in `chain` through the given connection one
+/// after another, without boxing them
+///
+/// Works like `chain` but accepts any iterator of commands of the same
+/// type, which avoids the allocation `boxed()` does for each command.
+/// (Use e.g. `command::EitherCmd` if a few different commands are needed.)
+pub fn chain_typed<C, I, H>(
+    con: Connection,
+    chain: I,
+    on_error: H,
+) -> impl Future<Item = (Connection, Result<(), (usize, LogicError)>), Error = std_io::Error> + Send
+where
+    C: Cmd,
+    I: IntoIterator<Item = C>,
+    I::IntoIter: Send + 'static,
+    H: HandleErrorInChain,
+{
+    chain_collect_typed(con, chain, on_error).map(|(con, result)| (con, result.map(|_| ())))
+}
+
+/// send all commands in `chain` like `chain`, returning the result of each command
+///
+/// If the chain isn't stopped the result of every command is returned (in
+/// order), including the errors the `on_error` handler decided to continue
+/// after, e.g. the rejected `RCPT` commands when allowing some of them to
+/// fail. If it is stopped only the index and error of the failed command
+/// is returned, like with `chain`.
+pub fn chain_collect<H>(
+    con: Connection,
+    chain: Vec<BoxedCmd>,
+    on_error: H,
+) -> impl Future<
+    Item = (Connection, Result<Vec<SmtpResult>, (usize, LogicError)>),
+    Error = std_io::Error,
+> + Send
+where
+    H: HandleErrorInChain,
+{
+    chain_collect_typed(con, chain, on_error)
+}
+
+/// like `chain_collect` but without boxing the commands, see `chain_typed`
+pub fn chain_collect_typed<C, I, H>(
+    con: Connection,
+    chain: I,
+    on_error: H,
+) -> impl Future<
+    Item = (Connection, Result<Vec<SmtpResult>, (usize, LogicError)>),
+    Error = std_io::Error,
+> + Send
+where
+    C: Cmd,
+    I: IntoIterator<Item = C>,
+    I::IntoIter: Send + 'static,
+    H: HandleErrorInChain,
 {
     let _on_error = Arc::new(on_error);
-    let mut chain = chain;
-    //stackify
-    chain.reverse();
+    let mut chain = chain.into_iter();
 
     // the index of the current operation in the chain plus 1
     let mut index_p1 = 0;
-    let fut = future::loop_fn(con, move |con| {
+    let fut = future::loop_fn((con, Vec::new()), move |(con, mut results)| {
         index_p1 += 1;
-        if let Some(next_cmd) = chain.pop() {
+        if let Some(next_cmd) = chain.next() {
             //FIXME[rust/co-rotines+self-borrow]: this is likly not needed with self borrow
             let on_error = _on_error.clone();
             let fut = con
                 .send(next_cmd)
                 .and_then(move |(con, result)| match result {
-                    Ok(_result) => Either::A(future::ok(Loop::Continue(con))),
+                    Ok(response) => {
+                        results.push(Ok(response));
+                        Either::A(future::ok(Loop::Continue((con, results))))
+                    }
                     Err(err) => {
                         let index = index_p1 - 1;
                         let fut =
@@ -109,7 +169,8 @@ where
                                     if stop {
                                         Loop::Break((con, Err((index, err))))
                                     } else {
-                                        Loop::Continue(con)
+                                        results.push(Err(err));
+                                        Loop::Continue((con, results))
                                     }
                                 });
                         Either::B(fut)
@@ -118,7 +179,7 @@ where
 
             Either::A(fut)
         } else {
-            Either::B(future::ok(Loop::Break((con, Ok(())))))
+            Either::B(future::ok(Loop::Break((con, Ok(results)))))
         }
     });
 
@@ -144,7 +205,7 @@ impl HandleErrorInChain for OnError {
             OnError::Stop => Either::A(future::ok((con, true))),
             OnError::StopAndReset => {
                 let fut = con
-                    .send(command::Reset)
+                    .reset()
                     //Note: Reset wont reach (con, Err(_)), ever! a reset error is turned
                     // into a io::Error
                     .map(|(con, _)| (con, true));
@@ -1,11 +1,13 @@
-use base64::encode;
+use base64::{decode, encode};
 use futures::future::{self, Either, Future};
 
-use super::validate_auth_capability;
+use super::{
+    auth_capability, mark_authenticated_on_success, validate_auth_capability, AuthIdentity,
+};
 use crate::{
     error::{LogicError, MissingCapabilities},
     future_ext::ResultWithContextExt,
-    Cmd, EhloData, ExecFuture, Io,
+    Capability, Cmd, EhloData, ExecFuture, Io,
 };
 
 /// Simple implementation of AUTH LOGIN for smtp.
@@ -42,6 +44,16 @@ impl Cmd for Login {
         validate_auth_capability(caps, "LOGIN")
     }
 
+    fn required_capabilities(&self) -> Vec<Capability> {
+        auth_capability()
+    }
+
+    /// the decoded username, if it is valid base64 encoded utf-8
+    fn auth_identity(&self) -> Option<AuthIdentity> {
+        let username = String::from_utf8(decode(&self.username).ok()?).ok()?;
+        Some(AuthIdentity::new(username, "LOGIN"))
+    }
+
     fn exec(self, mut io: Io) -> ExecFuture {
         let Login { username, password } = self;
 
@@ -62,6 +74,6 @@ impl Cmd for Login {
                 }
             });
 
-        Box::new(fut)
+        mark_authenticated_on_success(Box::new(fut))
     }
 }
@@ -1,4 +1,6 @@
-use crate::{error::MissingCapabilities, Capability, EhloData, EsmtpKeyword};
+use futures::Future;
+
+use crate::{error::MissingCapabilities, Capability, EhloData, EsmtpKeyword, ExecFuture};
 
 mod login;
 pub use self::login::*;
@@ -8,16 +10,79 @@ pub use self::plain::*;
 
 const CAP_AUTH: &str = "AUTH";
 
+/// the identity an auth command authenticates as and the used mechanism
+///
+/// This is returned by `Cmd::auth_identity` and recorded on the connection
+/// once the command succeeded, see `Connection::authenticated_as`.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct AuthIdentity {
+    identity: String,
+    mechanism: String,
+}
+
+impl AuthIdentity {
+    pub fn new<I1, I2>(identity: I1, mechanism: I2) -> Self
+    where
+        I1: Into<String>,
+        I2: Into<String>,
+    {
+        AuthIdentity {
+            identity: identity.into(),
+            mechanism: mechanism.into(),
+        }
+    }
+
+    /// the identity authenticated as, e.g. the username
+    pub fn identity(&self) -> &str {
+        &self.identity
+    }
+
+    /// the name of the sasl mechanism, e.g. `"PLAIN"`
+    pub fn mechanism(&self) -> &str {
+        &self.mechanism
+    }
+}
+
+/// marks the `Io` as authenticated if the auth command succeeded
+fn mark_authenticated_on_success(fut: ExecFuture) -> ExecFuture {
+    let fut = fut.map(|(mut io, result)| {
+        if result.is_ok() {
+            io.set_authenticated();
+        }
+        (io, result)
+    });
+
+    Box::new(fut)
+}
+
+/// records the identity on the `Io` if the command succeeded
+pub(crate) fn record_auth_identity(identity: Option<AuthIdentity>, fut: ExecFuture) -> ExecFuture {
+    let identity = match identity {
+        Some(identity) => identity,
+        None => return fut,
+    };
+
+    let fut = fut.map(move |(mut io, result)| {
+        if result.is_ok() {
+            io.set_authenticated_as(identity);
+        }
+        (io, result)
+    });
+
+    Box::new(fut)
+}
+
+fn auth_capability() -> Vec<Capability> {
+    vec![Capability::from(EsmtpKeyword::from_unchecked(CAP_AUTH))]
+}
+
 fn validate_auth_capability(
     caps: Option<&EhloData>,
     auth_kind: &'static str,
 ) -> Result<(), MissingCapabilities> {
-    caps.and_then(|ehlo_data| ehlo_data.get_capability_params(CAP_AUTH))
-        .and_then(|auth_methos| {
-            auth_methos
-                .iter()
-                .find(|method| method.as_str().eq_ignore_ascii_case(auth_kind))
-        })
+    caps.into_iter()
+        .flat_map(|ehlo_data| ehlo_data.capability_param_list(CAP_AUTH))
+        .find(|method| method.eq_ignore_ascii_case(auth_kind))
         .map(|_| ())
         .ok_or_else(|| {
             //FIXME specify it to be auth login
@@ -4,9 +4,11 @@ use std::sync::Arc;
 
 use base64::encode;
 
-use crate::{error::MissingCapabilities, Cmd, EhloData, ExecFuture, Io};
+use crate::{error::MissingCapabilities, Capability, Cmd, EhloData, ExecFuture, Io};
 
-use super::validate_auth_capability;
+use super::{
+    auth_capability, mark_authenticated_on_success, validate_auth_capability, AuthIdentity,
+};
 
 /// AUTH PLAIN smtp authentication based on rfc4954/rfc4616
 #[derive(Debug, Clone)]
@@ -71,13 +73,17 @@ impl Plain {
 
     //intentionally no fn password(&self)!
 
+    fn identity(&self) -> AuthIdentity {
+        AuthIdentity::new(self.authentication_identity.clone(), "PLAIN")
+    }
+
     fn exec_ref(&self, io: Io) -> ExecFuture {
         let auth_str = encode(&format!(
             "{}\0{}\0{}",
             &self.authorization_identity, &self.authentication_identity, &self.password
         ));
 
-        io.exec_simple_cmd(&["AUTH PLAIN ", auth_str.as_str()])
+        mark_authenticated_on_success(io.exec_simple_cmd(&["AUTH PLAIN ", auth_str.as_str()]))
     }
 }
 
@@ -86,6 +92,14 @@ impl Cmd for Plain {
         validate_auth_capability(caps, "PLAIN")
     }
 
+    fn required_capabilities(&self) -> Vec<Capability> {
+        auth_capability()
+    }
+
+    fn auth_identity(&self) -> Option<AuthIdentity> {
+        Some(self.identity())
+    }
+
     fn exec(self, con: Io) -> ExecFuture {
         self.exec_ref(con)
     }
@@ -93,10 +107,18 @@ impl Cmd for Plain {
 
 impl Cmd for Arc<Plain> {
     fn check_cmd_availability(&self, caps: Option<&EhloData>) -> Result<(), MissingCapabilities> {
-        let me: &Plain = &*self;
+        let me: &Plain = self;
         me.check_cmd_availability(caps)
     }
 
+    fn required_capabilities(&self) -> Vec<Capability> {
+        auth_capability()
+    }
+
+    fn auth_identity(&self) -> Option<AuthIdentity> {
+        Some(self.identity())
+    }
+
     fn exec(self, con: Io) -> ExecFuture {
         self.exec_ref(con)
     }
@@ -1,4 +1,15 @@
-use crate::{error::MissingCapabilities, Cmd, EhloData, ExecFuture, Io};
+use std::{io as std_io, time::Duration};
+
+use futures::{future, Future};
+use tokio::timer::Timeout;
+
+use super::auth::{record_auth_identity, AuthIdentity};
+use crate::{
+    error::{LogicError, MissingCapabilities},
+    future_ext::ResultWithContextExt,
+    io::SmtpResult,
+    Capability, Cmd, EhloData, ExecFuture, Io,
+};
 
 /// An either of two commands
 ///
@@ -49,6 +60,18 @@ where
             EitherCmd::B(b) => b.check_cmd_availability(caps),
         }
     }
+    fn required_capabilities(&self) -> Vec<Capability> {
+        match self {
+            EitherCmd::A(a) => a.required_capabilities(),
+            EitherCmd::B(b) => b.required_capabilities(),
+        }
+    }
+    fn auth_identity(&self) -> Option<AuthIdentity> {
+        match self {
+            EitherCmd::A(a) => a.auth_identity(),
+            EitherCmd::B(b) => b.auth_identity(),
+        }
+    }
     fn exec(self, con: Io) -> ExecFuture {
         match self {
             EitherCmd::A(a) => a.exec(con),
@@ -96,11 +119,258 @@ where
             .check_cmd_availability(caps)
             .or_else(|_| self.1.check_cmd_availability(caps))
     }
+    /// only the capabilities required by both commands are required
+    fn required_capabilities(&self) -> Vec<Capability> {
+        let second = self.1.required_capabilities();
+        let mut caps = self.0.required_capabilities();
+        caps.retain(|cap| second.contains(cap));
+        caps
+    }
+    /// the identity of the selected command is recorded by `exec`
     fn exec(self, con: Io) -> ExecFuture {
         if self.0.check_cmd_availability(con.ehlo_data()).is_ok() {
-            Box::new(self.0.exec(con))
+            record_auth_identity(self.0.auth_identity(), self.0.exec(con))
+        } else {
+            record_auth_identity(self.1.auth_identity(), self.1.exec(con))
+        }
+    }
+}
+
+/// A sequence of two commands, run as one logical command
+///
+/// Runs the first command and if it succeeded runs the second one on the
+/// same connection, resolving to the result of the second command. If the
+/// first command fails with a logic error the second one is not run and the
+/// error is returned.
+///
+/// Both commands need to be available for the connection.
+///
+/// ```
+/// extern crate new_tokio_smtp;
+///
+/// use new_tokio_smtp::command::{Noop, Reset, ThenCmd};
+///
+/// fn main() {
+///     // sends `NOOP` and then, if it succeeded, `RSET`
+///     let _cmd = ThenCmd(Noop, Reset);
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ThenCmd<A, B>(pub A, pub B);
+
+impl<A, B> Cmd for ThenCmd<A, B>
+where
+    A: Cmd,
+    B: Cmd,
+{
+    fn check_cmd_availability(&self, caps: Option<&EhloData>) -> Result<(), MissingCapabilities> {
+        match (
+            self.0.check_cmd_availability(caps),
+            self.1.check_cmd_availability(caps),
+        ) {
+            (Ok(()), Ok(())) => Ok(()),
+            (Err(err), Ok(())) | (Ok(()), Err(err)) => Err(err),
+            (Err(err_a), Err(err_b)) => {
+                let mut capabilities = err_a.capabilities().to_vec();
+                capabilities.extend_from_slice(err_b.capabilities());
+                Err(MissingCapabilities::new(capabilities))
+            }
+        }
+    }
+    fn required_capabilities(&self) -> Vec<Capability> {
+        let mut caps = self.0.required_capabilities();
+        for cap in self.1.required_capabilities() {
+            if !caps.contains(&cap) {
+                caps.push(cap);
+            }
+        }
+        caps
+    }
+    /// the identities of both commands are recorded by `exec`
+    fn exec(self, con: Io) -> ExecFuture {
+        let ThenCmd(first, second) = self;
+        let first_identity = first.auth_identity();
+        let second_identity = second.auth_identity();
+        let fut = record_auth_identity(first_identity, first.exec(con))
+            .ctx_and_then(move |io, _| record_auth_identity(second_identity, second.exec(io)));
+        Box::new(fut)
+    }
+}
+
+/// A command calling a closure with the result of the wrapped command
+///
+/// Useful for e.g. instrumentation, the closure is called with the
+/// `SmtpResult` (but not with connection failures) before the result
+/// is returned.
+///
+/// ```
+/// extern crate new_tokio_smtp;
+///
+/// use new_tokio_smtp::{command::{Inspect, Noop}, io::SmtpResult};
+///
+/// fn main() {
+///     let _cmd = Inspect(Noop, |result: &SmtpResult| println!("NOOP: {:?}", result));
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Inspect<C, F>(pub C, pub F);
+
+impl<C, F> Cmd for Inspect<C, F>
+where
+    C: Cmd,
+    F: FnOnce(&SmtpResult) + Send + 'static,
+{
+    fn check_cmd_availability(&self, caps: Option<&EhloData>) -> Result<(), MissingCapabilities> {
+        self.0.check_cmd_availability(caps)
+    }
+    fn required_capabilities(&self) -> Vec<Capability> {
+        self.0.required_capabilities()
+    }
+    fn auth_identity(&self) -> Option<AuthIdentity> {
+        self.0.auth_identity()
+    }
+    fn exec(self, con: Io) -> ExecFuture {
+        let Inspect(cmd, func) = self;
+        Box::new(cmd.exec(con).map(move |(io, result)| {
+            func(&result);
+            (io, result)
+        }))
+    }
+}
+
+/// A command mapping the result of the wrapped command with a closure
+///
+/// Useful for e.g. custom error classification, like treating a specific
+/// error response of a quirky server as success.
+///
+/// ```
+/// extern crate new_tokio_smtp;
+///
+/// use new_tokio_smtp::{command::{MapResult, Noop}, error::LogicError};
+///
+/// fn main() {
+///     // treat a `502` (command not implemented) response to NOOP as success
+///     let _cmd = MapResult(Noop, |result| match result {
+///         Err(LogicError::Code(response)) if response.code().as_byte_string() == *b"502" => {
+///             Ok(response)
+///         }
+///         other => other,
+///     });
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct MapResult<C, F>(pub C, pub F);
+
+impl<C, F> Cmd for MapResult<C, F>
+where
+    C: Cmd,
+    F: FnOnce(SmtpResult) -> SmtpResult + Send + 'static,
+{
+    fn check_cmd_availability(&self, caps: Option<&EhloData>) -> Result<(), MissingCapabilities> {
+        self.0.check_cmd_availability(caps)
+    }
+    fn required_capabilities(&self) -> Vec<Capability> {
+        self.0.required_capabilities()
+    }
+    fn auth_identity(&self) -> Option<AuthIdentity> {
+        self.0.auth_identity()
+    }
+    fn exec(self, con: Io) -> ExecFuture {
+        let MapResult(cmd, func) = self;
+        Box::new(cmd.exec(con).map(move |(io, result)| (io, func(result))))
+    }
+}
+
+/// A command which refuses to run the wrapped command over an unencrypted connection
+///
+/// If the connection isn't secure (see `Io::is_secure`) nothing is send and
+/// the command fails with a `LogicError::Custom` instead. This is used by
+/// `ConnectionBuilder::auth` to make sure credentials are never send in
+/// plaintext (e.g. with `Security::Opportunistic` if the server doesn't
+/// support `STARTTLS`).
+///
+/// ```
+/// extern crate new_tokio_smtp;
+///
+/// use new_tokio_smtp::command::{auth, RequireSecure};
+///
+/// fn main() {
+///     let _cmd = RequireSecure(auth::Login::new("user", "password"));
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct RequireSecure<C>(pub C);
+
+impl<C> Cmd for RequireSecure<C>
+where
+    C: Cmd,
+{
+    fn check_cmd_availability(&self, caps: Option<&EhloData>) -> Result<(), MissingCapabilities> {
+        self.0.check_cmd_availability(caps)
+    }
+    fn required_capabilities(&self) -> Vec<Capability> {
+        self.0.required_capabilities()
+    }
+    fn auth_identity(&self) -> Option<AuthIdentity> {
+        self.0.auth_identity()
+    }
+    fn exec(self, con: Io) -> ExecFuture {
+        if con.is_secure() {
+            self.0.exec(con)
         } else {
-            Box::new(self.1.exec(con))
+            let err = LogicError::Custom("refusing to run over plaintext".into());
+            Box::new(future::ok((con, Err(err))))
         }
     }
 }
+
+/// A command failing with an io error of kind `TimedOut` if the wrapped command takes too long
+///
+/// The timeout starts once the command is executed. As the connection is
+/// in an unknown state if the command didn't complete it's dropped (i.e.
+/// closed) on timeout, like with any other io error. This requires a tokio
+/// runtime with a timer.
+///
+/// ```
+/// extern crate new_tokio_smtp;
+///
+/// use std::time::Duration;
+/// use new_tokio_smtp::command::{Noop, WithTimeout};
+///
+/// fn main() {
+///     let _cmd = WithTimeout(Noop, Duration::from_secs(30));
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct WithTimeout<C>(pub C, pub Duration);
+
+impl<C> Cmd for WithTimeout<C>
+where
+    C: Cmd,
+{
+    fn check_cmd_availability(&self, caps: Option<&EhloData>) -> Result<(), MissingCapabilities> {
+        self.0.check_cmd_availability(caps)
+    }
+    fn required_capabilities(&self) -> Vec<Capability> {
+        self.0.required_capabilities()
+    }
+    fn auth_identity(&self) -> Option<AuthIdentity> {
+        self.0.auth_identity()
+    }
+    fn exec(self, con: Io) -> ExecFuture {
+        let WithTimeout(cmd, timeout) = self;
+        let fut = Timeout::new(cmd.exec(con), timeout).map_err(|err| {
+            if err.is_elapsed() {
+                std_io::Error::new(std_io::ErrorKind::TimedOut, "command timed out")
+            } else if err.is_inner() {
+                err.into_inner().expect("checked above")
+            } else {
+                std_io::Error::new(
+                    std_io::ErrorKind::Other,
+                    err.into_timer().expect("neither inner nor elapsed"),
+                )
+            }
+        });
+        Box::new(fut)
+    }
+}
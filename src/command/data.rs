@@ -1,21 +1,25 @@
 use std::io as std_io;
 
-use bytes::{Buf, IntoBuf};
+use bytes::{Buf, Bytes, IntoBuf};
 use futures::{
     future::{self, Either, Future},
     stream::{self, Stream},
 };
 
 use crate::{
-    error::{LogicError, MissingCapabilities},
+    error::{DataBodyInterrupted, LogicError, MissingCapabilities},
     future_ext::ResultWithContextExt,
-    response::codes,
+    io::SmtpResult,
+    response::{codes, ResponseCode},
     Cmd, EhloData, ExecFuture, Io,
 };
 
 pub struct Data<S> {
     //TODO add parameter support
     source: S,
+    pre_stuffed: bool,
+    normalize_line_endings: bool,
+    intermediate_code: ResponseCode,
 }
 
 impl<BF> Data<stream::Once<BF, std_io::Error>>
@@ -27,16 +31,113 @@ where
     }
 }
 
+/// the source of a `Data` command created with `Data::from_bytes_stream`
+pub type BytesBufStream<S> = stream::Map<S, fn(Bytes) -> std_io::Cursor<Bytes>>;
+
+impl<S> Data<BytesBufStream<S>>
+where
+    S: Stream<Item = Bytes, Error = std_io::Error>,
+{
+    /// create a `Data` command from a stream of `Bytes` chunks
+    ///
+    /// E.g. to send a mail which is assembled on the fly. The chunks
+    /// are dot-stashed as if they were one continuous buffer.
+    pub fn from_bytes_stream(source: S) -> Self {
+        Data::new(source.map(IntoBuf::into_buf as fn(Bytes) -> std_io::Cursor<Bytes>))
+    }
+}
+
 impl<S> Data<S>
 where
     S: Stream<Error = std_io::Error>,
     S::Item: Buf,
 {
     pub fn new(source: S) -> Self {
-        Data { source }
+        Data {
+            source,
+            pre_stuffed: false,
+            normalize_line_endings: false,
+            intermediate_code: codes::START_MAIL_DATA,
+        }
+    }
+
+    /// create a `Data` command for a source which already is dot-stashed
+    ///
+    /// The source is written verbatim (using `Io::write_raw`) instead
+    /// of scanning it for lines which need to be dot-stashed. **If the
+    /// source isn't dot-stashed a line starting with `'.'` will terminate
+    /// the mail prematurely**, see `Io::write_raw`.
+    pub fn pre_stuffed(source: S) -> Self {
+        Data {
+            source,
+            pre_stuffed: true,
+            normalize_line_endings: false,
+            intermediate_code: codes::START_MAIL_DATA,
+        }
+    }
+
+    /// makes the command turn orphan `'\r'`/`'\n'` into `"\r\n"` while dot-stashing
+    ///
+    /// This allows sending mails with unix line endings. It has no effect
+    /// if combined with `Data::pre_stuffed` as pre-stuffed data isn't scanned.
+    pub fn with_line_ending_normalization(mut self) -> Self {
+        self.normalize_line_endings = true;
+        self
+    }
+
+    /// makes the command wait for `code` instead of `354` before sending the body
+    ///
+    /// This is meant for non-standard deployments answering `DATA` with a
+    /// different intermediate code. Any other code makes the command fail
+    /// with `LogicError::UnexpectedCode` without sending the body.
+    pub fn expecting_intermediate(mut self, code: ResponseCode) -> Self {
+        self.intermediate_code = code;
+        self
+    }
+}
+
+impl<S: 'static> Data<S>
+where
+    S: Stream<Error = std_io::Error> + Send,
+    S::Item: Buf,
+{
+    /// write the body (and the terminating `".\r\n"`) as configured
+    fn write_body(self, io: Io) -> impl Future<Item = Io, Error = std_io::Error> + Send {
+        let Data {
+            source,
+            pre_stuffed,
+            normalize_line_endings,
+            ..
+        } = self;
+
+        if pre_stuffed {
+            io.write_raw(source)
+        } else if normalize_line_endings {
+            io.write_dot_stashed(source)
+                .with_line_ending_normalization()
+        } else {
+            io.write_dot_stashed(source)
+        }
+    }
+
+    /// execute the command against a LMTP server, see `Connection::send_lmtp_data`
+    ///
+    /// A LMTP server (RFC 2033) answers the end of the mail data with one
+    /// response for each recipient accepted with `RCPT TO`, in the same
+    /// order as the recipients. So one response per recipient counted by
+    /// `Io::accepted_recipients` is read (at last one). If the server
+    /// doesn't accept the `DATA` command the returned vector only contains
+    /// the result for it.
+    pub fn exec_lmtp(self, io: Io) -> LmtpExecFuture {
+        let intermediate_code = self.intermediate_code;
+        exec_lmtp_data(io, intermediate_code, move |io| self.write_body(io))
     }
 }
 
+/// the future returned by `Data::exec_lmtp`
+pub type LmtpExecFuture =
+    Box<dyn Future<Item = (Io, Vec<SmtpResult>), Error = std_io::Error> + Send + 'static>;
+
 impl<S: 'static> Cmd for Data<S>
 where
     S: Stream<Error = std_io::Error> + Send,
@@ -47,21 +148,165 @@ where
     }
 
     fn exec(self, io: Io) -> ExecFuture {
-        let Data { source } = self;
+        let intermediate_code = self.intermediate_code;
+        exec_data(io, intermediate_code, move |io| self.write_body(io))
+    }
+}
+
+/// a `DATA` command for a mail body contained in a single `Bytes` buffer
+///
+/// This does the same as `Data::from_buf` (and writes exactly the same
+/// bytes), but the body is dot-stashed into the output buffer in one pass
+/// (see `Io::put_dot_stashed`) instead of through a `Stream`, which is
+/// cheaper for the common case of an already fully assembled mail.
+#[derive(Debug, Clone)]
+pub struct DataBytes {
+    body: Bytes,
+    intermediate_code: ResponseCode,
+}
+
+impl DataBytes {
+    pub fn new(body: Bytes) -> Self {
+        DataBytes {
+            body,
+            intermediate_code: codes::START_MAIL_DATA,
+        }
+    }
+
+    /// makes the command wait for `code` instead of `354`, see `Data::expecting_intermediate`
+    pub fn expecting_intermediate(mut self, code: ResponseCode) -> Self {
+        self.intermediate_code = code;
+        self
+    }
+
+    /// write the dot-stashed body (and the terminating `".\r\n"`)
+    fn write_body(self, mut io: Io) -> impl Future<Item = Io, Error = std_io::Error> + Send {
+        io.put_dot_stashed(&self.body);
+        io.flush()
+    }
+
+    /// execute the command against a LMTP server, see `Data::exec_lmtp`
+    pub fn exec_lmtp(self, io: Io) -> LmtpExecFuture {
+        let intermediate_code = self.intermediate_code;
+        exec_lmtp_data(io, intermediate_code, move |io| self.write_body(io))
+    }
+}
+
+impl From<Bytes> for DataBytes {
+    fn from(body: Bytes) -> Self {
+        DataBytes::new(body)
+    }
+}
+
+impl Cmd for DataBytes {
+    fn check_cmd_availability(&self, _caps: Option<&EhloData>) -> Result<(), MissingCapabilities> {
+        Ok(())
+    }
+
+    fn exec(self, io: Io) -> ExecFuture {
+        let intermediate_code = self.intermediate_code;
+        exec_data(io, intermediate_code, move |io| self.write_body(io))
+    }
+}
+
+/// send `DATA`, wait for the intermediate response and write the body using `write_body`
+fn send_body<F, W>(
+    io: Io,
+    intermediate_code: ResponseCode,
+    write_body: F,
+) -> impl Future<Item = (Io, Result<(), LogicError>), Error = std_io::Error> + Send
+where
+    F: FnOnce(Io) -> W + Send + 'static,
+    W: Future<Item = Io, Error = std_io::Error> + Send,
+{
+    io.flush_line_from_parts(&["DATA"])
+        .and_then(Io::parse_response)
+        .ctx_and_then(move |io, response| {
+            if response.code() != intermediate_code {
+                return Either::A(future::ok((io, Err(LogicError::UnexpectedCode(response)))));
+            }
+
+            let fut = write_body(io)
+                .map_err(DataBodyInterrupted::wrap)
+                .map(|io| (io, Ok(())));
+
+            Either::B(fut)
+        })
+}
+
+/// executes a `DATA` command writing the body with `write_body`
+///
+/// In LMTP mode (see `Io::is_lmtp`) this reads one response per accepted
+/// recipient and resolves to the first failed one.
+fn exec_data<F, W>(io: Io, intermediate_code: ResponseCode, write_body: F) -> ExecFuture
+where
+    F: FnOnce(Io) -> W + Send + 'static,
+    W: Future<Item = Io, Error = std_io::Error> + Send + 'static,
+{
+    if io.is_lmtp() {
+        let fut = exec_lmtp_data(io, intermediate_code, write_body)
+            .map(|(io, results)| (io, first_failure(results)));
+        return Box::new(fut);
+    }
+
+    let fut = send_body(io, intermediate_code, write_body).ctx_and_then(|io, ()| {
+        io.parse_response()
+            // the transaction is completed independent of the result
+            .map(|(mut io, result)| {
+                io.set_in_transaction(false);
+                (io, result)
+            })
+    });
 
-        let fut = io
-            .flush_line_from_parts(&["DATA"])
-            .and_then(Io::parse_response)
-            .ctx_and_then(move |io, response| {
-                if response.code() != codes::START_MAIL_DATA {
-                    return Either::A(future::ok((io, Err(LogicError::UnexpectedCode(response)))));
-                }
+    Box::new(fut)
+}
 
-                let fut = io.write_dot_stashed(source).and_then(Io::parse_response);
+/// executes a `DATA` command against a LMTP server, see `Data::exec_lmtp`
+fn exec_lmtp_data<F, W>(io: Io, intermediate_code: ResponseCode, write_body: F) -> LmtpExecFuture
+where
+    F: FnOnce(Io) -> W + Send + 'static,
+    W: Future<Item = Io, Error = std_io::Error> + Send + 'static,
+{
+    let recipients = io.accepted_recipients();
+    let fut = send_body(io, intermediate_code, write_body).and_then(move |(io, result)| {
+        if let Err(err) = result {
+            return Either::A(future::ok((io, vec![Err(err)])));
+        }
 
-                Either::B(fut)
+        let count = recipients.max(1);
+        let results = Vec::with_capacity(count);
+        let fut = stream::iter_ok(0..count)
+            .fold((io, results), |(io, mut results), _| {
+                io.parse_response().map(move |(io, result)| {
+                    results.push(result);
+                    (io, results)
+                })
+            })
+            // the transaction is completed independent of the results
+            .map(|(mut io, results)| {
+                io.set_in_transaction(false);
+                (io, results)
             });
 
-        Box::new(fut)
+        Either::B(fut)
+    });
+
+    Box::new(fut)
+}
+
+/// turns the per recipient results of a LMTP server into one result
+///
+/// This is the first failed result, or the last result if all succeeded.
+fn first_failure(results: Vec<SmtpResult>) -> SmtpResult {
+    let mut results = results.into_iter();
+    let mut combined = results
+        .next()
+        .expect("exec_lmtp returns at last one result");
+    for result in results {
+        if combined.is_err() {
+            break;
+        }
+        combined = result;
     }
+    combined
 }
@@ -6,8 +6,8 @@ use futures::Future;
 use log_facade::warn;
 
 use crate::{
-    error::MissingCapabilities, Capability, ClientId, Cmd, Domain, EhloData, EhloParam, ExecFuture,
-    Io, Response, SyntaxError, SyntaxErrorHandling,
+    error::MissingCapabilities, AddressLiteral, Capability, ClientId, Cmd, Domain, EhloData,
+    EhloParam, ExecFuture, Io, Response, SyntaxError, SyntaxErrorHandling,
 };
 
 #[derive(Debug, Clone)]
@@ -47,9 +47,9 @@ impl From<ClientId> for Ehlo {
     }
 }
 
-impl Into<ClientId> for Ehlo {
-    fn into(self) -> ClientId {
-        self.identity
+impl From<Ehlo> for ClientId {
+    fn from(ehlo: Ehlo) -> Self {
+        ehlo.identity
     }
 }
 
@@ -58,49 +58,123 @@ impl Cmd for Ehlo {
         Ok(())
     }
 
-    fn exec(self, mut io: Io) -> ExecFuture {
-        let error_on_bad_ehlo_capabilities =
-            self.syntax_error_handling() == &SyntaxErrorHandling::Strict;
-        let str_me = match self.identity() {
-            ClientId::Domain(domain) => domain.as_str(),
-            ClientId::AddressLiteral(addr_lit) => addr_lit.as_str(),
-        };
-
-        {
-            //7 == "EHLO ".len() + "\r\n".len()
-            let out = io.out_buffer(7 + str_me.len());
-            out.put("EHLO ");
-            out.put(str_me);
-            out.put("\r\n");
+    fn exec(self, io: Io) -> ExecFuture {
+        exec_hello("EHLO ", self.identity, self.syntax_error_handling, io)
+    }
+}
+
+/// the `LHLO` command used instead of `EHLO` by LMTP (RFC 2033)
+///
+/// It works exactly like `Ehlo`, i.e. the response is parsed into
+/// `EhloData` which is stored in the connection, it just sends `LHLO`.
+/// If successful it also puts the connection into LMTP mode (see
+/// `Io::is_lmtp`), as a LMTP server answers the end of the mail data with
+/// one response per accepted recipient, see `Connection::send_lmtp_data`.
+#[derive(Debug, Clone)]
+pub struct Lhlo {
+    identity: ClientId,
+    syntax_error_handling: SyntaxErrorHandling,
+}
+
+impl Lhlo {
+    pub fn new(identity: ClientId) -> Self {
+        Lhlo {
+            identity,
+            syntax_error_handling: Default::default(),
         }
+    }
 
-        let fut = io
-            .flush()
-            .and_then(Io::parse_response)
-            //TODO ctx_and_then
-            .and_then(move |(mut io, result)| match result {
-                Err(response) => Ok((io, Err(response))),
-                Ok(response) => {
-                    let ehlo = parse_ehlo_response(&response, error_on_bad_ehlo_capabilities)
-                        .map_err(|err| std_io::Error::new(std_io::ErrorKind::Other, err))?;
-
-                    io.set_ehlo_data(ehlo);
-                    Ok((io, Ok(response)))
+    pub fn with_syntax_error_handling(mut self, method: SyntaxErrorHandling) -> Self {
+        self.syntax_error_handling = method;
+        self
+    }
+
+    pub fn syntax_error_handling(&self) -> &SyntaxErrorHandling {
+        &self.syntax_error_handling
+    }
+
+    pub fn identity(&self) -> &ClientId {
+        &self.identity
+    }
+}
+
+impl From<ClientId> for Lhlo {
+    fn from(identity: ClientId) -> Self {
+        Lhlo::new(identity)
+    }
+}
+
+impl From<Lhlo> for ClientId {
+    fn from(lhlo: Lhlo) -> Self {
+        lhlo.identity
+    }
+}
+
+impl Cmd for Lhlo {
+    fn check_cmd_availability(&self, _caps: Option<&EhloData>) -> Result<(), MissingCapabilities> {
+        Ok(())
+    }
+
+    fn exec(self, io: Io) -> ExecFuture {
+        let fut = exec_hello("LHLO ", self.identity, self.syntax_error_handling, io).map(
+            |(mut io, result)| {
+                if result.is_ok() {
+                    io.set_lmtp(true);
                 }
-            });
+                (io, result)
+            },
+        );
 
         Box::new(fut)
     }
 }
 
+/// sends `EHLO`/`LHLO` (passed in including the trailing space) and parses the response
+fn exec_hello(
+    verb: &'static str,
+    identity: ClientId,
+    syntax_error_handling: SyntaxErrorHandling,
+    mut io: Io,
+) -> ExecFuture {
+    let error_on_bad_ehlo_capabilities = syntax_error_handling == SyntaxErrorHandling::Strict;
+
+    {
+        let str_me = identity.as_str();
+        //2 == "\r\n".len()
+        let out = io.out_buffer(verb.len() + str_me.len() + 2);
+        out.put(verb);
+        out.put(str_me);
+        out.put("\r\n");
+    }
+
+    let fut = io
+        .flush()
+        .and_then(Io::parse_response)
+        //TODO ctx_and_then
+        .and_then(move |(mut io, result)| match result {
+            Err(response) => Ok((io, Err(response))),
+            Ok(response) => {
+                let ehlo = parse_ehlo_response(&response, error_on_bad_ehlo_capabilities)
+                    .map_err(|err| std_io::Error::new(std_io::ErrorKind::Other, err))?;
+
+                io.set_ehlo_data(ehlo);
+                io.set_client_id(identity);
+                // EHLO resets the smtp state, like RSET does
+                io.set_in_transaction(false);
+                Ok((io, Ok(response)))
+            }
+        });
+
+    Box::new(fut)
+}
+
 fn parse_ehlo_response(
     response: &Response,
     error_on_bad_ehlo_capabilities: bool,
 ) -> Result<EhloData, SyntaxError> {
     let lines = response.msg();
     let first = lines.first().expect("response with 0 lines should not");
-    //UNWRAP_SAFE: Split has at last one entry
-    let domain: Domain = first.split(' ').next().unwrap().parse()?;
+    let domain = parse_ehlo_domain(first, error_on_bad_ehlo_capabilities)?;
     let mut caps = HashMap::new();
 
     for line in lines[1..].iter() {
@@ -121,6 +195,48 @@ fn parse_ehlo_response(
     Ok(EhloData::new(domain, caps))
 }
 
+/// parses the domain from the first line of a ehlo response
+///
+/// The domain is the first token, but some servers put a banner (e.g.
+/// `"*** Welcome"`) in front of it. If not `strict` the first token which
+/// is a domain with more than one label (i.e. contains a `'.'`) or an
+/// address literal is used in that case, only if there is no such token
+/// the first token parsing as a (single label) domain is used.
+fn parse_ehlo_domain(first: &str, strict: bool) -> Result<Domain, SyntaxError> {
+    let mut tokens = first.split(' ');
+    //UNWRAP_SAFE: Split has at last one entry
+    let err = match tokens.next().unwrap().parse::<Domain>() {
+        Ok(domain) => return Ok(domain),
+        Err(err) => err,
+    };
+
+    if strict {
+        return Err(err);
+    }
+
+    let qualified = tokens.clone().find_map(|token| {
+        if token.contains('.') {
+            token.parse::<Domain>().ok()
+        } else {
+            None
+        }
+    });
+    let literal = || {
+        tokens
+            .clone()
+            .find(|token| token.parse::<AddressLiteral>().is_ok())
+            .map(Domain::from_unchecked)
+    };
+    qualified
+        .or_else(literal)
+        .or_else(|| {
+            tokens
+                .filter_map(|token| token.parse::<Domain>().ok())
+                .next()
+        })
+        .ok_or(err)
+}
+
 fn parse_capability_in_ehlo_response(
     line: &str,
 ) -> Result<(Capability, Vec<EhloParam>), SyntaxError> {
@@ -238,6 +354,43 @@ mod test {
             let _err = parse_ehlo_response(&response, true).unwrap_err();
         }
 
+        #[test]
+        fn lax_finds_domain_after_banner() {
+            let response = Response::new(
+                OK,
+                vec![
+                    "*** Welcome! example.de ESMTP ready".to_owned(),
+                    "PIPELINING".to_owned(),
+                ],
+            );
+            let ehlo_data = parse_ehlo_response(&response, false).unwrap();
+            assert_eq!(ehlo_data.domain(), "example.de");
+            assert!(ehlo_data.supports_pipelining());
+
+            let _err = parse_ehlo_response(&response, true).unwrap_err();
+        }
+
+        #[test]
+        fn lax_prefers_qualified_domains_and_address_literals() {
+            let response = Response::new(OK, vec!["*** Welcome to example.de".to_owned()]);
+            let ehlo_data = parse_ehlo_response(&response, false).unwrap();
+            assert_eq!(ehlo_data.domain(), "example.de");
+
+            let response = Response::new(OK, vec!["*** Welcome at [127.0.0.1]".to_owned()]);
+            let ehlo_data = parse_ehlo_response(&response, false).unwrap();
+            assert_eq!(ehlo_data.domain(), "[127.0.0.1]");
+
+            let response = Response::new(OK, vec!["*** Welcome ***".to_owned()]);
+            let ehlo_data = parse_ehlo_response(&response, false).unwrap();
+            assert_eq!(ehlo_data.domain(), "welcome");
+        }
+
+        #[test]
+        fn lax_still_fails_without_any_domain() {
+            let response = Response::new(OK, vec!["*** (#!) ***".to_owned()]);
+            let _err = parse_ehlo_response(&response, false).unwrap_err();
+        }
+
         #[test]
         fn issue_05_b() {
             let response = Response::new(
@@ -276,5 +429,27 @@ mod test {
             );
             let _ehlo_data = parse_ehlo_response(&response, true).unwrap();
         }
+
+        #[test]
+        fn typed_capability_helpers() {
+            let response = Response::new(
+                OK,
+                vec![
+                    "example.de".to_owned(),
+                    "pipelining".to_owned(),
+                    "STARTTLS".to_owned(),
+                    "8BITMIME".to_owned(),
+                    "DSN".to_owned(),
+                ],
+            );
+            let ehlo_data = parse_ehlo_response(&response, true).unwrap();
+
+            assert!(ehlo_data.supports_pipelining());
+            assert!(ehlo_data.supports_starttls());
+            assert!(ehlo_data.supports_8bitmime());
+            assert!(ehlo_data.supports_dsn());
+            assert!(!ehlo_data.supports_chunking());
+            assert!(!ehlo_data.supports_smtputf8());
+        }
     }
 }
@@ -1,6 +1,6 @@
 //! Module containing all commands already provided by this crate
 mod ehlo;
-pub use self::ehlo::Ehlo;
+pub use self::ehlo::{Ehlo, Lhlo};
 
 mod simple;
 pub use self::simple::*;
@@ -16,5 +16,7 @@ pub mod auth;
 mod reset;
 pub use self::reset::*;
 
+pub use crate::xtext;
+
 mod combinators;
 pub use self::combinators::*;
@@ -1,11 +1,13 @@
 use std::io as std_io;
 
+use futures::future::{self, Either};
 use futures::Future;
 
 use crate::{
     common::EhloData,
     error::{LogicError, MissingCapabilities},
-    Cmd, ExecFuture, Io,
+    response::codes,
+    Cmd, ExecFuture, Io, Response,
 };
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
@@ -22,9 +24,10 @@ impl Cmd for Reset {
             .and_then(Io::parse_response)
             // server should not, ever, answer with anything but 250, we can be tolerant and
             // accept all non-error codes but on error codes we have no way to handle it
-            .and_then(|(io, result)| match result {
+            .and_then(|(mut io, result)| match result {
                 Ok(response) => {
                     if response.code().is_positive() {
+                        io.set_in_transaction(false);
                         Ok((io, Ok(response)))
                     } else {
                         let logic_err = LogicError::UnexpectedCode(response);
@@ -37,3 +40,29 @@ impl Cmd for Reset {
         Box::new(fut)
     }
 }
+
+/// sends `RSET` only if a mail transaction is in progress
+///
+/// If no transaction is in progress (see `Io::is_in_transaction`) nothing
+/// is written and this directly resolves to a synthetic `250` response,
+/// saving a round trip when reusing connections. Else it behaves exactly
+/// like `Reset`.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct ResetIfNeeded;
+
+impl Cmd for ResetIfNeeded {
+    fn check_cmd_availability(&self, _caps: Option<&EhloData>) -> Result<(), MissingCapabilities> {
+        Ok(())
+    }
+
+    fn exec(self, io: Io) -> ExecFuture {
+        let fut = if io.is_in_transaction() {
+            Either::A(Reset.exec(io))
+        } else {
+            let response = Response::new(codes::OK, vec![]);
+            Either::B(future::ok((io, Ok(response))))
+        };
+
+        Box::new(fut)
+    }
+}
@@ -1,9 +1,19 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt::{self, Display},
+};
+
+use futures::{future, Future};
 
 use crate::{
     common::EhloData,
-    data_types::{EsmtpKeyword, EsmtpValue, ForwardPath, ReversePath},
-    error::MissingCapabilities,
+    data_types::{
+        validate_no_ctl, Capability, EsmtpKeyword, EsmtpValue, ForwardPath, ReversePath,
+        SyntaxError,
+    },
+    error::{LogicError, MissingCapabilities},
+    response::{codes, Response},
     Cmd, ExecFuture, Io,
 };
 
@@ -37,6 +47,42 @@ impl Cmd for Noop {
     }
 }
 
+impl Noop {
+    /// create a `NOOP <arg>` command, failing if `arg` contains `'\r'`, `'\n'` or `'\0'`
+    ///
+    /// The argument is ignored by the server (RFC 5321, 4.1.1.9), but some
+    /// keepalive setups use it to e.g. send a token.
+    pub fn with_arg<I>(arg: I) -> Result<NoopWithArg, SyntaxError>
+    where
+        I: Into<String>,
+    {
+        check_line(arg.into()).map(|arg| NoopWithArg { arg })
+    }
+}
+
+/// A `NOOP` command with an argument, created with `Noop::with_arg`
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct NoopWithArg {
+    arg: String,
+}
+
+impl NoopWithArg {
+    /// the argument send with `NOOP`
+    pub fn arg(&self) -> &str {
+        &self.arg
+    }
+}
+
+impl Cmd for NoopWithArg {
+    fn check_cmd_availability(&self, _caps: Option<&EhloData>) -> Result<(), MissingCapabilities> {
+        Ok(())
+    }
+
+    fn exec(self, io: Io) -> ExecFuture {
+        io.exec_simple_cmd(&["NOOP ", self.arg.as_str()])
+    }
+}
+
 pub type Params = HashMap<EsmtpKeyword, Option<EsmtpValue>>;
 
 pub fn params_with_smtputf8(mut p: Params) -> Params {
@@ -46,26 +92,215 @@ pub fn params_with_smtputf8(mut p: Params) -> Params {
 
 #[derive(Debug, Clone)]
 pub struct Mail {
-    pub reverse_path: ReversePath,
-    pub params: Params,
+    reverse_path: ReversePath,
+    pub(crate) params: Params,
 }
 
 impl Mail {
+    /// create a new `MAIL FROM:` command
+    ///
+    /// The path isn't validated again, `ReversePath::new` already rejects
+    /// paths which could be used to inject commands.
     pub fn new(reverse_path: ReversePath) -> Self {
         Mail {
             reverse_path,
             params: Params::new(),
         }
     }
+
+    /// the reverse path send with the command
+    pub fn reverse_path(&self) -> &ReversePath {
+        &self.reverse_path
+    }
+
+    /// the parameters send with the command
+    pub fn params(&self) -> &Params {
+        &self.params
+    }
+
+    /// adds a (e.g. extension specific) parameter to the command
+    pub fn with_param(mut self, keyword: EsmtpKeyword, value: Option<EsmtpValue>) -> Self {
+        self.params.insert(keyword, value);
+        self
+    }
+
+    /// sets the `AUTH=<mailbox>` parameter (RFC 4954, formerly RFC 2554)
+    ///
+    /// This tells the server on behalf of which (authenticated) submitter
+    /// the mail is send, e.g. when relaying a mail. The mailbox is send
+    /// xtext encoded and without angle brackets, an empty mailbox is send
+    /// as `AUTH=<>` (see `with_anonymous_auth`). Using this requires the
+    /// server to support `AUTH`.
+    pub fn with_auth_identity(self, identity: impl Into<ReversePath>) -> Self {
+        let identity = identity.into();
+        if identity.as_str().is_empty() {
+            return self.with_anonymous_auth();
+        }
+        self.with_param(
+            EsmtpKeyword::from_unchecked(PARAM_AUTH),
+            Some(EsmtpValue::from_encoded(identity.as_str())),
+        )
+    }
+
+    /// sets the `AUTH=<>` parameter, i.e. the submitter is not known/trusted
+    ///
+    /// Using this requires the server to support `AUTH`.
+    pub fn with_anonymous_auth(self) -> Self {
+        self.with_param(
+            EsmtpKeyword::from_unchecked(PARAM_AUTH),
+            Some(EsmtpValue::from_unchecked("<>")),
+        )
+    }
+
+    /// sets the `DELIVERBY=<seconds><mode>` parameter (RFC 2852)
+    ///
+    /// Asks the server to deliver the mail within `seconds`, if it can't
+    /// it either returns the mail (`DeliverByMode::Return`) or sends a
+    /// delay notification (`DeliverByMode::Notify`). Using this requires the
+    /// server to support `DELIVERBY`.
+    ///
+    /// With `DeliverByMode::Return` `seconds` has to be positive (else this
+    /// fails) and at last the min-by-time advertised by the server (else
+    /// sending the command fails with a `LogicError::Custom` containing a
+    /// `InvalidDeliverBy::BelowMinByTime`).
+    pub fn with_deliver_by(
+        self,
+        seconds: i32,
+        mode: DeliverByMode,
+    ) -> Result<Self, InvalidDeliverBy> {
+        if mode == DeliverByMode::Return && seconds <= 0 {
+            return Err(InvalidDeliverBy::NonPositiveReturnTime { seconds });
+        }
+        let value = format!("{}{}", seconds, mode.as_char());
+        Ok(self.with_param(
+            EsmtpKeyword::from_unchecked(PARAM_DELIVERBY),
+            Some(EsmtpValue::from_unchecked(value)),
+        ))
+    }
+
+    /// the by-time of the `DELIVERBY` parameter if it is set with the return mode
+    fn deliver_by_return_time(&self) -> Option<i64> {
+        let value = self
+            .params
+            .get(&EsmtpKeyword::from_unchecked(PARAM_DELIVERBY))?
+            .as_ref()?
+            .as_str();
+        // a trace flag (`T`) can follow the mode
+        let value = value.trim_end_matches(['T', 't']);
+        let seconds = value.strip_suffix(['R', 'r'])?;
+        seconds.parse().ok()
+    }
+
+    /// checks the by-time (with the return mode) against the server's min-by-time
+    fn check_deliver_by_time(&self, caps: Option<&EhloData>) -> Result<(), InvalidDeliverBy> {
+        let min_by_time =
+            caps.and_then(|ehlo_data| ehlo_data.capability_param_u64(PARAM_DELIVERBY));
+        if let (Some(min_by_time), Some(by_time)) = (min_by_time, self.deliver_by_return_time()) {
+            if by_time < min_by_time as i64 {
+                return Err(InvalidDeliverBy::BelowMinByTime {
+                    by_time,
+                    min_by_time,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// what the server should do if a mail can't be delivered in time, see `Mail::with_deliver_by`
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum DeliverByMode {
+    /// send a delay notification (`N`)
+    Notify,
+    /// return the mail as undeliverable (`R`)
+    Return,
+}
+
+impl DeliverByMode {
+    fn as_char(self) -> char {
+        match self {
+            DeliverByMode::Notify => 'N',
+            DeliverByMode::Return => 'R',
+        }
+    }
+}
+
+/// error for a `DELIVERBY` by-time which can't be used, see `Mail::with_deliver_by`
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum InvalidDeliverBy {
+    /// with the return mode the by-time has to be positive (RFC 2852, 4.1)
+    NonPositiveReturnTime { seconds: i32 },
+    /// the by-time (with the return mode) is less than the server's min-by-time
+    BelowMinByTime { by_time: i64, min_by_time: u64 },
 }
 
+impl Display for InvalidDeliverBy {
+    fn fmt(&self, fter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InvalidDeliverBy::NonPositiveReturnTime { seconds } => write!(
+                fter,
+                "DELIVERBY with return mode needs a positive by-time, got {}",
+                seconds
+            ),
+            InvalidDeliverBy::BelowMinByTime {
+                by_time,
+                min_by_time,
+            } => write!(
+                fter,
+                "DELIVERBY by-time {} is less than the server's min-by-time {}",
+                by_time, min_by_time
+            ),
+        }
+    }
+}
+
+impl Error for InvalidDeliverBy {}
+
+const PARAM_AUTH: &str = "AUTH";
+const PARAM_DELIVERBY: &str = "DELIVERBY";
+
 impl Cmd for Mail {
-    fn check_cmd_availability(&self, _caps: Option<&EhloData>) -> Result<(), MissingCapabilities> {
-        Ok(())
+    /// checks the capabilities needed by the used parameters
+    fn check_cmd_availability(&self, caps: Option<&EhloData>) -> Result<(), MissingCapabilities> {
+        let missing = self
+            .required_capabilities()
+            .into_iter()
+            .filter(|cap| {
+                !caps
+                    .map(|ehlo_data| ehlo_data.has_capability(cap.as_str()))
+                    .unwrap_or(false)
+            })
+            .collect::<Vec<_>>();
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(MissingCapabilities::new(missing))
+        }
+    }
+
+    fn required_capabilities(&self) -> Vec<Capability> {
+        [PARAM_AUTH, PARAM_DELIVERBY]
+            .iter()
+            .map(|param| EsmtpKeyword::from_unchecked(*param))
+            .filter(|keyword| self.params.contains_key(keyword))
+            .map(Capability::from)
+            .collect()
     }
 
     fn exec(self, con: Io) -> ExecFuture {
-        handle_pathy_cmd(con, "MAIL FROM:", self.reverse_path.as_str(), &self.params)
+        if let Err(err) = self.check_deliver_by_time(con.ehlo_data()) {
+            return Box::new(future::ok((con, Err(LogicError::Custom(Box::new(err))))));
+        }
+
+        let fut = handle_pathy_cmd(con, "MAIL FROM:", self.reverse_path.as_str(), &self.params)
+            .map(|(mut io, result)| {
+                if result.is_ok() {
+                    io.set_in_transaction(true);
+                }
+                (io, result)
+            });
+
+        Box::new(fut)
     }
 }
 
@@ -73,17 +308,37 @@ impl Cmd for Mail {
 pub struct Recipient {
     //Grammar: "<Postmaster@" Domain ">" / "<Postmaster>" / forward-path
     //Note: that Postmaster is case-sensitive
-    pub forward_path: ForwardPath,
-    pub params: Params,
+    forward_path: ForwardPath,
+    params: Params,
 }
 
 impl Recipient {
+    /// create a new `RCPT TO:` command
+    ///
+    /// The path isn't validated again, `ForwardPath::new` already rejects
+    /// paths which could be used to inject commands.
     pub fn new(forward_path: ForwardPath) -> Self {
         Recipient {
             forward_path,
             params: Params::new(),
         }
     }
+
+    /// the forward path send with the command
+    pub fn forward_path(&self) -> &ForwardPath {
+        &self.forward_path
+    }
+
+    /// the parameters send with the command
+    pub fn params(&self) -> &Params {
+        &self.params
+    }
+
+    /// adds a (e.g. extension specific) parameter to the command
+    pub fn with_param(mut self, keyword: EsmtpKeyword, value: Option<EsmtpValue>) -> Self {
+        self.params.insert(keyword, value);
+        self
+    }
 }
 
 impl Cmd for Recipient {
@@ -92,7 +347,16 @@ impl Cmd for Recipient {
     }
 
     fn exec(self, con: Io) -> ExecFuture {
-        handle_pathy_cmd(con, "RCPT TO:", self.forward_path.as_str(), &self.params)
+        let fut = handle_pathy_cmd(con, "RCPT TO:", self.forward_path.as_str(), &self.params).map(
+            |(mut io, result)| {
+                if result.is_ok() {
+                    io.add_accepted_recipient();
+                }
+                (io, result)
+            },
+        );
+
+        Box::new(fut)
     }
 }
 
@@ -114,14 +378,79 @@ fn handle_pathy_cmd(io: Io, cmd: &str, path: &str, params: &Params) -> ExecFutur
     }
 }
 
+/// A `VRFY <query>` command, asking the server to verify a user or mailbox
+///
+/// A non-ascii query requires the server to support `SMTPUTF8`, the
+/// response can be interpreted with `Verify::parse_result`.
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct Verify {
-    pub query: String,
+    query: String,
 }
 
-impl Cmd for Verify {
-    fn check_cmd_availability(&self, _caps: Option<&EhloData>) -> Result<(), MissingCapabilities> {
+impl Verify {
+    /// create a new `VRFY` command, failing if the query contains `'\r'`, `'\n'` or `'\0'`
+    pub fn new<I>(query: I) -> Result<Self, SyntaxError>
+    where
+        I: Into<String>,
+    {
+        check_line(query.into()).map(|query| Verify { query })
+    }
+
+    /// the user or mailbox to verify
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    /// parses the (successful) response to a `VRFY` command
+    ///
+    /// For `250` and `251` the mailbox/forward path is returned, if
+    /// the response contains a `<...>` delimited address only the address
+    /// is returned, else the whole first response line is.
+    ///
+    /// Returns `None` if the response code is neither `250`, `251` nor `252`.
+    pub fn parse_result(response: &Response) -> Option<VrfyOutcome> {
+        let code = response.code();
+        let line = &response.msg()[0];
+        if code == codes::OK {
+            Some(VrfyOutcome::Verified(extract_address(line)))
+        } else if code == codes::OK_NOT_LOCAL {
+            Some(VrfyOutcome::NotLocalForwardTo(extract_address(line)))
+        } else if code == codes::OK_UNVERIFIED {
+            Some(VrfyOutcome::CannotVerifyWillAccept)
+        } else {
+            None
+        }
+    }
+}
+
+/// a non-ascii `VRFY`/`EXPN` query requires `SMTPUTF8` (RFC 6531, 3.7.4)
+fn query_capabilities(query: &str) -> Vec<Capability> {
+    if query.is_ascii() {
+        Vec::new()
+    } else {
+        vec![Capability::from(EsmtpKeyword::from_unchecked("SMTPUTF8"))]
+    }
+}
+
+fn check_query_availability(
+    query: &str,
+    caps: Option<&EhloData>,
+) -> Result<(), MissingCapabilities> {
+    let supports_smtputf8 = caps.map(EhloData::supports_smtputf8).unwrap_or(false);
+    if query.is_ascii() || supports_smtputf8 {
         Ok(())
+    } else {
+        Err(MissingCapabilities::new(query_capabilities(query)))
+    }
+}
+
+impl Cmd for Verify {
+    fn check_cmd_availability(&self, caps: Option<&EhloData>) -> Result<(), MissingCapabilities> {
+        check_query_availability(&self.query, caps)
+    }
+
+    fn required_capabilities(&self) -> Vec<Capability> {
+        query_capabilities(&self.query)
     }
 
     fn exec(self, io: Io) -> ExecFuture {
@@ -129,9 +458,119 @@ impl Cmd for Verify {
     }
 }
 
+/// outcome of a successful `VRFY` command, see `Verify::parse_result`
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum VrfyOutcome {
+    /// `250`, the mailbox was verified
+    Verified(String),
+    /// `251`, the user is not local, mails will be forwarded to the given path
+    NotLocalForwardTo(String),
+    /// `252`, the user can't be verified, but mails will be accepted
+    CannotVerifyWillAccept,
+}
+
+/// A `EXPN <list>` command, asking the server to expand a mailing list
+///
+/// A non-ascii query requires the server to support `SMTPUTF8`, the members
+/// of the list can be extracted from the response with `Expn::members`.
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Expn {
+    query: String,
+}
+
+impl Expn {
+    /// create a new `EXPN` command, failing if the query contains `'\r'`, `'\n'` or `'\0'`
+    pub fn new<I>(query: I) -> Result<Self, SyntaxError>
+    where
+        I: Into<String>,
+    {
+        check_line(query.into()).map(|query| Expn { query })
+    }
+
+    /// the mailing list to expand
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    /// returns the lines of a (successful) `EXPN` response, one per list member
+    pub fn members(response: &Response) -> &[String] {
+        response.msg()
+    }
+}
+
+impl Cmd for Expn {
+    fn check_cmd_availability(&self, caps: Option<&EhloData>) -> Result<(), MissingCapabilities> {
+        check_query_availability(&self.query, caps)
+    }
+
+    fn required_capabilities(&self) -> Vec<Capability> {
+        query_capabilities(&self.query)
+    }
+
+    fn exec(self, io: Io) -> ExecFuture {
+        io.exec_simple_cmd(&["EXPN ", self.query.as_str()])
+    }
+}
+
+fn extract_address(line: &str) -> String {
+    let address = line.find('<').and_then(|start| {
+        line[start + 1..]
+            .find('>')
+            .map(|len| &line[start + 1..start + 1 + len])
+    });
+    address.unwrap_or_else(|| line.trim()).to_owned()
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Default)]
 pub struct Help {
-    pub topic: Option<String>,
+    topic: Option<String>,
+}
+
+impl Help {
+    /// create a `HELP` command without topic
+    pub fn new() -> Self {
+        Help { topic: None }
+    }
+
+    /// create a `HELP <topic>` command, failing if the topic contains `'\r'`, `'\n'` or `'\0'`
+    pub fn with_topic<I>(topic: I) -> Result<Self, SyntaxError>
+    where
+        I: Into<String>,
+    {
+        check_line(topic.into()).map(|topic| Help { topic: Some(topic) })
+    }
+
+    /// the topic help is asked for, if any
+    pub fn topic(&self) -> Option<&str> {
+        self.topic.as_deref()
+    }
+
+    /// returns the lines of a `HELP` response
+    pub fn lines(response: &Response) -> &[String] {
+        response.msg()
+    }
+
+    /// best-effort extraction of the commands listed in a `HELP` response
+    ///
+    /// Returns all (de-duplicated) words consisting only of at last three
+    /// upper case ascii letters, in the order they appear. As the help text
+    /// is free form this can include false positives (e.g. `"ESMTP"` from
+    /// a greeting).
+    pub fn supported_commands(response: &Response) -> Vec<String> {
+        let mut commands = Vec::new();
+        let words = response
+            .msg()
+            .iter()
+            .flat_map(|line| line.split(|ch: char| !ch.is_ascii_alphanumeric()));
+
+        for word in words {
+            let is_command = word.len() >= 3 && word.bytes().all(|bch| bch.is_ascii_uppercase());
+            if is_command && !commands.iter().any(|cmd| cmd == word) {
+                commands.push(word.to_owned());
+            }
+        }
+        commands
+    }
 }
 
 impl Cmd for Help {
@@ -147,3 +586,221 @@ impl Cmd for Help {
         }
     }
 }
+
+/// sends a custom, raw command line and parses a single response
+///
+/// This is meant for debugging/experimenting with extensions, for which
+/// no `Cmd` implementation exists. The line is send as is (with `"\r\n"`
+/// appended), so it has to be valid smtp and a command answered with a
+/// multi step exchange (like `DATA` or `AUTH`) can not be used with it.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Raw {
+    line: String,
+    required_caps: Vec<Capability>,
+}
+
+impl Raw {
+    /// create a new raw command, failing if the line contains `'\r'`, `'\n'` or `'\0'`
+    pub fn new<I>(line: I, required_caps: Vec<Capability>) -> Result<Self, SyntaxError>
+    where
+        I: Into<String>,
+    {
+        check_line(line.into()).map(|line| Raw {
+            line,
+            required_caps,
+        })
+    }
+
+    /// the line send to the server (without the trailing `"\r\n"`)
+    pub fn line(&self) -> &str {
+        &self.line
+    }
+
+    /// the capabilities the server has to support to use the command
+    pub fn required_caps(&self) -> &[Capability] {
+        &self.required_caps
+    }
+}
+
+impl Cmd for Raw {
+    fn required_capabilities(&self) -> Vec<Capability> {
+        self.required_caps.clone()
+    }
+
+    fn check_cmd_availability(&self, caps: Option<&EhloData>) -> Result<(), MissingCapabilities> {
+        let missing = self
+            .required_caps
+            .iter()
+            .filter(|cap| {
+                !caps
+                    .map(|caps| caps.has_capability(cap.as_str()))
+                    .unwrap_or(false)
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(MissingCapabilities::new(missing))
+        }
+    }
+
+    fn exec(self, io: Io) -> ExecFuture {
+        io.exec_simple_cmd(&[self.line.as_str()])
+    }
+}
+
+fn check_line(line: String) -> Result<String, SyntaxError> {
+    if validate_no_ctl(&line) {
+        Ok(line)
+    } else {
+        Err(SyntaxError::CommandLine(line))
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    mod Raw {
+        #![allow(non_snake_case)]
+        use super::super::Raw;
+        use crate::data_types::SyntaxErrorKind;
+
+        #[test]
+        fn rejects_line_breaks() {
+            assert!(Raw::new("NOOP\r\nQUIT", vec![]).is_err());
+            assert!(Raw::new("NOOP\n", vec![]).is_err());
+            assert!(Raw::new("NOOP\r", vec![]).is_err());
+            let err = Raw::new("NOOP\0", vec![]).unwrap_err();
+            assert_eq!(err.kind(), SyntaxErrorKind::CommandLine);
+            assert_eq!(err.invalid_input(), "NOOP\0");
+            assert_eq!(Raw::new("HELP FOO", vec![]).unwrap().line(), "HELP FOO");
+        }
+    }
+
+    mod Noop {
+        #![allow(non_snake_case)]
+        use super::super::Noop;
+
+        #[test]
+        fn with_arg_rejects_line_breaks() {
+            assert!(Noop::with_arg("token\r\nQUIT").is_err());
+            assert!(Noop::with_arg("token\n").is_err());
+            assert!(Noop::with_arg("tok\0en").is_err());
+            assert_eq!(Noop::with_arg("token").unwrap().arg(), "token");
+        }
+    }
+
+    mod Verify {
+        #![allow(non_snake_case)]
+        use super::super::{Verify, VrfyOutcome};
+        use crate::{
+            response::codes::{MAILBOX_UNAVAILABLE, OK, OK_NOT_LOCAL, OK_UNVERIFIED},
+            Response,
+        };
+
+        #[test]
+        fn parses_verified_mailbox() {
+            let response = Response::new(OK, vec!["1itus <testitus1@test.test>".to_owned()]);
+            assert_eq!(
+                Verify::parse_result(&response),
+                Some(VrfyOutcome::Verified("testitus1@test.test".to_owned()))
+            );
+        }
+
+        #[test]
+        fn parses_forward_path() {
+            let response = Response::new(
+                OK_NOT_LOCAL,
+                vec!["User not local; will forward to <joe@other.test>".to_owned()],
+            );
+            assert_eq!(
+                Verify::parse_result(&response),
+                Some(VrfyOutcome::NotLocalForwardTo("joe@other.test".to_owned()))
+            );
+        }
+
+        #[test]
+        fn parses_cannot_verify() {
+            let response = Response::new(OK_UNVERIFIED, vec![]);
+            assert_eq!(
+                Verify::parse_result(&response),
+                Some(VrfyOutcome::CannotVerifyWillAccept)
+            );
+        }
+
+        #[test]
+        fn other_codes_are_not_parsed() {
+            let response = Response::new(MAILBOX_UNAVAILABLE, vec!["no".to_owned()]);
+            assert_eq!(Verify::parse_result(&response), None);
+        }
+
+        #[test]
+        fn new_rejects_command_injection() {
+            assert!(Verify::new("joe\r\nRSET").is_err());
+            assert!(Verify::new("joe\0").is_err());
+            assert_eq!(Verify::new("joe").unwrap().query(), "joe");
+        }
+    }
+
+    mod Expn {
+        #![allow(non_snake_case)]
+        use super::super::Expn;
+
+        #[test]
+        fn new_rejects_command_injection() {
+            assert!(Expn::new("list\nRSET").is_err());
+            assert!(Expn::new("list\r").is_err());
+            assert_eq!(Expn::new("list").unwrap().query(), "list");
+        }
+    }
+
+    mod Help {
+        #![allow(non_snake_case)]
+        use super::super::Help;
+        use crate::{response::codes::HELP_RESPONSE, Response};
+
+        fn help_response() -> Response {
+            let lines = vec![
+                "2.0.0 This is sendmail",
+                "2.0.0 Topics:",
+                "2.0.0       HELO    EHLO    MAIL    RCPT    DATA",
+                "2.0.0       RSET    NOOP    QUIT    HELP    VRFY",
+                "2.0.0       STARTTLS",
+                "2.0.0 For more info use \"HELP <topic>\".",
+                "2.0.0 End of HELP info",
+            ];
+            Response::new(
+                HELP_RESPONSE,
+                lines.into_iter().map(ToOwned::to_owned).collect(),
+            )
+        }
+
+        #[test]
+        fn with_topic_rejects_command_injection() {
+            assert!(Help::with_topic("MAIL\r\nRSET").is_err());
+            assert!(Help::with_topic("MAIL\0").is_err());
+            assert_eq!(Help::with_topic("MAIL").unwrap().topic(), Some("MAIL"));
+            assert_eq!(Help::new().topic(), None);
+        }
+
+        #[test]
+        fn lines_are_passed_through() {
+            let response = help_response();
+            assert_eq!(Help::lines(&response).len(), 7);
+        }
+
+        #[test]
+        fn extracts_supported_commands() {
+            let response = help_response();
+            assert_eq!(
+                Help::supported_commands(&response),
+                vec![
+                    "HELO", "EHLO", "MAIL", "RCPT", "DATA", "RSET", "NOOP", "QUIT", "HELP", "VRFY",
+                    "STARTTLS"
+                ]
+            );
+        }
+    }
+}
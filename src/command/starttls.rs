@@ -1,14 +1,20 @@
+#[cfg(any(feature = "native-tls", feature = "mock-support"))]
 use std::io as std_io;
 
-use futures::future::{self, Either, Future};
+use futures::future;
+#[cfg(feature = "native-tls")]
+use futures::future::{Either, Future};
 
+#[cfg(feature = "native-tls")]
 use native_tls::TlsConnector as NativeTlsConnector;
+#[cfg(feature = "native-tls")]
 use tokio_tls::TlsConnector;
 
+#[cfg(feature = "native-tls")]
+use crate::map_tls_err;
 use crate::{
     error::MissingCapabilities,
     io::{Io, Socket},
-    map_tls_err,
     response::{codes, Response},
     Capability, Cmd, DefaultTlsSetup, Domain, EhloData, EsmtpKeyword, ExecFuture, SetupTls,
 };
@@ -53,6 +59,7 @@ fn tls_done_result() -> Response {
     Response::new(codes::STATUS_RESPONSE, vec!["Ready".to_owned()])
 }
 
+#[cfg(any(feature = "native-tls", feature = "mock-support"))]
 fn connection_already_secure_error_future() -> ExecFuture {
     let fut = future::err(std_io::Error::new(
         std_io::ErrorKind::AlreadyExists,
@@ -68,6 +75,10 @@ impl<S> Cmd for StartTls<S>
 where
     S: SetupTls,
 {
+    fn required_capabilities(&self) -> Vec<Capability> {
+        vec![Capability::from(EsmtpKeyword::from_unchecked(STARTTLS))]
+    }
+
     fn check_cmd_availability(&self, caps: Option<&EhloData>) -> Result<(), MissingCapabilities> {
         caps.and_then(|ehlo_data| {
             if ehlo_data.has_capability(STARTTLS) {
@@ -90,6 +101,7 @@ where
 
         let was_mock = match io.socket_mut() {
             Socket::Insecure(_) => false,
+            #[cfg(feature = "native-tls")]
             Socket::Secure(_) => {
                 return connection_already_secure_error_future();
             }
@@ -109,41 +121,61 @@ where
             return Box::new(fut);
         }
 
-        let fut = io
-            .flush_line_from_parts(&["STARTTLS"])
-            .and_then(Io::parse_response)
-            .and_then(move |(io, smtp_result)| match smtp_result {
-                Err(response) => Either::A(future::ok((io, Err(response)))),
-                Ok(_) => {
-                    let connector = alttry!(
-                        {
-                            let contor = setup_tls.setup(NativeTlsConnector::builder())?;
-                            Ok(TlsConnector::from(contor))
-                        } =>
-                        |err| Either::A(future::err(map_tls_err(err)))
-                    );
-
-                    let (socket, _buffer, _ehlo_data) = io.split();
-                    let stream = match socket {
-                        Socket::Insecure(stream) => stream,
-                        _ => unreachable!(),
-                    };
-
-                    let fut = connector
-                        .connect(sni_domain.as_str(), stream)
-                        .map_err(map_tls_err)
-                        .map(move |stream| {
-                            let socket = Socket::Secure(stream);
-                            let io = Io::from(socket);
-                            #[cfg(feature = "log")]
-                            log_facade::trace!("now using TLS");
-                            (io, Ok(tls_done_result()))
-                        });
-
-                    Either::B(fut)
-                }
-            });
-
-        Box::new(fut)
+        start_tls(io, sni_domain, setup_tls)
     }
 }
+
+/// sends `STARTTLS` and does the tls handshake on the (insecure tcp) socket of `io`
+#[cfg(feature = "native-tls")]
+fn start_tls<S>(io: Io, sni_domain: Domain, setup_tls: S) -> ExecFuture
+where
+    S: SetupTls,
+{
+    let fut = io
+        .flush_line_from_parts(&["STARTTLS"])
+        .and_then(Io::parse_response)
+        .and_then(move |(io, smtp_result)| match smtp_result {
+            Err(response) => Either::A(future::ok((io, Err(response)))),
+            Ok(_) => {
+                let connector = alttry!(
+                    {
+                        let contor = setup_tls.setup(NativeTlsConnector::builder())?;
+                        Ok(TlsConnector::from(contor))
+                    } =>
+                    |err| Either::A(future::err(map_tls_err(err)))
+                );
+
+                // settings (e.g. the trace hook) and the setup information
+                // (e.g. the greeting) are kept, the smtp session state is reset
+                let (socket, mut state) = io.into_socket_and_state();
+                state.reset_session();
+                let stream = match socket {
+                    Socket::Insecure(stream) => stream,
+                    _ => unreachable!(),
+                };
+
+                let fut = connector
+                    .connect(sni_domain.as_str(), stream)
+                    .map_err(map_tls_err)
+                    .map(move |stream| {
+                        let io = Io::from_socket_and_state(Socket::Secure(stream), state);
+                        #[cfg(feature = "log")]
+                        log_facade::trace!("{} now using TLS", io.log_tag());
+                        (io, Ok(tls_done_result()))
+                    });
+
+                Either::B(fut)
+            }
+        });
+
+    Box::new(fut)
+}
+
+/// fails without sending anything, as the `native-tls` feature is disabled
+#[cfg(not(feature = "native-tls"))]
+fn start_tls<S>(_io: Io, _sni_domain: Domain, _setup_tls: S) -> ExecFuture
+where
+    S: SetupTls,
+{
+    Box::new(future::err(crate::common::tls_disabled_error()))
+}
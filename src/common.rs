@@ -1,14 +1,16 @@
 use std::collections::HashMap;
-use std::fmt::Debug;
+use std::convert::TryFrom;
+use std::fmt::{self, Debug, Display};
 use std::io as std_io;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 
 use hostname::get_hostname;
+#[cfg(feature = "native-tls")]
 use native_tls::{self, TlsConnector as NativeTlsConnector, TlsConnectorBuilder};
 
 use crate::{
     ascii::IgnoreAsciiCaseStr,
-    data_types::{AddressLiteral, Capability, Domain, EhloParam},
+    data_types::{AddressLiteral, Capability, Domain, EhloParam, SyntaxError},
 };
 
 /// Represents the identity of an client
@@ -66,6 +68,109 @@ impl ClientId {
             ClientId::Domain(domain)
         })
     }
+
+    /// creates a client identity from a domain, validating it's syntax
+    pub fn from_domain_str(domain: &str) -> Result<Self, SyntaxError> {
+        domain.parse().map(ClientId::Domain)
+    }
+
+    /// creates a client identity from a ip address (as address literal)
+    pub fn from_ip(addr: IpAddr) -> Self {
+        ClientId::from(addr)
+    }
+
+    /// creates a client identity using hostname, if it's a fully qualified domain name
+    ///
+    /// If no hostname can be found or it's not a (syntactically valid) fully
+    /// qualified domain name `fallback` is used, e.g. `ClientId::from_ip(..)`
+    /// with the public ip of the client, as strict MX servers might reject
+    /// `EHLO` with a name which isn't a fully qualified domain name.
+    pub fn hostname_or(fallback: ClientId) -> Self {
+        Self::try_hostname()
+            .filter(|id| id.validate().is_ok() && id.as_str().contains('.'))
+            .unwrap_or(fallback)
+    }
+
+    /// checks if the identity is syntactically valid
+    ///
+    /// Identities created with `from_domain_str` or from address literals
+    /// are always valid, but e.g. `ClientId::hostname()` uses the hostname
+    /// of the system without checking it.
+    pub fn validate(&self) -> Result<(), SyntaxError> {
+        match self {
+            ClientId::Domain(domain) => domain.as_str().parse::<Domain>().map(|_| ()),
+            ClientId::AddressLiteral(addr_lit) => {
+                addr_lit.as_str().parse::<AddressLiteral>().map(|_| ())
+            }
+        }
+    }
+
+    /// true if the identity is `localhost` or a loopback address literal
+    ///
+    /// E.g. `ClientId::localhost()`, which is fine for connecting to a MSA
+    /// but nearly always makes a MX reject the mail.
+    pub fn is_loopback(&self) -> bool {
+        match self {
+            ClientId::Domain(domain) => domain.as_str().eq_ignore_ascii_case("localhost"),
+            ClientId::AddressLiteral(addr_lit) => {
+                let inner = addr_lit
+                    .as_str()
+                    .trim_start_matches('[')
+                    .trim_end_matches(']');
+                let inner = if inner.len() > 5 && inner[..5].eq_ignore_ascii_case("IPv6:") {
+                    &inner[5..]
+                } else {
+                    inner
+                };
+                inner
+                    .parse::<IpAddr>()
+                    .map(|addr| addr.is_loopback())
+                    .unwrap_or(false)
+            }
+        }
+    }
+
+    /// returns the identity as it is send with `EHLO`
+    ///
+    /// I.e. domains are returned as-is and address literals
+    /// in brackets, e.g. `"[IPv6:::1]"`.
+    pub fn as_str(&self) -> &str {
+        match self {
+            ClientId::Domain(domain) => domain.as_str(),
+            ClientId::AddressLiteral(addr_lit) => addr_lit.as_str(),
+        }
+    }
+}
+
+/// displays the identity exactly as it's send with `EHLO`
+impl Display for ClientId {
+    fn fmt(&self, fter: &mut fmt::Formatter) -> fmt::Result {
+        fter.write_str(self.as_str())
+    }
+}
+
+/// a likely misconfiguration noticed while setting up a connection
+///
+/// Warnings don't change how the connection behaves, they are logged (with
+/// the `log` feature) and available through `Connection::warnings`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SetupWarning {
+    /// a loopback client id (e.g. `ClientId::localhost()`) is used to connect to port 25
+    ///
+    /// Mail exchangers almost always reject mails from a client using
+    /// such an identity, use e.g. `ClientId::hostname_or` instead.
+    LoopbackClientIdForMx,
+}
+
+impl Display for SetupWarning {
+    fn fmt(&self, fter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SetupWarning::LoopbackClientIdForMx => write!(
+                fter,
+                "connecting to a MX (port 25) with a loopback client id, mails will likely be rejected"
+            ),
+        }
+    }
 }
 
 impl From<Domain> for ClientId {
@@ -131,21 +236,67 @@ impl From<Domain> for TlsConfig {
 }
 
 /// Trait used when setting up tls to modify the setup process
+///
+/// (Without the `native-tls` feature this trait has no methods.) As `setup`
+/// has a default body an impl which doesn't override it compiles independent
+/// of whether or not some other crate enables the `native-tls` feature.
 pub trait SetupTls: Debug + Send + 'static {
     /// Accepts a connection builder and returns a connector if possible
-    fn setup(self, builder: TlsConnectorBuilder) -> Result<NativeTlsConnector, native_tls::Error>;
+    ///
+    /// By default this just calls `builder.build()`.
+    #[cfg(feature = "native-tls")]
+    fn setup(self, builder: TlsConnectorBuilder) -> Result<NativeTlsConnector, native_tls::Error>
+    where
+        Self: Sized,
+    {
+        builder.build()
+    }
 }
 
 /// The default tls setup, which just calls `builder.build()`
 #[derive(Debug, Clone, PartialEq)]
 pub struct DefaultTlsSetup;
 
-impl SetupTls for DefaultTlsSetup {
-    fn setup(self, builder: TlsConnectorBuilder) -> Result<NativeTlsConnector, native_tls::Error> {
+impl SetupTls for DefaultTlsSetup {}
+
+/// A tls setup disabling parts of the server verification
+///
+/// Created by `ConnectionBuilder::danger_accept_invalid_hostnames` and
+/// `ConnectionBuilder::danger_disable_sni`. **This makes the connection
+/// vulnerable to man-in-the-middle attacks**, it's meant for connecting to
+/// e.g. test servers by ip. The certificate is still verified.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DangerousTlsSetup {
+    pub(crate) accept_invalid_hostnames: bool,
+    pub(crate) disable_sni: bool,
+}
+
+impl DangerousTlsSetup {
+    /// true if certificates not matching the hostname are accepted
+    pub fn accepts_invalid_hostnames(&self) -> bool {
+        self.accept_invalid_hostnames
+    }
+
+    /// true if no Server Name Indication (SNI) is send
+    pub fn sni_disabled(&self) -> bool {
+        self.disable_sni
+    }
+}
+
+impl SetupTls for DangerousTlsSetup {
+    #[cfg(feature = "native-tls")]
+    fn setup(
+        self,
+        mut builder: TlsConnectorBuilder,
+    ) -> Result<NativeTlsConnector, native_tls::Error> {
+        builder
+            .danger_accept_invalid_hostnames(self.accept_invalid_hostnames)
+            .use_sni(!self.disable_sni);
         builder.build()
     }
 }
 
+#[cfg(feature = "native-tls")]
 impl<F: 'static> SetupTls for F
 where
     F: Send + Debug + FnOnce(TlsConnectorBuilder) -> Result<NativeTlsConnector, native_tls::Error>,
@@ -156,6 +307,7 @@ where
 }
 
 //FIXME[rust/catch]: use catch once in stable
+#[cfg(feature = "native-tls")]
 macro_rules! alttry {
     ($block:block => $emap:expr) => {{
         let func = move || -> Result<_, _> { $block };
@@ -169,10 +321,20 @@ macro_rules! alttry {
     }};
 }
 
+#[cfg(feature = "native-tls")]
 pub(crate) fn map_tls_err(err: native_tls::Error) -> std_io::Error {
     std_io::Error::new(std_io::ErrorKind::Other, err)
 }
 
+/// the error returned when trying to use tls without the `native-tls` feature
+#[cfg(not(feature = "native-tls"))]
+pub(crate) fn tls_disabled_error() -> std_io::Error {
+    std_io::Error::new(
+        std_io::ErrorKind::Other,
+        "tls support is disabled (feature `native-tls`)",
+    )
+}
+
 /// A type representing the ehlo response of the last ehlo call
 ///
 /// This is mainly used to check if a certain capability/command
@@ -190,6 +352,24 @@ impl EhloData {
         EhloData { domain, data }
     }
 
+    /// create a new Ehlo data from a list of capabilities and their parameters
+    ///
+    /// This is mainly meant for tests and tools replaying sessions, e.g.
+    /// `EhloData::from_caps(domain, &[("SIZE", &["1024"]), ("SMTPUTF8", &[])])`.
+    /// All keywords and parameters are validated.
+    pub fn from_caps(domain: Domain, caps: &[(&str, &[&str])]) -> Result<Self, SyntaxError> {
+        let mut data = HashMap::new();
+        for (cap, params) in caps {
+            let cap = cap.parse::<Capability>()?;
+            let params = params
+                .iter()
+                .map(|param| param.parse::<EhloParam>())
+                .collect::<Result<Vec<_>, _>>()?;
+            data.insert(cap, params);
+        }
+        Ok(EhloData::new(domain, data))
+    }
+
     /// check if a ehlo contained a specific capability e.g. `SMTPUTF8`
     pub fn has_capability<A>(&self, cap: A) -> bool
     where
@@ -199,6 +379,36 @@ impl EhloData {
             .contains_key(<&IgnoreAsciiCaseStr>::from(cap.as_ref()))
     }
 
+    /// true if the server supports `PIPELINING` (RFC 2920)
+    pub fn supports_pipelining(&self) -> bool {
+        self.has_capability("PIPELINING")
+    }
+
+    /// true if the server supports `8BITMIME` (RFC 6152)
+    pub fn supports_8bitmime(&self) -> bool {
+        self.has_capability("8BITMIME")
+    }
+
+    /// true if the server supports `STARTTLS` (RFC 3207)
+    pub fn supports_starttls(&self) -> bool {
+        self.has_capability("STARTTLS")
+    }
+
+    /// true if the server supports `DSN` (RFC 3461)
+    pub fn supports_dsn(&self) -> bool {
+        self.has_capability("DSN")
+    }
+
+    /// true if the server supports `CHUNKING` i.e. `BDAT` (RFC 3030)
+    pub fn supports_chunking(&self) -> bool {
+        self.has_capability("CHUNKING")
+    }
+
+    /// true if the server supports `SMTPUTF8` (RFC 6531)
+    pub fn supports_smtputf8(&self) -> bool {
+        self.has_capability("SMTPUTF8")
+    }
+
     /// get the parameters for a specific capability e.g. the size of `SIZE`
     pub fn get_capability_params<A>(&self, cap: A) -> Option<&[EhloParam]>
     where
@@ -209,6 +419,43 @@ impl EhloData {
             .map(|vec| &**vec)
     }
 
+    /// the maximal message size the server accepts, as advertised with `SIZE` (RFC 1870)
+    ///
+    /// Returns `None` if `SIZE` isn't supported, has no valid parameter
+    /// or the server advertised `SIZE 0` (i.e. there is no fixed limit).
+    pub fn max_size(&self) -> Option<usize> {
+        self.capability_param_u64("SIZE")
+            .and_then(|limit| usize::try_from(limit).ok())
+            .filter(|limit| *limit > 0)
+    }
+
+    /// the first parameter of a capability parsed as number
+    ///
+    /// E.g. the max hold time of `FUTURERELEASE` or the min by time of
+    /// `DELIVERBY`. Returns `None` if the capability isn't supported or its
+    /// first parameter is missing or not a (non-negative) number.
+    pub fn capability_param_u64<A>(&self, cap: A) -> Option<u64>
+    where
+        A: AsRef<str>,
+    {
+        self.get_capability_params(cap)
+            .and_then(|params| params.first())
+            .and_then(|param| param.as_str().parse::<u64>().ok())
+    }
+
+    /// all parameters of a capability as `&str`, e.g. the mechanisms of `AUTH`
+    ///
+    /// Returns an empty `Vec` if the capability isn't supported (or has no
+    /// parameters), use `has_capability` to distinguish between both cases.
+    pub fn capability_param_list<A>(&self, cap: A) -> Vec<&str>
+    where
+        A: AsRef<str>,
+    {
+        self.get_capability_params(cap)
+            .map(|params| params.iter().map(EhloParam::as_str).collect())
+            .unwrap_or_default()
+    }
+
     /// return a reference to the inner hash map
     pub fn capability_map(&self) -> &HashMap<Capability, Vec<EhloParam>> {
         &self.data
@@ -218,6 +465,85 @@ impl EhloData {
     pub fn domain(&self) -> &Domain {
         &self.domain
     }
+
+    /// compare the capabilities of this (older) ehlo data with the ones of `other`
+    ///
+    /// E.g. after `STARTTLS` servers commonly add `AUTH` and remove
+    /// `STARTTLS`. A capability whose parameters differ (e.g. different
+    /// `AUTH` mechanisms) is listed as changed. The domain is not compared.
+    pub fn diff(&self, other: &EhloData) -> CapabilityDiff {
+        let mut added = Vec::new();
+        let mut changed = Vec::new();
+        for (cap, params) in other.data.iter() {
+            match self.data.get(cap) {
+                None => added.push(cap.clone()),
+                Some(old_params) if old_params != params => changed.push(cap.clone()),
+                Some(_) => (),
+            }
+        }
+
+        let mut removed = self
+            .data
+            .keys()
+            .filter(|cap| !other.data.contains_key(*cap))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        for caps in [&mut added, &mut removed, &mut changed] {
+            caps.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        }
+
+        CapabilityDiff {
+            added,
+            removed,
+            changed,
+        }
+    }
+
+    /// overlays the (newer) ehlo data `newer` onto this ehlo data
+    ///
+    /// The domain and all capabilities of `newer` replace the ones of
+    /// `self`, capabilities only in `self` are kept. Use the newer data
+    /// directly if capabilities the server no longer advertises (e.g.
+    /// `STARTTLS` after starting TLS) should be dropped.
+    pub fn merge(&mut self, newer: &EhloData) {
+        self.domain = newer.domain.clone();
+        for (cap, params) in newer.data.iter() {
+            self.data.insert(cap.clone(), params.clone());
+        }
+    }
+}
+
+/// the difference between the capabilities of two `EhloData` instances, see `EhloData::diff`
+///
+/// All lists are sorted by the capability name.
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+pub struct CapabilityDiff {
+    added: Vec<Capability>,
+    removed: Vec<Capability>,
+    changed: Vec<Capability>,
+}
+
+impl CapabilityDiff {
+    /// capabilities only advertised by the newer ehlo data
+    pub fn added(&self) -> &[Capability] {
+        &self.added
+    }
+
+    /// capabilities no longer advertised by the newer ehlo data
+    pub fn removed(&self) -> &[Capability] {
+        &self.removed
+    }
+
+    /// capabilities advertised by both but with different parameters
+    pub fn changed(&self) -> &[Capability] {
+        &self.changed
+    }
+
+    /// true if the capabilities didn't change at all
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
 }
 
 impl From<(Domain, HashMap<Capability, Vec<EhloParam>>)> for EhloData {
@@ -226,9 +552,238 @@ impl From<(Domain, HashMap<Capability, Vec<EhloParam>>)> for EhloData {
     }
 }
 
-impl Into<(Domain, HashMap<Capability, Vec<EhloParam>>)> for EhloData {
-    fn into(self) -> (Domain, HashMap<Capability, Vec<EhloParam>>) {
-        let EhloData { domain, data } = self;
+impl From<EhloData> for (Domain, HashMap<Capability, Vec<EhloParam>>) {
+    fn from(ehlo: EhloData) -> Self {
+        let EhloData { domain, data } = ehlo;
         (domain, data)
     }
 }
+
+#[cfg(test)]
+mod test {
+
+    mod EhloData {
+        #![allow(non_snake_case)]
+        use super::super::EhloData;
+        use crate::data_types::{Capability, Domain, SyntaxError};
+
+        fn pre_and_post_starttls() -> (EhloData, EhloData) {
+            let pre = EhloData::from_caps(
+                Domain::from_unchecked("they.test"),
+                &[
+                    ("SIZE", &["1024"]),
+                    ("STARTTLS", &[]),
+                    ("PIPELINING", &[]),
+                    ("AUTH", &["LOGIN"]),
+                ],
+            )
+            .unwrap();
+            let post = EhloData::from_caps(
+                Domain::from_unchecked("they.test"),
+                &[
+                    ("SIZE", &["1024"]),
+                    ("PIPELINING", &[]),
+                    ("AUTH", &["PLAIN", "LOGIN"]),
+                    ("SMTPUTF8", &[]),
+                ],
+            )
+            .unwrap();
+            (pre, post)
+        }
+
+        fn names(caps: &[Capability]) -> Vec<&str> {
+            caps.iter().map(|cap| cap.as_str()).collect()
+        }
+
+        #[test]
+        fn diff_after_starttls() {
+            let (pre, post) = pre_and_post_starttls();
+            let diff = pre.diff(&post);
+            assert_eq!(names(diff.added()), vec!["SMTPUTF8"]);
+            assert_eq!(names(diff.removed()), vec!["STARTTLS"]);
+            assert_eq!(names(diff.changed()), vec!["AUTH"]);
+            assert!(!diff.is_empty());
+
+            assert!(post.diff(&post.clone()).is_empty());
+        }
+
+        #[test]
+        fn merge_overlays_newer_data() {
+            let (mut merged, post) = pre_and_post_starttls();
+            merged.merge(&post);
+            assert_eq!(merged.capability_param_list("AUTH"), vec!["PLAIN", "LOGIN"]);
+            assert!(merged.supports_smtputf8());
+            assert!(merged.supports_starttls());
+            assert!(merged.diff(&post).added().is_empty());
+            assert_eq!(names(merged.diff(&post).removed()), vec!["STARTTLS"]);
+        }
+
+        #[test]
+        fn from_caps() {
+            let ehlo = EhloData::from_caps(
+                Domain::from_unchecked("they.test"),
+                &[
+                    ("SIZE", &["1024"]),
+                    ("smtputf8", &[]),
+                    ("AUTH", &["PLAIN", "LOGIN"]),
+                ],
+            )
+            .unwrap();
+
+            assert_eq!(ehlo.domain().as_str(), "they.test");
+            assert!(ehlo.supports_smtputf8());
+            assert_eq!(ehlo.get_capability_params("SIZE").unwrap()[0], "1024");
+            assert_eq!(ehlo.get_capability_params("AUTH").unwrap().len(), 2);
+            assert!(!ehlo.has_capability("DSN"));
+            assert_eq!(ehlo.max_size(), Some(1024));
+        }
+
+        #[test]
+        fn max_size_zero_means_no_limit() {
+            let domain = Domain::from_unchecked("they.test");
+            let ehlo = EhloData::from_caps(domain.clone(), &[("SIZE", &["0"])]).unwrap();
+            assert_eq!(ehlo.max_size(), None);
+            let ehlo = EhloData::from_caps(domain, &[("SIZE", &[])]).unwrap();
+            assert_eq!(ehlo.max_size(), None);
+        }
+
+        #[test]
+        fn capability_param_u64() {
+            let ehlo = EhloData::from_caps(
+                Domain::from_unchecked("they.test"),
+                &[
+                    ("FUTURERELEASE", &["604800", "2012-10-01T12:00:00Z"]),
+                    ("DELIVERBY", &["120"]),
+                    ("SIZE", &["huge"]),
+                    ("ATRN", &[]),
+                ],
+            )
+            .unwrap();
+            assert_eq!(ehlo.capability_param_u64("FUTURERELEASE"), Some(604800));
+            assert_eq!(ehlo.capability_param_u64("deliverby"), Some(120));
+            assert_eq!(ehlo.capability_param_u64("SIZE"), None);
+            assert_eq!(ehlo.capability_param_u64("ATRN"), None);
+            assert_eq!(ehlo.capability_param_u64("BURL"), None);
+        }
+
+        #[test]
+        fn capability_param_list() {
+            let ehlo = EhloData::from_caps(
+                Domain::from_unchecked("they.test"),
+                &[
+                    ("AUTH", &["PLAIN", "LOGIN"]),
+                    ("BURL", &["imap"]),
+                    ("ATRN", &[]),
+                ],
+            )
+            .unwrap();
+            assert_eq!(ehlo.capability_param_list("AUTH"), vec!["PLAIN", "LOGIN"]);
+            assert_eq!(ehlo.capability_param_list("burl"), vec!["imap"]);
+            assert!(ehlo.capability_param_list("ATRN").is_empty());
+            assert!(ehlo.capability_param_list("XFOO").is_empty());
+        }
+
+        #[test]
+        fn from_caps_validates_keywords() {
+            let res = EhloData::from_caps(Domain::from_unchecked("they.test"), &[("SI ZE", &[])]);
+            match res {
+                Err(SyntaxError::EsmtpKeyword(_)) => {}
+                other => panic!("unexpected result: {:?}", other),
+            }
+        }
+    }
+
+    mod ClientId {
+        #![allow(non_snake_case)]
+        use super::super::ClientId;
+        use crate::data_types::Domain;
+        use std::net::{Ipv4Addr, Ipv6Addr};
+
+        #[test]
+        fn displays_domain_as_is() {
+            let id = ClientId::from_domain_str("mail.example.test").unwrap();
+            assert_eq!(id.to_string(), "mail.example.test");
+        }
+
+        #[test]
+        fn displays_ipv6_literal_in_brackets() {
+            let id = ClientId::from(Ipv6Addr::LOCALHOST);
+            assert_eq!(id.to_string(), "[IPv6:::1]");
+        }
+
+        #[test]
+        fn from_domain_str_validates() {
+            assert!(ClientId::from_domain_str("not a domain").is_err());
+        }
+
+        #[test]
+        fn validates_domain_identity() {
+            assert!(ClientId::from_domain_str("mail.example.test")
+                .unwrap()
+                .validate()
+                .is_ok());
+            let bad = ClientId::Domain(Domain::new_unchecked("not a domain".to_owned()));
+            assert!(bad.validate().is_err());
+        }
+
+        #[test]
+        fn validates_ip_literal_identity() {
+            let id = ClientId::from_ip(Ipv4Addr::new(203, 0, 113, 7).into());
+            assert_eq!(id.as_str(), "[203.0.113.7]");
+            assert!(id.validate().is_ok());
+        }
+
+        #[test]
+        fn hostname_identity_is_fqdn_or_fallback() {
+            let fallback = ClientId::from_ip(Ipv4Addr::new(203, 0, 113, 7).into());
+            let id = ClientId::hostname_or(fallback);
+            match id {
+                ClientId::Domain(domain) => assert!(domain.as_str().contains('.')),
+                ClientId::AddressLiteral(addr_lit) => {
+                    assert_eq!(addr_lit.as_str(), "[203.0.113.7]")
+                }
+            }
+        }
+
+        #[test]
+        fn detects_loopback_identities() {
+            assert!(ClientId::localhost().is_loopback());
+            assert!(ClientId::from(Ipv6Addr::LOCALHOST).is_loopback());
+            assert!(ClientId::from_domain_str("LocalHost")
+                .unwrap()
+                .is_loopback());
+            assert!(!ClientId::from_ip(Ipv4Addr::new(203, 0, 113, 7).into()).is_loopback());
+            assert!(!ClientId::from_domain_str("mail.example.test")
+                .unwrap()
+                .is_loopback());
+        }
+    }
+
+    mod SetupTls {
+        #![allow(non_snake_case)]
+        use super::super::{SetupTls, TlsConfig};
+        use crate::data_types::Domain;
+
+        /// compiles both with and without the `native-tls` feature
+        #[derive(Debug)]
+        struct KeepDefaults;
+
+        impl SetupTls for KeepDefaults {}
+
+        #[test]
+        fn setup_without_override_can_be_used_in_tls_config() {
+            let config = TlsConfig {
+                domain: Domain::from_unchecked("they.test"),
+                setup: KeepDefaults,
+            };
+            assert_eq!(config.domain.as_str(), "they.test");
+        }
+
+        #[cfg(feature = "native-tls")]
+        #[test]
+        fn setup_defaults_to_building_the_connector() {
+            let builder = native_tls::TlsConnector::builder();
+            assert!(KeepDefaults.setup(builder).is_ok());
+        }
+    }
+}
@@ -1,17 +1,21 @@
 use std::fmt::Debug;
 use std::io as std_io;
 use std::net::{Ipv4Addr, SocketAddr, ToSocketAddrs};
+use std::time::{Duration, Instant};
 
-use futures::future::{self, Either, Future};
+use futures::future::{self, Either, Future, Loop};
+use tokio::timer::{Delay, Timeout};
 
 use crate::{
-    command::Noop,
-    common::{ClientId, DefaultTlsSetup, SetupTls, TlsConfig},
+    command::{Noop, RequireSecure},
+    common::{
+        ClientId, DangerousTlsSetup, DefaultTlsSetup, EhloData, SetupTls, SetupWarning, TlsConfig,
+    },
     connection::{Cmd, Connection},
-    data_types::Domain,
+    data_types::{Domain, SyntaxError},
     error::{ConnectingFailed, LogicError},
     future_ext::ResultWithContextExt,
-    io::{Io, SmtpResult},
+    io::{Io, SmtpResult, TcpOptions},
 };
 
 /// A future resolving to an `Connection` instance
@@ -20,13 +24,14 @@ pub type ConnectingFuture =
 
 pub const DEFAULT_SMTP_MSA_PORT: u16 = 587;
 pub const DEFAULT_SMTP_MX_PORT: u16 = 25;
+pub const DEFAULT_SMTP_SUBMISSIONS_PORT: u16 = 465;
 
-fn cmd_future2connecting_future<LE: 'static, E>(
+fn cmd_future2connecting_future<LE, E>(
     res: Result<(Connection, SmtpResult), E>,
     new_logic_err: LE,
 ) -> impl Future<Item = Connection, Error = ConnectingFailed> + Send
 where
-    LE: Send + FnOnce(LogicError) -> ConnectingFailed,
+    LE: Send + 'static + FnOnce(LogicError) -> ConnectingFailed,
     E: Into<ConnectingFailed>,
 {
     let fut = match res {
@@ -38,6 +43,144 @@ where
     fut
 }
 
+/// the settings of a `ConnectionConfig` used while setting up the connection
+#[derive(Debug, Clone, Default)]
+struct SetupSettings {
+    syntax_error_handling: SyntaxErrorHandling,
+    greeting_timeout: Option<Duration>,
+    read_idle_timeout: Option<Duration>,
+    tcp_options: TcpOptions,
+    lmtp: bool,
+}
+
+impl SetupSettings {
+    /// the settings of `config`
+    fn of<A, S>(config: &ConnectionConfig<A, S>) -> Self
+    where
+        S: SetupTls,
+        A: Cmd,
+    {
+        SetupSettings {
+            syntax_error_handling: config.syntax_error_handling.clone(),
+            greeting_timeout: config.greeting_timeout,
+            read_idle_timeout: config.read_idle_timeout,
+            tcp_options: config.tcp_options,
+            lmtp: config.lmtp,
+        }
+    }
+
+    /// the default settings but with given syntax error handling
+    fn with_syntax_error_handling(syntax_error_handling: SyntaxErrorHandling) -> Self {
+        SetupSettings {
+            syntax_error_handling,
+            ..Default::default()
+        }
+    }
+
+    /// stores the settings which are kept for the whole connection in the `Io`
+    fn apply_to(&self, io: &mut Io) {
+        io.set_syntax_error_handling(self.syntax_error_handling.clone());
+        io.set_read_idle_timeout(self.read_idle_timeout);
+        io.set_lmtp(self.lmtp);
+    }
+}
+
+/// sends `EHLO`, or `LHLO` if the connection is in LMTP mode, see `Connection::rehlo`
+fn send_hello(
+    con: Connection,
+    clid: ClientId,
+) -> impl Future<Item = (Connection, SmtpResult), Error = std_io::Error> + Send {
+    con.with_identity(clid).rehlo()
+}
+
+/// sends `STARTTLS` and then `EHLO` as the capabilities change with TLS
+fn upgrade_to_tls<S>(
+    con: Connection,
+    clid: ClientId,
+    config: TlsConfig<S>,
+) -> impl Future<Item = Connection, Error = ConnectingFailed> + Send
+where
+    S: SetupTls,
+{
+    //Note: this has a circular dependency between Connection <-> cmd StartTls which
+    // could be resolved using a ext. trait, but it's more ergonomic this way
+    use crate::command::StartTls;
+    let TlsConfig { domain, setup } = config;
+
+    let fut = con
+        .send(StartTls {
+            setup_tls: setup,
+            sni_domain: domain,
+        })
+        .map_err(ConnectingFailed::Io)
+        .ctx_and_then(move |con, _| send_hello(con, clid).map_err(ConnectingFailed::Io))
+        .then(|res| cmd_future2connecting_future(res, ConnectingFailed::Setup));
+
+    fut
+}
+
+/// records (and logs) likely misconfigurations, see `SetupWarning`
+fn record_setup_warnings(con: Connection, addr: &SocketAddr, client_id: &ClientId) -> Connection {
+    let mut io = con.into_inner();
+    if addr.port() == DEFAULT_SMTP_MX_PORT && client_id.is_loopback() {
+        let warning = SetupWarning::LoopbackClientIdForMx;
+        #[cfg(feature = "log")]
+        log_facade::warn!("{} {}", io.log_tag(), warning);
+        io.add_setup_warning(warning);
+    }
+    Connection::from(io)
+}
+
+/// reads the greeting, stashing it in the connection if it's a valid greeting
+///
+/// Only `220` is a valid greeting, other positive responses are turned
+/// into a `LogicError::UnexpectedCode`. On a `421` the server is closing
+/// the connection so it's poisoned and no `QUIT` will be send.
+fn read_greeting(
+    io: Io,
+    greeting_timeout: Option<Duration>,
+) -> impl Future<Item = (Connection, SmtpResult), Error = std_io::Error> + Send {
+    let parsing = io.parse_response();
+    let parsing = match greeting_timeout {
+        None => Either::A(parsing),
+        Some(timeout) => Either::B(Timeout::new(parsing, timeout).map_err(|err| {
+            if err.is_elapsed() {
+                std_io::Error::new(
+                    std_io::ErrorKind::TimedOut,
+                    "server did not send a greeting in time",
+                )
+            } else if err.is_inner() {
+                err.into_inner().expect("checked above")
+            } else {
+                std_io::Error::new(
+                    std_io::ErrorKind::Other,
+                    err.into_timer().expect("neither inner nor elapsed"),
+                )
+            }
+        })),
+    };
+
+    let fut = parsing.map(|(mut io, result)| {
+        let result = match result {
+            Ok(response) if response.is_service_ready() => {
+                io.set_greeting(response.clone());
+                Ok(response)
+            }
+            Ok(response) => Err(LogicError::UnexpectedCode(response)),
+            Err(LogicError::Code(response)) => {
+                if response.is_shutdown() {
+                    io.poison();
+                }
+                Err(LogicError::Code(response))
+            }
+            Err(err) => Err(err),
+        };
+        (Connection::from(io), result)
+    });
+
+    fut
+}
+
 impl Connection {
     /// open a connection to an smtp server using given configuration
     pub fn connect<S, A>(
@@ -47,41 +190,95 @@ impl Connection {
         S: SetupTls,
         A: Cmd + Send,
     {
+        Connection::connect_using(config, None)
+    }
+
+    /// open a connection reusing the ehlo data of a previous connection
+    ///
+    /// This works like `Connection::connect` but instead of sending `EHLO`
+    /// after the greeting the given (cached) ehlo data is used, e.g. the
+    /// data returned by `Connection::capabilities` of a previous connection
+    /// to the same server. As the capabilities legitimately change when
+    /// starting TLS `EHLO` is still send (twice) when using `STARTTLS`, i.e.
+    /// the cached data is only used with `Security::DirectTls`/`None`
+    /// (`Security::Opportunistic` needs `EHLO` to know if `STARTTLS` is
+    /// supported, so it doesn't use the cached data either).
+    ///
+    /// **The cached data can be stale**, e.g. if the server was reconfigured
+    /// or the address now points to a different server. As commands check
+    /// the ehlo data for availability a stale capability can lead to a
+    /// command being send to a server which doesn't support it, so the cache
+    /// should be dropped on errors and refreshed from time to time.
+    pub fn connect_with_cached_ehlo<S, A>(
+        config: ConnectionConfig<A, S>,
+        ehlo_data: EhloData,
+    ) -> impl Future<Item = Connection, Error = ConnectingFailed> + Send
+    where
+        S: SetupTls,
+        A: Cmd + Send,
+    {
+        Connection::connect_using(config, Some(ehlo_data))
+    }
+
+    /// connects, then either sends `EHLO` or uses the cached ehlo data
+    ///
+    /// The cached data is only used for `Security::DirectTls`/`None`, see
+    /// `Connection::connect_with_cached_ehlo`.
+    fn connect_using<S, A>(
+        config: ConnectionConfig<A, S>,
+        cached_ehlo: Option<EhloData>,
+    ) -> impl Future<Item = Connection, Error = ConnectingFailed> + Send
+    where
+        S: SetupTls,
+        A: Cmd + Send,
+    {
+        let settings = SetupSettings::of(&config);
         let ConnectionConfig {
             addr,
             security,
             client_id,
             auth_cmd,
-            syntax_error_handling,
+            ..
         } = config;
 
+        let hello_client_id = client_id.clone();
+        let after_connect = move |con: Connection| match cached_ehlo {
+            Some(ehlo_data) => {
+                let mut io = con.into_inner();
+                io.set_ehlo_data(ehlo_data);
+                io.set_client_id(hello_client_id);
+                Either::A(future::ok(Connection::from(io)))
+            }
+            None => Either::B(
+                send_hello(con, hello_client_id)
+                    .then(|res| cmd_future2connecting_future(res, ConnectingFailed::Setup)),
+            ),
+        };
+
+        let warn_client_id = client_id.clone();
         #[allow(deprecated)]
         let con_fut = match security {
-            Security::None => Either::B(Either::A(Connection::_connect_insecure(
-                &addr,
-                client_id,
-                syntax_error_handling,
+            Security::None => Either::B(Either::A(
+                Connection::connect_insecure_no_ehlo(&addr, settings).and_then(after_connect),
+            )),
+            Security::DirectTls(tls_config) => Either::B(Either::B(
+                Connection::connect_direct_tls_no_ehlo(&addr, tls_config, settings)
+                    .and_then(after_connect),
+            )),
+            Security::StartTls(tls_config) => Either::A(Either::A(Connection::connect_starttls(
+                &addr, client_id, tls_config, settings,
             ))),
-            Security::DirectTls(tls_config) => {
-                Either::B(Either::B(Connection::_connect_direct_tls(
-                    &addr,
-                    client_id,
-                    tls_config,
-                    syntax_error_handling,
-                )))
-            }
-            Security::StartTls(tls_config) => Either::A(Connection::_connect_starttls(
-                &addr,
-                client_id,
-                tls_config,
-                syntax_error_handling,
+            Security::Opportunistic(tls_config) => Either::A(Either::B(
+                Connection::connect_opportunistic(&addr, client_id, tls_config, settings),
             )),
         };
 
-        let fut = con_fut.and_then(|con| {
-            con.send(auth_cmd)
-                .then(|res| cmd_future2connecting_future(res, ConnectingFailed::Auth))
-        });
+        let fut = con_fut
+            .map(move |con| record_setup_warnings(con, &addr, &warn_client_id))
+            .and_then(|con| {
+                con.send(auth_cmd)
+                    .then(|res| cmd_future2connecting_future(res, ConnectingFailed::Auth))
+            });
 
         fut
     }
@@ -89,28 +286,34 @@ impl Connection {
     #[doc(hidden)]
     pub fn _connect_insecure_no_ehlo(
         addr: &SocketAddr,
+    ) -> impl Future<Item = Connection, Error = ConnectingFailed> + Send {
+        Connection::connect_insecure_no_ehlo(addr, SetupSettings::default())
+    }
+
+    fn connect_insecure_no_ehlo(
+        addr: &SocketAddr,
+        settings: SetupSettings,
     ) -> impl Future<Item = Connection, Error = ConnectingFailed> + Send {
         //FIXME accept SocketAddr instead, but this would brake the API, make more of the API internal!
         #[cfg(feature = "log")]
-        let _addr = addr.clone();
+        let _addr = *addr;
 
-        let fut = Io::connect_insecure(addr)
+        let fut = Io::connect_insecure_with(addr, settings.tcp_options)
             .then(move |res| {
                 #[cfg(feature = "log")]
                 {
-                    if let Err(err) = &res {
-                        log_facade::trace!("Connecting to {} failed: {}", _addr, err)
-                    } else {
-                        log_facade::trace!("Connected to {}", _addr)
+                    match &res {
+                        Err(err) => log_facade::trace!("Connecting to {} failed: {}", _addr, err),
+                        Ok(io) => log_facade::trace!("{} Connected to {}", io.log_tag(), _addr),
                     }
                 }
-                res
+                res.map(|mut io| {
+                    settings.apply_to(&mut io);
+                    (io, settings.greeting_timeout)
+                })
             })
-            .and_then(Io::parse_response)
-            .then(|res| {
-                let res = res.map(|(io, res)| (Connection::from(io), res));
-                cmd_future2connecting_future(res, ConnectingFailed::Setup)
-            });
+            .and_then(|(io, greeting_timeout)| read_greeting(io, greeting_timeout))
+            .then(|res| cmd_future2connecting_future(res, ConnectingFailed::Setup));
 
         fut
     }
@@ -120,30 +323,40 @@ impl Connection {
         addr: &SocketAddr,
         config: TlsConfig<S>,
     ) -> impl Future<Item = Connection, Error = ConnectingFailed> + Send
+    where
+        S: SetupTls,
+    {
+        Connection::connect_direct_tls_no_ehlo(addr, config, SetupSettings::default())
+    }
+
+    fn connect_direct_tls_no_ehlo<S>(
+        addr: &SocketAddr,
+        config: TlsConfig<S>,
+        settings: SetupSettings,
+    ) -> impl Future<Item = Connection, Error = ConnectingFailed> + Send
     where
         S: SetupTls,
     {
         //FIXME accept SocketAddr instead, but this would brake the API, make more of the API internal!
         #[cfg(feature = "log")]
-        let _addr = addr.clone();
+        let _addr = *addr;
 
-        let fut = Io::connect_secure(addr, config)
+        let fut = Io::connect_secure_with(addr, config, settings.tcp_options)
             .then(move |res| {
                 #[cfg(feature = "log")]
                 {
-                    if let Err(err) = &res {
-                        log_facade::trace!("Connecting to {} failed: {}", _addr, err)
-                    } else {
-                        log_facade::trace!("Connected to {}", _addr)
+                    match &res {
+                        Err(err) => log_facade::trace!("Connecting to {} failed: {}", _addr, err),
+                        Ok(io) => log_facade::trace!("{} Connected to {}", io.log_tag(), _addr),
                     }
                 }
-                res
+                res.map(|mut io| {
+                    settings.apply_to(&mut io);
+                    (io, settings.greeting_timeout)
+                })
             })
-            .and_then(Io::parse_response)
-            .then(|res| {
-                let res = res.map(|(io, res)| (Connection::from(io), res));
-                cmd_future2connecting_future(res, ConnectingFailed::Setup)
-            });
+            .and_then(|(io, greeting_timeout)| read_greeting(io, greeting_timeout))
+            .then(|res| cmd_future2connecting_future(res, ConnectingFailed::Setup));
 
         fut
     }
@@ -154,11 +367,17 @@ impl Connection {
         clid: ClientId,
         syntax_error_handling: SyntaxErrorHandling,
     ) -> impl Future<Item = Connection, Error = ConnectingFailed> + Send {
-        //Note: this has a circular dependency between Connection <-> cmd Ehlo which
-        // could be resolved using a ext. trait, but it's more ergonomic this way
-        use crate::command::Ehlo;
-        let fut = Connection::_connect_insecure_no_ehlo(addr).and_then(move |con| {
-            con.send(Ehlo::from(clid).with_syntax_error_handling(syntax_error_handling))
+        let settings = SetupSettings::with_syntax_error_handling(syntax_error_handling);
+        Connection::connect_insecure(addr, clid, settings)
+    }
+
+    fn connect_insecure(
+        addr: &SocketAddr,
+        clid: ClientId,
+        settings: SetupSettings,
+    ) -> impl Future<Item = Connection, Error = ConnectingFailed> + Send {
+        let fut = Connection::connect_insecure_no_ehlo(addr, settings).and_then(move |con| {
+            send_hello(con, clid)
                 .then(|res| cmd_future2connecting_future(res, ConnectingFailed::Setup))
         });
 
@@ -175,13 +394,24 @@ impl Connection {
     where
         S: SetupTls,
     {
-        //Note: this has a circular dependency between Connection <-> cmd Ehlo which
-        // could be resolved using a ext. trait, but it's more ergonomic this way
-        use crate::command::Ehlo;
-        let fut = Connection::_connect_direct_tls_no_ehlo(addr, config).and_then(|con| {
-            con.send(Ehlo::from(clid).with_syntax_error_handling(syntax_error_handling))
-                .then(|res| cmd_future2connecting_future(res, ConnectingFailed::Setup))
-        });
+        let settings = SetupSettings::with_syntax_error_handling(syntax_error_handling);
+        Connection::connect_direct_tls(addr, clid, config, settings)
+    }
+
+    fn connect_direct_tls<S>(
+        addr: &SocketAddr,
+        clid: ClientId,
+        config: TlsConfig<S>,
+        settings: SetupSettings,
+    ) -> impl Future<Item = Connection, Error = ConnectingFailed> + Send
+    where
+        S: SetupTls,
+    {
+        let fut =
+            Connection::connect_direct_tls_no_ehlo(addr, config, settings).and_then(move |con| {
+                send_hello(con, clid)
+                    .then(|res| cmd_future2connecting_future(res, ConnectingFailed::Setup))
+            });
 
         fut
     }
@@ -196,24 +426,71 @@ impl Connection {
     where
         S: SetupTls,
     {
-        //Note: this has a circular dependency between Connection <-> cmd StartTls/Ehlo which
-        // could be resolved using a ext. trait, but it's more ergonomic this way
-        use crate::command::{Ehlo, StartTls};
-        let TlsConfig { domain, setup } = config;
+        let settings = SetupSettings::with_syntax_error_handling(syntax_error_handling);
+        Connection::connect_starttls(addr, clid, config, settings)
+    }
 
-        let fut = Connection::_connect_insecure(&addr, clid.clone(), syntax_error_handling.clone())
-            .and_then(|con| {
-                con.send(StartTls {
-                    setup_tls: setup,
-                    sni_domain: domain,
-                })
-                .map_err(ConnectingFailed::Io)
-            })
-            .ctx_and_then(move |con, _| {
-                con.send(Ehlo::from(clid).with_syntax_error_handling(syntax_error_handling))
-                    .map_err(ConnectingFailed::Io)
-            })
-            .then(|res| cmd_future2connecting_future(res, ConnectingFailed::Setup));
+    fn connect_starttls<S>(
+        addr: &SocketAddr,
+        clid: ClientId,
+        config: TlsConfig<S>,
+        settings: SetupSettings,
+    ) -> impl Future<Item = Connection, Error = ConnectingFailed> + Send
+    where
+        S: SetupTls,
+    {
+        let fut = Connection::connect_insecure(addr, clid.clone(), settings)
+            .and_then(move |con| upgrade_to_tls(con, clid, config));
+
+        fut
+    }
+
+    fn connect_opportunistic<S>(
+        addr: &SocketAddr,
+        clid: ClientId,
+        config: TlsConfig<S>,
+        settings: SetupSettings,
+    ) -> impl Future<Item = Connection, Error = ConnectingFailed> + Send
+    where
+        S: SetupTls,
+    {
+        let fut = Connection::connect_insecure(addr, clid.clone(), settings).and_then(move |con| {
+            if con.has_capability("STARTTLS") {
+                Either::A(upgrade_to_tls(con, clid, config))
+            } else {
+                #[cfg(feature = "log")]
+                log_facade::debug!(
+                    "{} STARTTLS not supported, continuing unencrypted",
+                    con.log_tag()
+                );
+                Either::B(future::ok(con))
+            }
+        });
+
+        fut
+    }
+
+    /// setup a smtp connection on top of an already established stream
+    ///
+    /// This reads the server greeting and then sends `EHLO`, i.e. it does
+    /// the same as `Connection::connect` except for opening the socket,
+    /// starting TLS and authenticating. The stream can be anything which
+    /// can be turned into an `Io`, e.g. a `TcpStream` or a
+    /// `TlsStream<TcpStream>` (or a `MockSocket`). Whether or not the
+    /// connection is encrypted is derived from the stream type.
+    pub fn from_stream<T>(
+        stream: T,
+        clid: ClientId,
+    ) -> impl Future<Item = Connection, Error = ConnectingFailed> + Send
+    where
+        T: Into<Io>,
+    {
+        let fut = read_greeting(stream.into(), None)
+            .then(|res| cmd_future2connecting_future(res, ConnectingFailed::Setup))
+            .and_then(move |con| {
+                send_hello(con, clid)
+                    .then(|res| cmd_future2connecting_future(res, ConnectingFailed::Setup))
+            });
 
         fut
     }
@@ -235,6 +512,13 @@ where
     DirectTls(TlsConfig<S>),
     /// connect with just TCP and then start TLS with the STARTTLS command
     StartTls(TlsConfig<S>),
+    /// like `StartTls` but continue unencrypted if `STARTTLS` isn't supported
+    ///
+    /// This is "opportunistic TLS" as commonly used for delivering mail to
+    /// an MX. It doesn't protect against an active attacker (which can just
+    /// remove `STARTTLS` from the `EHLO` response), so it should not be used
+    /// when submitting mail to an MSA, especially not with authentication.
+    Opportunistic(TlsConfig<S>),
 }
 
 /// Configuration specifing how to setup an SMTP connection.
@@ -251,14 +535,15 @@ where
 /// use new_tokio_smtp::command::auth::Login;
 ///
 /// // For connecting with auth Login using the defaults, i.e.:
-/// // STARTTLS, port 587 and the ip gotten from resolving
-/// // the passed in domain/host name as well as the hostname
-/// // as client identity.
+/// // STARTTLS and the hostname as client identity. The domain
+/// // is used to verify the servers certificate, the address
+/// // can also be resolved with `ConnectionBuilder::new_with_resolver`.
 /// let host = "smtp.gmail.com".parse()
 ///     .expect("malformed domain/host name");
+/// let addr = "203.0.113.7:587".parse()
+///     .expect("malformed socket address");
 /// let config = ConnectionBuilder
-///     ::new(host)
-///     .expect("could not resolve host name")
+///     ::new_with_addr(addr, host)
 ///     .auth(Login::new("user", "password"))
 ///     .build();
 /// ```
@@ -283,6 +568,24 @@ where
 
     /// How strict error handling is done.
     pub syntax_error_handling: SyntaxErrorHandling,
+
+    /// How long to wait for the server greeting, `None` waits forever.
+    ///
+    /// (A timeout requires a tokio runtime with a timer.)
+    pub greeting_timeout: Option<Duration>,
+
+    /// How long a started response may stall, `None` waits forever.
+    ///
+    /// See `Io::set_read_idle_timeout`, this is also used for the greeting.
+    pub read_idle_timeout: Option<Duration>,
+
+    /// Options applied to the tcp stream once connected (default: OS defaults).
+    pub tcp_options: TcpOptions,
+
+    /// If true `LHLO` is send instead of `EHLO`, i.e. the server speaks LMTP.
+    ///
+    /// See `command::Lhlo` and `Connection::send_lmtp_data`.
+    pub lmtp: bool,
 }
 
 /// Which method should be used to handle syntax errors.
@@ -294,25 +597,22 @@ where
 //  to have some connection config _contained_ in IO from which this
 //  options are grabbed. But this is best done with other refactors,
 //  which make sense to be done best when porting to async/await.
-#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Default)]
 pub enum SyntaxErrorHandling {
     /// More strict handling.
     ///
-    /// (currently only affects the ehlo command during connection setup)
+    /// (currently affects the ehlo command during connection setup and
+    /// multi line responses with mismatching response codes)
     Strict,
 
     /// Less strict handling.
     ///
-    /// (currently only affects the ehlo command during connection setup)
+    /// (currently affects the ehlo command during connection setup and
+    /// multi line responses with mismatching response codes)
+    #[default]
     Lax,
 }
 
-impl Default for SyntaxErrorHandling {
-    fn default() -> Self {
-        SyntaxErrorHandling::Lax
-    }
-}
-
 impl<A> ConnectionConfig<A, DefaultTlsSetup>
 where
     A: Cmd,
@@ -323,6 +623,56 @@ where
     }
 }
 
+impl<A, S> ConnectionConfig<A, S>
+where
+    S: SetupTls + Clone,
+    A: Cmd + Clone,
+{
+    /// Connects using this config, retrying with exponential backoff on I/O-Errors.
+    ///
+    /// At most `max_attempts` connection attempts are made (at last one), the
+    /// delay between the attempts starts at `base_delay` and is doubled after
+    /// each attempt. Only `ConnectingFailed::Io` errors and transient setup
+    /// failures (e.g. a `421` greeting) are retried, other setup and all auth
+    /// failures are returned directly, as retrying them is unlikely to help.
+    ///
+    /// The delay uses a tokio timer, so this needs to run in a tokio runtime.
+    pub fn connect_with_retry(
+        self,
+        max_attempts: u32,
+        base_delay: Duration,
+    ) -> impl Future<Item = Connection, Error = ConnectingFailed> + Send {
+        future::loop_fn((self, 1, base_delay), move |(config, attempt, delay)| {
+            Connection::connect(config.clone()).then(move |res| match res {
+                Err(err) if attempt < max_attempts && is_retryable(&err) => {
+                    #[cfg(feature = "log")]
+                    log_facade::debug!(
+                        "connecting failed (attempt {}), retrying in {:?}: {}",
+                        attempt,
+                        delay,
+                        err
+                    );
+                    let fut = Delay::new(Instant::now() + delay)
+                        .map_err(|err| {
+                            ConnectingFailed::Io(std_io::Error::new(std_io::ErrorKind::Other, err))
+                        })
+                        .map(move |()| Loop::Continue((config, attempt + 1, delay * 2)));
+                    Either::B(fut)
+                }
+                res => Either::A(future::result(res.map(Loop::Break))),
+            })
+        })
+    }
+}
+
+fn is_retryable(err: &ConnectingFailed) -> bool {
+    match err {
+        ConnectingFailed::Io(_) => true,
+        ConnectingFailed::Setup(err) => err.is_transient(),
+        ConnectingFailed::Auth(_) => false,
+    }
+}
+
 impl ConnectionConfig<Noop, DefaultTlsSetup> {
     /// Creates a connection to `127.0.0.1` without any form of encryption.
     ///
@@ -334,15 +684,23 @@ impl ConnectionConfig<Noop, DefaultTlsSetup> {
             port: DEFAULT_SMTP_MSA_PORT,
             auth_cmd: Noop,
             syntax_error_handling: Default::default(),
+            greeting_timeout: None,
+            read_idle_timeout: None,
+            tcp_options: TcpOptions::default(),
+            lmtp: false,
         }
     }
 
+    #[deprecated(note = "blocks while resolving the host, see `ConnectionBuilder::new`")]
+    #[allow(deprecated)]
     pub fn builder(
         host: Domain,
     ) -> Result<ConnectionBuilder<Noop, DefaultTlsSetup>, std_io::Error> {
         ConnectionBuilder::new(host)
     }
 
+    #[deprecated(note = "blocks while resolving the host, see `ConnectionBuilder::new`")]
+    #[allow(deprecated)]
     pub fn builder_with_port(
         host: Domain,
         port: u16,
@@ -370,6 +728,10 @@ where
     port: u16,
     auth_cmd: A,
     syntax_error_handling: SyntaxErrorHandling,
+    greeting_timeout: Option<Duration>,
+    read_idle_timeout: Option<Duration>,
+    tcp_options: TcpOptions,
+    lmtp: bool,
 }
 
 impl<A> LocalNonSecureBuilder<A>
@@ -398,6 +760,10 @@ where
             port,
             auth_cmd: _,
             syntax_error_handling,
+            greeting_timeout,
+            read_idle_timeout,
+            tcp_options,
+            lmtp,
         } = self;
 
         LocalNonSecureBuilder {
@@ -405,17 +771,63 @@ where
             port,
             auth_cmd,
             syntax_error_handling,
+            greeting_timeout,
+            read_idle_timeout,
+            tcp_options,
+            lmtp,
         }
     }
 
     /// Sets which SyntaxErrorHandling is used during connection setup.
     ///
-    /// (Currently this only affects EHLO.)
+    /// (Currently this affects EHLO and the parsing of responses.)
     pub fn syntax_error_handling(mut self, method: SyntaxErrorHandling) -> Self {
         self.syntax_error_handling = method;
         self
     }
 
+    /// Sets how long to wait for the server greeting (default: forever).
+    ///
+    /// If the server doesn't send a greeting in time connecting fails with
+    /// an `io::Error` of kind `TimedOut`. This requires a tokio runtime with
+    /// a timer.
+    pub fn greeting_timeout(mut self, timeout: Duration) -> Self {
+        self.greeting_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets how long a started response may stall (default: forever).
+    ///
+    /// If the server stops sending in the middle of a response connecting
+    /// (or the command) fails with an `io::Error` of kind `TimedOut`, see
+    /// `Io::set_read_idle_timeout`. This requires a tokio runtime with a
+    /// timer.
+    pub fn read_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.read_idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets if `TCP_NODELAY` is set on the tcp stream (default: false).
+    pub fn tcp_nodelay(mut self, nodelay: bool) -> Self {
+        self.tcp_options.nodelay = nodelay;
+        self
+    }
+
+    /// Sets the `SO_KEEPALIVE` time of the tcp stream, `None` keeps the OS default.
+    pub fn tcp_keepalive(mut self, keepalive: Option<Duration>) -> Self {
+        self.tcp_options.keepalive = keepalive;
+        self
+    }
+
+    /// Sets if `LHLO` is send instead of `EHLO`, i.e. the server speaks LMTP (default: false).
+    ///
+    /// This puts the connection into LMTP mode (see `Connection::is_lmtp`),
+    /// use `Connection::send_lmtp_data` to get the result for each recipient.
+    pub fn lmtp(mut self, lmtp: bool) -> Self {
+        self.lmtp = lmtp;
+        self
+    }
+
     /// builds the connection config
     pub fn build(self) -> ConnectionConfig<A, DefaultTlsSetup> {
         let LocalNonSecureBuilder {
@@ -423,6 +835,10 @@ where
             port,
             auth_cmd,
             syntax_error_handling,
+            greeting_timeout,
+            read_idle_timeout,
+            tcp_options,
+            lmtp,
         } = self;
 
         let client_id = client_id.unwrap_or_else(ClientId::hostname);
@@ -438,9 +854,23 @@ where
             auth_cmd,
             security,
             syntax_error_handling,
+            greeting_timeout,
+            read_idle_timeout,
+            tcp_options,
+            lmtp,
         }
     }
 
+    /// builds the connection config, failing if the client id is not valid
+    ///
+    /// See `ClientId::validate`, this allows detecting e.g. a bad hostname
+    /// early instead of failing when sending `EHLO`.
+    pub fn try_build(self) -> Result<ConnectionConfig<A, DefaultTlsSetup>, SyntaxError> {
+        let config = self.build();
+        config.client_id.validate()?;
+        Ok(config)
+    }
+
     /// Calls `Connection::connect(self.build())`.
     pub fn connect(self) -> impl Future<Item = Connection, Error = ConnectingFailed> + Send {
         Connection::connect(self.build())
@@ -461,6 +891,10 @@ where
     use_security: UseSecurity,
     auth_cmd: A,
     syntax_error_handling: SyntaxErrorHandling,
+    greeting_timeout: Option<Duration>,
+    read_idle_timeout: Option<Duration>,
+    tcp_options: TcpOptions,
+    lmtp: bool,
 }
 
 impl ConnectionBuilder<Noop, DefaultTlsSetup> {
@@ -476,6 +910,16 @@ impl ConnectionBuilder<Noop, DefaultTlsSetup> {
     /// `std::net::ToSocketAddrs` is used internally and can cause an
     /// io error, e.g. if it can not resolve an address for the given
     /// host name.
+    ///
+    /// # Deprecated
+    ///
+    /// Resolving the host blocks the current thread (and with it the event
+    /// loop if called from a future), use `ConnectionBuilder::new_with_addr`
+    /// or `ConnectionBuilder::new_with_resolver` (feature `resolve`) instead.
+    #[deprecated(
+        note = "blocks while resolving the host, use `new_with_resolver` or `new_with_addr`"
+    )]
+    #[allow(deprecated)]
     pub fn new(host: Domain) -> Result<Self, std_io::Error> {
         Self::new_with_port(host, DEFAULT_SMTP_MSA_PORT)
     }
@@ -490,8 +934,15 @@ impl ConnectionBuilder<Noop, DefaultTlsSetup> {
     /// `std::net::ToSocketAddrs` is used internally and can cause an
     /// io error, e.g. if it can not resolve an address for the given
     /// host name.
+    ///
+    /// # Deprecated
+    ///
+    /// Like `ConnectionBuilder::new` this blocks while resolving the host.
+    #[deprecated(
+        note = "blocks while resolving the host, use `new_with_resolver` or `new_with_addr`"
+    )]
     pub fn new_with_port(host: Domain, port: u16) -> Result<Self, std_io::Error> {
-        let addr = get_addr((host.as_str(), port))?;
+        let addr = first_addr(lookup_host(&host, port)?, &host, port)?;
         Ok(Self::new_with_addr(addr, host))
     }
 
@@ -508,10 +959,62 @@ impl ConnectionBuilder<Noop, DefaultTlsSetup> {
             setup_tls: DefaultTlsSetup,
             auth_cmd: Noop,
             syntax_error_handling: Default::default(),
+            greeting_timeout: None,
+            read_idle_timeout: None,
+            tcp_options: TcpOptions::default(),
+            lmtp: false,
         }
     }
 }
 
+impl<A> ConnectionBuilder<A, DefaultTlsSetup>
+where
+    A: Cmd,
+{
+    /// **Dangerous**: accept server certificates which don't match the domain.
+    ///
+    /// This switches to the `DangerousTlsSetup`. It's meant for connecting to
+    /// e.g. test servers by ip, **it makes man-in-the-middle attacks possible**.
+    /// The certificate itself is still verified.
+    pub fn danger_accept_invalid_hostnames(
+        self,
+        accept: bool,
+    ) -> ConnectionBuilder<A, DangerousTlsSetup> {
+        self.use_tls_setup(DangerousTlsSetup::default())
+            .danger_accept_invalid_hostnames(accept)
+    }
+
+    /// **Dangerous**: don't send the domain as Server Name Indication (SNI).
+    ///
+    /// This switches to the `DangerousTlsSetup`. Servers hosting multiple
+    /// domains might present the wrong certificate without SNI.
+    pub fn danger_disable_sni(self, disable: bool) -> ConnectionBuilder<A, DangerousTlsSetup> {
+        self.use_tls_setup(DangerousTlsSetup::default())
+            .danger_disable_sni(disable)
+    }
+}
+
+impl<A> ConnectionBuilder<A, DangerousTlsSetup>
+where
+    A: Cmd,
+{
+    /// **Dangerous**: accept server certificates which don't match the domain.
+    ///
+    /// Like the method of the same name of a builder using the `DefaultTlsSetup`.
+    pub fn danger_accept_invalid_hostnames(mut self, accept: bool) -> Self {
+        self.setup_tls.accept_invalid_hostnames = accept;
+        self
+    }
+
+    /// **Dangerous**: don't send the domain as Server Name Indication (SNI).
+    ///
+    /// Like the method of the same name of a builder using the `DefaultTlsSetup`.
+    pub fn danger_disable_sni(mut self, disable: bool) -> Self {
+        self.setup_tls.disable_sni = disable;
+        self
+    }
+}
+
 impl<A, S> ConnectionBuilder<A, S>
 where
     S: SetupTls,
@@ -537,6 +1040,10 @@ where
             setup_tls: _,
             auth_cmd,
             syntax_error_handling,
+            greeting_timeout,
+            read_idle_timeout,
+            tcp_options,
+            lmtp,
         } = self;
 
         ConnectionBuilder {
@@ -547,6 +1054,10 @@ where
             setup_tls: setup,
             auth_cmd,
             syntax_error_handling,
+            greeting_timeout,
+            read_idle_timeout,
+            tcp_options,
+            lmtp,
         }
     }
 
@@ -575,11 +1086,34 @@ where
         self
     }
 
+    /// Make the builder use opportunistic tls when building.
+    ///
+    /// I.e. `STARTTLS` is used if the server supports it, else the
+    /// connection stays unencrypted, see `Security::Opportunistic`.
+    pub fn use_opportunistic_tls(mut self) -> Self {
+        self.use_security = UseSecurity::Opportunistic;
+        self
+    }
+
     /// Set the command to use for authentication.
     ///
     /// If this function is not called `Noop` is used,
     /// i.e. no authentication is done.
-    pub fn auth<NA: Cmd>(self, auth_cmd: NA) -> ConnectionBuilder<NA, S> {
+    ///
+    /// The command is wrapped in `RequireSecure`, i.e. connecting fails
+    /// with `ConnectingFailed::Auth` instead of sending the credentials
+    /// if the connection isn't encrypted (e.g. when using opportunistic
+    /// tls with a server not supporting `STARTTLS`).
+    pub fn auth<NA: Cmd>(self, auth_cmd: NA) -> ConnectionBuilder<RequireSecure<NA>, S> {
+        self.auth_allow_plaintext(RequireSecure(auth_cmd))
+    }
+
+    /// Set the command to use for authentication, allowing it to run over plaintext.
+    ///
+    /// Like `auth` but the command isn't wrapped in `RequireSecure`.
+    ///
+    /// **Should only be used for test setups or connections to loopback.**
+    pub fn auth_allow_plaintext<NA: Cmd>(self, auth_cmd: NA) -> ConnectionBuilder<NA, S> {
         let ConnectionBuilder {
             addr,
             domain,
@@ -588,6 +1122,10 @@ where
             setup_tls,
             auth_cmd: _,
             syntax_error_handling,
+            greeting_timeout,
+            read_idle_timeout,
+            tcp_options,
+            lmtp,
         } = self;
 
         ConnectionBuilder {
@@ -598,6 +1136,10 @@ where
             setup_tls,
             auth_cmd,
             syntax_error_handling,
+            greeting_timeout,
+            read_idle_timeout,
+            tcp_options,
+            lmtp,
         }
     }
 
@@ -611,12 +1153,54 @@ where
 
     /// Set's if syntax errors are handled lax or strict when setting up a connection.
     ///
-    /// (Currently this only affects EHLO.)
+    /// (Currently this affects EHLO and the parsing of responses.)
     pub fn syntax_error_handling(mut self, method: SyntaxErrorHandling) -> Self {
         self.syntax_error_handling = method;
         self
     }
 
+    /// Sets how long to wait for the server greeting (default: forever).
+    ///
+    /// If the server doesn't send a greeting in time connecting fails with
+    /// an `io::Error` of kind `TimedOut`. This requires a tokio runtime with
+    /// a timer.
+    pub fn greeting_timeout(mut self, timeout: Duration) -> Self {
+        self.greeting_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets how long a started response may stall (default: forever).
+    ///
+    /// If the server stops sending in the middle of a response connecting
+    /// (or the command) fails with an `io::Error` of kind `TimedOut`, see
+    /// `Io::set_read_idle_timeout`. This requires a tokio runtime with a
+    /// timer.
+    pub fn read_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.read_idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets if `TCP_NODELAY` is set on the tcp stream (default: false).
+    pub fn tcp_nodelay(mut self, nodelay: bool) -> Self {
+        self.tcp_options.nodelay = nodelay;
+        self
+    }
+
+    /// Sets the `SO_KEEPALIVE` time of the tcp stream, `None` keeps the OS default.
+    pub fn tcp_keepalive(mut self, keepalive: Option<Duration>) -> Self {
+        self.tcp_options.keepalive = keepalive;
+        self
+    }
+
+    /// Sets if `LHLO` is send instead of `EHLO`, i.e. the server speaks LMTP (default: false).
+    ///
+    /// This puts the connection into LMTP mode (see `Connection::is_lmtp`),
+    /// use `Connection::send_lmtp_data` to get the result for each recipient.
+    pub fn lmtp(mut self, lmtp: bool) -> Self {
+        self.lmtp = lmtp;
+        self
+    }
+
     /// Creates a new connection config.
     ///
     /// If not specified differently, then
@@ -635,12 +1219,17 @@ where
             setup_tls: setup,
             auth_cmd,
             syntax_error_handling,
+            greeting_timeout,
+            read_idle_timeout,
+            tcp_options,
+            lmtp,
         } = self;
 
         let tls_config = TlsConfig { domain, setup };
         let security = match use_security {
             UseSecurity::StartTls => Security::StartTls(tls_config),
             UseSecurity::DirectTls => Security::DirectTls(tls_config),
+            UseSecurity::Opportunistic => Security::Opportunistic(tls_config),
         };
 
         let client_id = client_id.unwrap_or_else(ClientId::hostname);
@@ -651,9 +1240,23 @@ where
             auth_cmd,
             client_id,
             syntax_error_handling,
+            greeting_timeout,
+            read_idle_timeout,
+            tcp_options,
+            lmtp,
         }
     }
 
+    /// Creates a new connection config, failing if the client id is not valid
+    ///
+    /// See `ClientId::validate`, this allows detecting e.g. a bad hostname
+    /// early instead of failing when sending `EHLO`.
+    pub fn try_build(self) -> Result<ConnectionConfig<A, S>, SyntaxError> {
+        let config = self.build();
+        config.client_id.validate()?;
+        Ok(config)
+    }
+
     /// Calls `Connection::connect(self.build())`.
     pub fn connect(self) -> impl Future<Item = Connection, Error = ConnectingFailed> + Send {
         Connection::connect(self.build())
@@ -664,17 +1267,32 @@ where
 enum UseSecurity {
     StartTls,
     DirectTls,
+    Opportunistic,
 }
 
-fn get_addr(tsas: impl ToSocketAddrs + Copy + Debug) -> Result<SocketAddr, std_io::Error> {
-    if let Some(addr) = tsas.to_socket_addrs()?.next() {
-        Ok(addr)
-    } else {
-        Err(std_io::Error::new(
+/// resolves the addresses of `host` using std's blocking `ToSocketAddrs`
+///
+/// This is the lookup used by `ConnectionBuilder::new_with_port` and (run
+/// off the event loop) by `resolve::StdResolver`.
+pub(crate) fn lookup_host(host: &Domain, port: u16) -> Result<Vec<SocketAddr>, std_io::Error> {
+    Ok((host.as_str(), port).to_socket_addrs()?.collect())
+}
+
+/// returns the first resolved address, failing with `AddrNotAvailable` if there is none
+pub(crate) fn first_addr(
+    addrs: Vec<SocketAddr>,
+    host: &Domain,
+    port: u16,
+) -> Result<SocketAddr, std_io::Error> {
+    addrs.into_iter().next().ok_or_else(|| {
+        std_io::Error::new(
             std_io::ErrorKind::AddrNotAvailable,
-            format!("{:?} is not associated with any socket address", tsas),
-        ))
-    }
+            format!(
+                "{:?} is not associated with any socket address",
+                (host.as_str(), port)
+            ),
+        )
+    })
 }
 
 #[cfg(test)]
@@ -686,6 +1304,7 @@ mod testd {
     const EXAMPLE_DOMAIN: &str = "1aim.com";
 
     #[test]
+    #[allow(deprecated)]
     fn builder_uses_right_defaults() {
         //
         let host = Domain::new_unchecked(EXAMPLE_DOMAIN.to_owned());
@@ -697,6 +1316,10 @@ mod testd {
             auth_cmd,
             client_id,
             syntax_error_handling,
+            greeting_timeout,
+            read_idle_timeout,
+            tcp_options,
+            lmtp,
         } = cb.build();
 
         assert!((EXAMPLE_DOMAIN, DEFAULT_SMTP_MSA_PORT)
@@ -719,5 +1342,84 @@ mod testd {
         }
 
         assert_eq!(syntax_error_handling, SyntaxErrorHandling::Lax);
+        assert_eq!(greeting_timeout, None);
+        assert_eq!(read_idle_timeout, None);
+        assert_eq!(tcp_options, TcpOptions::default());
+        assert!(!lmtp);
+    }
+
+    #[test]
+    fn builder_auth_requires_secure_connection() {
+        let addr = "127.0.0.1:587".parse().unwrap();
+        let domain = Domain::new_unchecked("localhost".to_owned());
+
+        let config = ConnectionBuilder::new_with_addr(addr, domain.clone())
+            .auth(Noop)
+            .build();
+        let _type_check: RequireSecure<Noop> = config.auth_cmd;
+
+        let config = ConnectionBuilder::new_with_addr(addr, domain)
+            .auth_allow_plaintext(Noop)
+            .build();
+        let _type_check: Noop = config.auth_cmd;
+    }
+
+    #[test]
+    fn builder_forwards_danger_flags_to_tls_setup() {
+        let addr = "127.0.0.1:465".parse().unwrap();
+        let domain = Domain::new_unchecked("localhost".to_owned());
+
+        let config = ConnectionBuilder::new_with_addr(addr, domain)
+            .use_direct_tls()
+            .danger_accept_invalid_hostnames(true)
+            .danger_disable_sni(true)
+            .build();
+
+        match config.security {
+            Security::DirectTls(TlsConfig { setup, .. }) => {
+                assert!(setup.accepts_invalid_hostnames());
+                assert!(setup.sni_disabled());
+            }
+            other => panic!("unexpected security: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn try_build_rejects_invalid_client_id() {
+        let bad = ClientId::Domain(Domain::new_unchecked("not a domain".to_owned()));
+        let res = ConnectionConfig::builder_local_unencrypted()
+            .client_id(bad)
+            .try_build();
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn try_build_accepts_ip_literal_client_id() {
+        let id = ClientId::from_ip(Ipv4Addr::new(203, 0, 113, 7).into());
+        let config = ConnectionConfig::builder_local_unencrypted()
+            .client_id(id)
+            .try_build()
+            .unwrap();
+        assert_eq!(config.client_id.as_str(), "[203.0.113.7]");
+    }
+
+    #[cfg(feature = "mock-impl")]
+    #[test]
+    fn records_warning_for_loopback_client_id_on_mx_port() {
+        use crate::mock::MockSocket;
+
+        let mock_con = || Connection::from(Io::from(MockSocket::new_no_check_shutdown(vec![])));
+        let mx_addr = SocketAddr::new(Ipv4Addr::new(192, 0, 2, 25).into(), DEFAULT_SMTP_MX_PORT);
+        let msa_addr = SocketAddr::new(Ipv4Addr::new(192, 0, 2, 25).into(), DEFAULT_SMTP_MSA_PORT);
+
+        let con = record_setup_warnings(mock_con(), &mx_addr, &ClientId::localhost());
+        assert_eq!(con.warnings(), &[SetupWarning::LoopbackClientIdForMx]);
+
+        let con = record_setup_warnings(mock_con(), &msa_addr, &ClientId::localhost());
+        assert!(con.warnings().is_empty());
+
+        let public_id = ClientId::from_ip(Ipv4Addr::new(203, 0, 113, 7).into());
+        let con = record_setup_warnings(mock_con(), &mx_addr, &public_id);
+        assert!(con.warnings().is_empty());
     }
 }
@@ -1,18 +1,48 @@
-use std::io as std_io;
+use std::{
+    error::Error,
+    fmt::{self, Display},
+    io as std_io,
+    net::SocketAddr,
+    time::Duration,
+};
 
-use futures::future::{self, Either, Future};
+use bytes::Buf;
+use futures::{
+    future::{self, Either, Future},
+    stream::{self, Stream},
+};
 use tokio::io::{shutdown, Shutdown};
 
 use crate::{
-    common::EhloData,
+    command::{
+        auth::{record_auth_identity, AuthIdentity},
+        Data,
+    },
+    common::{ClientId, EhloData, SetupWarning},
+    data_types::Capability,
     error::{LogicError, MissingCapabilities},
-    io::{Io, SmtpResult, Socket},
+    graceful_quit::GracefulQuit,
+    idle_timeout::IdleTimeout,
+    io::{Io, IoState, PipelineGuard, ReassembleToken, SmtpResult, Socket, TraceHook},
+    quit_on_drop::QuitOnDrop,
+    response::{Response, ResponseCode},
 };
 
 /// future returned by `Cmd::exec`
 pub type ExecFuture =
     Box<dyn Future<Item = (Io, SmtpResult), Error = std_io::Error> + Send + 'static>;
 
+/// boxed version of the future returned by `Connection::send`
+///
+/// `send` returns an `impl Future`, which can't be named e.g. for storing
+/// it in a struct field, but it can be boxed into this type, i.e.
+/// `let fut: SendFuture = Box::new(con.send(cmd));`.
+pub type SendFuture =
+    Box<dyn Future<Item = (Connection, SmtpResult), Error = std_io::Error> + Send + 'static>;
+
+/// boxed version of the future returned by `Connection::quit`, see `SendFuture`
+pub type QuitFuture = Box<dyn Future<Item = Socket, Error = std_io::Error> + Send + 'static>;
+
 /// The basic `Connection` type representing an (likely) open smtp connection
 ///
 /// It's only likely open as the server could disconnect at any time. But it
@@ -108,20 +138,145 @@ impl Connection {
     /// If the connection fails (e.g. the internet connection is interrupted)
     /// the future will resolve to an `io::Error` and the connection is gone.
     ///
+    /// If the server responds with `421` (see `Response::is_shutdown`) the
+    /// connection is closing, the response is still returned as usual but
+    /// any further `send` resolves to an `io::Error` of kind `NotConnected`.
+    ///
     pub fn send<C: Cmd>(
         self,
         cmd: C,
     ) -> impl Future<Item = (Connection, SmtpResult), Error = std_io::Error> {
-        let fut = if let Err(err) = cmd.check_cmd_availability(self.io.ehlo_data()) {
-            Either::B(future::ok((
-                self,
-                Err(LogicError::MissingCapabilities(err)),
+        if !self.io.is_poisoned() {
+            if let Err(err) = cmd.check_cmd_availability(self.io.ehlo_data()) {
+                return Either::B(future::ok((
+                    self,
+                    Err(LogicError::MissingCapabilities(err)),
+                )));
+            }
+        }
+
+        Either::A(self.send_unchecked(cmd))
+    }
+
+    /// send a command without checking if the server advertised support for it
+    ///
+    /// This works like `send` except that `Cmd::check_cmd_availability`
+    /// is not called, i.e. the command is send even if e.g. the EHLO
+    /// response lacks the needed capability. It's meant for diagnostics
+    /// and for servers known to support commands they don't advertise.
+    ///
+    /// **Be careful**: a server not supporting the command will at best
+    /// reject it, but it also might interpret it (or e.g. its parameters)
+    /// differently. Commands which change how following data is handled
+    /// (e.g. `BDAT`) can leave the connection in an unusable state.
+    pub fn send_unchecked<C: Cmd>(
+        self,
+        cmd: C,
+    ) -> impl Future<Item = (Connection, SmtpResult), Error = std_io::Error> {
+        let fut = if self.io.is_poisoned() {
+            Either::B(future::err(std_io::Error::new(
+                std_io::ErrorKind::NotConnected,
+                "server closed the connection (421)",
+            )))
+        } else {
+            let auth_identity = cmd.auth_identity();
+            let exec = record_auth_identity(auth_identity, cmd.exec(self.into()));
+            Either::A(exec.map(|(mut io, smtp_res)| {
+                if is_shutdown(&smtp_res) {
+                    io.poison();
+                }
+                (Connection::from(io), smtp_res)
+            }))
+        };
+
+        fut
+    }
+
+    /// send a clone of the given command, see `send`
+    ///
+    /// As `Cmd::exec` consumes the command `send` needs to take it by value,
+    /// so this is just a shortcut for `con.send(cmd.clone())` (a blanket
+    /// `Cmd` impl for `&C` isn't possible). It's meant for commands which
+    /// are send repeatedly, e.g. the same auth command across reconnects
+    /// or a `Noop` used as keep alive. Cloning happens for each call, so
+    /// for commands expensive to clone wrapping (parts of) them in an `Arc`
+    /// might be preferable.
+    pub fn send_ref<C: Cmd + Clone>(
+        self,
+        cmd: &C,
+    ) -> impl Future<Item = (Connection, SmtpResult), Error = std_io::Error> {
+        self.send(cmd.clone())
+    }
+
+    /// send all commands one after another, collecting all results
+    ///
+    /// Unlike `chain::chain` this does not stop on the first logic
+    /// error, all commands are send and the result of each of them is
+    /// returned (in the same order as the commands). Connection failures
+    /// still resolve the future to an `io::Error`.
+    pub fn send_batch(
+        self,
+        cmds: Vec<BoxedCmd>,
+    ) -> impl Future<Item = (Connection, Vec<SmtpResult>), Error = std_io::Error> {
+        let results = Vec::with_capacity(cmds.len());
+        stream::iter_ok(cmds).fold((self, results), |(con, mut results), cmd| {
+            con.send(cmd).map(move |(con, result)| {
+                results.push(result);
+                (con, results)
+            })
+        })
+    }
+
+    /// send a command, treating any positive response but one with the `expected` code as error
+    ///
+    /// Works like `send` but a successful response with a different code
+    /// is turned into a `LogicError::UnexpectedCode`. (Error responses are
+    /// returned as they are, i.e. as `LogicError::Code`.)
+    pub fn send_expecting<C: Cmd>(
+        self,
+        cmd: C,
+        expected: ResponseCode,
+    ) -> impl Future<Item = (Connection, SmtpResult), Error = std_io::Error> {
+        self.send(cmd).map(move |(con, result)| {
+            let result = match result {
+                Ok(response) if !response.code_matches(expected) => {
+                    Err(LogicError::UnexpectedCode(response))
+                }
+                other => other,
+            };
+            (con, result)
+        })
+    }
+
+    /// send the mail data to a LMTP server, returning one result per recipient
+    ///
+    /// Unlike a smtp server a LMTP server (RFC 2033) answers the end of the
+    /// mail data with a response for each recipient accepted by `RCPT TO`
+    /// (as counted by the connection, see `Io::accepted_recipients`). The
+    /// results are in the same order as the recipients. If `DATA` itself is
+    /// rejected only the result for it is returned. Use `command::Lhlo` (or
+    /// the `lmtp` option of the connection builders) instead of `EHLO`, then
+    /// sending `command::Data` normally resolves to the first failed result.
+    pub fn send_lmtp_data<S>(
+        self,
+        data: Data<S>,
+    ) -> impl Future<Item = (Connection, Vec<SmtpResult>), Error = std_io::Error>
+    where
+        S: Stream<Error = std_io::Error> + Send + 'static,
+        S::Item: Buf,
+    {
+        let fut = if self.io.is_poisoned() {
+            Either::B(future::err(std_io::Error::new(
+                std_io::ErrorKind::NotConnected,
+                "server closed the connection (421)",
             )))
         } else {
-            Either::A(
-                cmd.exec(self.into())
-                    .map(|(io, smtp_res)| (Connection::from(io), smtp_res)),
-            )
+            Either::A(data.exec_lmtp(self.into()).map(|(mut io, results)| {
+                if results.iter().any(is_shutdown) {
+                    io.poison();
+                }
+                (Connection::from(io), results)
+            }))
         };
 
         fut
@@ -142,11 +297,155 @@ impl Connection {
         self.io.has_capability(cap)
     }
 
+    /// returns a `PipelineGuard` if the server advertised `PIPELINING`
+    ///
+    /// Fails with `MissingCapabilities` without sending anything to the
+    /// server, see `io::PipelineGuard`.
+    pub fn pipeline_guard(&self) -> Result<PipelineGuard, MissingCapabilities> {
+        self.io.pipeline_guard()
+    }
+
+    /// returns the (process wide) unique id of the connection, see `Io::id`
+    pub fn id(&self) -> u64 {
+        self.io.id()
+    }
+
+    /// returns the address of the server, if known
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.io.peer_addr()
+    }
+
+    /// returns a tag identifying the connection in log output
+    #[cfg(feature = "log")]
+    pub(crate) fn log_tag(&self) -> crate::io::LogTag {
+        self.io.log_tag()
+    }
+
     /// returns a opt. reference to the ehlo data stored from the last ehlo call
     pub fn ehlo_data(&self) -> Option<&EhloData> {
         self.io.ehlo_data()
     }
 
+    /// returns a copy of the ehlo data stored from the last ehlo call
+    ///
+    /// Unlike `ehlo_data` this isn't bound to the connection, so it can e.g.
+    /// be kept around after the connection was moved into `send_mail`.
+    pub fn capabilities(&self) -> Option<EhloData> {
+        self.io.ehlo_data().cloned()
+    }
+
+    /// returns the size of the mail data send by the last `DATA` command
+    ///
+    /// The size is measured after dot-stashing and includes the end of mail
+    /// sequence `"\r\n.\r\n"`. It's `None` if no mail data was send.
+    pub fn last_data_bytes(&self) -> Option<usize> {
+        self.io.last_data_bytes()
+    }
+
+    /// returns the greeting the server send when the connection was opened
+    ///
+    /// E.g. to check the server banner. It's only available if the
+    /// connection was setup with `Connection::connect`/`Connection::from_stream`.
+    pub fn greeting(&self) -> Option<&Response> {
+        self.io.greeting()
+    }
+
+    /// returns the warnings noticed while setting up the connection
+    ///
+    /// E.g. `SetupWarning::LoopbackClientIdForMx`. These are only collected
+    /// by `Connection::connect` (and `connect_with_cached_ehlo`).
+    pub fn warnings(&self) -> &[SetupWarning] {
+        self.io.setup_warnings()
+    }
+
+    /// returns the identity announced with the last successful `EHLO`, if known
+    ///
+    /// This is the `ClientId` of the `ConnectionConfig` for connections setup
+    /// through `Connection::connect` (or the one set with `with_identity`).
+    pub fn client_id(&self) -> Option<&ClientId> {
+        self.io.client_id()
+    }
+
+    /// sets the identity used by `Connection::rehlo`
+    ///
+    /// This doesn't send anything, the new identity is only announced to
+    /// the server once `rehlo` is called.
+    pub fn with_identity(mut self, client_id: ClientId) -> Self {
+        self.io.set_client_id(client_id);
+        self
+    }
+
+    /// sends `EHLO` again using the identity returned by `client_id`
+    ///
+    /// This replaces the stored ehlo data with the new response and, like
+    /// `EHLO` always does, resets any mail transaction the server had in
+    /// progress. Combined with `with_identity` it can be used to re-announce
+    /// the client under a different name mid-session. In LMTP mode (see
+    /// `is_lmtp`) `LHLO` is send instead. If no identity is known nothing
+    /// is send and it resolves to a `LogicError::Custom`.
+    pub fn rehlo(self) -> impl Future<Item = (Connection, SmtpResult), Error = std_io::Error> {
+        //Note: this has a circular dependency between Connection <-> cmd Ehlo which
+        // could be resolved using a ext. trait, but it's more ergonomic this way
+        use crate::command::{Ehlo, Lhlo};
+
+        let fut = match self.io.client_id().cloned() {
+            Some(client_id) => {
+                let syntax_error_handling = self.io.syntax_error_handling().clone();
+                if self.io.is_lmtp() {
+                    Either::A(Either::A(self.send(
+                        Lhlo::from(client_id).with_syntax_error_handling(syntax_error_handling),
+                    )))
+                } else {
+                    Either::A(Either::B(self.send(
+                        Ehlo::from(client_id).with_syntax_error_handling(syntax_error_handling),
+                    )))
+                }
+            }
+            None => Either::B(future::ok((
+                self,
+                Err(LogicError::Custom(
+                    "no client id known to send EHLO with".into(),
+                )),
+            ))),
+        };
+
+        fut
+    }
+
+    /// returns the identity and mechanism of the last successful auth command
+    ///
+    /// This is recorded for auth commands reporting their identity through
+    /// `Cmd::auth_identity`, e.g. `auth::Plain` and `auth::Login` used as
+    /// `auth_cmd` during `Connection::connect`. Like `is_authenticated`
+    /// it's reset by `STARTTLS`.
+    pub fn authenticated_as(&self) -> Option<&AuthIdentity> {
+        self.io.authenticated_as()
+    }
+
+    /// set a hook which is called for every line send to/received from the server
+    ///
+    /// This can be used for e.g. metrics or audit logging without needing
+    /// to enable the `log` feature. Credentials in `AUTH` lines are redacted
+    /// and the mail data is not passed to the hook (see `io::TraceHook`).
+    pub fn set_trace_hook(&mut self, hook: TraceHook) {
+        self.io.set_trace_hook(hook)
+    }
+
+    /// set if the raw bytes of responses are kept, see `Io::set_retain_raw_responses`
+    pub fn set_retain_raw_responses(&mut self, retain: bool) {
+        self.io.set_retain_raw_responses(retain)
+    }
+
+    /// set the capacity above which the output buffer is shrunk, see `Io::set_output_shrink_threshold`
+    pub fn set_output_shrink_threshold(&mut self, threshold: Option<usize>) {
+        self.io.set_output_shrink_threshold(threshold)
+    }
+
+    /// set how long a started response may stall, see `Io::set_read_idle_timeout`
+    pub fn set_read_idle_timeout(&mut self, timeout: Option<Duration>) {
+        self.io.set_read_idle_timeout(timeout)
+    }
+
     /// converts the `Connection` into an `Io` instance
     ///
     /// This is only need when implementing custom `Cmd`'s
@@ -155,24 +454,189 @@ impl Connection {
         io
     }
 
+    /// takes the socket out of the connection, e.g. to do custom operations on it
+    ///
+    /// The returned token contains everything else (buffers, ehlo data, etc.)
+    /// and can be used with `Connection::restore_socket` to re-create the
+    /// connection. As data in the buffers would be lost/misplaced when
+    /// operating on the raw socket this fails if the buffers are not empty.
+    pub fn take_socket(self) -> Result<(Socket, ReassembleToken), BuffersNotEmptyError> {
+        if self.io.buffers_are_empty() {
+            Ok(self.io.take_socket())
+        } else {
+            Err(BuffersNotEmptyError {
+                con: Box::new(self),
+            })
+        }
+    }
+
+    /// re-creates a connection from a token returned by `take_socket` and a socket
+    pub fn restore_socket(token: ReassembleToken, socket: Socket) -> Self {
+        Connection::from(Io::restore_socket(token, socket))
+    }
+
+    /// converts the connection into the `Io` and a copy of its state
+    ///
+    /// Unlike the `ReassembleToken` of `take_socket` the `ConnectionMeta`
+    /// doesn't contain the buffers, so e.g. a connection pool can store it
+    /// alongside the socket and later re-create an equivalent connection
+    /// with `from_parts`. The returned `Io` still contains the state.
+    pub fn into_parts(self) -> (Io, ConnectionMeta) {
+        let io = self.io;
+        let meta = io.state().clone();
+        (io, meta)
+    }
+
+    /// creates a connection from an `Io` and the state returned by `into_parts`
+    ///
+    /// The whole state of the `Io` (including e.g. the connection id) is
+    /// replaced with `meta`.
+    pub fn from_parts(mut io: Io, meta: ConnectionMeta) -> Self {
+        io.replace_state(meta);
+        Connection::from(io)
+    }
+
     /// shutdown the connection _without_ sending quit
     pub fn shutdown(self) -> Shutdown<Socket> {
         let io = self.into_inner();
+        #[cfg(feature = "log")]
+        log_facade::trace!("{} Shutting down connection", io.log_tag());
         let (socket, _, _) = io.split();
         shutdown(socket)
     }
 
+    /// sends `RSET` to the server, aborting the current mail transaction
+    ///
+    /// Like with `command::Reset` a server responding to `RSET` with an
+    /// error or a non positive response code is treated as an I/O-Error,
+    /// as there is no reasonable way to continue using the connection.
+    pub fn reset(self) -> impl Future<Item = (Connection, SmtpResult), Error = std_io::Error> {
+        //Note: this has a circular dependency between Connection <-> cmd Reset which
+        // could be resolved using a ext. trait, but it's more ergonomic this way
+        use crate::command::Reset;
+
+        self.send(Reset)
+    }
+
+    /// makes sure no mail transaction is in progress, sending `RSET` if needed
+    ///
+    /// If a previous mail transaction was started (`MAIL`) but not completed
+    /// (e.g. because a `RCPT` failed and no `RSET` was send) `RSET` is send,
+    /// else this resolves directly. Like with `reset` a failing `RSET` is
+    /// treated as an I/O-Error.
+    pub fn begin_transaction(self) -> impl Future<Item = Connection, Error = std_io::Error> {
+        let fut = if self.io.is_in_transaction() {
+            Either::A(self.reset().map(|(con, _response)| con))
+        } else {
+            Either::B(future::ok(self))
+        };
+
+        fut
+    }
+
+    /// returns true if a mail transaction was started but not yet completed
+    pub fn is_in_transaction(&self) -> bool {
+        self.io.is_in_transaction()
+    }
+
+    /// returns true if the connection is in LMTP mode, see `Io::is_lmtp`
+    pub fn is_lmtp(&self) -> bool {
+        self.io.is_lmtp()
+    }
+
+    /// returns a snapshot of the state of the connection
+    pub fn state(&self) -> ConnectionState {
+        let ehlo_data = self.io.ehlo_data();
+        let supports = |check: fn(&EhloData) -> bool| ehlo_data.map(check).unwrap_or(false);
+        ConnectionState {
+            is_secure: self.io.is_secure(),
+            is_authenticated: self.io.is_authenticated(),
+            transaction_in_progress: self.io.is_in_transaction(),
+            has_ehlo_data: ehlo_data.is_some(),
+            supports_pipelining: supports(EhloData::supports_pipelining),
+            supports_8bitmime: supports(EhloData::supports_8bitmime),
+            supports_smtputf8: supports(EhloData::supports_smtputf8),
+            supports_starttls: supports(EhloData::supports_starttls),
+        }
+    }
+
+    /// wraps the connection so that it's quit if it's not used for `timeout`
+    ///
+    /// The returned `IdleTimeout` needs to be polled to enforce the
+    /// timeout, see it's documentation for more details.
+    pub fn with_idle_timeout(self, timeout: Duration) -> IdleTimeout {
+        IdleTimeout::new(self, timeout)
+    }
+
+    /// wraps the connection so that it's quit when the wrapper is dropped
+    ///
+    /// The quit is spawned onto the current tokio runtime, see the
+    /// documentation of `QuitOnDrop` for more details.
+    pub fn quit_on_drop(self) -> QuitOnDrop {
+        QuitOnDrop::new(self)
+    }
+
     /// sends quit to the server and then shuts down the socket
     ///
     /// The socked is shut down independent of wether or not sending
     /// quit failed, while sending quit should not cause any logic
     /// error if it does it's not returned by this method.
+    ///
+    /// If the server already closed the connection (i.e. responded
+    /// with `421`) no quit is send and the socket is just shut down.
     pub fn quit(self) -> impl Future<Item = Socket, Error = std_io::Error> {
         //Note: this has a circular dependency between Connection <-> cmd StartTls/Ehlo which
         // could be resolved using a ext. trait, but it's more ergonomic this way
         use crate::command::Quit;
 
-        self.send(Quit).and_then(|(con, _res)| con.shutdown())
+        let fut = if self.io.is_poisoned() {
+            Either::B(self.shutdown())
+        } else {
+            Either::A(self.send(Quit).and_then(|(con, _res)| con.shutdown()))
+        };
+
+        fut
+    }
+
+    /// sends quit to the server (best-effort) and then always shuts down the socket
+    ///
+    /// Unlike `quit` this doesn't fail if sending `QUIT` fails (e.g. because
+    /// the server is already gone) and only waits up to `timeout` for the
+    /// response before shutting down the socket. Only failing to shut down
+    /// the socket is returned as error. This needs a tokio runtime with a timer.
+    pub fn quit_graceful(self, timeout: Duration) -> impl Future<Item = (), Error = std_io::Error> {
+        GracefulQuit::new(self.into_inner(), timeout)
+    }
+}
+
+/// error returned by `Connection::take_socket` if the buffers are not empty
+#[derive(Debug)]
+pub struct BuffersNotEmptyError {
+    con: Box<Connection>,
+}
+
+impl BuffersNotEmptyError {
+    /// returns the connection `take_socket` was called on
+    pub fn into_connection(self) -> Connection {
+        *self.con
+    }
+}
+
+impl Display for BuffersNotEmptyError {
+    fn fmt(&self, fter: &mut fmt::Formatter) -> fmt::Result {
+        write!(fter, "can not take socket, connection has buffered data")
+    }
+}
+
+impl Error for BuffersNotEmptyError {}
+
+/// true if the result indicates that the server is closing the connection
+fn is_shutdown(result: &SmtpResult) -> bool {
+    match result {
+        Ok(response)
+        | Err(LogicError::Code(response))
+        | Err(LogicError::UnexpectedCode(response)) => response.is_shutdown(),
+        Err(_) => false,
     }
 }
 
@@ -204,6 +668,35 @@ impl From<Socket> for Connection {
     }
 }
 
+/// the state of a connection, see `Connection::state`
+///
+/// This is a snapshot, it's not updated once the connection is used again.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct ConnectionState {
+    /// the connection is TLS encrypted
+    pub is_secure: bool,
+    /// an auth command succeeded (after the last `STARTTLS`)
+    pub is_authenticated: bool,
+    /// `MAIL` succeeded but the transaction wasn't completed/reset yet
+    pub transaction_in_progress: bool,
+    /// `EHLO` was send and it's response is available
+    pub has_ehlo_data: bool,
+    /// the server advertised `PIPELINING`
+    pub supports_pipelining: bool,
+    /// the server advertised `8BITMIME`
+    pub supports_8bitmime: bool,
+    /// the server advertised `SMTPUTF8`
+    pub supports_smtputf8: bool,
+    /// the server advertised `STARTTLS`
+    pub supports_starttls: bool,
+}
+
+/// the state of a connection, see `Connection::into_parts`
+///
+/// This is the `IoState` of the connection, i.e. everything apart from the
+/// socket and buffers (ehlo data, greeting, auth, settings, etc.).
+pub type ConnectionMeta = IoState;
+
 /// Trait implemented by any smtp command
 ///
 /// While it is not object safe on itself using
@@ -214,6 +707,28 @@ pub trait Cmd: Send + 'static {
     /// for a given connection
     fn check_cmd_availability(&self, caps: Option<&EhloData>) -> Result<(), MissingCapabilities>;
 
+    /// Returns the capabilities the server needs to support for this command
+    ///
+    /// This is meant for diagnostics and for checking the requirements of
+    /// a number of commands up front, `check_cmd_availability` is still
+    /// what decides if a command can be send. Commands requiring a specific
+    /// parameter of a capability (e.g. `AUTH` with `PLAIN`) only return the
+    /// capability (e.g. `AUTH`). The default implementation returns no
+    /// capabilities.
+    fn required_capabilities(&self) -> Vec<Capability> {
+        Vec::new()
+    }
+
+    /// Returns the identity this command authenticates as, if it's an auth command
+    ///
+    /// If the command succeeds the identity is recorded on the connection,
+    /// see `Connection::authenticated_as`. Custom auth mechanisms can
+    /// implement this to report their identity. The default implementation
+    /// returns `None`.
+    fn auth_identity(&self) -> Option<AuthIdentity> {
+        None
+    }
+
     /// Executes this command on the given connection
     ///
     /// This method should not be called directly, instead it
@@ -260,6 +775,24 @@ pub trait TypeErasableCmd {
     #[doc(hidden)]
     fn _check_cmd_availability(&self, caps: Option<&EhloData>) -> Result<(), MissingCapabilities>;
 
+    /// # Panics
+    ///
+    /// may panic if called after `_only_once_exec` was
+    /// called
+    #[doc(hidden)]
+    fn _required_capabilities(&self) -> Vec<Capability> {
+        Vec::new()
+    }
+
+    /// # Panics
+    ///
+    /// may panic if called after `_only_once_exec` was
+    /// called
+    #[doc(hidden)]
+    fn _auth_identity(&self) -> Option<AuthIdentity> {
+        None
+    }
+
     /// # Panics
     ///
     /// may panic if called more then once
@@ -281,6 +814,20 @@ where
         me.check_cmd_availability(caps)
     }
 
+    fn _required_capabilities(&self) -> Vec<Capability> {
+        let me = self
+            .as_ref()
+            .expect("_required_capabilities called after _only_onece_exec");
+        me.required_capabilities()
+    }
+
+    fn _auth_identity(&self) -> Option<AuthIdentity> {
+        let me = self
+            .as_ref()
+            .expect("_auth_identity called after _only_onece_exec");
+        me.auth_identity()
+    }
+
     fn _only_once_exec(&mut self, io: Io) -> ExecFuture {
         let me = self.take().expect("_only_once_exec called a second time");
         me.exec(io)
@@ -292,6 +839,14 @@ impl Cmd for BoxedCmd {
         self._check_cmd_availability(caps)
     }
 
+    fn required_capabilities(&self) -> Vec<Capability> {
+        self._required_capabilities()
+    }
+
+    fn auth_identity(&self) -> Option<AuthIdentity> {
+        self._auth_identity()
+    }
+
     fn exec(mut self, io: Io) -> ExecFuture {
         self._only_once_exec(io)
     }
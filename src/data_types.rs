@@ -7,6 +7,7 @@ use std::ops::Deref;
 use std::str::FromStr;
 
 use crate::ascii::{IgnoreAsciiCaseStr, IgnoreAsciiCaseString};
+use crate::xtext;
 
 /// represents a smtp extension/capability indicated through ehlo
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
@@ -32,9 +33,9 @@ impl From<EsmtpKeyword> for Capability {
     }
 }
 
-impl Into<EsmtpKeyword> for Capability {
-    fn into(self) -> EsmtpKeyword {
-        self.0
+impl From<Capability> for EsmtpKeyword {
+    fn from(cap: Capability) -> Self {
+        cap.0
     }
 }
 
@@ -124,9 +125,9 @@ macro_rules! impl_str_wrapper {
             }
         }
 
-        impl Into<String> for $name {
-            fn into(self) -> String {
-                self.0.into()
+        impl From<$name> for String {
+            fn from(val: $name) -> Self {
+                val.0.into()
             }
         }
 
@@ -161,7 +162,47 @@ impl_str_wrapper!(
     ReversePath
 );
 
+/// true if `inp` contains neither `'\r'`, `'\n'` nor `'\0'`
+///
+/// Strings send as part of a command line must not contain a line break,
+/// else e.g. user input could be used to inject additional commands.
+pub(crate) fn validate_no_ctl(inp: &str) -> bool {
+    !inp.contains(['\r', '\n', '\0'])
+}
+
+impl ForwardPath {
+    /// create a new forward path, failing if it contains `'\r'`, `'\n'` or `'\0'`
+    ///
+    /// Apart from this the path isn't validated, see the type level documentation.
+    pub fn new<I>(path: I) -> Result<Self, SyntaxError>
+    where
+        I: Into<String>,
+    {
+        let path = path.into();
+        if validate_no_ctl(&path) {
+            Ok(ForwardPath(path))
+        } else {
+            Err(SyntaxError::MailAddress(path))
+        }
+    }
+}
+
 impl ReversePath {
+    /// create a new reverse path, failing if it contains `'\r'`, `'\n'` or `'\0'`
+    ///
+    /// Apart from this the path isn't validated, see the type level documentation.
+    pub fn new<I>(path: I) -> Result<Self, SyntaxError>
+    where
+        I: Into<String>,
+    {
+        let path = path.into();
+        if validate_no_ctl(&path) {
+            Ok(ReversePath(path))
+        } else {
+            Err(SyntaxError::MailAddress(path))
+        }
+    }
+
     /// creates an empty reverse path
     ///
     /// In a mail command this will lead to `"MAIL FROM:<>"`.
@@ -183,7 +224,7 @@ impl FromStr for EhloParam {
     type Err = SyntaxError;
 
     fn from_str(inp: &str) -> Result<Self, Self::Err> {
-        let valid = inp.bytes().all(|bch| 33 <= bch && bch <= 126);
+        let valid = inp.bytes().all(|bch| (33..=126).contains(&bch));
 
         if valid {
             Ok(EhloParam(inp.into()))
@@ -243,7 +284,7 @@ impl EsmtpValue {
         let val = val.into();
         let valid = val
             .bytes()
-            .all(|bch| 33 <= bch && (bch <= 60 || (62 <= bch && bch <= 128)));
+            .all(|bch| 33 <= bch && (bch <= 60 || (62..=128).contains(&bch)));
 
         if valid {
             Ok(EsmtpValue(val))
@@ -251,6 +292,15 @@ impl EsmtpValue {
             Err(SyntaxError::EsmtpValue(val))
         }
     }
+
+    /// create a new `EsmtpValue` from any string by xtext encoding it
+    ///
+    /// Unlike `new` this never fails, all bytes outside of `'!'..='~'` as
+    /// well as `'+'` and `'='` are encoded as `"+XX"` (RFC 3461), e.g. for
+    /// the `ENVID` parameter. See the `xtext` module for decoding.
+    pub fn from_encoded(raw: &str) -> Self {
+        EsmtpValue(xtext::encode(raw))
+    }
 }
 
 impl FromStr for EsmtpValue {
@@ -312,6 +362,9 @@ pub enum SyntaxError {
     },
     EsmtpValue(String),
     EsmtpKeyword(String),
+    MailAddress(String),
+    /// a string send as part of a command line contained `'\r'`, `'\n'` or `'\0'`
+    CommandLine(String),
 }
 
 impl Display for SyntaxError {
@@ -328,6 +381,14 @@ impl Display for SyntaxError {
             EsmtpValue(bad_value) => {
                 write!(fter, "syntax error parsing esmtp-value in {:?}", bad_value)
             }
+            MailAddress(bad_addr) => {
+                write!(fter, "syntax error parsing mail address in {:?}", bad_addr)
+            }
+            CommandLine(bad_line) => write!(
+                fter,
+                "command line contains a line break or NUL in {:?}",
+                bad_line
+            ),
             AddressLiteral {
                 tag,
                 value,
@@ -346,6 +407,67 @@ impl Display for SyntaxError {
 
 impl Error for SyntaxError {}
 
+impl SyntaxError {
+    /// returns which kind of syntax element failed to parse
+    pub fn kind(&self) -> SyntaxErrorKind {
+        use self::SyntaxError::*;
+        match self {
+            Domain(_) => SyntaxErrorKind::Domain,
+            EhloParam(_) => SyntaxErrorKind::EhloParam,
+            EsmtpKeyword(_) => SyntaxErrorKind::EsmtpKeyword,
+            EsmtpValue(_) => SyntaxErrorKind::EsmtpValue,
+            MailAddress(_) => SyntaxErrorKind::MailAddress,
+            CommandLine(_) => SyntaxErrorKind::CommandLine,
+            AddressLiteral { was_bad_tag, .. } => {
+                if *was_bad_tag {
+                    SyntaxErrorKind::AddressLiteralTag
+                } else {
+                    SyntaxErrorKind::AddressLiteralValue
+                }
+            }
+        }
+    }
+
+    /// returns the input which failed to parse
+    ///
+    /// For address literals this is only the malformed part,
+    /// i.e. either the tag or the value.
+    pub fn invalid_input(&self) -> &str {
+        use self::SyntaxError::*;
+        match self {
+            Domain(inp) | EhloParam(inp) | EsmtpKeyword(inp) | EsmtpValue(inp)
+            | MailAddress(inp) | CommandLine(inp) => inp,
+            AddressLiteral {
+                tag,
+                value,
+                was_bad_tag,
+            } => {
+                if *was_bad_tag {
+                    tag
+                } else {
+                    value
+                }
+            }
+        }
+    }
+}
+
+/// the kind of syntax element a `SyntaxError` was produced for
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum SyntaxErrorKind {
+    Domain,
+    EhloParam,
+    /// the tag of a general address literal was malformed
+    AddressLiteralTag,
+    /// the value of an address literal was malformed
+    AddressLiteralValue,
+    EsmtpValue,
+    EsmtpKeyword,
+    MailAddress,
+    /// a string send as part of a command line, e.g. the query of `VRFY`
+    CommandLine,
+}
+
 impl AddressLiteral {
     /// Create a "general" AddressLiteral which is not IPv4/v6
     ///
@@ -382,7 +504,7 @@ impl AddressLiteral {
         let custom_part = custom_part.as_ref();
         let valid = custom_part
             .bytes()
-            .all(|bch| (33 <= bch && bch <= 90) || (94 <= bch && bch <= 126));
+            .all(|bch| (33..=90).contains(&bch) || (94..=126).contains(&bch));
 
         if valid {
             Ok(AddressLiteral(format!("[{}:{}]", tag, custom_part).into()))
@@ -396,6 +518,49 @@ impl AddressLiteral {
     }
 }
 
+/// parses a bracketed address literal e.g. `"[192.0.2.1]"` or `"[IPv6:2001:db8::1]"`
+///
+/// Besides IPv4 and IPv6 literals general `"[Tag:value]"` literals are
+/// accepted, with the same validation as done by `custom_literal`.
+impl FromStr for AddressLiteral {
+    type Err = SyntaxError;
+
+    fn from_str(inp: &str) -> Result<Self, Self::Err> {
+        let bad_tag = |tag: &str, value: &str| SyntaxError::AddressLiteral {
+            tag: tag.into(),
+            value: value.into(),
+            was_bad_tag: true,
+        };
+
+        let inner = inp
+            .strip_prefix('[')
+            .and_then(|rest| rest.strip_suffix(']'))
+            .ok_or_else(|| bad_tag("", inp))?;
+
+        if let Ok(addr) = inner.parse::<Ipv4Addr>() {
+            return Ok(AddressLiteral::from(addr));
+        }
+
+        let (tag, value) = inner
+            .find(':')
+            .map(|idx| (&inner[..idx], &inner[idx + 1..]))
+            .ok_or_else(|| bad_tag("", inner))?;
+
+        if tag.eq_ignore_ascii_case("IPv6") {
+            value
+                .parse::<Ipv6Addr>()
+                .map(AddressLiteral::from)
+                .map_err(|_| SyntaxError::AddressLiteral {
+                    tag: tag.into(),
+                    value: value.into(),
+                    was_bad_tag: false,
+                })
+        } else {
+            AddressLiteral::custom_literal(tag, value)
+        }
+    }
+}
+
 impl From<IpAddr> for AddressLiteral {
     fn from(addr: IpAddr) -> Self {
         use self::IpAddr::*;
@@ -434,6 +599,93 @@ impl<'a> From<&'a Ipv6Addr> for AddressLiteral {
 mod test {
     #![allow(non_snake_case)]
 
+    mod SyntaxError {
+        use super::super::{
+            AddressLiteral, Domain, EhloParam, EsmtpKeyword, EsmtpValue, SyntaxError,
+            SyntaxErrorKind,
+        };
+
+        #[test]
+        fn kind_of_domain() {
+            let err = "not a domain".parse::<Domain>().unwrap_err();
+            assert_eq!(err.kind(), SyntaxErrorKind::Domain);
+            assert_eq!(err.invalid_input(), "not a domain");
+        }
+
+        #[test]
+        fn kind_of_ehlo_param() {
+            let err = "a b".parse::<EhloParam>().unwrap_err();
+            assert_eq!(err.kind(), SyntaxErrorKind::EhloParam);
+            assert_eq!(err.invalid_input(), "a b");
+        }
+
+        #[test]
+        fn kind_of_esmtp_keyword() {
+            let err = "-x".parse::<EsmtpKeyword>().unwrap_err();
+            assert_eq!(err.kind(), SyntaxErrorKind::EsmtpKeyword);
+            assert_eq!(err.invalid_input(), "-x");
+        }
+
+        #[test]
+        fn kind_of_esmtp_value() {
+            let err = "a=b".parse::<EsmtpValue>().unwrap_err();
+            assert_eq!(err.kind(), SyntaxErrorKind::EsmtpValue);
+            assert_eq!(err.invalid_input(), "a=b");
+        }
+
+        #[test]
+        fn kind_of_mail_address() {
+            let err = SyntaxError::MailAddress("no-at-sign".into());
+            assert_eq!(err.kind(), SyntaxErrorKind::MailAddress);
+            assert_eq!(err.invalid_input(), "no-at-sign");
+        }
+
+        #[test]
+        fn kind_of_address_literal_tag() {
+            let err = "[X_TAG:value]".parse::<AddressLiteral>().unwrap_err();
+            assert_eq!(err.kind(), SyntaxErrorKind::AddressLiteralTag);
+            assert_eq!(err.invalid_input(), "X_TAG");
+        }
+
+        #[test]
+        fn kind_of_address_literal_value() {
+            let err = "[IPv6:not-an-ip]".parse::<AddressLiteral>().unwrap_err();
+            assert_eq!(err.kind(), SyntaxErrorKind::AddressLiteralValue);
+            assert_eq!(err.invalid_input(), "not-an-ip");
+        }
+
+        #[test]
+        fn display_is_unchanged() {
+            let err = SyntaxError::Domain("x y".into());
+            assert_eq!(err.to_string(), "syntax error parsing Domain in \"x y\"");
+        }
+    }
+
+    mod Paths {
+        #![allow(non_snake_case)]
+        use super::super::{ForwardPath, ReversePath, SyntaxErrorKind};
+
+        #[test]
+        fn reject_command_injection() {
+            for bad in &[
+                "a@b.test>\r\nRSET",
+                "a@b.test\n",
+                "a@b.test\r",
+                "a\0@b.test",
+            ] {
+                let err = ForwardPath::new(*bad).unwrap_err();
+                assert_eq!(err.kind(), SyntaxErrorKind::MailAddress);
+                assert!(ReversePath::new(*bad).is_err());
+            }
+        }
+
+        #[test]
+        fn accept_normal_paths() {
+            assert_eq!(ForwardPath::new("a@b.test").unwrap(), "a@b.test");
+            assert_eq!(ReversePath::new("").unwrap(), ReversePath::empty());
+        }
+    }
+
     mod EhloParams {
         use super::super::EhloParam;
 
@@ -491,6 +743,64 @@ mod test {
             let s: String = a.into();
             assert_eq!(s, "afFen")
         }
+
+        #[test]
+        fn from_encoded_is_always_valid() {
+            let raw = "id with spaces=1+ü";
+            assert!(EsmtpValue::new(raw).is_err());
+
+            let value = EsmtpValue::from_encoded(raw);
+            assert_eq!(value, "id+20with+20spaces+3D1+2B+C3+BC");
+            assert!(EsmtpValue::new(value.as_str()).is_ok());
+            assert_eq!(crate::xtext::decode(value.as_str()).unwrap(), raw);
+        }
+
+        #[test]
+        fn from_encoded_keeps_allowed_chars() {
+            let value = EsmtpValue::from_encoded("QQ314159");
+            assert_eq!(value, "QQ314159");
+        }
+    }
+
+    mod AddressLiteral {
+        use super::super::{AddressLiteral, SyntaxError};
+
+        #[test]
+        fn parses_ipv4_literal() {
+            let lit: AddressLiteral = "[192.0.2.1]".parse().unwrap();
+            assert_eq!(lit.as_str(), "[192.0.2.1]");
+        }
+
+        #[test]
+        fn parses_ipv6_literal() {
+            let lit: AddressLiteral = "[IPv6:2001:db8::1]".parse().unwrap();
+            assert_eq!(lit.as_str(), "[IPv6:2001:db8::1]");
+        }
+
+        #[test]
+        fn parses_general_literal() {
+            let lit: AddressLiteral = "[X-TAG:some-value]".parse().unwrap();
+            assert_eq!(lit.as_str(), "[X-TAG:some-value]");
+        }
+
+        #[test]
+        fn rejects_malformed_tag() {
+            let err = "[X_TAG:value]".parse::<AddressLiteral>().unwrap_err();
+            assert_eq!(
+                err,
+                SyntaxError::AddressLiteral {
+                    tag: "X_TAG".into(),
+                    value: "value".into(),
+                    was_bad_tag: true,
+                }
+            );
+        }
+
+        #[test]
+        fn rejects_invalid_ipv6_and_missing_brackets() {
+            assert!("[IPv6:not-an-ip]".parse::<AddressLiteral>().is_err());
+            assert!("192.0.2.1".parse::<AddressLiteral>().is_err());
+        }
     }
 
     mod Capability {
@@ -128,6 +128,56 @@ pub enum LogicError {
 
     /// command can not be used, as the server does not promotes the necessary capabilities
     MissingCapabilities(MissingCapabilities),
+
+    /// a mail was not send as it was clear beforehand that the server won't accept it
+    ///
+    /// This is produced by `send_mail` before sending any command.
+    PreflightRejected(PreflightReason),
+}
+
+/// the reason `send_mail` rejected a mail before sending it
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum PreflightReason {
+    /// the mail requires `SMTPUTF8` but the server does not support it
+    Smtputf8Unsupported,
+    /// the mail requires `8BITMIME` but the server does not support it
+    Mime8bitUnsupported,
+    /// the mail is larger than the limit the server advertised with `SIZE`
+    ///
+    /// This uses the size of the mail before dot-stuffing, so it's only an
+    /// approximation, the server might still reject a mail slightly below
+    /// the limit.
+    MessageTooLarge { limit: usize, actual: usize },
+}
+
+impl Display for PreflightReason {
+    fn fmt(&self, fter: &mut fmt::Formatter) -> fmt::Result {
+        use self::PreflightReason::*;
+        match self {
+            Smtputf8Unsupported => write!(fter, "mail requires SMTPUTF8 which is not supported"),
+            Mime8bitUnsupported => write!(fter, "mail requires 8BITMIME which is not supported"),
+            MessageTooLarge { limit, actual } => write!(
+                fter,
+                "mail has {} bytes but server only accepts up to {} bytes",
+                actual, limit
+            ),
+        }
+    }
+}
+
+impl LogicError {
+    /// true if retrying the command later might succeed
+    ///
+    /// This is the case if the server responded with a transient
+    /// failure (`4xx`) code. Permanent failures (`5xx`), missing
+    /// capabilities, preflight rejections and custom errors are not transient.
+    pub fn is_transient(&self) -> bool {
+        use self::LogicError::*;
+        match self {
+            Code(response) | UnexpectedCode(response) => response.code().is_transient_failure(),
+            Custom(_) | MissingCapabilities(_) | PreflightRejected(_) => false,
+        }
+    }
 }
 
 impl From<MissingCapabilities> for LogicError {
@@ -160,6 +210,7 @@ impl Display for LogicError {
             ),
             //FIXME print which capabilities are missing
             MissingCapabilities(_caps) => write!(fter, "server is missing required capabilities"),
+            PreflightRejected(reason) => write!(fter, "mail rejected before sending: {}", reason),
         }
     }
 }
@@ -192,9 +243,9 @@ impl MissingCapabilities {
     }
 }
 
-impl Into<Vec<Capability>> for MissingCapabilities {
-    fn into(self) -> Vec<Capability> {
-        let MissingCapabilities { capabilities } = self;
+impl From<MissingCapabilities> for Vec<Capability> {
+    fn from(err: MissingCapabilities) -> Self {
+        let MissingCapabilities { capabilities } = err;
         capabilities
     }
 }
@@ -227,3 +278,78 @@ impl Display for MissingCapabilities {
         Ok(())
     }
 }
+
+/// error representing that the connection broke while the body of `DATA` was send
+///
+/// The server already accepted `DATA` (with `354`) at that point, so unlike
+/// a failure before `DATA` the server might have seen part of the mail. It's
+/// passed on as the inner error of an `io::Error` with the same kind as
+/// the original I/O-Error, which is available through `Error::source`.
+#[derive(Debug)]
+pub struct DataBodyInterrupted {
+    cause: std_io::Error,
+}
+
+impl DataBodyInterrupted {
+    /// wraps the I/O-Error into an `io::Error` with the same kind
+    pub fn wrap(cause: std_io::Error) -> std_io::Error {
+        std_io::Error::new(cause.kind(), DataBodyInterrupted { cause })
+    }
+
+    /// the I/O-Error which interrupted the sending of the body
+    pub fn cause(&self) -> &std_io::Error {
+        &self.cause
+    }
+}
+
+impl Error for DataBodyInterrupted {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.cause)
+    }
+}
+
+impl Display for DataBodyInterrupted {
+    fn fmt(&self, fter: &mut fmt::Formatter) -> fmt::Result {
+        write!(fter, "connection lost during DATA body: {}", self.cause)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    mod LogicError {
+        #![allow(non_snake_case)]
+        use super::super::{LogicError, MissingCapabilities};
+        use crate::response::{codes, Response, ResponseCode};
+        use std::io as std_io;
+
+        fn response(code: ResponseCode) -> Response {
+            Response::new(code, vec!["msg".to_owned()])
+        }
+
+        #[test]
+        fn transient_failures_are_transient() {
+            assert!(LogicError::Code(response(codes::SERVICE_UNAVAILABLE)).is_transient());
+            assert!(LogicError::Code(response(codes::LOCAL_ERROR)).is_transient());
+            assert!(
+                LogicError::UnexpectedCode(response(codes::MAILBOX_TEMP_UNAVAILABLE))
+                    .is_transient()
+            );
+        }
+
+        #[test]
+        fn other_errors_are_not_transient() {
+            assert!(!LogicError::Code(response(codes::MAILBOX_UNAVAILABLE)).is_transient());
+            assert!(!LogicError::Code(response(codes::SYNTAX_ERROR)).is_transient());
+            assert!(!LogicError::UnexpectedCode(response(codes::OK)).is_transient());
+            assert!(
+                !LogicError::MissingCapabilities(MissingCapabilities::new_from_unchecked(
+                    "SMTPUTF8"
+                ))
+                .is_transient()
+            );
+            let custom = std_io::Error::new(std_io::ErrorKind::Other, "custom");
+            assert!(!LogicError::Custom(Box::new(custom)).is_transient());
+        }
+    }
+}
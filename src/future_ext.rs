@@ -14,11 +14,11 @@ pub trait ResultWithContextExt<Ctx, I, E>: Future<Item = (Ctx, Result<I, E>)> {
     /// given a `Future<(Ctx, Result<Item, Err>), Err2>` this:
     ///
     /// 1. resolves forwards the result of resolving the future if
-    ///     1. the future resolves to err (Err2)
-    ///     2. the inner result is a error (Err)
+    ///    1. the future resolves to err (Err2)
+    ///    2. the inner result is a error (Err)
     /// 2. calls `f(ctx, item)` if the inner result is Ok
-    ///     note that the result of f has to be convertible into an
-    ///     future of the impl `Future<(Ctx, Result<Item2, Err>), Err2>`
+    ///    note that the result of f has to be convertible into an
+    ///    future of the impl `Future<(Ctx, Result<Item2, Err>), Err2>`
     fn ctx_and_then<FN, B, I2>(self, f: FN) -> CtxAndThen<Self, B, FN>
     where
         FN: FnOnce(Ctx, I) -> B,
@@ -31,11 +31,11 @@ pub trait ResultWithContextExt<Ctx, I, E>: Future<Item = (Ctx, Result<I, E>)> {
     /// given a `Future<(Ctx, Result<Item, Err>), Err2>` this:
     ///
     /// 1. resolves forwards the result of resolving the future if
-    ///     1. the future resolves to err (Err2)
-    ///     2. the inner result is ok (Err)
+    ///    1. the future resolves to err (Err2)
+    ///    2. the inner result is ok (Err)
     /// 2. calls `f(ctx, err)` if the inner result is err
-    ///     note that the result of f has to be convertible into an
-    ///     future of the impl `Future<(Ctx, Result<Item, Err3>), Err2>`
+    ///    note that the result of f has to be convertible into an
+    ///    future of the impl `Future<(Ctx, Result<Item, Err3>), Err2>`
     fn ctx_or_else<FN, B, E2>(self, f: FN) -> CtxOrElse<Self, B, FN>
     where
         FN: FnOnce(Ctx, E) -> B,
@@ -0,0 +1,114 @@
+use std::{
+    fmt::{self, Debug},
+    io as std_io,
+    time::{Duration, Instant},
+};
+
+use futures::{Async, Future, Poll};
+use tokio::{
+    io::{shutdown, Shutdown},
+    timer::Delay,
+};
+
+use crate::{
+    io::{Io, Socket},
+    response::parser,
+};
+
+/// future returned by `Connection::quit_graceful`
+///
+/// Sends `QUIT` and waits for the response until the timeout elapsed,
+/// then the socket is shut down. Failing to send `QUIT` or to read the
+/// response is ignored, only failing to shut down the socket is an error.
+pub(crate) struct GracefulQuit {
+    /// `None` once the socket is being shut down
+    io: Option<Io>,
+    /// true once `QUIT` was written and flushed
+    flushed: bool,
+    delay: Delay,
+    shutdown: Option<Shutdown<Socket>>,
+}
+
+impl GracefulQuit {
+    pub(crate) fn new(mut io: Io, timeout: Duration) -> Self {
+        // if the server already closed the connection there is no point in sending QUIT
+        if !io.is_poisoned() {
+            io.write_line_from_parts(&["QUIT"]);
+            io.trace_output();
+        }
+        GracefulQuit {
+            io: Some(io),
+            flushed: false,
+            delay: Delay::new(Instant::now() + timeout),
+            shutdown: None,
+        }
+    }
+}
+
+/// polls the `QUIT` exchange, resolving once it's done or failed
+fn poll_quit_exchange(io: &mut Io, flushed: &mut bool) -> Poll<(), ()> {
+    if io.is_poisoned() {
+        return Ok(Async::Ready(()));
+    }
+    if !*flushed {
+        try_ready!(io.poll_flush().map_err(|_| ()));
+        *flushed = true;
+    }
+    let state = io.read_from_socket().map_err(|_| ())?;
+    loop {
+        match io.try_pop_line(parser::parse_line) {
+            Ok(Some(line)) if line.last_line => return Ok(Async::Ready(())),
+            Ok(Some(_)) => continue,
+            Ok(None) => break,
+            Err(_) => return Err(()),
+        }
+    }
+    if state.is_socket_closed() {
+        Err(())
+    } else {
+        Ok(Async::NotReady)
+    }
+}
+
+impl Future for GracefulQuit {
+    type Item = ();
+    type Error = std_io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        if let Some(shutdown) = self.shutdown.as_mut() {
+            try_ready!(shutdown.poll());
+            return Ok(Async::Ready(()));
+        }
+
+        let io = self.io.as_mut().expect("poll after completion");
+        let exchange_done = match poll_quit_exchange(io, &mut self.flushed) {
+            Ok(Async::NotReady) => false,
+            // failing to send quit is fine, the socket is shut down anyway
+            Ok(Async::Ready(())) | Err(()) => true,
+        };
+        let timed_out = match self.delay.poll() {
+            Ok(Async::NotReady) => false,
+            // a failing timer is handled like a timeout
+            Ok(Async::Ready(())) | Err(_) => true,
+        };
+        if !(exchange_done || timed_out) {
+            return Ok(Async::NotReady);
+        }
+
+        let io = self.io.take().expect("checked above");
+        #[cfg(feature = "log")]
+        log_facade::trace!("{} Shutting down connection", io.log_tag());
+        let (socket, _, _) = io.split();
+        self.shutdown = Some(shutdown(socket));
+        self.poll()
+    }
+}
+
+impl Debug for GracefulQuit {
+    fn fmt(&self, fter: &mut fmt::Formatter) -> fmt::Result {
+        fter.debug_struct("GracefulQuit")
+            .field("io", &self.io)
+            .field("is_shutting_down", &self.shutdown.is_some())
+            .finish()
+    }
+}
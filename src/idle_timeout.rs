@@ -0,0 +1,131 @@
+use std::{
+    fmt::{self, Debug},
+    io as std_io,
+    time::{Duration, Instant},
+};
+
+use futures::{
+    future::{self, Either},
+    Async, Future, Poll,
+};
+use tokio::timer::Delay;
+
+use crate::{
+    connection::{Cmd, Connection},
+    io::SmtpResult,
+};
+
+type QuitFuture = Box<dyn Future<Item = (), Error = std_io::Error> + Send + 'static>;
+
+/// a `Connection` which sends `QUIT` and shuts down if not used for some time
+///
+/// Created with `Connection::with_idle_timeout`. The timer is reset every
+/// time a command was send using `IdleTimeout::send`.
+///
+/// **The timeout is only enforced if the wrapper is polled**, i.e. it is a
+/// future resolving once the connection was closed because it was idle (or
+/// had been closed before). So it has to be driven by e.g. the connection
+/// pool holding it, a `poll` or `select` call on a `&mut IdleTimeout` will
+/// do. Like all tokio timers it needs a running tokio runtime with a timer.
+pub struct IdleTimeout {
+    con: Option<Connection>,
+    timeout: Duration,
+    delay: Delay,
+    quitting: Option<QuitFuture>,
+}
+
+impl IdleTimeout {
+    /// create a new wrapper, starting the timer
+    pub fn new(con: Connection, timeout: Duration) -> Self {
+        IdleTimeout {
+            con: Some(con),
+            timeout,
+            delay: Delay::new(Instant::now() + timeout),
+            quitting: None,
+        }
+    }
+
+    /// send a command to the smtp server, resetting the idle timer
+    ///
+    /// Works like `Connection::send`, except that it fails with an
+    /// `io::Error` of kind `NotConnected` if the connection was already
+    /// closed because it was idle for to long.
+    pub fn send<C: Cmd>(
+        self,
+        cmd: C,
+    ) -> impl Future<Item = (IdleTimeout, SmtpResult), Error = std_io::Error> {
+        let timeout = self.timeout;
+        let fut = match self.con {
+            Some(con) => Either::A(
+                con.send(cmd)
+                    .map(move |(con, result)| (IdleTimeout::new(con, timeout), result)),
+            ),
+            None => Either::B(future::err(std_io::Error::new(
+                std_io::ErrorKind::NotConnected,
+                "connection was closed because it was idle",
+            ))),
+        };
+
+        fut
+    }
+
+    /// returns true if the connection was closed (or is being closed) because it was idle
+    pub fn is_closed(&self) -> bool {
+        self.con.is_none()
+    }
+
+    /// returns the duration after which an idle connection is closed
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// returns a reference to the wrapped connection, if it is still open
+    pub fn connection(&self) -> Option<&Connection> {
+        self.con.as_ref()
+    }
+
+    /// returns the wrapped connection, if it is still open
+    ///
+    /// If the connection is currently being closed the closing
+    /// is aborted and `None` is returned.
+    pub fn into_inner(self) -> Option<Connection> {
+        self.con
+    }
+}
+
+impl Future for IdleTimeout {
+    type Item = ();
+    type Error = std_io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            if let Some(quitting) = self.quitting.as_mut() {
+                try_ready!(quitting.poll());
+                self.quitting = None;
+                return Ok(Async::Ready(()));
+            }
+
+            if self.con.is_none() {
+                return Ok(Async::Ready(()));
+            }
+
+            try_ready!(self
+                .delay
+                .poll()
+                .map_err(|err| std_io::Error::new(std_io::ErrorKind::Other, err)));
+
+            let con = self.con.take().expect("checked above");
+            self.quitting = Some(Box::new(con.quit().map(|_socket| ())));
+        }
+    }
+}
+
+impl Debug for IdleTimeout {
+    fn fmt(&self, fter: &mut fmt::Formatter) -> fmt::Result {
+        fter.debug_struct("IdleTimeout")
+            .field("con", &self.con)
+            .field("timeout", &self.timeout)
+            .field("is_quitting", &self.quitting.is_some())
+            .finish()
+    }
+}
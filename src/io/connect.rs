@@ -1,12 +1,48 @@
-use std::{io as std_io, net::SocketAddr};
+use std::{io as std_io, net::SocketAddr, time::Duration};
 
-use futures::future::{self, Either, Future, Map};
+#[cfg(feature = "native-tls")]
+use futures::future::Either;
+use futures::future::{self, Future, Map};
+#[cfg(feature = "native-tls")]
 use native_tls::TlsConnector as NativeTlsConnector;
 use tokio::net::tcp::{ConnectFuture, TcpStream};
+#[cfg(feature = "native-tls")]
 use tokio_tls::TlsConnector;
 
 use super::Io;
-use crate::common::{map_tls_err, SetupTls, TlsConfig};
+#[cfg(feature = "native-tls")]
+use crate::common::map_tls_err;
+use crate::common::{SetupTls, TlsConfig};
+
+/// options applied to the `TcpStream` after connecting, before any smtp is done
+///
+/// The default matches the OS defaults, i.e. `TCP_NODELAY` is not set and
+/// the keepalive settings are not changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct TcpOptions {
+    /// if `TCP_NODELAY` is set (true) or cleared (false), true disables Nagle's algorithm
+    pub nodelay: bool,
+    /// if set `SO_KEEPALIVE` is enabled with the given keepalive time
+    pub keepalive: Option<Duration>,
+}
+
+impl TcpOptions {
+    /// applies the options to the stream
+    pub fn apply(&self, stream: &TcpStream) -> Result<(), std_io::Error> {
+        stream.set_nodelay(self.nodelay)?;
+        if self.keepalive.is_some() {
+            stream.set_keepalive(self.keepalive)?;
+        }
+        Ok(())
+    }
+}
+
+fn connect_tcp(
+    addr: &SocketAddr,
+    options: TcpOptions,
+) -> impl Future<Item = TcpStream, Error = std_io::Error> + Send {
+    TcpStream::connect(addr).and_then(move |stream| options.apply(&stream).map(|()| stream))
+}
 
 impl Io {
     /// create a new Tcp only connection to the given address
@@ -16,11 +52,32 @@ impl Io {
         fut
     }
 
+    /// create a new Tcp only connection to the given address, applying the given tcp options
+    pub fn connect_insecure_with(
+        addr: &SocketAddr,
+        options: TcpOptions,
+    ) -> impl Future<Item = Io, Error = std_io::Error> + Send {
+        connect_tcp(addr, options).map(Io::from)
+    }
+
     /// create a new Tcp-Tls connection to the given address using the given tls config
     pub fn connect_secure<S>(
         addr: &SocketAddr,
         config: TlsConfig<S>,
     ) -> impl Future<Item = Io, Error = std_io::Error> + Send
+    where
+        S: SetupTls,
+    {
+        Io::connect_secure_with(addr, config, TcpOptions::default())
+    }
+
+    /// create a new Tcp-Tls connection like `connect_secure`, applying the given tcp options
+    #[cfg(feature = "native-tls")]
+    pub fn connect_secure_with<S>(
+        addr: &SocketAddr,
+        config: TlsConfig<S>,
+        options: TcpOptions,
+    ) -> impl Future<Item = Io, Error = std_io::Error> + Send
     where
         S: SetupTls,
     {
@@ -33,7 +90,7 @@ impl Io {
             |err| Either::B(future::err(map_tls_err(err)))
         );
 
-        let fut = TcpStream::connect(&addr)
+        let fut = connect_tcp(addr, options)
             .and_then(move |stream| {
                 connector
                     .connect(domain.as_str(), stream)
@@ -43,4 +100,59 @@ impl Io {
 
         Either::A(fut)
     }
+
+    /// create a new Tcp-Tls connection like `connect_secure`, applying the given tcp options
+    ///
+    /// Always fails as the `native-tls` feature is disabled.
+    #[cfg(not(feature = "native-tls"))]
+    pub fn connect_secure_with<S>(
+        _addr: &SocketAddr,
+        _config: TlsConfig<S>,
+        _options: TcpOptions,
+    ) -> impl Future<Item = Io, Error = std_io::Error> + Send
+    where
+        S: SetupTls,
+    {
+        future::err(crate::common::tls_disabled_error())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{net::TcpListener, time::Duration};
+
+    use futures::Future;
+    use tokio::net::TcpStream;
+
+    use super::TcpOptions;
+    use crate::io::{Io, Socket};
+
+    fn connect_with(options: TcpOptions) -> TcpStream {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let io = Io::connect_insecure_with(&addr, options).wait().unwrap();
+        let (socket, _, _) = io.split();
+        #[allow(unreachable_patterns)]
+        match socket {
+            Socket::Insecure(stream) => stream,
+            _ => panic!("expected a tcp socket"),
+        }
+    }
+
+    #[test]
+    fn default_options_keep_os_defaults() {
+        let stream = connect_with(TcpOptions::default());
+        assert!(!stream.nodelay().unwrap());
+        assert_eq!(stream.keepalive().unwrap(), None);
+    }
+
+    #[test]
+    fn options_are_applied_to_the_stream() {
+        let stream = connect_with(TcpOptions {
+            nodelay: true,
+            keepalive: Some(Duration::from_secs(60)),
+        });
+        assert!(stream.nodelay().unwrap());
+        assert!(stream.keepalive().unwrap().is_some());
+    }
 }
@@ -19,8 +19,42 @@ impl Io {
         S::Item: Buf,
     {
         #[cfg(feature = "log")]
-        log_facade::trace!("C: <mail body redacted>");
-        DotStashedWrite::new(self, source)
+        log_facade::trace!("{} C: <mail body redacted>", self.log_tag());
+        DotStashedWrite::new(self, source, false)
+    }
+
+    /// write all data from source to the output socket without dot-stashing it
+    ///
+    /// Like `write_dot_stashed` this includes the end of message sequence
+    /// "\r\n.\r\n", but the data is written verbatim, without scanning it
+    /// for lines starting with `'.'`. This is only a valid thing to do if
+    /// the source is _already_ dot-stashed (e.g. a stored canonical message).
+    ///
+    /// **Be careful**: any not dot-stashed `'.'` at the beginning of a line
+    /// will be seen as (part of) the end of the mail data by the server,
+    /// terminating the message prematurely and making the server interpret
+    /// the rest of the mail as smtp commands.
+    pub fn write_raw<S>(self, source: S) -> DotStashedWrite<S>
+    where
+        S: Stream<Error = std_io::Error>,
+        S::Item: Buf,
+    {
+        #[cfg(feature = "log")]
+        log_facade::trace!("{} C: <mail body redacted>", self.log_tag());
+        DotStashedWrite::new(self, source, true)
+    }
+
+    /// dot-stashes `data` directly into the output buffer, including the end of message sequence
+    ///
+    /// This is the single buffer version of `write_dot_stashed`, the data is
+    /// stashed in one pass without polling a stream. Nothing is flushed, but
+    /// like with `write_dot_stashed` `last_data_bytes` is set.
+    pub fn put_dot_stashed(&mut self, data: &[u8]) {
+        #[cfg(feature = "log")]
+        log_facade::trace!("{} C: <mail body redacted>", self.log_tag());
+        let (state, body_len) = stash_into(self, std_io::Cursor::new(data), CrLf::None, false);
+        let eom_len = put_eom_seq(self, state, false);
+        self.set_last_data_bytes(body_len + eom_len);
     }
 }
 
@@ -41,6 +75,12 @@ where
     stash_state: CrLf,
     /// end of mail sequence i.e. "\r\n.\r\n"
     write_eom_seq: bool,
+    /// if true the source is written verbatim
+    pre_stuffed: bool,
+    /// if true orphan '\r'/'\n' are turned into "\r\n"
+    normalize_line_endings: bool,
+    /// number of bytes written to the output buffer (after dot-stashing)
+    written: usize,
 }
 
 impl<S> DotStashedWrite<S>
@@ -48,15 +88,29 @@ where
     S: Stream<Error = std_io::Error>,
     S::Item: Buf,
 {
-    fn new(io: Io, source: S) -> Self {
+    fn new(io: Io, source: S, pre_stuffed: bool) -> Self {
         DotStashedWrite {
             source,
             io: Some(io),
             stash_state: CrLf::None,
             write_eom_seq: false,
+            pre_stuffed,
+            normalize_line_endings: false,
+            written: 0,
         }
     }
 
+    /// makes the write turn any orphan `'\r'`/`'\n'` into `"\r\n"`
+    ///
+    /// Smtp requires all lines to end with `"\r\n"` but many mail generators
+    /// use unix line endings, which can confuse both the dot-stashing and the
+    /// server. This has no effect on writes created with `Io::write_raw` as
+    /// they don't scan the data at all.
+    pub fn with_line_ending_normalization(mut self) -> Self {
+        self.normalize_line_endings = true;
+        self
+    }
+
     fn io_mut(&mut self) -> &mut Io {
         self.io.as_mut().expect("poll after completion")
     }
@@ -66,52 +120,96 @@ where
 
         if next.is_none() {
             self.write_eom_seq = true;
-            let add_newline = self.stash_state != CrLf::HitLf;
-            let need = 3 + if add_newline { 2 } else { 0 };
-            let out = self.io_mut().out_buffer(need);
-            if add_newline {
-                out.put("\r\n");
-            }
-            out.put(".\r\n");
+            let state = self.stash_state;
+            let normalize = self.normalize_line_endings;
+            self.written += put_eom_seq(self.io_mut(), state, normalize);
         }
 
         Ok(Async::Ready(next))
     }
 
     fn write_dot_stashed_output(&mut self, unstashed: S::Item) {
-        let mut state = self.stash_state;
-        {
-            let raw_len = unstashed.remaining();
-            let out = self.io_mut().out_buffer(raw_len);
-            let mut over_capacity = out.remaining_mut() - raw_len;
-            for bch in unstashed.iter() {
-                let (stash, new_state) = match (bch, state) {
-                    (b'\r', CrLf::None) => (false, CrLf::HitCr),
-                    (b'\n', CrLf::HitCr) => (false, CrLf::HitLf),
-                    (b'.', CrLf::HitLf) => (true, CrLf::None),
-                    (_, CrLf::None) => (false, CrLf::None),
-                    // this _could_ be invalid data but legacy systems _should_
-                    // be able to handle orphan '\r'/'\n' so treat it as ok
-                    (_, _) => (false, CrLf::None),
-                };
-                state = new_state;
-                if stash {
-                    if over_capacity == 0 {
-                        //increase buffer capacity
-                        let rem = out.remaining_mut();
-                        out.reserve(rem + OUTPUT_BUFFER_INC_SIZE);
-                        over_capacity += OUTPUT_BUFFER_INC_SIZE;
-                    }
-                    over_capacity -= 1;
-                    out.put_u8(b'.');
-                }
-                out.put_u8(bch);
-            }
+        let state = self.stash_state;
+        let normalize = self.normalize_line_endings;
+        let (state, written) = stash_into(self.io_mut(), unstashed, state, normalize);
+        self.stash_state = state;
+        self.written += written;
+    }
+
+    fn write_pre_stuffed_output(&mut self, stuffed: S::Item) {
+        let raw_len = stuffed.remaining();
+        if raw_len == 0 {
+            return;
         }
+        let prev_state = self.stash_state;
+        self.written += raw_len;
+        let out = self.io_mut().out_buffer(raw_len);
+        out.put(stuffed);
+        // only whether or not the data ends with "\r\n" matters
+        let state = match (&out[out.len() - raw_len.min(2)..], prev_state) {
+            (b"\r\n", _) | (b"\n", CrLf::HitCr) => CrLf::HitLf,
+            ([.., b'\r'], _) => CrLf::HitCr,
+            (_, _) => CrLf::None,
+        };
         self.stash_state = state;
     }
 }
 
+/// dot-stashes `unstashed` into the output buffer, returns the new state and the bytes written
+fn stash_into<B: Buf>(
+    io: &mut Io,
+    unstashed: B,
+    mut state: CrLf,
+    normalize: bool,
+) -> (CrLf, usize) {
+    let raw_len = unstashed.remaining();
+    let out = io.out_buffer(raw_len);
+    let len_before = out.len();
+    let mut over_capacity = out.remaining_mut() - raw_len;
+    for bch in unstashed.iter() {
+        // `prefix` is written before `bch`
+        let (prefix, new_state): (&[u8], _) = match (bch, state) {
+            (b'\r', CrLf::HitCr) if normalize => (b"\n", CrLf::HitCr),
+            (b'\r', _) => (b"", CrLf::HitCr),
+            (b'\n', CrLf::HitCr) => (b"", CrLf::HitLf),
+            (b'\n', _) if normalize => (b"\r", CrLf::HitLf),
+            (b'.', CrLf::HitLf) => (b".", CrLf::None),
+            (b'.', CrLf::HitCr) if normalize => (b"\n.", CrLf::None),
+            (_, CrLf::HitCr) if normalize => (b"\n", CrLf::None),
+            // if not normalized this _could_ be invalid data but legacy
+            // systems _should_ be able to handle orphan '\r'/'\n' so
+            // treat it as ok
+            (_, _) => (b"", CrLf::None),
+        };
+        state = new_state;
+        if !prefix.is_empty() {
+            while over_capacity < prefix.len() {
+                //increase buffer capacity
+                let rem = out.remaining_mut();
+                out.reserve(rem + OUTPUT_BUFFER_INC_SIZE);
+                over_capacity += OUTPUT_BUFFER_INC_SIZE;
+            }
+            over_capacity -= prefix.len();
+            out.put_slice(prefix);
+        }
+        out.put_u8(bch);
+    }
+    (state, out.len() - len_before)
+}
+
+/// writes the end of message sequence, adding a "\r\n" before it if needed
+fn put_eom_seq(io: &mut Io, state: CrLf, normalize: bool) -> usize {
+    let newline: &[u8] = match state {
+        CrLf::HitLf => b"",
+        CrLf::HitCr if normalize => b"\n",
+        _ => b"\r\n",
+    };
+    let out = io.out_buffer(3 + newline.len());
+    out.put_slice(newline);
+    out.put(".\r\n");
+    newline.len() + 3
+}
+
 impl<S> Future for DotStashedWrite<S>
 where
     S: Stream<Error = std_io::Error>,
@@ -121,6 +219,10 @@ where
     type Error = std_io::Error;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        if self.io.is_none() {
+            return Err(super::poll_after_completion_error());
+        }
+
         loop {
             //TODO the think below is needed so to handle put wrt. buffer capacity (it panics
             // if it runs out of capacity)
@@ -130,7 +232,9 @@ where
             try_ready!(self.io_mut().poll_flush());
 
             if self.write_eom_seq {
-                return Ok(Async::Ready(self.io.take().expect("poll after completion")));
+                let mut io = self.io.take().expect("poll after completion");
+                io.set_last_data_bytes(self.written);
+                return Ok(Async::Ready(io));
             }
 
             let pending = match try_ready!(self.poll_source()) {
@@ -138,7 +242,76 @@ where
                 None => continue,
             };
 
-            self.write_dot_stashed_output(pending);
+            if self.pre_stuffed {
+                self.write_pre_stuffed_output(pending);
+            } else {
+                self.write_dot_stashed_output(pending);
+            }
         }
     }
 }
+
+#[cfg(all(test, feature = "mock-impl"))]
+mod test {
+    use std::io::Cursor;
+
+    use futures::{stream, Future};
+
+    use crate::{
+        io::Io,
+        mock::{ActionData::Blob, Actor::Client, MockSocket},
+    };
+
+    fn assert_dot_stashed_as(body: &'static [u8], expected: &'static [u8]) {
+        let io: Io =
+            MockSocket::new_no_check_shutdown(vec![(Client, Blob(expected.to_vec()))]).into();
+        let io = io
+            .write_dot_stashed(stream::once(Ok(Cursor::new(body))))
+            .wait()
+            .unwrap();
+        assert_eq!(io.last_data_bytes(), Some(expected.len()));
+
+        // the single buffer version has to write exactly the same bytes
+        let mut io: Io = MockSocket::new_no_check_shutdown(vec![]).into();
+        io.put_dot_stashed(body);
+        assert_eq!(&io.out_buffer(0)[..], expected);
+        assert_eq!(io.last_data_bytes(), Some(expected.len()));
+    }
+
+    #[test]
+    fn empty_body() {
+        assert_dot_stashed_as(b"", b"\r\n.\r\n");
+    }
+
+    #[test]
+    fn empty_source() {
+        let io: Io =
+            MockSocket::new_no_check_shutdown(vec![(Client, Blob(b"\r\n.\r\n".to_vec()))]).into();
+        let io = io
+            .write_dot_stashed(stream::empty::<Cursor<&[u8]>, _>())
+            .wait()
+            .unwrap();
+        assert_eq!(io.last_data_bytes(), Some(5));
+    }
+
+    #[test]
+    fn body_ending_in_crlf() {
+        assert_dot_stashed_as(b"Subject: x\r\n\r\n", b"Subject: x\r\n\r\n.\r\n");
+    }
+
+    #[test]
+    fn body_not_ending_in_crlf() {
+        assert_dot_stashed_as(
+            b"Subject: x\r\n\r\nbody",
+            b"Subject: x\r\n\r\nbody\r\n.\r\n",
+        );
+    }
+
+    #[test]
+    fn stashes_dots_at_line_start() {
+        assert_dot_stashed_as(
+            b"first\r\nmid.dle\r\n.\r\n..two\r\n",
+            b"first\r\nmid.dle\r\n..\r\n...two\r\n.\r\n",
+        );
+    }
+}
@@ -3,7 +3,7 @@ use std::io as std_io;
 use futures::{Async, Future, Poll};
 use tokio::io::AsyncWrite;
 
-use super::Io;
+use super::{trace, Direction, Io};
 
 impl Io {
     /// return a futures resolving back to this instance once all output data is flushed
@@ -17,6 +17,31 @@ impl Io {
         self.flush()
     }
 
+    /// logs/traces all lines in the output buffer, called before flushing them
+    pub(crate) fn trace_output(&self) {
+        #[cfg(feature = "log")]
+        {
+            use log_facade::*; // This is needed due to something which is probably a rustc bug.
+            if log_enabled!(Level::Trace) {
+                let tag = self.log_tag();
+                trace::for_each_line(&self.buffer.output, |line| {
+                    if let Some(end) = trace::redaction_point(line) {
+                        let line = String::from_utf8_lossy(&line[..end]);
+                        log_facade::trace!("{} C: {:?} <redacted>", tag, line);
+                    } else {
+                        log_facade::trace!("{} C: {:?}", tag, String::from_utf8_lossy(line));
+                    }
+                });
+            }
+        }
+
+        if let Some(hook) = self.trace_hook() {
+            trace::for_each_line(&self.buffer.output, |line| {
+                trace::call_hook(hook, Direction::Sent, line)
+            });
+        }
+    }
+
     /// writes data from the output buffer to the socket and polls flush
     ///
     /// This first poll the writing of data from output to socket until
@@ -27,8 +52,14 @@ impl Io {
         while !output.is_empty() {
             let n = try_ready!(socket.poll_write(output));
 
-            // as long as output is not empty a it should never write 0 bytes
-            assert!(n > 0);
+            // as long as output is not empty writing 0 bytes means the socket
+            // can't accept any more data, e.g. because it was shut down
+            if n == 0 {
+                return Err(std_io::Error::new(
+                    std_io::ErrorKind::WriteZero,
+                    "socket accepted zero bytes while flushing output",
+                ));
+            }
 
             // remove the bytes written from the buffer
             output.advance(n);
@@ -40,31 +71,14 @@ impl Io {
     }
 }
 
+/// future returned by `Io::flush`, resolves to the `Io` once all output was flushed
 pub struct Flushing {
     inner: Option<Io>,
 }
 
 impl Flushing {
     pub(crate) fn new(inner: Io) -> Self {
-        #[cfg(feature = "log")]
-        {
-            use log_facade::*; // This is needed due to something which is probably a rustc bug.
-            if log_enabled!(Level::Trace) {
-                let out = &inner.buffer.output[..];
-                let out = String::from_utf8_lossy(out);
-                for line in out.lines() {
-                    if line.starts_with("AUTH") {
-                        let additional_chars_for_auth_subcommand =
-                            line[5..].bytes().position(|ch| ch == b' ').unwrap_or(0);
-                        let end = 5 + additional_chars_for_auth_subcommand;
-                        log_facade::trace!("C: {:?} <redacted>", &line[..end]);
-                    } else {
-                        log_facade::trace!("C: {:?}", line);
-                    }
-                }
-            }
-        }
-
+        inner.trace_output();
         Flushing { inner: Some(inner) }
     }
 }
@@ -74,12 +88,12 @@ impl Future for Flushing {
     type Error = std_io::Error;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        try_ready!({
-            let io = self.inner.as_mut().expect("poll after completion");
-            io.poll_flush()
+        try_ready!(match self.inner.as_mut() {
+            Some(io) => io.poll_flush(),
+            None => return Err(super::poll_after_completion_error()),
         });
 
-        let io = self.inner.take().unwrap();
+        let io = self.inner.take().expect("checked above");
         Ok(Async::Ready(io))
     }
 }
@@ -1,12 +1,27 @@
 //! This modules contains all the `Io` type related parts (for implementing `Cmd`)
 //!
+use std::{
+    fmt::{self, Debug},
+    io as std_io,
+    net::SocketAddr,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
 use bytes::{buf::BufMut, BytesMut};
 use futures::Future;
 use tokio::net::TcpStream;
+#[cfg(feature = "native-tls")]
 use tokio_tls::TlsStream;
 
 use super::ExecFuture;
-use crate::{common::EhloData, error::LogicError, response::Response};
+use crate::{
+    command::auth::AuthIdentity,
+    common::{ClientId, EhloData, SetupWarning},
+    connect::SyntaxErrorHandling,
+    error::LogicError,
+    response::{parser::ResponseLimits, Response},
+};
 
 mod socket;
 pub use self::socket::*;
@@ -21,7 +36,15 @@ mod dot_stashing;
 pub use self::dot_stashing::*;
 
 mod connect;
-pub use self::connect::*;
+pub use self::connect::TcpOptions;
+
+mod trace;
+#[cfg(feature = "log")]
+pub(crate) use self::trace::LogTag;
+pub use self::trace::{Direction, TraceHook};
+
+mod pipeline;
+pub use self::pipeline::PipelineGuard;
 
 pub const CR_LF: &str = "\r\n";
 
@@ -29,16 +52,140 @@ pub const CR_LF: &str = "\r\n";
 const INPUT_BUFFER_INC_SIZE: usize = 256;
 // most commands should fit in 1024 bytes (except e.g. DATA/BDAT)
 const OUTPUT_BUFFER_INC_SIZE: usize = 1024;
+// the capacity above which an empty output buffer is shrunk (by default)
+const DEFAULT_OUTPUT_SHRINK_THRESHOLD: usize = 64 * 1024;
+
+static NEXT_IO_ID: AtomicU64 = AtomicU64::new(0);
+
+/// returns a new (process wide) unique id for an `Io` instance
+fn next_io_id() -> u64 {
+    NEXT_IO_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// the error the futures of this module fail with if polled after completion
+fn poll_after_completion_error() -> std_io::Error {
+    std_io::Error::new(std_io::ErrorKind::Other, "future polled after completion")
+}
 
 /// smtp result, either a `Response` or a `LogicError` potentially wrapping a `Response`
 pub type SmtpResult = Result<Response, LogicError>;
 
 /// A `Io` object representing a smtp connection with buffers, socket and ehlo data
-#[derive(Debug)]
 pub struct Io {
     socket: Socket,
     buffer: Buffers,
+    /// the capacity the output buffer was grown to
+    ///
+    /// (`BytesMut::capacity` doesn't include the space of already flushed bytes)
+    output_capacity: usize,
+    state: IoState,
+    peer_addr: Option<SocketAddr>,
+}
+
+impl Debug for Io {
+    fn fmt(&self, fter: &mut fmt::Formatter) -> fmt::Result {
+        fter.debug_struct("Io")
+            .field("socket", &self.socket)
+            .field("buffer", &self.buffer)
+            .field("output_capacity", &self.output_capacity)
+            .field("state", &self.state)
+            .field("peer_addr", &self.peer_addr)
+            .finish()
+    }
+}
+
+/// the settings and state of a connection, i.e. all parts of an `Io` except socket and buffers
+///
+/// It's moved as a unit whenever the socket of a connection is replaced
+/// (`Io::take_socket`, `STARTTLS`) and is returned as `ConnectionMeta` by
+/// `Connection::into_parts`. It can be cloned, e.g. to store it alongside
+/// the socket in a connection pool. Use the accessors of `Io` to inspect it.
+#[derive(Clone)]
+pub struct IoState {
     ehlo_data: Option<EhloData>,
+    client_id: Option<ClientId>,
+    syntax_error_handling: SyntaxErrorHandling,
+    trace_hook: Option<TraceHook>,
+    last_data_bytes: Option<usize>,
+    poisoned: bool,
+    response_limits: ResponseLimits,
+    retain_raw_responses: bool,
+    output_shrink_threshold: Option<usize>,
+    read_idle_timeout: Option<Duration>,
+    greeting: Option<Response>,
+    setup_warnings: Vec<SetupWarning>,
+    in_transaction: bool,
+    accepted_recipients: usize,
+    lmtp: bool,
+    authenticated: bool,
+    authenticated_as: Option<AuthIdentity>,
+    id: u64,
+}
+
+impl IoState {
+    /// the state of a new connection, with a new unique id
+    fn new(ehlo_data: Option<EhloData>) -> Self {
+        IoState {
+            ehlo_data,
+            client_id: None,
+            syntax_error_handling: Default::default(),
+            trace_hook: None,
+            last_data_bytes: None,
+            poisoned: false,
+            response_limits: Default::default(),
+            retain_raw_responses: false,
+            output_shrink_threshold: Some(DEFAULT_OUTPUT_SHRINK_THRESHOLD),
+            read_idle_timeout: None,
+            greeting: None,
+            setup_warnings: Vec::new(),
+            in_transaction: false,
+            accepted_recipients: 0,
+            lmtp: false,
+            authenticated: false,
+            authenticated_as: None,
+            id: next_io_id(),
+        }
+    }
+
+    /// resets the smtp session state, keeping settings and connection setup information
+    ///
+    /// This is what starting TLS does, the ehlo data, transaction and auth
+    /// state are reset while e.g. the trace hook, greeting and id are kept.
+    #[cfg(feature = "native-tls")]
+    pub(crate) fn reset_session(&mut self) {
+        self.ehlo_data = None;
+        self.last_data_bytes = None;
+        self.poisoned = false;
+        self.in_transaction = false;
+        self.accepted_recipients = 0;
+        self.authenticated = false;
+        self.authenticated_as = None;
+    }
+}
+
+impl Debug for IoState {
+    fn fmt(&self, fter: &mut fmt::Formatter) -> fmt::Result {
+        fter.debug_struct("IoState")
+            .field("ehlo_data", &self.ehlo_data)
+            .field("client_id", &self.client_id)
+            .field("syntax_error_handling", &self.syntax_error_handling)
+            .field("has_trace_hook", &self.trace_hook.is_some())
+            .field("last_data_bytes", &self.last_data_bytes)
+            .field("poisoned", &self.poisoned)
+            .field("response_limits", &self.response_limits)
+            .field("retain_raw_responses", &self.retain_raw_responses)
+            .field("output_shrink_threshold", &self.output_shrink_threshold)
+            .field("read_idle_timeout", &self.read_idle_timeout)
+            .field("greeting", &self.greeting)
+            .field("setup_warnings", &self.setup_warnings)
+            .field("in_transaction", &self.in_transaction)
+            .field("accepted_recipients", &self.accepted_recipients)
+            .field("lmtp", &self.lmtp)
+            .field("authenticated", &self.authenticated)
+            .field("authenticated_as", &self.authenticated_as)
+            .field("id", &self.id)
+            .finish()
+    }
 }
 
 impl Io {
@@ -53,9 +200,82 @@ impl Io {
         let Io {
             socket,
             buffer,
-            ehlo_data,
+            state,
+            ..
+        } = self;
+        (socket, buffer, state.ehlo_data)
+    }
+
+    /// true if both the input and output buffer are empty
+    pub fn buffers_are_empty(&self) -> bool {
+        self.buffer.input.is_empty() && self.buffer.output.is_empty()
+    }
+
+    /// split this instance into the socket and all other parts
+    ///
+    /// Unlike `split` this keeps all state (e.g. the trace hook), so
+    /// that `Io::restore_socket` can re-create an equivalent instance.
+    pub fn take_socket(self) -> (Socket, ReassembleToken) {
+        let Io {
+            socket,
+            buffer,
+            output_capacity,
+            state,
+            peer_addr: _,
         } = self;
-        (socket, buffer, ehlo_data)
+
+        let token = ReassembleToken {
+            buffer,
+            output_capacity,
+            state,
+        };
+
+        (socket, token)
+    }
+
+    /// re-creates a `Io` instance from a socket and all other parts
+    pub fn restore_socket(token: ReassembleToken, socket: Socket) -> Self {
+        let ReassembleToken {
+            buffer,
+            output_capacity,
+            state,
+        } = token;
+
+        let peer_addr = socket.peer_addr();
+        Io {
+            socket,
+            buffer,
+            output_capacity,
+            state,
+            peer_addr,
+        }
+    }
+
+    /// creates a `Io` instance with new buffers from a socket and the state of a connection
+    pub fn from_socket_and_state(socket: Socket, state: IoState) -> Self {
+        let peer_addr = socket.peer_addr();
+        Io {
+            socket,
+            buffer: Buffers::new(),
+            output_capacity: 0,
+            state,
+            peer_addr,
+        }
+    }
+
+    /// splits this instance into the socket and the state of the connection, dropping the buffers
+    pub fn into_socket_and_state(self) -> (Socket, IoState) {
+        (self.socket, self.state)
+    }
+
+    /// returns the settings and state of the connection, see `IoState`
+    pub fn state(&self) -> &IoState {
+        &self.state
+    }
+
+    /// replaces the settings and state of the connection, returning the previous one
+    pub fn replace_state(&mut self, state: IoState) -> IoState {
+        std::mem::replace(&mut self.state, state)
     }
 
     /// writes all strings in `parts` to the output buffer followed by `"\r\n"`
@@ -90,6 +310,7 @@ impl Io {
     pub fn out_buffer(&mut self, need_rem: usize) -> &mut BytesMut {
         let buf = &mut self.buffer.output;
         reverse_buffer_cap(buf, need_rem, OUTPUT_BUFFER_INC_SIZE);
+        self.output_capacity = self.output_capacity.max(buf.capacity());
         buf
     }
 
@@ -100,12 +321,277 @@ impl Io {
 
     /// access the stored ehlo data
     pub fn ehlo_data(&self) -> Option<&EhloData> {
-        self.ehlo_data.as_ref()
+        self.state.ehlo_data.as_ref()
     }
 
     /// store different helo data
     pub fn set_ehlo_data(&mut self, data: EhloData) {
-        self.ehlo_data = Some(data);
+        self.state.ehlo_data = Some(data);
+    }
+
+    /// returns the identity used with the last successful `EHLO`, if known
+    ///
+    /// This is set by `command::Ehlo`, so it's e.g. the `ClientId` of the
+    /// `ConnectionConfig` (or the one given to `Connection::with_identity`).
+    pub fn client_id(&self) -> Option<&ClientId> {
+        self.state.client_id.as_ref()
+    }
+
+    /// set the identity used for `EHLO`, see `client_id`
+    pub fn set_client_id(&mut self, client_id: ClientId) {
+        self.state.client_id = Some(client_id);
+    }
+
+    /// returns how strict syntax errors in responses are handled
+    pub fn syntax_error_handling(&self) -> &SyntaxErrorHandling {
+        &self.state.syntax_error_handling
+    }
+
+    /// set how strict syntax errors in responses are handled
+    ///
+    /// This is used by e.g. `parse_response` to decide if a multi line
+    /// response with mismatching response codes is an error.
+    pub fn set_syntax_error_handling(&mut self, method: SyntaxErrorHandling) {
+        self.state.syntax_error_handling = method;
+    }
+
+    /// returns the limits for the size of responses
+    pub fn response_limits(&self) -> &ResponseLimits {
+        &self.state.response_limits
+    }
+
+    /// set the limits for the size of responses
+    ///
+    /// Responses exceeding them make `parse_response` fail with
+    /// an `io::Error` of kind `InvalidData`.
+    pub fn set_response_limits(&mut self, limits: ResponseLimits) {
+        self.state.response_limits = limits;
+    }
+
+    /// true if the raw bytes of responses are kept, see `set_retain_raw_responses`
+    pub fn retain_raw_responses(&self) -> bool {
+        self.state.retain_raw_responses
+    }
+
+    /// set if `parse_response` keeps the raw bytes of responses (default: false)
+    ///
+    /// If enabled the bytes of all lines of a response (including the
+    /// codes, separators and `"\r\n"`s) are available through
+    /// `Response::raw`, e.g. for auditing. This is disabled by default
+    /// to avoid copying each response.
+    pub fn set_retain_raw_responses(&mut self, retain: bool) {
+        self.state.retain_raw_responses = retain;
+    }
+
+    /// returns the capacity above which the output buffer is shrunk, see `set_output_shrink_threshold`
+    pub fn output_shrink_threshold(&self) -> Option<usize> {
+        self.state.output_shrink_threshold
+    }
+
+    /// set the capacity above which the output buffer is shrunk (default: 64KiB)
+    ///
+    /// Writing e.g. a large mail body grows the output buffer, without
+    /// shrinking it the capacity would be kept for the lifetime of the
+    /// connection. If the capacity of the (then empty) output buffer is
+    /// above the threshold once a response was parsed it's reset to a
+    /// small default capacity. `None` disables the shrinking.
+    pub fn set_output_shrink_threshold(&mut self, threshold: Option<usize>) {
+        self.state.output_shrink_threshold = threshold;
+    }
+
+    /// returns the read idle timeout, see `set_read_idle_timeout`
+    pub fn read_idle_timeout(&self) -> Option<Duration> {
+        self.state.read_idle_timeout
+    }
+
+    /// set how long `parse_response` waits for more bytes of a started response (default: none)
+    ///
+    /// If a response was partially received but no further bytes arrive
+    /// within the given duration parsing fails with an `io::Error` of kind
+    /// `TimedOut`. Unlike a timeout for a whole command (e.g. `WithTimeout`)
+    /// this catches servers which stall in the middle of a response while
+    /// not limiting how long a server can take to start responding.
+    ///
+    /// (A timeout requires a tokio runtime with a timer.)
+    pub fn set_read_idle_timeout(&mut self, timeout: Option<Duration>) {
+        self.state.read_idle_timeout = timeout;
+    }
+
+    /// shrinks the output buffer if it is empty and above `output_shrink_threshold`
+    pub(crate) fn shrink_output_buffer(&mut self) {
+        let output = &mut self.buffer.output;
+        let capacity = self.output_capacity.max(output.capacity());
+        let too_large = self
+            .state
+            .output_shrink_threshold
+            .map(|threshold| capacity > threshold)
+            .unwrap_or(false);
+        if too_large && output.is_empty() {
+            *output = BytesMut::with_capacity(OUTPUT_BUFFER_INC_SIZE);
+            self.output_capacity = output.capacity();
+        }
+    }
+
+    /// returns the hook called for every line send/received, if there is one
+    pub fn trace_hook(&self) -> Option<&TraceHook> {
+        self.state.trace_hook.as_ref()
+    }
+
+    /// set a hook which is called for every line send/received
+    ///
+    /// See `TraceHook` for more details.
+    pub fn set_trace_hook(&mut self, hook: TraceHook) {
+        self.state.trace_hook = Some(hook);
+    }
+
+    /// removes the trace hook returning it, if there was one
+    pub fn take_trace_hook(&mut self) -> Option<TraceHook> {
+        self.state.trace_hook.take()
+    }
+
+    /// returns the number of bytes written by the last dot-stashed write
+    ///
+    /// This is the size of the mail data _after_ dot-stashing, including
+    /// the end of mail sequence. It's `None` if no mail data was written
+    /// through this `Io` instance.
+    pub fn last_data_bytes(&self) -> Option<usize> {
+        self.state.last_data_bytes
+    }
+
+    /// set the number of bytes written by the last dot-stashed write
+    pub fn set_last_data_bytes(&mut self, bytes: usize) {
+        self.state.last_data_bytes = Some(bytes);
+    }
+
+    /// returns the greeting the server send when the connection was opened
+    ///
+    /// This is only set if the connection was setup through `Connection::connect`
+    /// or `Connection::from_stream`.
+    pub fn greeting(&self) -> Option<&Response> {
+        self.state.greeting.as_ref()
+    }
+
+    /// set the greeting the server send when the connection was opened
+    pub fn set_greeting(&mut self, greeting: Response) {
+        self.state.greeting = Some(greeting);
+    }
+
+    /// returns the warnings noticed while setting up the connection, see `SetupWarning`
+    pub fn setup_warnings(&self) -> &[SetupWarning] {
+        &self.state.setup_warnings
+    }
+
+    /// adds a warning noticed while setting up the connection
+    pub fn add_setup_warning(&mut self, warning: SetupWarning) {
+        self.state.setup_warnings.push(warning);
+    }
+
+    /// true if a mail transaction was started (with `MAIL`) but not yet completed
+    ///
+    /// A transaction is completed by the response to the mail data or by
+    /// `RSET`/`EHLO`. This is tracked by the commands provided by this crate.
+    pub fn is_in_transaction(&self) -> bool {
+        self.state.in_transaction
+    }
+
+    /// set if a mail transaction is in progress, see `is_in_transaction`
+    ///
+    /// As starting or completing a transaction starts over with no
+    /// recipients this also resets `accepted_recipients`.
+    pub fn set_in_transaction(&mut self, in_transaction: bool) {
+        self.state.in_transaction = in_transaction;
+        self.state.accepted_recipients = 0;
+    }
+
+    /// the number of recipients accepted (by `RCPT TO`) in the current mail transaction
+    ///
+    /// This is tracked by `command::Recipient` and is e.g. used in LMTP mode
+    /// to know how many responses the end of the mail data gets.
+    pub fn accepted_recipients(&self) -> usize {
+        self.state.accepted_recipients
+    }
+
+    /// record that the server accepted a recipient, see `accepted_recipients`
+    pub fn add_accepted_recipient(&mut self) {
+        self.state.accepted_recipients += 1;
+    }
+
+    /// true if the server speaks LMTP (RFC 2033) instead of SMTP
+    ///
+    /// This is set by a successful `command::Lhlo` (or the `lmtp` option of
+    /// the connection builders). In LMTP mode `Connection::rehlo` sends
+    /// `LHLO` and `command::Data` reads one response per accepted recipient.
+    pub fn is_lmtp(&self) -> bool {
+        self.state.lmtp
+    }
+
+    /// set if the server speaks LMTP, see `is_lmtp`
+    pub fn set_lmtp(&mut self, lmtp: bool) {
+        self.state.lmtp = lmtp;
+    }
+
+    /// true if an auth command succeeded on this connection
+    ///
+    /// This is set by the auth commands provided by this crate. As `STARTTLS`
+    /// resets the smtp state it is reset when starting TLS.
+    pub fn is_authenticated(&self) -> bool {
+        self.state.authenticated
+    }
+
+    /// mark the connection as authenticated, see `is_authenticated`
+    pub fn set_authenticated(&mut self) {
+        self.state.authenticated = true;
+    }
+
+    /// returns the identity recorded by the last successful auth command, if any
+    pub fn authenticated_as(&self) -> Option<&AuthIdentity> {
+        self.state.authenticated_as.as_ref()
+    }
+
+    /// record the identity the connection is authenticated as
+    ///
+    /// This also marks the connection as authenticated.
+    pub fn set_authenticated_as(&mut self, identity: AuthIdentity) {
+        self.state.authenticated = true;
+        self.state.authenticated_as = Some(identity);
+    }
+
+    /// returns the id of the connection
+    ///
+    /// Each `Io` instance created from a socket gets a new (process wide)
+    /// unique id, it's kept when the socket is replaced (e.g. by `STARTTLS`).
+    /// It's included in the output of the `log` feature to correlate lines
+    /// belonging to the same connection.
+    pub fn id(&self) -> u64 {
+        self.state.id
+    }
+
+    /// returns the address of the server, if known
+    ///
+    /// This is `None` for e.g. mock sockets.
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr
+    }
+
+    /// returns a tag identifying the connection in log output
+    #[cfg(feature = "log")]
+    pub(crate) fn log_tag(&self) -> LogTag {
+        LogTag {
+            id: self.state.id,
+            peer_addr: self.peer_addr,
+        }
+    }
+
+    /// true if the connection is known to be unusable
+    ///
+    /// E.g. because the server responded with `421` to the last command.
+    pub fn is_poisoned(&self) -> bool {
+        self.state.poisoned
+    }
+
+    /// mark the connection as unusable, see `is_poisoned`
+    pub fn poison(&mut self) {
+        self.state.poisoned = true;
     }
 
     /// checks if a specific `EsmtpKeyword` had been in the last
@@ -119,7 +605,35 @@ impl Io {
             .unwrap_or(false)
     }
 
-    /// used to impl. simple commands e.g. `con.send_simple_cmd(&["NOOP"])`
+    /// sends a line consisting of `parts` and parses exactly one response
+    ///
+    /// The parts are written joined (without any separator) followed by
+    /// `"\r\n"`, then the output is flushed and one response is parsed.
+    /// This is what most simple request/response commands like `NOOP`,
+    /// `QUIT` or `MAIL` use to implement `Cmd::exec`.
+    ///
+    /// ```
+    /// use new_tokio_smtp::{error::MissingCapabilities, Cmd, EhloData, ExecFuture, Io};
+    ///
+    /// /// sends `HELP [<topic>]`
+    /// struct Help(Option<String>);
+    ///
+    /// impl Cmd for Help {
+    ///     fn check_cmd_availability(
+    ///         &self,
+    ///         _caps: Option<&EhloData>,
+    ///     ) -> Result<(), MissingCapabilities> {
+    ///         Ok(())
+    ///     }
+    ///
+    ///     fn exec(self, io: Io) -> ExecFuture {
+    ///         match &self.0 {
+    ///             Some(topic) => io.exec_simple_cmd(&["HELP ", topic]),
+    ///             None => io.exec_simple_cmd(&["HELP"]),
+    ///         }
+    ///     }
+    /// }
+    /// ```
     pub fn exec_simple_cmd(mut self, parts: &[&str]) -> ExecFuture {
         self.write_line_from_parts(parts);
 
@@ -131,41 +645,32 @@ impl Io {
 
 impl From<(Socket, Buffers, Option<EhloData>)> for Io {
     fn from((socket, buffer, ehlo_data): (Socket, Buffers, Option<EhloData>)) -> Self {
+        let peer_addr = socket.peer_addr();
         Io {
             socket,
             buffer,
-            ehlo_data,
+            output_capacity: 0,
+            state: IoState::new(ehlo_data),
+            peer_addr,
         }
     }
 }
 
 impl From<(Socket, Buffers, EhloData)> for Io {
     fn from((socket, buffer, ehlo_data): (Socket, Buffers, EhloData)) -> Self {
-        Io {
-            socket,
-            buffer,
-            ehlo_data: Some(ehlo_data),
-        }
+        Io::from((socket, buffer, Some(ehlo_data)))
     }
 }
 
 impl From<(Socket, Buffers)> for Io {
     fn from((socket, buffer): (Socket, Buffers)) -> Self {
-        Io {
-            socket,
-            buffer,
-            ehlo_data: None,
-        }
+        Io::from((socket, buffer, None))
     }
 }
 
 impl From<Socket> for Io {
     fn from(socket: Socket) -> Self {
-        Io {
-            socket,
-            buffer: Buffers::new(),
-            ehlo_data: None,
-        }
+        Io::from((socket, Buffers::new(), None))
     }
 }
 
@@ -177,6 +682,7 @@ impl From<TcpStream> for Io {
     }
 }
 
+#[cfg(feature = "native-tls")]
 impl From<TlsStream<TcpStream>> for Io {
     fn from(stream: TlsStream<TcpStream>) -> Self {
         let socket = Socket::Secure(stream);
@@ -185,6 +691,18 @@ impl From<TlsStream<TcpStream>> for Io {
     }
 }
 
+/// all parts of an `Io` instance except the socket
+///
+/// Returned by `Io::take_socket`/`Connection::take_socket` and used to
+/// put a (potentially different) socket back in.
+#[derive(Debug)]
+pub struct ReassembleToken {
+    buffer: Buffers,
+    /// the capacity the output buffer was grown to
+    output_capacity: usize,
+    state: IoState,
+}
+
 /// represents the buffers of an smtp connection
 #[derive(Debug, Default)]
 pub struct Buffers {
@@ -217,3 +735,45 @@ fn reverse_buffer_cap(buf: &mut BytesMut, need_rem: usize, increase: usize) {
         buf.reserve(reserve)
     }
 }
+
+#[cfg(all(test, feature = "mock-impl", feature = "native-tls"))]
+mod test {
+    use crate::{
+        command::auth::AuthIdentity,
+        common::{ClientId, EhloData},
+        data_types::Domain,
+        io::Io,
+        mock::MockSocket,
+        SyntaxErrorHandling,
+    };
+
+    #[test]
+    fn reset_session_keeps_settings_and_setup_information() {
+        let mut io: Io = MockSocket::new_no_check_shutdown(vec![]).into();
+        let id = io.id();
+        io.set_ehlo_data(EhloData::from((
+            Domain::from_unchecked("they.test"),
+            Default::default(),
+        )));
+        io.set_client_id(ClientId::Domain(Domain::from_unchecked("me.test")));
+        io.set_syntax_error_handling(SyntaxErrorHandling::Strict);
+        io.set_in_transaction(true);
+        io.add_accepted_recipient();
+        io.set_lmtp(true);
+        io.set_authenticated_as(AuthIdentity::new("tim", "PLAIN"));
+
+        let (socket, mut state) = io.into_socket_and_state();
+        state.reset_session();
+        let io = Io::from_socket_and_state(socket, state);
+
+        assert_eq!(io.id(), id);
+        assert_eq!(io.client_id().map(ClientId::as_str), Some("me.test"));
+        assert_eq!(io.syntax_error_handling(), &SyntaxErrorHandling::Strict);
+        assert!(io.is_lmtp());
+        assert!(io.ehlo_data().is_none());
+        assert!(!io.is_in_transaction());
+        assert_eq!(io.accepted_recipients(), 0);
+        assert!(!io.is_authenticated());
+        assert!(io.authenticated_as().is_none());
+    }
+}
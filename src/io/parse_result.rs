@@ -1,25 +1,37 @@
-use std::{io as std_io, mem};
+use std::{
+    io as std_io, mem,
+    time::{Duration, Instant},
+};
 
 use bytes::BufMut;
-use futures::{Async, Future, Poll};
-use tokio::io::AsyncRead;
+use futures::{Async, Future, Poll, Stream};
+use tokio::{io::AsyncRead, timer::Delay};
 
-use super::{Io, SmtpResult, INPUT_BUFFER_INC_SIZE};
-use crate::{error::check_response, response::parser};
+use super::{trace, Direction, Io, SmtpResult, INPUT_BUFFER_INC_SIZE};
+use crate::{
+    connect::SyntaxErrorHandling,
+    error::check_response,
+    response::{parser, ResponseCode},
+};
 
 impl Io {
     /// parse a "normal" smtp response
     ///
-    /// # Panics
-    ///
-    /// Panics if the write buffer is not empty
+    /// If the output buffer is not empty (i.e. not all output was flushed)
+    /// the returned future fails with an `io::Error` of kind `InvalidInput`
+    /// without reading anything, as the server would still be waiting for
+    /// the rest of the command.
     pub fn parse_response(self) -> Parsing {
-        if !self.buffer.output.is_empty() {
-            panic!("parsing input before writing all output")
-        }
         Parsing::new(self)
     }
 
+    /// parse a response line by line, see `StreamedResponse`
+    ///
+    /// Like `parse_response` it fails if not all output was flushed.
+    pub fn parse_response_streamed(self) -> StreamedResponse {
+        StreamedResponse::new(self)
+    }
+
     /// read data from the socket to buffer.input until it would block or the socket closed
     ///
     /// The input buffer is increased in increments of 256 bytes (`INPUT_BUFFER_INC_SIZE`)
@@ -50,14 +62,19 @@ impl Io {
     where
         F: FnOnce(&[u8]) -> Result<R, E>,
     {
-        let input = self.in_buffer();
+        #[cfg(feature = "log")]
+        let tag = self.log_tag();
+        let input = &mut self.buffer.input;
 
-        let eol = (&*input).windows(2).position(|pair| pair == b"\r\n");
+        let eol = input.windows(2).position(|pair| pair == b"\r\n");
 
         if let Some(eol) = eol {
             let line = &input[..eol];
             #[cfg(feature = "log")]
-            log_facade::trace!("S: {:?}", String::from_utf8_lossy(line));
+            log_facade::trace!("{} S: {:?}", tag, String::from_utf8_lossy(line));
+            if let Some(hook) = self.state.trace_hook.as_ref() {
+                trace::call_hook(hook, Direction::Received, line);
+            }
             let parsed = parse_line_fn(line)?;
             input.advance(eol + 2);
             Ok(Some(parsed))
@@ -92,17 +109,42 @@ impl ReadState {
     }
 }
 
-/// future returned by `Connection.parse_result`
+/// future returned by `Io::parse_response`, resolves to the `Io` and the parsed response
+///
+/// Exactly one response is read, i.e. any data following it stays in the
+/// input buffer.
 pub struct Parsing {
     inner: Option<Io>,
     lines: Vec<parser::ResponseLine>,
+    /// total size of the messages in `lines`
+    size: usize,
+    /// the raw bytes of `lines`, if `Io::retain_raw_responses` is enabled
+    raw: Option<Vec<u8>>,
+    /// true if there was unflushed output when parsing was started
+    output_pending: bool,
+    /// see `Io::set_read_idle_timeout`
+    idle_timeout: Option<Duration>,
+    /// the running idle timer, it's (re-)started whenever bytes of a partial response arrive
+    idle_delay: Option<Delay>,
 }
 
 impl Parsing {
     pub(crate) fn new(inner: Io) -> Self {
+        let raw = if inner.retain_raw_responses() {
+            Some(Vec::new())
+        } else {
+            None
+        };
+        let output_pending = !inner.buffer.output.is_empty();
+        let idle_timeout = inner.read_idle_timeout();
         Parsing {
             inner: Some(inner),
             lines: Vec::new(),
+            size: 0,
+            raw,
+            output_pending,
+            idle_timeout,
+            idle_delay: None,
         }
     }
 
@@ -110,26 +152,78 @@ impl Parsing {
         self.inner.as_mut().expect("[BUG] poll after completion")
     }
 
+    /// fails with `TimedOut` if a started response stalls for longer than the idle timeout
+    fn poll_idle_timeout(&mut self, got_bytes: bool) -> Poll<(Io, SmtpResult), std_io::Error> {
+        let timeout = match self.idle_timeout {
+            Some(timeout) => timeout,
+            None => return Ok(Async::NotReady),
+        };
+
+        let started = !self.lines.is_empty() || !self.io_mut().in_buffer().is_empty();
+        if !started {
+            return Ok(Async::NotReady);
+        }
+
+        if got_bytes || self.idle_delay.is_none() {
+            self.idle_delay = Some(Delay::new(Instant::now() + timeout));
+        }
+
+        let delay = self.idle_delay.as_mut().expect("set above");
+        try_ready!(delay
+            .poll()
+            .map_err(|err| std_io::Error::new(std_io::ErrorKind::Other, err)));
+
+        // the connection is in an unknown state, so drop it
+        self.inner = None;
+        Err(std_io::Error::new(
+            std_io::ErrorKind::TimedOut,
+            "server stalled in the middle of a response",
+        ))
+    }
+
     fn read_result(&mut self) -> Result<Option<(Io, SmtpResult)>, parser::ParseError> {
         loop {
+            let raw = &mut self.raw;
             let opt_line = self
-                .io_mut()
-                .try_pop_line(|line| parser::parse_line(line))?;
+                .inner
+                .as_mut()
+                .expect("[BUG] poll after completion")
+                .try_pop_line(|line| {
+                    if let Some(raw) = raw.as_mut() {
+                        raw.extend_from_slice(line);
+                        raw.extend_from_slice(b"\r\n");
+                    }
+                    parser::parse_line(line)
+                })?;
 
             if let Some(line) = opt_line {
                 let last = line.last_line;
+                self.size += line.msg.len();
                 self.lines.push(line);
+                let (line_count, size) = (self.lines.len(), self.size);
+                self.io_mut().response_limits().check(line_count, size)?;
 
                 if !last {
                     continue;
                 }
 
-                let lines = mem::replace(&mut self.lines, Vec::new());
-                let response = parser::response_from_parsed_lines(lines.into_iter())?;
+                let lines = mem::take(&mut self.lines);
+                self.size = 0;
+                let mut response = parser::response_from_parsed_lines_with_handling(
+                    lines,
+                    self.io_mut().syntax_error_handling(),
+                )?;
+                if let Some(raw) = self.raw.take() {
+                    response.set_raw(raw);
+                }
 
-                let io = self.inner.take().expect("[BUG] poll after completion");
-                //FIXME[buf_management]: maybe normalize output bufer to have at most cap of 1024
+                let mut io = self.inner.take().expect("[BUG] poll after completion");
+                io.shrink_output_buffer();
                 return Ok(Some((io, check_response(response))));
+            } else if self.io_mut().in_buffer().len() > parser::MAX_LINE_LENGTH + 1 {
+                // no "\r\n" in the buffer, so it's all one (partial) line
+                // (the `+ 1` is for a "\r" without the "\n" yet)
+                return Err(parser::ParseError::LineLength);
             } else {
                 return Ok(None);
             }
@@ -142,8 +236,23 @@ impl Future for Parsing {
     type Error = std_io::Error;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        if self.inner.is_none() {
+            return Err(super::poll_after_completion_error());
+        }
+
+        if self.output_pending {
+            // the connection is in an unknown state, so drop it
+            self.inner = None;
+            return Err(std_io::Error::new(
+                std_io::ErrorKind::InvalidInput,
+                "parsing input before writing all output",
+            ));
+        }
+
         //1. parse more data
+        let len_before = self.io_mut().in_buffer().len();
         let state = self.io_mut().read_from_socket()?;
+        let got_bytes = self.io_mut().in_buffer().len() != len_before;
 
         //2. see if we have a full response now
         match self.read_result() {
@@ -154,7 +263,7 @@ impl Future for Parsing {
 
         //3. if not see if the socked was closed
         match state {
-            ReadState::NotReady => Ok(Async::NotReady),
+            ReadState::NotReady => self.poll_idle_timeout(got_bytes),
             ReadState::SocketClosed => Err(std_io::Error::new(
                 std_io::ErrorKind::ConnectionAborted,
                 "socked closed before getting full smtp response",
@@ -162,3 +271,189 @@ impl Future for Parsing {
         }
     }
 }
+
+/// stream returned by `Io::parse_response_streamed`, yields the lines of exactly one response
+///
+/// Unlike `Parsing` the lines are not collected into a `Response`, so memory
+/// use stays bounded by the line length even for very large replies (e.g.
+/// `EXPN` of a large list), as such the `ResponseLimits` are not checked.
+/// The last item is the terminating line (i.e. `last_line` is true), after
+/// it the stream ends and the `Io` can be retrieved using `into_io`. With
+/// `SyntaxErrorHandling::Strict` a line with a different code than the first
+/// line is an error. On any error the `Io` is dropped.
+pub struct StreamedResponse {
+    inner: Option<Io>,
+    first_code: Option<ResponseCode>,
+    done: bool,
+    /// true if there was unflushed output when parsing was started
+    output_pending: bool,
+}
+
+impl StreamedResponse {
+    fn new(inner: Io) -> Self {
+        let output_pending = !inner.buffer.output.is_empty();
+        StreamedResponse {
+            inner: Some(inner),
+            first_code: None,
+            done: false,
+            output_pending,
+        }
+    }
+
+    /// returns the `Io` if the terminating line was yielded
+    pub fn into_io(self) -> Option<Io> {
+        if self.done {
+            self.inner
+        } else {
+            None
+        }
+    }
+
+    fn pop_line(&mut self) -> Result<Option<parser::ResponseLine>, parser::ParseError> {
+        let io = self.inner.as_mut().expect("[BUG] poll after completion");
+        let line = match io.try_pop_line(parser::parse_line)? {
+            Some(line) => line,
+            None if io.in_buffer().len() > parser::MAX_LINE_LENGTH + 1 => {
+                return Err(parser::ParseError::LineLength);
+            }
+            None => return Ok(None),
+        };
+
+        let expected = *self.first_code.get_or_insert(line.code);
+        if expected != line.code && io.syntax_error_handling() == &SyntaxErrorHandling::Strict {
+            return Err(parser::ParseError::Code {
+                expected,
+                got: line.code,
+            });
+        }
+
+        if line.last_line {
+            self.done = true;
+            io.shrink_output_buffer();
+        }
+        Ok(Some(line))
+    }
+}
+
+impl Stream for StreamedResponse {
+    type Item = parser::ResponseLine;
+    type Error = std_io::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        if self.done {
+            return Ok(Async::Ready(None));
+        }
+        if self.inner.is_none() {
+            return Err(super::poll_after_completion_error());
+        }
+
+        if self.output_pending {
+            // the connection is in an unknown state, so drop it
+            self.inner = None;
+            return Err(std_io::Error::new(
+                std_io::ErrorKind::InvalidInput,
+                "parsing input before writing all output",
+            ));
+        }
+
+        let mut read_state = None;
+        loop {
+            match self.pop_line() {
+                Ok(Some(line)) => return Ok(Async::Ready(Some(line))),
+                Ok(None) => (),
+                Err(err) => {
+                    self.inner = None;
+                    return Err(std_io::Error::new(std_io::ErrorKind::InvalidData, err));
+                }
+            }
+
+            match read_state {
+                None => {
+                    let io = self.inner.as_mut().expect("checked above");
+                    read_state = Some(io.read_from_socket()?);
+                }
+                Some(ReadState::NotReady) => return Ok(Async::NotReady),
+                Some(ReadState::SocketClosed) => {
+                    self.inner = None;
+                    return Err(std_io::Error::new(
+                        std_io::ErrorKind::ConnectionAborted,
+                        "socked closed before getting full smtp response",
+                    ));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "mock-impl"))]
+mod test {
+    use std::io as std_io;
+
+    use futures::{future, Future};
+
+    use crate::{
+        io::Io,
+        mock::{ActionData::Lines, Actor::Server, MockSocket},
+        response::codes,
+        SyntaxErrorHandling,
+    };
+
+    fn mismatched_codes_io(handling: SyntaxErrorHandling) -> Io {
+        let mut io: Io = MockSocket::new_no_check_shutdown(vec![(
+            Server,
+            Lines(vec!["250-first", "220-second", "250 third"]),
+        )])
+        .into();
+        io.set_syntax_error_handling(handling);
+        io
+    }
+
+    #[test]
+    fn strict_parsing_fails_on_mismatched_codes() {
+        let io = mismatched_codes_io(SyntaxErrorHandling::Strict);
+
+        let err = io.parse_response().wait().unwrap_err();
+        assert_eq!(err.kind(), std_io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn lax_parsing_keeps_the_first_code_on_mismatched_codes() {
+        let io = mismatched_codes_io(SyntaxErrorHandling::Lax);
+
+        let (_io, result) = io.parse_response().wait().unwrap();
+        let response = result.unwrap();
+        assert_eq!(response.code(), codes::OK);
+        assert_eq!(response.msg(), &["first", "second", "third"]);
+    }
+
+    #[test]
+    fn parsing_is_lax_by_default() {
+        let io: Io = MockSocket::new_no_check_shutdown(vec![]).into();
+        assert_eq!(io.syntax_error_handling(), &SyntaxErrorHandling::Lax);
+    }
+
+    #[test]
+    fn parsing_with_pending_output_is_an_error() {
+        let mut io: Io = MockSocket::new_no_check_shutdown(vec![]).into();
+        io.write_line_from_parts(&["NOOP"]);
+
+        let err = io.parse_response().wait().unwrap_err();
+        assert_eq!(err.kind(), std_io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn polling_after_completion_is_an_error() {
+        let io: Io =
+            MockSocket::new_no_check_shutdown(vec![(Server, Lines(vec!["250 Ok"]))]).into();
+        let mut parsing = io.parse_response();
+
+        let (_io, result) = (&mut parsing).wait().unwrap();
+        assert!(result.is_ok());
+
+        // polling needs a task context
+        let second_poll = future::lazy(move || Ok::<_, ()>(parsing.poll().map(|_| ())))
+            .wait()
+            .unwrap();
+        assert!(second_poll.is_err());
+    }
+}
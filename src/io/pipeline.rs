@@ -0,0 +1,32 @@
+use super::Io;
+use crate::error::MissingCapabilities;
+
+const PIPELINING: &str = "PIPELINING";
+
+/// proof that the server supports `PIPELINING` (RFC 2920)
+///
+/// Created with `Io::pipeline_guard`/`Connection::pipeline_guard`, code
+/// writing multiple commands before reading their responses should require
+/// it so that it can't accidentally pipeline against a server which doesn't
+/// support it (which would corrupt the session).
+///
+/// The guard is only valid for the connection (and ehlo data) it was
+/// created for, e.g. it has to be re-created after `STARTTLS`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct PipelineGuard {
+    _priv: (),
+}
+
+impl Io {
+    /// returns a `PipelineGuard` if the server advertised `PIPELINING`
+    ///
+    /// This only checks the ehlo data, i.e. it fails with
+    /// `MissingCapabilities` before anything was written to the server.
+    pub fn pipeline_guard(&self) -> Result<PipelineGuard, MissingCapabilities> {
+        if self.has_capability(PIPELINING) {
+            Ok(PipelineGuard { _priv: () })
+        } else {
+            Err(MissingCapabilities::new_from_unchecked(PIPELINING))
+        }
+    }
+}
@@ -1,10 +1,12 @@
 use std::fmt::Debug;
 use std::io as std_io;
+use std::net::SocketAddr;
 
 use bytes::buf::{Buf, BufMut};
 use futures::Poll;
 use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::net::TcpStream;
+#[cfg(feature = "native-tls")]
 use tokio_tls::TlsStream;
 
 /// Abstraction over Tcp, TcpTls (and Mock)
@@ -13,6 +15,10 @@ use tokio_tls::TlsStream;
 /// `TlsStream<TcpStream>` the same.
 ///
 /// # Features
+/// ## `native-tls`
+///
+/// the `Secure` variant only exists if enabled (default)
+///
 /// ## `mock_support`
 ///
 /// if enabled this abstracts not only over `TcpStream` and
@@ -20,6 +26,7 @@ use tokio_tls::TlsStream;
 ///
 #[derive(Debug)]
 pub enum Socket {
+    #[cfg(feature = "native-tls")]
     Secure(TlsStream<TcpStream>),
     Insecure(TcpStream),
     #[cfg(feature = "mock-support")]
@@ -30,17 +37,30 @@ impl Socket {
     /// true if it's a `TlsStream` (or if mock says so)
     pub fn is_secure(&self) -> bool {
         match self {
+            #[cfg(feature = "native-tls")]
             Socket::Secure(_) => true,
             Socket::Insecure(_) => false,
             #[cfg(feature = "mock-support")]
             Socket::Mock(mock) => mock.is_secure(),
         }
     }
+
+    /// the address of the remote side, if it's a tcp socket
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        match self {
+            #[cfg(feature = "native-tls")]
+            Socket::Secure(socket) => socket.get_ref().get_ref().peer_addr().ok(),
+            Socket::Insecure(socket) => socket.peer_addr().ok(),
+            #[cfg(feature = "mock-support")]
+            Socket::Mock(_) => None,
+        }
+    }
 }
 
 macro_rules! socket_mux {
     ($self:ident, |$socket:ident| $block:block) => {{
         match $self {
+            #[cfg(feature = "native-tls")]
             Socket::Secure($socket) => $block,
             Socket::Insecure($socket) => $block,
             #[cfg(feature = "mock-support")]
@@ -75,6 +95,7 @@ impl AsyncRead for Socket {
     #[inline]
     unsafe fn prepare_uninitialized_buffer(&self, buf: &mut [u8]) -> bool {
         match self {
+            #[cfg(feature = "native-tls")]
             Socket::Secure(socket) => socket.prepare_uninitialized_buffer(buf),
             Socket::Insecure(socket) => socket.prepare_uninitialized_buffer(buf),
             #[cfg(feature = "mock-support")]
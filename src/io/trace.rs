@@ -0,0 +1,106 @@
+use std::sync::Arc;
+#[cfg(feature = "log")]
+use std::{fmt, net::SocketAddr};
+
+/// the direction a traced line was transmitted in
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Direction {
+    /// the line was send from the client to the server
+    Sent,
+    /// the line was received by the client from the server
+    Received,
+}
+
+/// a hook called for every line send to/received from the server
+///
+/// The lines are passed without the trailing `"\r\n"`. Like with the
+/// `log` feature the credentials in `AUTH` lines are replaced with
+/// `<redacted>` and the mail data send with `DATA` is not passed to
+/// the hook.
+pub type TraceHook = Arc<dyn Fn(Direction, &[u8]) + Send + Sync>;
+
+/// identifies a connection in the output of the `log` feature, e.g. `[#3 127.0.0.1:25]`
+#[cfg(feature = "log")]
+pub(crate) struct LogTag {
+    pub(crate) id: u64,
+    pub(crate) peer_addr: Option<SocketAddr>,
+}
+
+#[cfg(feature = "log")]
+impl fmt::Display for LogTag {
+    fn fmt(&self, fter: &mut fmt::Formatter) -> fmt::Result {
+        match self.peer_addr {
+            Some(addr) => write!(fter, "[#{} {}]", self.id, addr),
+            None => write!(fter, "[#{}]", self.id),
+        }
+    }
+}
+
+/// returns the length of the part of the line which can be shown if it needs redaction
+///
+/// Only `AUTH` lines need redaction, for them all but the `AUTH` command and the
+/// name of the auth mechanism is redacted.
+pub(crate) fn redaction_point(line: &[u8]) -> Option<usize> {
+    if !line.starts_with(b"AUTH") {
+        return None;
+    }
+    let additional_chars_for_auth_subcommand = line
+        .get(5..)
+        .and_then(|rest| rest.iter().position(|ch| *ch == b' '))
+        .unwrap_or(0);
+    Some(line.len().min(5 + additional_chars_for_auth_subcommand))
+}
+
+/// calls `func` for each "\r\n" terminated line in `output` (without the "\r\n")
+pub(crate) fn for_each_line<F>(output: &[u8], mut func: F)
+where
+    F: FnMut(&[u8]),
+{
+    let mut rem = output;
+    while let Some(eol) = rem.windows(2).position(|pair| pair == b"\r\n") {
+        func(&rem[..eol]);
+        rem = &rem[eol + 2..];
+    }
+    if !rem.is_empty() {
+        func(rem);
+    }
+}
+
+/// calls the hook with the line, redacting it if needed
+pub(crate) fn call_hook(hook: &TraceHook, direction: Direction, line: &[u8]) {
+    if let Some(end) = redaction_point(line) {
+        let mut redacted = line[..end].to_owned();
+        redacted.extend_from_slice(b" <redacted>");
+        hook(direction, &redacted)
+    } else {
+        hook(direction, line)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn redacts_auth_credentials() {
+        assert_eq!(redaction_point(b"AUTH PLAIN AGZvbwBiYXI="), Some(10));
+        assert_eq!(redaction_point(b"AUTH LOGINdXNlcg=="), Some(5));
+        assert_eq!(redaction_point(b"AUTH"), Some(4));
+        assert_eq!(redaction_point(b"MAIL FROM:<a@b.test>"), None);
+    }
+
+    #[test]
+    fn splits_output_into_lines() {
+        let mut lines = Vec::new();
+        for_each_line(b"MAIL FROM:<a@b.test>\r\nRCPT TO:<c@d.test>\r\n", |line| {
+            lines.push(line.to_owned())
+        });
+        assert_eq!(
+            lines,
+            vec![
+                b"MAIL FROM:<a@b.test>".to_vec(),
+                b"RCPT TO:<c@d.test>".to_vec()
+            ]
+        );
+    }
+}
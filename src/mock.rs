@@ -3,7 +3,7 @@ use std::cmp::min;
 use std::io::{self as std_io, Read, Write};
 use std::mem;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use rand::{random, thread_rng, Rng};
 
@@ -19,6 +19,12 @@ use crate::io::MockStream;
 #[derive(Debug)]
 pub enum Actor {
     Server,
+    /// like `Server` but the data only becomes readable after the given delay
+    ///
+    /// The delay starts once the client starts reading (i.e. after the
+    /// previous action completed). Unlike the random latency injected by
+    /// the mock the delay is fixed, which makes it usable for timeout tests.
+    ServerAfter(Duration),
     Client,
 }
 
@@ -48,6 +54,11 @@ impl ActionData {
         }
     }
 
+    /// true if `len()` is 0
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     pub fn assert_same_start(&self, other: &[u8]) {
         match self {
             ActionData::Blob(blob) => {
@@ -119,6 +130,8 @@ enum State {
     ServerIsWorking {
         waker: Waker,
         to_be_read: BytesMut,
+        /// set for `ServerAfter` actions until the delay passed
+        ready_at: Option<Instant>,
     },
     ClientIsWorking {
         expected: ActionData,
@@ -149,6 +162,7 @@ pub struct MockSocket {
     fake_secure: bool,
     state: State,
     check_shutdown: bool,
+    check_conversation: bool,
 }
 
 /// MockSocket going through a pre-coded interlocked client-server conversation
@@ -174,6 +188,17 @@ impl MockSocket {
         Self::new_with_params(conversation, false)
     }
 
+    /// create a new `MockSocket` which doesn't panic on drop if it was abandoned
+    ///
+    /// Neither checks if the socket was shutdown nor if the conversation
+    /// ended before the socket is dropped, which allows testing futures which
+    /// are intentionally dropped mid-flight (e.g. because of a timeout).
+    pub fn new_allow_incomplete(conversation: Vec<(Actor, ActionData)>) -> Self {
+        let mut socket = Self::new_with_params(conversation, false);
+        socket.check_conversation = false;
+        socket
+    }
+
     /// create a new `MockSocket` from a sequence of "actions"
     ///
     /// Actions are taken interlocked between `Client` (client write something, server reads)
@@ -187,6 +212,7 @@ impl MockSocket {
         MockSocket {
             conversation,
             check_shutdown,
+            check_conversation: true,
             fake_secure: false,
             state: State::NeedNewAction {
                 buffer: BytesMut::new(),
@@ -244,7 +270,7 @@ impl MockSocket {
         let mut buffer = buffer;
 
         match actor {
-            Actor::Server => {
+            Actor::Server | Actor::ServerAfter(_) => {
                 // 1. data into() buffer
                 assert!(
                     buffer.is_empty(),
@@ -263,9 +289,14 @@ impl MockSocket {
                         buffer.put(blob);
                     }
                 }
+                let ready_at = match actor {
+                    Actor::ServerAfter(delay) => Some(Instant::now() + delay),
+                    _ => None,
+                };
                 State::ServerIsWorking {
                     waker,
                     to_be_read: buffer,
+                    ready_at,
                 }
             }
             Actor::Client => {
@@ -288,6 +319,8 @@ impl Drop for MockSocket {
     /// if the thread is not panicking it will panic:
     /// - if the socket was not shutdown
     /// - if the conversation did not end, i.e. was not empty
+    ///
+    /// Both checks are disabled for sockets created with `new_allow_incomplete`.
     fn drop(&mut self) {
         if !thread::panicking() {
             if self.check_shutdown {
@@ -298,7 +331,7 @@ impl Drop for MockSocket {
             }
 
             assert!(
-                self.conversation.is_empty(),
+                !self.check_conversation || self.conversation.is_empty(),
                 "premature cancellation of conversation"
             );
         }
@@ -358,7 +391,8 @@ impl Write for MockSocket {
     }
 
     fn flush(&mut self) -> Result<(), std_io::Error> {
-        Ok(try_ready_or_would_block!(self.poll_flush()))
+        try_ready_or_would_block!(self.poll_flush());
+        Ok(())
     }
 }
 
@@ -399,6 +433,9 @@ impl Write for MockSocket {
 //
 // on NotReady return:
 //   send Task to DelayedWakerThread
+//
+// on read before the delay of a `ServerAfter` action passed:
+//   spawn thread notifying the Task once the delay passed -> NotReady
 
 impl AsyncRead for MockSocket {
     /// `poll_read` impl
@@ -409,6 +446,7 @@ impl AsyncRead for MockSocket {
     /// - panics if the state is `ClientIsWorking` or `ShutdownOrPoison`
     /// - on `NeedNewAction` it advances the state to the next action if
     ///   there is any and returns `NotReady`
+    /// - returns `NotReady` until the delay of a `ServerAfter` action passed
     /// - writes a random amount of bytes to the passed in read buffer
     ///   (at last 1), advancing the state to `NeedNewAction` once all bytes
     ///   have been read
@@ -429,9 +467,23 @@ impl AsyncRead for MockSocket {
                 self.schedule_delayed_wake();
                 Ok(Async::NotReady)
             }
+            State::ServerIsWorking {
+                waker,
+                to_be_read,
+                ready_at: Some(ready_at),
+            } if Instant::now() < ready_at => {
+                self.state = State::ServerIsWorking {
+                    waker,
+                    to_be_read,
+                    ready_at: Some(ready_at),
+                };
+                schedule_wake_at(ready_at);
+                Ok(Async::NotReady)
+            }
             State::ServerIsWorking {
                 waker,
                 mut to_be_read,
+                ready_at: _,
             } => {
                 let rem = to_be_read.len();
                 let can_write = buf.len();
@@ -446,7 +498,11 @@ impl AsyncRead for MockSocket {
                         buffer: to_be_read,
                     }
                 } else {
-                    self.state = State::ServerIsWorking { waker, to_be_read }
+                    self.state = State::ServerIsWorking {
+                        waker,
+                        to_be_read,
+                        ready_at: None,
+                    }
                 }
                 Ok(Async::Ready(should_write))
             }
@@ -581,9 +637,9 @@ impl AsyncWrite for MockSocket {
     fn shutdown(&mut self) -> Poll<(), std_io::Error> {
         // shutdown implies flush, so we flush
         try_ready!(self.poll_flush());
-        match &self.state {
-            &State::ShutdownOrPoison => (),
-            &State::NeedNewAction { .. } => (),
+        match self.state {
+            State::ShutdownOrPoison => (),
+            State::NeedNewAction { .. } => (),
             _ => panic!("unexpected state when shutting down"),
         }
         self.state = State::ShutdownOrPoison;
@@ -610,6 +666,18 @@ fn write_n_to_slice(from: &[u8], to: &mut [u8], n: usize) {
     to[..n].copy_from_slice(&from[..n]);
 }
 
+/// notifies the current `Task` at (the earliest) `at`
+fn schedule_wake_at(at: Instant) {
+    let task = task::current();
+    thread::spawn(move || {
+        let now = Instant::now();
+        if at > now {
+            thread::sleep(at - now);
+        }
+        task.notify();
+    });
+}
+
 fn delayed_waker() -> mpsc::UnboundedSender<Task> {
     let (tx, rx) = mpsc::unbounded();
     thread::spawn(move || {
@@ -717,9 +785,8 @@ mod test {
         where
             FN: panic::UnwindSafe + FnOnce(),
         {
-            match panic::catch_unwind(func) {
-                Ok(_) => panic!("closure should have paniced"),
-                Err(_) => (),
+            if panic::catch_unwind(func).is_ok() {
+                panic!("closure should have paniced")
             }
         }
 
@@ -805,6 +872,7 @@ mod test {
                 socket.state = State::ServerIsWorking {
                     waker,
                     to_be_read: BytesMut::new(),
+                    ready_at: None,
                 };
 
                 let _res = future::poll_fn(|| socket.shutdown())
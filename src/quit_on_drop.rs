@@ -0,0 +1,68 @@
+use std::{
+    fmt::{self, Debug},
+    ops::{Deref, DerefMut},
+};
+
+use futures::Future;
+use tokio::executor::{DefaultExecutor, Executor};
+
+use crate::connection::Connection;
+
+/// a `Connection` which sends `QUIT` and shuts down when dropped
+///
+/// Created with `Connection::quit_on_drop`. It derefs to the wrapped
+/// `Connection` and `into_inner` can be used to opt out.
+///
+/// As there is no async drop this **spawns** the quit future onto the
+/// default executor of the current tokio runtime when dropped. This is
+/// best-effort: errors are ignored, the spawned future only completes if
+/// the runtime keeps running and if there is no runtime (e.g. the guard
+/// is dropped outside of a task) the connection is just dropped, closing
+/// the socket without sending `QUIT`.
+pub struct QuitOnDrop {
+    con: Option<Connection>,
+}
+
+impl QuitOnDrop {
+    /// wraps the connection
+    pub fn new(con: Connection) -> Self {
+        QuitOnDrop { con: Some(con) }
+    }
+
+    /// returns the wrapped connection, it won't be quit on drop anymore
+    pub fn into_inner(mut self) -> Connection {
+        self.con.take().expect("only taken on drop/into_inner")
+    }
+}
+
+impl Deref for QuitOnDrop {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.con.as_ref().expect("only taken on drop/into_inner")
+    }
+}
+
+impl DerefMut for QuitOnDrop {
+    fn deref_mut(&mut self) -> &mut Connection {
+        self.con.as_mut().expect("only taken on drop/into_inner")
+    }
+}
+
+impl Drop for QuitOnDrop {
+    fn drop(&mut self) {
+        if let Some(con) = self.con.take() {
+            let fut = con.quit().map(|_socket| ()).map_err(|_err| ());
+            // if there is no runtime there is nothing we can do
+            let _ = DefaultExecutor::current().spawn(Box::new(fut));
+        }
+    }
+}
+
+impl Debug for QuitOnDrop {
+    fn fmt(&self, fter: &mut fmt::Formatter) -> fmt::Result {
+        fter.debug_struct("QuitOnDrop")
+            .field("con", &self.con)
+            .finish()
+    }
+}
@@ -0,0 +1,370 @@
+//! Provides MX resolution to create connections for delivering mail to a recipient domain
+//!
+//! This crate doesn't come with a dns resolver, instead `MxResolver` and
+//! `Resolver` (which extends `MxResolver` with host address lookup) have to
+//! be implemented for the resolver used by the application (e.g. one based
+//! on `trust-dns-resolver`). `ConnectionBuilder::for_mx` can then be used to
+//! create a builder for the most preferred mail exchanger of a domain, and
+//! `ConnectionBuilder::new_with_resolver` for any other host.
+//!
+//! `StdResolver` is a `Resolver` using std's (blocking) `ToSocketAddrs`, as std
+//! can't look up MX records it's only usable with `new_with_resolver`.
+use std::io as std_io;
+use std::net::SocketAddr;
+
+use futures::future::{self, Future};
+use futures::Async;
+use tokio_threadpool::blocking;
+
+use crate::{
+    command::Noop,
+    connect::{first_addr, lookup_host, ConnectionBuilder, DEFAULT_SMTP_MX_PORT},
+    data_types::Domain,
+};
+
+/// a MX record, i.e. a mail exchanger responsible for a domain
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct MxRecord {
+    /// the preference of the mail exchanger, lower values are preferred
+    pub preference: u16,
+    /// the domain of the mail exchanger
+    pub exchange: Domain,
+}
+
+impl MxRecord {
+    /// creates a builder connecting to this mail exchanger on port 25
+    ///
+    /// The exchange is used as TLS SNI/verification domain. Like with
+    /// `ConnectionBuilder::new_with_port` the ip address of the exchange is
+    /// resolved using std's `ToSocketAddrs`.
+    #[deprecated(note = "blocks while resolving the exchange, use `builder_with_resolver`")]
+    #[allow(deprecated)]
+    pub fn builder(&self) -> Result<ConnectionBuilder<Noop>, std_io::Error> {
+        ConnectionBuilder::new_with_port(self.exchange.clone(), DEFAULT_SMTP_MX_PORT)
+    }
+
+    /// creates a builder connecting to this mail exchanger on port 25, resolving it with `resolver`
+    ///
+    /// The exchange is used as TLS SNI/verification domain, its ip address is
+    /// resolved using `Resolver::resolve_host`, see `ConnectionBuilder::new_with_resolver`.
+    pub fn builder_with_resolver<R>(
+        &self,
+        resolver: &R,
+    ) -> impl Future<Item = ConnectionBuilder<Noop>, Error = std_io::Error> + Send
+    where
+        R: Resolver,
+    {
+        ConnectionBuilder::new_with_resolver(self.exchange.clone(), DEFAULT_SMTP_MX_PORT, resolver)
+    }
+}
+
+/// future returned by `MxResolver::lookup_mx`
+pub type MxLookupFuture = Box<dyn Future<Item = Vec<MxRecord>, Error = std_io::Error> + Send>;
+
+/// a (async) resolver for MX records
+pub trait MxResolver {
+    /// looks up the MX records of a domain, in any order
+    ///
+    /// If the domain has no MX records this should resolve to an empty `Vec`
+    /// (and not to an error).
+    fn lookup_mx(&self, domain: &Domain) -> MxLookupFuture;
+}
+
+/// future returned by `Resolver::resolve_host`
+pub type HostLookupFuture = Box<dyn Future<Item = Vec<SocketAddr>, Error = std_io::Error> + Send>;
+
+/// a (async) resolver for host addresses and (through `MxResolver`) MX records
+pub trait Resolver: MxResolver {
+    /// resolves the socket addresses of `host` using `port`, preferred ones first
+    ///
+    /// If the host has no addresses this can resolve either to an empty
+    /// `Vec` or to an error.
+    fn resolve_host(&self, host: &Domain, port: u16) -> HostLookupFuture;
+}
+
+/// the default `Resolver`, using std's blocking `ToSocketAddrs`
+///
+/// The lookup is run using `tokio_threadpool::blocking`, so it doesn't block
+/// the event loop when run on tokio's (default) threadpool runtime. Outside of
+/// a threadpool (e.g. on a current thread runtime) it falls back to resolving
+/// in place, like `ConnectionBuilder::new_with_port` does.
+///
+/// As std can't look up MX records `lookup_mx` always fails with an io error
+/// of kind `Other`, so it can't be used with `ConnectionBuilder::for_mx`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct StdResolver;
+
+impl Resolver for StdResolver {
+    fn resolve_host(&self, host: &Domain, port: u16) -> HostLookupFuture {
+        let host = host.clone();
+        let lookup = move || lookup_host(&host, port);
+        let fut = future::poll_fn(move || match blocking(&lookup) {
+            Ok(Async::Ready(result)) => result.map(Async::Ready),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            // not running on a threadpool
+            Err(_) => lookup().map(Async::Ready),
+        });
+
+        Box::new(fut)
+    }
+}
+
+impl MxResolver for StdResolver {
+    fn lookup_mx(&self, _domain: &Domain) -> MxLookupFuture {
+        Box::new(future::err(std_io::Error::new(
+            std_io::ErrorKind::Other,
+            "looking up MX records is unsupported by StdResolver",
+        )))
+    }
+}
+
+/// sorts the records by preference, adding the implicit MX if there are none
+fn sort_mx_records(domain: Domain, mut records: Vec<MxRecord>) -> Vec<MxRecord> {
+    if records.is_empty() {
+        records.push(MxRecord {
+            preference: 0,
+            exchange: domain,
+        });
+    }
+    records.sort_by_key(|record| record.preference);
+    records
+}
+
+/// resolves the mail exchangers for a domain, sorted by preference
+///
+/// If the domain has no MX records the domain itself is used as mail
+/// exchanger (the "implicit MX" of RFC 5321, 5.1). Records with the
+/// same preference keep the order they where returned in by the resolver.
+pub fn resolve_mx<R>(
+    resolver: &R,
+    domain: &Domain,
+) -> impl Future<Item = Vec<MxRecord>, Error = std_io::Error> + Send
+where
+    R: MxResolver,
+{
+    let domain = domain.clone();
+    resolver
+        .lookup_mx(&domain)
+        .map(move |records| sort_mx_records(domain, records))
+}
+
+impl ConnectionBuilder<Noop> {
+    /// creates a builder for the given host and port, resolving the host with `resolver`
+    ///
+    /// Like `ConnectionBuilder::new_with_port` but the address isn't resolved
+    /// with std's blocking `ToSocketAddrs`, instead the first address returned
+    /// by `Resolver::resolve_host` is used. If there is none this fails with
+    /// an io error of kind `AddrNotAvailable`.
+    pub fn new_with_resolver<R>(
+        host: Domain,
+        port: u16,
+        resolver: &R,
+    ) -> impl Future<Item = ConnectionBuilder<Noop>, Error = std_io::Error> + Send
+    where
+        R: Resolver,
+    {
+        resolver.resolve_host(&host, port).and_then(move |addrs| {
+            let addr = first_addr(addrs, &host, port)?;
+            Ok(ConnectionBuilder::new_with_addr(addr, host))
+        })
+    }
+
+    /// creates a builder for the most preferred mail exchanger of a recipient domain
+    ///
+    /// The builder connects to port 25 using the MX host as both connect
+    /// address and TLS SNI/verification domain. Both the MX records and the
+    /// address of the mail exchanger are resolved with `resolver`, as the
+    /// address lookup happens after the MX lookup the resolver is moved into
+    /// the returned future. All mail exchangers (sorted by preference,
+    /// including the one used for the builder) are returned too, so that less
+    /// preferred ones can be tried if connecting fails (using
+    /// `MxRecord::builder_with_resolver`).
+    pub fn for_mx<R>(
+        recipient_domain: &Domain,
+        resolver: R,
+    ) -> impl Future<Item = (ConnectionBuilder<Noop>, Vec<MxRecord>), Error = std_io::Error> + Send
+    where
+        R: Resolver + Send + 'static,
+    {
+        resolve_mx(&resolver, recipient_domain).and_then(move |records| {
+            records[0]
+                .builder_with_resolver(&resolver)
+                .map(move |builder| (builder, records))
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::{future, Future};
+
+    use std::io as std_io;
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+    use super::{
+        resolve_mx, HostLookupFuture, MxLookupFuture, MxRecord, MxResolver, Resolver, StdResolver,
+    };
+    use crate::{connect::ConnectionBuilder, data_types::Domain};
+
+    struct StubResolver(Vec<MxRecord>);
+
+    impl MxResolver for StubResolver {
+        fn lookup_mx(&self, _domain: &Domain) -> MxLookupFuture {
+            Box::new(future::ok(self.0.clone()))
+        }
+    }
+
+    /// resolves every host to the same fixed addresses
+    struct FixedResolver {
+        records: Vec<MxRecord>,
+        ips: Vec<IpAddr>,
+    }
+
+    impl Resolver for FixedResolver {
+        fn resolve_host(&self, _host: &Domain, port: u16) -> HostLookupFuture {
+            let addrs = self
+                .ips
+                .iter()
+                .map(|ip| SocketAddr::new(*ip, port))
+                .collect();
+            Box::new(future::ok(addrs))
+        }
+    }
+
+    impl MxResolver for FixedResolver {
+        fn lookup_mx(&self, _domain: &Domain) -> MxLookupFuture {
+            Box::new(future::ok(self.records.clone()))
+        }
+    }
+
+    fn ip(last: u8) -> IpAddr {
+        Ipv4Addr::new(192, 0, 2, last).into()
+    }
+
+    fn record(preference: u16, exchange: &str) -> MxRecord {
+        MxRecord {
+            preference,
+            exchange: Domain::from_unchecked(exchange),
+        }
+    }
+
+    #[test]
+    fn sorts_records_by_preference() {
+        let resolver = StubResolver(vec![
+            record(20, "mx2.they.test"),
+            record(10, "mx1.they.test"),
+            record(20, "mx3.they.test"),
+        ]);
+        let records = resolve_mx(&resolver, &Domain::from_unchecked("they.test"))
+            .wait()
+            .unwrap();
+        assert_eq!(
+            records,
+            vec![
+                record(10, "mx1.they.test"),
+                record(20, "mx2.they.test"),
+                record(20, "mx3.they.test"),
+            ]
+        );
+    }
+
+    #[test]
+    fn falls_back_to_implicit_mx() {
+        let resolver = StubResolver(vec![]);
+        let records = resolve_mx(&resolver, &Domain::from_unchecked("they.test"))
+            .wait()
+            .unwrap();
+        assert_eq!(records, vec![record(0, "they.test")]);
+    }
+
+    #[test]
+    fn new_with_resolver_uses_first_resolved_address() {
+        let resolver = FixedResolver {
+            records: vec![],
+            ips: vec![ip(1), ip(2)],
+        };
+        let builder = ConnectionBuilder::new_with_resolver(
+            Domain::from_unchecked("they.test"),
+            587,
+            &resolver,
+        )
+        .wait()
+        .unwrap();
+
+        let config = builder.build();
+        assert_eq!(config.addr, SocketAddr::new(ip(1), 587));
+        match config.security {
+            crate::Security::StartTls(tls_config) => {
+                assert_eq!(tls_config.domain.as_str(), "they.test")
+            }
+            other => panic!("unexpected security: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn new_with_resolver_fails_without_addresses() {
+        let resolver = FixedResolver {
+            records: vec![],
+            ips: vec![],
+        };
+        let err = ConnectionBuilder::new_with_resolver(
+            Domain::from_unchecked("they.test"),
+            587,
+            &resolver,
+        )
+        .wait()
+        .unwrap_err();
+        assert_eq!(err.kind(), std_io::ErrorKind::AddrNotAvailable);
+    }
+
+    #[test]
+    fn for_mx_uses_most_preferred_exchange() {
+        let resolver = FixedResolver {
+            records: vec![record(20, "backup.test"), record(5, "mx.they.test")],
+            ips: vec![ip(25)],
+        };
+        let (builder, records) =
+            ConnectionBuilder::for_mx(&Domain::from_unchecked("they.test"), resolver)
+                .wait()
+                .unwrap();
+        assert_eq!(
+            records,
+            vec![record(5, "mx.they.test"), record(20, "backup.test")]
+        );
+
+        let config = builder.build();
+        assert_eq!(config.addr, SocketAddr::new(ip(25), 25));
+        match config.security {
+            crate::Security::StartTls(tls_config) => {
+                assert_eq!(tls_config.domain.as_str(), "mx.they.test")
+            }
+            other => panic!("unexpected security: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn std_resolver_resolves_ip_literals() {
+        let host = Domain::from_unchecked("127.0.0.1");
+        let expected = vec![SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 25)];
+
+        // outside of a threadpool
+        let addrs = StdResolver.resolve_host(&host, 25).wait().unwrap();
+        assert_eq!(addrs, expected);
+
+        // on tokio's threadpool
+        let mut runtime = tokio::runtime::Runtime::new().unwrap();
+        let addrs = runtime
+            .block_on(StdResolver.resolve_host(&host, 25))
+            .unwrap();
+        assert_eq!(addrs, expected);
+    }
+
+    #[test]
+    fn std_resolver_cannot_look_up_mx_records() {
+        let err = StdResolver
+            .lookup_mx(&Domain::from_unchecked("they.test"))
+            .wait()
+            .unwrap_err();
+        assert_eq!(err.kind(), std_io::ErrorKind::Other);
+    }
+}
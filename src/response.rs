@@ -1,9 +1,15 @@
 //! Provides access to `Response`, `ResponseCode` and parsing parts (form impl `Cmd`'s)
+use std::hash::{Hash, Hasher};
+
 /// response of a smtp server
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+///
+/// Two responses are equal if they have the same code and lines, the
+/// (optionally retained) raw bytes are not compared.
+#[derive(Debug, Clone)]
 pub struct Response {
     code: ResponseCode,
     lines: Vec<String>,
+    raw: Option<Vec<u8>>,
 }
 
 impl Response {
@@ -15,7 +21,24 @@ impl Response {
         if lines.is_empty() {
             lines.push(String::new());
         }
-        Response { code, lines }
+        Response {
+            code,
+            lines,
+            raw: None,
+        }
+    }
+
+    /// sets the raw bytes the response was parsed from
+    pub(crate) fn set_raw(&mut self, raw: Vec<u8>) {
+        self.raw = Some(raw);
+    }
+
+    /// the bytes the response was parsed from, including codes, separators and `"\r\n"`s
+    ///
+    /// This is only available if it was enabled with `Io::set_retain_raw_responses`
+    /// (or `Connection::set_retain_raw_responses`) before the response was parsed.
+    pub fn raw(&self) -> Option<&[u8]> {
+        self.raw.as_deref()
     }
 
     /// true if the response code is unknown or indicates an error
@@ -23,6 +46,19 @@ impl Response {
         self.code.is_erroneous()
     }
 
+    /// true if the server indicates it's closing the connection (code `421`)
+    ///
+    /// This may be the response to any command, always meaning the
+    /// connection is going away.
+    pub fn is_shutdown(&self) -> bool {
+        self.code == codes::SERVICE_UNAVAILABLE
+    }
+
+    /// true if this is a valid greeting, i.e. the service is ready (code `220`)
+    pub fn is_service_ready(&self) -> bool {
+        self.code == codes::READY
+    }
+
     /// return the response code
     pub fn code(&self) -> ResponseCode {
         self.code
@@ -35,6 +71,34 @@ impl Response {
     pub fn msg(&self) -> &[String] {
         &self.lines
     }
+
+    /// true if the response has the given response code
+    pub fn code_matches(&self, code: ResponseCode) -> bool {
+        self.code == code
+    }
+
+    /// true if any line of the msg/payload contains `needle`
+    ///
+    /// The comparison is case sensitive and done per line, i.e.
+    /// `needle` can't span multiple lines.
+    pub fn message_contains(&self, needle: &str) -> bool {
+        self.lines.iter().any(|line| line.contains(needle))
+    }
+}
+
+impl PartialEq for Response {
+    fn eq(&self, other: &Response) -> bool {
+        self.code == other.code && self.lines == other.lines
+    }
+}
+
+impl Eq for Response {}
+
+impl Hash for Response {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.code.hash(state);
+        self.lines.hash(state);
+    }
 }
 
 /// The response code of used by smtp server.
@@ -69,6 +133,20 @@ impl ResponseCode {
         !self.is_positive() && !self.is_intermediate()
     }
 
+    /// the category of the code, i.e. the meaning of it's first digit (RFC 5321, 4.2.1)
+    ///
+    /// This is an alternative to the `is_*` methods allowing an exhaustive
+    /// `match`. Codes not starting with `2`-`5` are `ResponseCategory::Unknown`.
+    pub fn category(self) -> ResponseCategory {
+        match self.0[0] {
+            b'2' => ResponseCategory::PositiveCompletion,
+            b'3' => ResponseCategory::PositiveIntermediate,
+            b'4' => ResponseCategory::TransientNegative,
+            b'5' => ResponseCategory::PermanentNegative,
+            _ => ResponseCategory::Unknown,
+        }
+    }
+
     /// The actual bytes returned as response code.
     ///
     /// This could be for example `*b'250'`. I.e. it's
@@ -80,15 +158,37 @@ impl ResponseCode {
     }
 }
 
+/// the category of a response code, see `ResponseCode::category`
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum ResponseCategory {
+    /// `2yz`, the command was successful
+    PositiveCompletion,
+    /// `3yz`, the command was accepted but more data is needed (e.g. after `DATA`)
+    PositiveIntermediate,
+    /// `4yz`, the command failed but might succeed if retried later
+    TransientNegative,
+    /// `5yz`, the command failed and shouldn't be retried as is
+    PermanentNegative,
+    /// any other code, which isn't defined by RFC 5321
+    Unknown,
+}
+
 pub mod parser {
     use super::{Response, ResponseCode};
+    use crate::connect::SyntaxErrorHandling;
 
     use std::error::Error;
     use std::fmt::{self, Display};
     use std::str::{self, Utf8Error};
 
+    /// the max length of a response line (excluding the `"\r\n"`)
+    ///
+    /// RFC 5321 limits text lines to 1000 bytes including the `"\r\n"`.
+    pub const MAX_LINE_LENGTH: usize = 998;
+
     #[derive(Debug, Clone)]
     pub enum ParseError {
+        /// the line is shorter than 4 bytes or longer than `MAX_LINE_LENGTH`
         LineLength,
         CodeMsgSeparator,
         Utf8(Utf8Error),
@@ -101,6 +201,57 @@ pub mod parser {
             expected: ResponseCode,
             got: ResponseCode,
         },
+        /// the response has more lines than allowed by `ResponseLimits`
+        TooManyLines {
+            limit: usize,
+        },
+        /// the messages of the response are larger than allowed by `ResponseLimits`
+        ResponseTooLarge {
+            limit: usize,
+        },
+        /// the input ended before the last line of the response
+        Incomplete,
+        /// there is more input after the last line of the response
+        TrailingData,
+    }
+
+    /// limits for the size of a (multi line) response
+    ///
+    /// This guards against servers sending an unbounded number of
+    /// continuation lines.
+    #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+    pub struct ResponseLimits {
+        /// the max number of lines in a response
+        pub max_lines: usize,
+        /// the max total size of the messages of all lines of a response (in bytes)
+        pub max_size: usize,
+    }
+
+    impl ResponseLimits {
+        /// checks if a response with given number of lines and size is in the limits
+        pub fn check(&self, line_count: usize, size: usize) -> Result<(), ParseError> {
+            if line_count > self.max_lines {
+                Err(ParseError::TooManyLines {
+                    limit: self.max_lines,
+                })
+            } else if size > self.max_size {
+                Err(ParseError::ResponseTooLarge {
+                    limit: self.max_size,
+                })
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    /// defaults to max 128 lines and max 64KiB
+    impl Default for ResponseLimits {
+        fn default() -> Self {
+            ResponseLimits {
+                max_lines: 128,
+                max_size: 64 * 1024,
+            }
+        }
     }
 
     impl Display for ParseError {
@@ -118,7 +269,7 @@ pub mod parser {
     }
 
     pub fn parse_line(line: &[u8]) -> Result<ResponseLine, ParseError> {
-        if line.len() < 4 {
+        if line.len() < 4 || line.len() > MAX_LINE_LENGTH {
             return Err(ParseError::LineLength);
         }
         let (code, tail) = line.split_at(3);
@@ -180,15 +331,77 @@ pub mod parser {
         str::from_utf8(msg).map_err(ParseError::Utf8)
     }
 
+    /// parses exactly one (multi line) response from `input`
+    ///
+    /// The input has to consist of `"\r\n"` terminated lines, the last of them
+    /// being the last line of the response. This runs the same steps as
+    /// `Io::parse_response` (with the default `ResponseLimits` and
+    /// `SyntaxErrorHandling::Strict`) but never panics, so it can be used
+    /// to e.g. fuzz test the parser.
+    pub fn parse_response_bytes(input: &[u8]) -> Result<Response, ParseError> {
+        let limits = ResponseLimits::default();
+        let mut lines = Vec::new();
+        let mut size = 0;
+        let mut rest = input;
+
+        loop {
+            let eol = match rest.windows(2).position(|pair| pair == b"\r\n") {
+                Some(eol) => eol,
+                None if rest.len() > MAX_LINE_LENGTH => return Err(ParseError::LineLength),
+                None => return Err(ParseError::Incomplete),
+            };
+            let line = parse_line(&rest[..eol])?;
+            rest = &rest[eol + 2..];
+
+            let last = line.last_line;
+            size += line.msg.len();
+            lines.push(line);
+            limits.check(lines.len(), size)?;
+
+            if last {
+                break;
+            }
+        }
+
+        if !rest.is_empty() {
+            return Err(ParseError::TrailingData);
+        }
+
+        response_from_parsed_lines(lines)
+    }
+
     ///
     /// Ignores the `last_line` field in the iterator, the called is required to
     /// check if the last line (and no previous line) has the field set to `true`.
     ///
+    /// This uses `SyntaxErrorHandling::Strict`, i.e. a line with a response
+    /// code different to the one of the first line is an error.
+    ///
     /// # Panics
     ///
     /// Panics if the lines iterator does not return at last one line.
     ///
     pub fn response_from_parsed_lines<I>(lines: I) -> Result<Response, ParseError>
+    where
+        I: IntoIterator<Item = ResponseLine>,
+    {
+        response_from_parsed_lines_with_handling(lines, &SyntaxErrorHandling::Strict)
+    }
+
+    /// like `response_from_parsed_lines` but with configurable syntax error handling
+    ///
+    /// With `SyntaxErrorHandling::Lax` lines with a response code different to
+    /// the one of the first line are accepted, the code of the first line is
+    /// used for the whole response. (Some broken servers emit such responses.)
+    ///
+    /// # Panics
+    ///
+    /// Panics if the lines iterator does not return at last one line.
+    ///
+    pub fn response_from_parsed_lines_with_handling<I>(
+        lines: I,
+        syntax_error_handling: &SyntaxErrorHandling,
+    ) -> Result<Response, ParseError>
     where
         I: IntoIterator<Item = ResponseLine>,
     {
@@ -199,10 +412,22 @@ pub mod parser {
 
         for line in iter {
             if code != line.code {
-                return Err(ParseError::Code {
-                    expected: code,
-                    got: line.code,
-                });
+                match syntax_error_handling {
+                    SyntaxErrorHandling::Strict => {
+                        return Err(ParseError::Code {
+                            expected: code,
+                            got: line.code,
+                        });
+                    }
+                    SyntaxErrorHandling::Lax => {
+                        #[cfg(feature = "log")]
+                        log_facade::warn!(
+                            "multi line response with mismatching codes: expected {:?} got {:?}",
+                            code,
+                            line.code
+                        );
+                    }
+                }
             }
 
             messages.push(line.msg);
@@ -211,6 +436,7 @@ pub mod parser {
         Ok(Response {
             code,
             lines: messages,
+            raw: None,
         })
     }
 }
@@ -314,3 +540,238 @@ pub mod codes {
     ///  with it at all
     pub static TARGET_DOES_NOT_ACCEPT_MAIL: ResponseCode = ResponseCode(*b"556");
 }
+
+#[cfg(test)]
+mod test {
+
+    mod Response {
+        #![allow(non_snake_case)]
+        use super::super::{codes, Response};
+
+        fn multi_line_response() -> Response {
+            Response::new(
+                codes::OK,
+                vec![
+                    "they.test greets you".to_owned(),
+                    "SIZE 1024".to_owned(),
+                    "8BITMIME".to_owned(),
+                ],
+            )
+        }
+
+        #[test]
+        fn code_matches() {
+            let response = multi_line_response();
+            assert!(response.code_matches(codes::OK));
+            assert!(!response.code_matches(codes::READY));
+        }
+
+        #[test]
+        fn message_contains_searches_all_lines() {
+            let response = multi_line_response();
+            assert!(response.message_contains("greets"));
+            assert!(response.message_contains("SIZE 10"));
+            assert!(response.message_contains("8BITMIME"));
+            assert!(!response.message_contains("8bitmime"));
+            assert!(!response.message_contains("you\nSIZE"));
+        }
+
+        #[test]
+        fn equality_ignores_raw_bytes() {
+            let plain = multi_line_response();
+            let mut with_raw = multi_line_response();
+            with_raw.set_raw(b"250-they.test greets you\r\n".to_vec());
+            assert_eq!(plain, with_raw);
+        }
+    }
+
+    mod ResponseCode {
+        #![allow(non_snake_case)]
+        use super::super::{codes, parser::parse_code, ResponseCategory};
+
+        #[test]
+        fn category() {
+            use self::ResponseCategory::*;
+            assert_eq!(codes::OK.category(), PositiveCompletion);
+            assert_eq!(codes::READY.category(), PositiveCompletion);
+            assert_eq!(codes::START_MAIL_DATA.category(), PositiveIntermediate);
+            assert_eq!(codes::SERVICE_UNAVAILABLE.category(), TransientNegative);
+            assert_eq!(codes::MAILBOX_UNAVAILABLE.category(), PermanentNegative);
+            assert_eq!(parse_code(b'1', b'0', b'0').unwrap().category(), Unknown);
+            assert_eq!(parse_code(b'6', b'0', b'0').unwrap().category(), Unknown);
+        }
+    }
+
+    mod ResponseLimits {
+        #![allow(non_snake_case)]
+        use super::super::parser::{ParseError, ResponseLimits};
+
+        #[test]
+        fn checks_line_count_and_size() {
+            let limits = ResponseLimits {
+                max_lines: 2,
+                max_size: 10,
+            };
+            assert!(limits.check(2, 10).is_ok());
+            match limits.check(3, 0) {
+                Err(ParseError::TooManyLines { limit: 2 }) => {}
+                other => panic!("unexpected: {:?}", other),
+            }
+            match limits.check(1, 11) {
+                Err(ParseError::ResponseTooLarge { limit: 10 }) => {}
+                other => panic!("unexpected: {:?}", other),
+            }
+        }
+    }
+
+    mod parse_response_bytes {
+        use super::super::codes;
+        use super::super::parser::{parse_response_bytes, ParseError, MAX_LINE_LENGTH};
+
+        #[test]
+        fn parses_multi_line_response() {
+            let response =
+                parse_response_bytes(b"250-they.test greets you\r\n250 SMTPUTF8\r\n").unwrap();
+            assert_eq!(response.code(), codes::OK);
+            assert_eq!(response.msg(), &["they.test greets you", "SMTPUTF8"]);
+        }
+
+        #[test]
+        fn rejects_overlong_lines() {
+            let mut line = b"250 ".to_vec();
+            line.resize(MAX_LINE_LENGTH, b'a');
+            line.extend_from_slice(b"\r\n");
+            assert!(parse_response_bytes(&line).is_ok());
+
+            line.insert(5, b'a');
+            match parse_response_bytes(&line) {
+                Err(ParseError::LineLength) => {}
+                other => panic!("unexpected: {:?}", other),
+            }
+        }
+
+        #[test]
+        fn rejects_overlong_unterminated_lines() {
+            let mut line = b"250 ".to_vec();
+            line.resize(MAX_LINE_LENGTH + 1, b'a');
+            match parse_response_bytes(&line) {
+                Err(ParseError::LineLength) => {}
+                other => panic!("unexpected: {:?}", other),
+            }
+        }
+
+        #[test]
+        fn rejects_incomplete_and_trailing_input() {
+            match parse_response_bytes(b"") {
+                Err(ParseError::Incomplete) => {}
+                other => panic!("unexpected: {:?}", other),
+            }
+            match parse_response_bytes(b"250-first\r\n") {
+                Err(ParseError::Incomplete) => {}
+                other => panic!("unexpected: {:?}", other),
+            }
+            match parse_response_bytes(b"250 Ok\r\n250 Ok\r\n") {
+                Err(ParseError::TrailingData) => {}
+                other => panic!("unexpected: {:?}", other),
+            }
+        }
+
+        #[test]
+        fn rejects_non_utf8_continuation_lines() {
+            match parse_response_bytes(b"250-first\r\n250 \xff\xfe\r\n") {
+                Err(ParseError::Utf8(_)) => {}
+                other => panic!("unexpected: {:?}", other),
+            }
+        }
+
+        /// a simple xorshift generator, to not need a dependency for property tests
+        fn next(state: &mut u64) -> u64 {
+            *state ^= *state << 13;
+            *state ^= *state >> 7;
+            *state ^= *state << 17;
+            *state
+        }
+
+        #[test]
+        fn never_panics_on_arbitrary_input() {
+            // biased towards bytes which are meaningful for the parser
+            const ALPHABET: &[u8] = b"0123456789 -\r\nab\xff";
+            let mut state = 0x2545_f491_4f6c_dd1d;
+            for _ in 0..10_000 {
+                let len = (next(&mut state) % 64) as usize;
+                let input: Vec<u8> = (0..len)
+                    .map(|_| ALPHABET[(next(&mut state) % ALPHABET.len() as u64) as usize])
+                    .collect();
+                let _ = parse_response_bytes(&input);
+            }
+        }
+
+        #[test]
+        fn never_panics_on_mutated_responses() {
+            let valid = b"250-they.test greets you\r\n250-SIZE 1024\r\n250 8BITMIME\r\n";
+            let mut state = 0x9e37_79b9_7f4a_7c15;
+            for _ in 0..10_000 {
+                let mut input = valid.to_vec();
+                for _ in 0..(next(&mut state) % 4) {
+                    let idx = (next(&mut state) % input.len() as u64) as usize;
+                    input[idx] = next(&mut state) as u8;
+                }
+                let cut = (next(&mut state) % (input.len() as u64 + 1)) as usize;
+                let _ = parse_response_bytes(&input[..cut]);
+            }
+        }
+    }
+
+    mod response_from_parsed_lines_with_handling {
+        use super::super::parser::{
+            response_from_parsed_lines_with_handling, ParseError, ResponseLine,
+        };
+        use super::super::{codes, ResponseCode};
+        use crate::SyntaxErrorHandling;
+
+        fn line(code: ResponseCode, last_line: bool, msg: &str) -> ResponseLine {
+            ResponseLine {
+                code,
+                last_line,
+                msg: msg.to_owned(),
+            }
+        }
+
+        fn mismatched_lines() -> Vec<ResponseLine> {
+            vec![
+                line(codes::OK, false, "first"),
+                line(codes::READY, false, "second"),
+                line(codes::OK, true, "third"),
+            ]
+        }
+
+        #[test]
+        fn strict_rejects_mismatched_codes() {
+            let err = response_from_parsed_lines_with_handling(
+                mismatched_lines(),
+                &SyntaxErrorHandling::Strict,
+            )
+            .unwrap_err();
+
+            match err {
+                ParseError::Code { expected, got } => {
+                    assert_eq!(expected, codes::OK);
+                    assert_eq!(got, codes::READY);
+                }
+                other => panic!("unexpected error: {:?}", other),
+            }
+        }
+
+        #[test]
+        fn lax_keeps_first_code() {
+            let response = response_from_parsed_lines_with_handling(
+                mismatched_lines(),
+                &SyntaxErrorHandling::Lax,
+            )
+            .unwrap();
+
+            assert_eq!(response.code(), codes::OK);
+            assert_eq!(response.msg(), &["first", "second", "third"]);
+        }
+    }
+}
@@ -86,23 +86,29 @@
 //! # fn mock_run_with_tokio(f: impl Future<Item=(), Error=()>) { unimplemented!() }
 //! ```
 //!
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt::{self, Display};
 use std::io as std_io;
 use std::mem::replace;
+use std::str::{self, FromStr};
 
-use bytes::Bytes;
+use bytes::{Buf, Bytes};
 use futures::future::{self, Either, Future};
-use futures::stream::Stream;
+use futures::stream::{self, Stream};
 use futures::{Async, IntoFuture, Poll};
 use vec1::Vec1;
 
 use crate::{
-    chain::{chain, HandleErrorInChain, OnError},
+    chain::{chain, chain_collect, HandleErrorInChain, OnError},
     command::{self, params_with_smtputf8},
-    common::SetupTls,
+    common::{EhloData, SetupTls},
     connect::ConnectionConfig,
-    data_types::{ForwardPath, ReversePath},
-    error::{GeneralError, LogicError, MissingCapabilities},
-    {Cmd, Connection},
+    data_types::{AddressLiteral, EsmtpKeyword, EsmtpValue, ForwardPath, ReversePath, SyntaxError},
+    error::{GeneralError, LogicError, PreflightReason},
+    io::SmtpResult,
+    response::Response,
+    BoxedCmd, {Cmd, Connection},
 };
 
 /// Specifies if the mail requires SMTPUTF8 (or Mime8bit)
@@ -113,6 +119,51 @@ pub enum EncodingRequirement {
     Mime8bit,
 }
 
+impl EncodingRequirement {
+    /// orders the requirements, `None` < `Mime8bit` < `Smtputf8`
+    fn strength(self) -> u8 {
+        match self {
+            EncodingRequirement::None => 0,
+            EncodingRequirement::Mime8bit => 1,
+            EncodingRequirement::Smtputf8 => 2,
+        }
+    }
+}
+
+/// What `send_mail` does if the mail requires `Mime8bit` but the server lacks `8BITMIME`
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum OnUnsupported8bit {
+    /// send the mail anyway, at the callers risk
+    ///
+    /// The 8-bit data is send as is, without `BODY=8BITMIME`. Servers are
+    /// allowed to reject or mangle such mails.
+    Downgrade,
+    /// fail with `PreflightReason::Mime8bitUnsupported` without sending anything
+    #[default]
+    Fail,
+}
+
+/// error returned by `Mail::new_checked` if the declared encoding requirement is to weak
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct EncodingRequirementTooWeak {
+    /// the encoding requirement passed to `Mail::new_checked`
+    pub declared: EncodingRequirement,
+    /// the minimal encoding requirement the mail needs
+    pub detected: EncodingRequirement,
+}
+
+impl Display for EncodingRequirementTooWeak {
+    fn fmt(&self, fter: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fter,
+            "mail declared as {:?} but requires {:?}",
+            self.declared, self.detected
+        )
+    }
+}
+
+impl Error for EncodingRequirementTooWeak {}
+
 /// A simplified representation of a mail consisting of an `EncodingRequirement` and a buffer
 ///
 /// Note that the mail data will be placed internally inside a Bytes instance.
@@ -139,6 +190,51 @@ impl Mail {
         }
     }
 
+    /// like `Mail::new` but fails if the requirement is weaker than the detected one
+    ///
+    /// See `Mail::detect_encoding_requirement` for how it is detected.
+    pub fn new_checked(
+        encoding_requirement: EncodingRequirement,
+        buffer: impl Into<Bytes>,
+    ) -> Result<Self, EncodingRequirementTooWeak> {
+        let mail = Mail::new(encoding_requirement, buffer);
+        let detected = mail.detect_encoding_requirement();
+        if encoding_requirement.strength() < detected.strength() {
+            Err(EncodingRequirementTooWeak {
+                declared: encoding_requirement,
+                detected,
+            })
+        } else {
+            Ok(mail)
+        }
+    }
+
+    /// scans the mail for 8-bit bytes to find the minimal needed encoding requirement
+    ///
+    /// - a mail consisting only of 7-bit ascii requires nothing (`None`)
+    /// - a mail with utf-8 in the header requires `Smtputf8`
+    /// - a mail with 8-bit bytes anywhere else requires `Mime8bit`
+    ///
+    /// The header is assumed to end at the first empty line.
+    pub fn detect_encoding_requirement(&self) -> EncodingRequirement {
+        let data = self.raw_data();
+        if data.is_ascii() {
+            return EncodingRequirement::None;
+        }
+
+        let header_end = data
+            .windows(4)
+            .position(|window| window == b"\r\n\r\n")
+            .unwrap_or(data.len());
+        let header = &data[..header_end];
+
+        if !header.is_ascii() && str::from_utf8(header).is_ok() {
+            EncodingRequirement::Smtputf8
+        } else {
+            EncodingRequirement::Mime8bit
+        }
+    }
+
     /// true if `SMTPUTF8` is required
     pub fn needs_smtputf8(&self) -> bool {
         self.encoding_requirement == EncodingRequirement::Smtputf8
@@ -243,13 +339,9 @@ impl From<MailEnvelop> for (Mail, EnvelopData) {
 ///
 /// This type also keeps track of wether or not `SMTPUTF8` is required.
 ///
-/// # Temporary Limitations
-///
-/// Currently this type doesn't has a mail address parser, once I find
-/// a good crate for this it will be included. I.e. currently you
-/// have to make sure you mail is valid and then use `from_unchecked`
-/// to crate a `MailAddress`, this will also check if it's an internationalized
-/// mail address as it can do so without needing to check the grammar.
+/// Use `MailAddress::parse` (or `str::parse`) to create a validated
+/// `MailAddress`, or `from_unchecked` if the address was already validated.
+/// Both check if it's an internationalized mail address.
 #[derive(Debug, Clone)]
 pub struct MailAddress {
     //FIXME[dep/good mail address crate]: use that
@@ -286,6 +378,26 @@ impl MailAddress {
         }
     }
 
+    /// parses a mailbox i.e. `local-part@domain` (RFC 5321, RFC 6531)
+    ///
+    /// The local part can be a dot-string or a quoted string, the domain
+    /// a (potentially internationalized) domain name or an address literal
+    /// like `[127.0.0.1]`. Non-ascii characters are accepted, making the
+    /// address require `SMTPUTF8`.
+    pub fn parse(raw: &str) -> Result<Self, SyntaxError> {
+        let valid = raw
+            .rfind('@')
+            .map(|at| (&raw[..at], &raw[at + 1..]))
+            .map(|(local, domain)| is_valid_local_part(local) && is_valid_mail_domain(domain))
+            .unwrap_or(false);
+
+        if valid {
+            Ok(MailAddress::from_unchecked(raw))
+        } else {
+            Err(SyntaxError::MailAddress(raw.to_owned()))
+        }
+    }
+
     pub fn needs_smtputf8(&self) -> bool {
         self.needs_smtputf8
     }
@@ -295,15 +407,85 @@ impl MailAddress {
     }
 }
 
+/// max length of the local part in octets (RFC 5321, 4.5.3.1.1)
+const MAX_LOCAL_PART_LEN: usize = 64;
+/// max length of the domain in octets (RFC 5321, 4.5.3.1.2)
+const MAX_DOMAIN_LEN: usize = 255;
+
+fn is_valid_local_part(local: &str) -> bool {
+    if local.is_empty() || local.len() > MAX_LOCAL_PART_LEN {
+        return false;
+    }
+
+    if local.len() >= 2 && local.starts_with('"') && local.ends_with('"') {
+        let mut chars = local[1..local.len() - 1].chars();
+        while let Some(ch) = chars.next() {
+            let valid = match ch {
+                // quoted-pair
+                '\\' => chars
+                    .next()
+                    .map(|ch| (' '..='~').contains(&ch))
+                    .unwrap_or(false),
+                '"' => false,
+                ' '..='~' => true,
+                ch => !ch.is_ascii(),
+            };
+            if !valid {
+                return false;
+            }
+        }
+        true
+    } else {
+        local
+            .split('.')
+            .all(|atom| !atom.is_empty() && atom.chars().all(is_atext))
+    }
+}
+
+fn is_atext(ch: char) -> bool {
+    ch.is_ascii_alphanumeric() || "!#$%&'*+-/=?^_`{|}~".contains(ch) || !ch.is_ascii()
+}
+
+fn is_valid_mail_domain(domain: &str) -> bool {
+    if domain.starts_with('[') {
+        return domain.parse::<AddressLiteral>().is_ok();
+    }
+
+    let is_label_char = |ch: char| ch.is_alphanumeric() && (ch.is_ascii() || !ch.is_control());
+    domain.len() <= MAX_DOMAIN_LEN
+        && domain.split('.').all(|label| {
+            !label.is_empty()
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|ch| ch == '-' || is_label_char(ch))
+        })
+}
+
+impl FromStr for MailAddress {
+    type Err = SyntaxError;
+
+    fn from_str(inp: &str) -> Result<Self, Self::Err> {
+        MailAddress::parse(inp)
+    }
+}
+
+impl<'a> TryFrom<&'a str> for MailAddress {
+    type Error = SyntaxError;
+
+    fn try_from(inp: &'a str) -> Result<Self, Self::Error> {
+        MailAddress::parse(inp)
+    }
+}
+
 impl AsRef<str> for MailAddress {
     fn as_ref(&self) -> &str {
         self.as_str()
     }
 }
 
-impl Into<String> for MailAddress {
-    fn into(self) -> String {
-        self.raw
+impl From<MailAddress> for String {
+    fn from(addr: MailAddress) -> Self {
+        addr.raw
     }
 }
 
@@ -325,14 +507,84 @@ impl From<MailAddress> for ForwardPath {
 /// This is either `()` meaning it succeeded or
 /// a tuple of the index of the command which failed
 /// and the error with witch it failed. (Detecting that
-/// the server does not support SMTPUTF8 but it being required
-/// will fail "one the first command", i.e. index 0).
+/// the server does not support SMTPUTF8/8BITMIME but it being
+/// required, or that the mail is to large, will fail "one the
+/// first command" with `LogicError::PreflightRejected`, i.e. index 0).
 ///
 pub type MailSendResult = Result<(), (usize, LogicError)>;
 
+/// A summary of a successfully send mail, see `send_mail_with_summary`
+#[derive(Debug)]
+pub struct MailSendSummary {
+    /// the number of recipients the server accepted
+    pub accepted: usize,
+    /// the recipients the server rejected, with the error it rejected them with
+    pub rejected: Vec<(MailAddress, LogicError)>,
+    /// the response to the end of the mail data
+    pub data_response: Response,
+}
+
+/// The result of `send_mail_with_summary`
+///
+/// Like `MailSendResult`, but a `MailSendSummary` instead of `()` on success.
+pub type MailSendSummaryResult = Result<MailSendSummary, (usize, LogicError)>;
+
+/// The outcome of sending one of the mails of `SendAllMails::outcomes`
+///
+/// Unlike the items/errors of the `SendAllMails` stream this keeps
+/// the `LogicError` (and with it e.g. the response code), so
+/// it can be decided which mails should be retried later.
+#[derive(Debug)]
+pub struct MailOutcome {
+    /// the index of the mail in the mails passed to `SendAllMails`
+    pub index: usize,
+    /// the result of sending the mail
+    pub result: MailSendResult,
+}
+
+impl MailOutcome {
+    /// true if sending the mail failed, but retrying it later might succeed
+    ///
+    /// E.g. because the server responded with a `4xx` code, see
+    /// `LogicError::is_transient`.
+    pub fn is_transient_failure(&self) -> bool {
+        match &self.result {
+            Ok(()) => false,
+            Err((_idx, err)) => err.is_transient(),
+        }
+    }
+}
+
+/// The result of `Connection::preflight`
+#[derive(Debug)]
+pub struct PreflightResult {
+    /// the result of the `MAIL` command, if it failed no `RCPT` was send
+    pub mail: SmtpResult,
+    /// the result of the `RCPT` command for each recipient (in order)
+    pub recipients: Vec<(MailAddress, SmtpResult)>,
+}
+
+impl PreflightResult {
+    /// true if the server accepted the sender and all recipients
+    pub fn is_accepted(&self) -> bool {
+        self.mail.is_ok() && self.recipients.iter().all(|(_, result)| result.is_ok())
+    }
+
+    /// returns the recipients the server accepted
+    pub fn accepted(&self) -> impl Iterator<Item = &MailAddress> {
+        self.recipients
+            .iter()
+            .filter(|(_, result)| result.is_ok())
+            .map(|(addr, _)| addr)
+    }
+}
+
 /// Future returned by `send_mail`
+///
+/// Both `send_mail` and `Connection::send_mail` return an `impl Future`
+/// which can be boxed into this type, e.g. to store it in a struct field.
 pub type MailSendFuture =
-    Box<dyn Future<Item = (Connection, MailSendResult), Error = std_io::Error> + Send>;
+    Box<dyn Future<Item = (Connection, MailSendResult), Error = std_io::Error> + Send + 'static>;
 
 /// Sends a mail specified through `MailEnvelop` through the connection `con`.
 ///
@@ -347,45 +599,390 @@ pub fn send_mail<H>(
 where
     H: HandleErrorInChain,
 {
-    let use_smtputf8 = envelop.needs_smtputf8();
-    let (mail, EnvelopData { from, to: tos }) = envelop.into();
+    send_mail_with_8bit_handling(con, envelop, OnUnsupported8bit::Fail, on_error)
+}
+
+/// Sends a mail like `send_mail`, using `on_unsupported_8bit` if the server lacks `8BITMIME`.
+///
+/// With `OnUnsupported8bit::Fail` this is the same as `send_mail`. With
+/// `OnUnsupported8bit::Downgrade` a mail requiring `Mime8bit` is send to a
+/// server not supporting `8BITMIME` anyway, so callers which would rather
+/// re-encode the mail to 7bit should use `Fail` and handle the
+/// `PreflightReason::Mime8bitUnsupported` rejection instead.
+pub fn send_mail_with_8bit_handling<H>(
+    con: Connection,
+    envelop: MailEnvelop,
+    on_unsupported_8bit: OnUnsupported8bit,
+    on_error: H,
+) -> impl Future<Item = (Connection, MailSendResult), Error = std_io::Error> + Send
+where
+    H: HandleErrorInChain,
+{
+    let (mail, envelop_data) = envelop.into();
+    send_mail_to_paths(
+        con,
+        envelop_data.into(),
+        mail,
+        on_unsupported_8bit,
+        on_error,
+    )
+}
 
-    let check_mime_8bit_support =
-        !use_smtputf8 && mail.encoding_requirement() == EncodingRequirement::Mime8bit;
+/// Sends a mail to the given `forward_paths` using `reverse_path` through the connection `con`.
+///
+/// This is the lower level version of `send_mail` using the path types
+/// directly, which allows paths a `MailAddress` can not represent, e.g. the
+/// `Postmaster` recipient. Whether or not `SMTPUTF8` is needed is detected
+/// from the paths (any non us-ascii character) and the mail.
+pub fn send_mail_paths<H>(
+    con: Connection,
+    reverse_path: ReversePath,
+    forward_paths: Vec1<ForwardPath>,
+    mail: Mail,
+    on_error: H,
+) -> impl Future<Item = (Connection, MailSendResult), Error = std_io::Error> + Send
+where
+    H: HandleErrorInChain,
+{
+    let needs_smtputf8 = !reverse_path.as_str().is_ascii()
+        || forward_paths.iter().any(|path| !path.as_str().is_ascii());
+    let paths = MailPaths {
+        reverse_path,
+        forward_paths,
+        needs_smtputf8,
+    };
+    send_mail_to_paths(con, paths, mail, OnUnsupported8bit::Fail, on_error)
+}
 
-    if (use_smtputf8 && !con.has_capability("SMTPUTF8"))
-        || (check_mime_8bit_support && !con.has_capability("8BITMIME"))
-    {
-        return Either::B(future::ok((
-            con,
-            Err((
-                0,
-                MissingCapabilities::new_from_unchecked("SMTPUTF8").into(),
-            )),
-        )));
+/// the paths used in the `MAIL`/`RCPT` commands of a transaction
+struct MailPaths {
+    reverse_path: ReversePath,
+    forward_paths: Vec1<ForwardPath>,
+    needs_smtputf8: bool,
+}
+
+impl From<EnvelopData> for MailPaths {
+    fn from(envelop: EnvelopData) -> Self {
+        let needs_smtputf8 = envelop.needs_smtputf8();
+        let EnvelopData { from, to } = envelop;
+        let reverse_path = from
+            .map(ReversePath::from)
+            .unwrap_or_else(|| ReversePath::from_unchecked(""));
+        MailPaths {
+            reverse_path,
+            forward_paths: to.mapped(ForwardPath::from),
+            needs_smtputf8,
+        }
     }
+}
 
-    let reverse_path = from
-        .map(ReversePath::from)
-        .unwrap_or_else(|| ReversePath::from_unchecked(""));
+fn send_mail_to_paths<H>(
+    con: Connection,
+    paths: MailPaths,
+    mail: Mail,
+    on_unsupported_8bit: OnUnsupported8bit,
+    on_error: H,
+) -> impl Future<Item = (Connection, MailSendResult), Error = std_io::Error> + Send
+where
+    H: HandleErrorInChain,
+{
+    let encoding_requirement = mail.encoding_requirement();
+    let size = mail.raw_data().len();
+    let data_cmd = command::Data::from_buf(mail.into_raw_data()).boxed();
+    send_envelop_with_data(
+        con,
+        paths,
+        encoding_requirement,
+        on_unsupported_8bit,
+        Some(size),
+        data_cmd,
+        on_error,
+    )
+}
+
+/// Sends a mail with the given envelop and a streamed body through the connection `con`.
+///
+/// Works like `send_mail` but the body is streamed using `command::Data::new`
+/// instead of being buffered completely in memory. As the stream can not be
+/// scanned up front the `encoding_requirement` of the body has to be given
+/// explicitly, it's used for the `SMTPUTF8`/`8BITMIME` preflight checks. The
+/// servers `SIZE` limit is not checked up front as the body size is unknown.
+pub fn send_mail_streaming<S, H>(
+    con: Connection,
+    envelop: EnvelopData,
+    encoding_requirement: EncodingRequirement,
+    body: S,
+    on_error: H,
+) -> impl Future<Item = (Connection, MailSendResult), Error = std_io::Error> + Send
+where
+    S: Stream<Error = std_io::Error> + Send + 'static,
+    S::Item: Buf,
+    H: HandleErrorInChain,
+{
+    let data_cmd = command::Data::new(body).boxed();
+    send_envelop_with_data(
+        con,
+        envelop.into(),
+        encoding_requirement,
+        OnUnsupported8bit::Fail,
+        None,
+        data_cmd,
+        on_error,
+    )
+}
+
+fn send_envelop_with_data<H>(
+    con: Connection,
+    paths: MailPaths,
+    encoding_requirement: EncodingRequirement,
+    on_unsupported_8bit: OnUnsupported8bit,
+    size: Option<usize>,
+    data_cmd: BoxedCmd,
+    on_error: H,
+) -> impl Future<Item = (Connection, MailSendResult), Error = std_io::Error> + Send
+where
+    H: HandleErrorInChain,
+{
+    let cmd_chain = match mail_cmd_chain(
+        &con,
+        paths,
+        encoding_requirement,
+        on_unsupported_8bit,
+        size,
+        data_cmd,
+    ) {
+        Ok(cmd_chain) => cmd_chain,
+        Err(reason) => {
+            return Either::B(future::ok((
+                con,
+                Err((0, LogicError::PreflightRejected(reason))),
+            )));
+        }
+    };
+
+    let fut = con
+        .begin_transaction()
+        .and_then(move |con| chain(con, cmd_chain, on_error));
+
+    Either::A(fut)
+}
+
+/// creates the `MAIL`, `RCPT`.., `DATA` chain, failing if the preflight checks fail
+///
+/// If `size` is known and the server supports `SIZE` it is sent as `SIZE=<size>`
+/// parameter on `MAIL`. It's the size of the mail data before dot-stuffing, so
+/// it (and the local check against the advertised limit) is only an estimate.
+fn mail_cmd_chain(
+    con: &Connection,
+    paths: MailPaths,
+    encoding_requirement: EncodingRequirement,
+    on_unsupported_8bit: OnUnsupported8bit,
+    size: Option<usize>,
+    data_cmd: BoxedCmd,
+) -> Result<Vec<BoxedCmd>, PreflightReason> {
+    let use_smtputf8 =
+        paths.needs_smtputf8 || encoding_requirement == EncodingRequirement::Smtputf8;
+    let MailPaths {
+        reverse_path,
+        forward_paths,
+        ..
+    } = paths;
+
+    let check_mime_8bit_support = !use_smtputf8
+        && encoding_requirement == EncodingRequirement::Mime8bit
+        && on_unsupported_8bit == OnUnsupported8bit::Fail;
+
+    let size_limit = con.ehlo_data().and_then(EhloData::max_size);
+
+    let rejection = if use_smtputf8 && !con.has_capability("SMTPUTF8") {
+        Some(PreflightReason::Smtputf8Unsupported)
+    } else if check_mime_8bit_support && !con.has_capability("8BITMIME") {
+        Some(PreflightReason::Mime8bitUnsupported)
+    } else {
+        size.and_then(|actual| {
+            size_limit
+                .filter(|limit| actual > *limit)
+                .map(|limit| PreflightReason::MessageTooLarge { limit, actual })
+        })
+    };
+
+    if let Some(reason) = rejection {
+        return Err(reason);
+    }
 
     let mut mail_params = Default::default();
     if use_smtputf8 {
         mail_params = params_with_smtputf8(mail_params);
     }
-    let mut cmd_chain = vec![command::Mail {
-        reverse_path,
-        params: mail_params,
+    let mut mail = command::Mail::new(reverse_path);
+    mail.params = mail_params;
+    if let Some(size) = size.filter(|_| con.has_capability("SIZE")) {
+        mail = mail.with_param(
+            EsmtpKeyword::from_unchecked("SIZE"),
+            Some(EsmtpValue::from_unchecked(size.to_string())),
+        );
     }
-    .boxed()];
+    let mut cmd_chain = vec![mail.boxed()];
 
-    for to in tos.into_iter() {
-        cmd_chain.push(command::Recipient::new(to.into()).boxed());
+    for to in forward_paths.into_iter() {
+        cmd_chain.push(command::Recipient::new(to).boxed());
     }
 
-    cmd_chain.push(command::Data::from_buf(mail.into_raw_data()).boxed());
+    cmd_chain.push(data_cmd);
+    Ok(cmd_chain)
+}
 
-    Either::A(chain(con, cmd_chain, on_error))
+/// Sends a mail like `send_mail`, returning a `MailSendSummary` if it succeeded.
+///
+/// This is mainly useful with a `on_error` handler which allows some of the
+/// `RCPT TO:` commands to fail, as the summary contains which recipients
+/// were rejected. If a command the handler decided to continue after isn't
+/// a `RCPT TO:` (i.e. `MAIL` or `DATA` failed) the result is the error of
+/// that command, as if the chain had been stopped.
+pub fn send_mail_with_summary<H>(
+    con: Connection,
+    envelop: MailEnvelop,
+    on_error: H,
+) -> impl Future<Item = (Connection, MailSendSummaryResult), Error = std_io::Error> + Send
+where
+    H: HandleErrorInChain,
+{
+    let (mail, envelop_data) = envelop.into();
+    let recipients = envelop_data.to.clone();
+    let encoding_requirement = mail.encoding_requirement();
+    let size = mail.raw_data().len();
+    let data_cmd = command::Data::from_buf(mail.into_raw_data()).boxed();
+
+    let cmd_chain = match mail_cmd_chain(
+        &con,
+        envelop_data.into(),
+        encoding_requirement,
+        OnUnsupported8bit::Fail,
+        Some(size),
+        data_cmd,
+    ) {
+        Ok(cmd_chain) => cmd_chain,
+        Err(reason) => {
+            return Either::B(future::ok((
+                con,
+                Err((0, LogicError::PreflightRejected(reason))),
+            )));
+        }
+    };
+
+    let fut = con
+        .begin_transaction()
+        .and_then(move |con| chain_collect(con, cmd_chain, on_error))
+        .map(move |(con, result)| {
+            let summary = result.and_then(|results| summarize(recipients, results));
+            (con, summary)
+        });
+
+    Either::A(fut)
+}
+
+/// turns the results of the `MAIL`, `RCPT`.., `DATA` chain into a summary
+fn summarize(recipients: Vec1<MailAddress>, results: Vec<SmtpResult>) -> MailSendSummaryResult {
+    let data_idx = recipients.len() + 1;
+    let mut results = results.into_iter();
+    // the chain was not stopped, so there is a result for each command
+    let mail_result = results.next().expect("missing MAIL result");
+    let data_result = results.next_back().expect("missing DATA result");
+    mail_result.map_err(|err| (0, err))?;
+
+    let mut accepted = 0;
+    let mut rejected = Vec::new();
+    for (to, result) in recipients.into_iter().zip(results) {
+        match result {
+            Ok(_) => accepted += 1,
+            Err(err) => rejected.push((to, err)),
+        }
+    }
+
+    let data_response = data_result.map_err(|err| (data_idx, err))?;
+    Ok(MailSendSummary {
+        accepted,
+        rejected,
+        data_response,
+    })
+}
+
+/// Sends a mail through `con` using at most `max_recipients_per_txn` recipients per transaction.
+///
+/// Many servers limit the number of recipients per mail transaction (often
+/// to 100) and reject any further `RCPT TO:` with `452 Too many recipients`.
+/// If `max_recipients_per_txn` is `Some` and the mail has more recipients
+/// they are split into chunks of (at most) that size and the same mail is
+/// send in one `MAIL`/`RCPT`/`DATA` transaction per chunk, one after another
+/// on the same connection.
+///
+/// The result contains one `MailSendResult` per transaction, in order, i.e.
+/// the `n`-th result is for the recipients starting at the
+/// `n * max_recipients_per_txn`-th recipient. A failing transaction does
+/// not stop the following ones from being send. `None` (or `Some(0)`) means
+/// no limit, which sends the mail in a single transaction.
+///
+/// The indices of errors are the same as if the mail had been send in a
+/// single transaction, i.e. `0` is `MAIL`, `i + 1` is the `RCPT TO:` of the
+/// `i`-th recipient of `envelop` and `recipients + 1` is `DATA`.
+pub fn send_mail_with_recipient_limit<H>(
+    con: Connection,
+    envelop: MailEnvelop,
+    max_recipients_per_txn: Option<usize>,
+    on_error: H,
+) -> impl Future<Item = (Connection, Vec<MailSendResult>), Error = std_io::Error> + Send
+where
+    H: HandleErrorInChain + Clone,
+{
+    let (mail, EnvelopData { from, to }) = envelop.into();
+    let recipient_count = to.len();
+    let chunks = match max_recipients_per_txn {
+        Some(max) if max > 0 && to.len() > max => to
+            .chunks(max)
+            .map(|chunk| Vec1::try_from_vec(chunk.to_vec()).expect("chunks are never empty"))
+            .collect(),
+        _ => vec![to],
+    };
+
+    let mut offset = 0;
+    let envelops = chunks.into_iter().map(move |to| {
+        let chunk_offset = offset;
+        offset += to.len();
+        let envelop_data = EnvelopData {
+            from: from.clone(),
+            to,
+        };
+        (
+            chunk_offset,
+            MailEnvelop::from((mail.clone(), envelop_data)),
+        )
+    });
+
+    futures::stream::iter_ok(envelops).fold(
+        (con, Vec::new()),
+        move |(con, mut results), (chunk_offset, envelop)| {
+            let chunk_len = envelop.to_address().len();
+            send_mail(con, envelop, on_error.clone()).map(move |(con, result)| {
+                let result = result.map_err(|(idx, err)| {
+                    let idx = original_cmd_index(idx, chunk_offset, chunk_len, recipient_count);
+                    (idx, err)
+                });
+                results.push(result);
+                (con, results)
+            })
+        },
+    )
+}
+
+/// maps the command index of a chunk's transaction to the index in a single transaction
+fn original_cmd_index(idx: usize, offset: usize, chunk_len: usize, recipients: usize) -> usize {
+    if idx == 0 {
+        0
+    } else if idx <= chunk_len {
+        offset + idx
+    } else {
+        recipients + 1
+    }
 }
 
 impl Connection {
@@ -403,6 +1000,182 @@ impl Connection {
         send_mail(self, envelop, OnError::StopAndReset)
     }
 
+    /// Sends a mail specified through `MailEnvelop` through this connection
+    /// using the given strategy to handle failing commands.
+    ///
+    /// Like `Connection::send_mail` but e.g. `OnError::Stop` can be used to
+    /// not send `RSET` after a failed command, see `send_mail`.
+    pub fn send_mail_with<H>(
+        self,
+        envelop: MailEnvelop,
+        on_error: H,
+    ) -> impl Future<Item = (Connection, MailSendResult), Error = std_io::Error> + Send
+    where
+        H: HandleErrorInChain,
+    {
+        send_mail(self, envelop, on_error)
+    }
+
+    /// Sends a mail to the given `forward_paths` using `reverse_path` through this connection.
+    ///
+    /// Like `Connection::send_mail` but with the path types instead of a
+    /// `MailEnvelop`, see `send_mail_paths`.
+    pub fn send_mail_paths(
+        self,
+        reverse_path: ReversePath,
+        forward_paths: Vec1<ForwardPath>,
+        mail: Mail,
+    ) -> impl Future<Item = (Connection, MailSendResult), Error = std_io::Error> + Send {
+        send_mail_paths(
+            self,
+            reverse_path,
+            forward_paths,
+            mail,
+            OnError::StopAndReset,
+        )
+    }
+
+    /// Sends a mail like `send_mail`, using `on_unsupported_8bit` if the server lacks `8BITMIME`.
+    ///
+    /// See `send_mail_with_8bit_handling`.
+    pub fn send_mail_with_8bit_handling(
+        self,
+        envelop: MailEnvelop,
+        on_unsupported_8bit: OnUnsupported8bit,
+    ) -> impl Future<Item = (Connection, MailSendResult), Error = std_io::Error> + Send {
+        send_mail_with_8bit_handling(self, envelop, on_unsupported_8bit, OnError::StopAndReset)
+    }
+
+    /// Sends a mail like `send_mail_with`, returning a summary including the rejected recipients.
+    ///
+    /// See `send_mail_with_summary`.
+    pub fn send_mail_with_summary<H>(
+        self,
+        envelop: MailEnvelop,
+        on_error: H,
+    ) -> impl Future<Item = (Connection, MailSendSummaryResult), Error = std_io::Error> + Send
+    where
+        H: HandleErrorInChain,
+    {
+        send_mail_with_summary(self, envelop, on_error)
+    }
+
+    /// Sends a mail through this connection, keeping it usable for the next mail.
+    ///
+    /// This is the path for sending mails one by one on a kept alive
+    /// connection, between the one-shot `connect_send_quit` and the stream
+    /// based `send_all_mails`. Unlike `send_mail` no `RSET` is send directly
+    /// after a failed command, instead the (then still open) transaction is
+    /// reset before the next mail is send (see `begin_transaction`), i.e.
+    /// `RSET` is only send if another mail is send on the connection. Just
+    /// like with `send_mail` the returned connection can be used for further
+    /// mails independent of the result.
+    pub fn send_mail_keep(
+        self,
+        envelop: MailEnvelop,
+    ) -> impl Future<Item = (Connection, MailSendResult), Error = std_io::Error> + Send {
+        send_mail(self, envelop, OnError::Stop)
+    }
+
+    /// Checks if the server would accept a mail with given envelop and body size, without sending it.
+    ///
+    /// This sends `MAIL` (with `SIZE=<body_len>` if the server supports
+    /// `SIZE`) and a `RCPT` for each recipient, then aborts the transaction
+    /// with `RSET`. `DATA` is never send. If `MAIL` fails no `RCPT` is send.
+    ///
+    /// Note that a server accepting the envelop doesn't guarantee that it
+    /// accepts the mail later on, e.g. because of it's content.
+    pub fn preflight(
+        self,
+        envelop: EnvelopData,
+        body_len: usize,
+    ) -> impl Future<Item = (Connection, PreflightResult), Error = std_io::Error> + Send {
+        let use_smtputf8 = envelop.needs_smtputf8();
+        let EnvelopData { from, to: tos } = envelop;
+
+        let reverse_path = from
+            .map(ReversePath::from)
+            .unwrap_or_else(|| ReversePath::from_unchecked(""));
+        let mut mail = command::Mail::new(reverse_path);
+        if use_smtputf8 {
+            mail.params = params_with_smtputf8(mail.params);
+        }
+        if self.has_capability("SIZE") {
+            mail = mail.with_param(
+                EsmtpKeyword::from_unchecked("SIZE"),
+                Some(EsmtpValue::from_unchecked(body_len.to_string())),
+            );
+        }
+
+        self.begin_transaction()
+            .and_then(move |con| con.send(mail))
+            .and_then(move |(con, mail_result)| {
+                if mail_result.is_err() {
+                    let result = PreflightResult {
+                        mail: mail_result,
+                        recipients: Vec::new(),
+                    };
+                    return Either::A(future::ok((con, result)));
+                }
+
+                let fut = stream::iter_ok(tos)
+                    .fold((con, Vec::new()), |(con, mut recipients), to| {
+                        con.send(command::Recipient::new(to.clone().into())).map(
+                            move |(con, result)| {
+                                recipients.push((to, result));
+                                (con, recipients)
+                            },
+                        )
+                    })
+                    .and_then(move |(con, recipients)| {
+                        con.reset().map(move |(con, _response)| {
+                            let result = PreflightResult {
+                                mail: mail_result,
+                                recipients,
+                            };
+                            (con, result)
+                        })
+                    });
+
+                Either::B(fut)
+            })
+    }
+
+    /// Sends a mail with a streamed body through this connection.
+    ///
+    /// Like `Connection::send_mail` but the body isn't buffered in memory,
+    /// see `send_mail_streaming` for more details.
+    pub fn send_mail_streaming<S>(
+        self,
+        envelop: EnvelopData,
+        encoding_requirement: EncodingRequirement,
+        body: S,
+    ) -> impl Future<Item = (Connection, MailSendResult), Error = std_io::Error> + Send
+    where
+        S: Stream<Error = std_io::Error> + Send + 'static,
+        S::Item: Buf,
+    {
+        send_mail_streaming(
+            self,
+            envelop,
+            encoding_requirement,
+            body,
+            OnError::StopAndReset,
+        )
+    }
+
+    /// Sends a mail through this connection, splitting the recipients over multiple transactions.
+    ///
+    /// Like `Connection::send_mail` but uses at most `max_recipients_per_txn`
+    /// recipients per transaction, see `send_mail_with_recipient_limit`.
+    pub fn send_mail_with_recipient_limit(
+        self,
+        envelop: MailEnvelop,
+        max_recipients_per_txn: Option<usize>,
+    ) -> impl Future<Item = (Connection, Vec<MailSendResult>), Error = std_io::Error> + Send {
+        send_mail_with_recipient_limit(self, envelop, max_recipients_per_txn, OnError::StopAndReset)
+    }
+
     /// Sends all mails from mails through the connection.
     ///
     /// The connection is moved into the `SendAllMails` adapter
@@ -454,7 +1227,8 @@ impl Connection {
     ///
     /// `Connection::connect_send_quit(config, one(mail))`
     ///
-    /// To get back a `Vec` of results you can use:
+    /// To get back a `Vec` of results you can use `collect_all_results(stream)`,
+    /// which is (roughly) the same as:
     ///
     /// `stream.then(|result| Ok(result)).collect()`
     ///
@@ -470,15 +1244,12 @@ impl Connection {
     /// # use futures::{stream, Future, Stream};
     /// # use new_tokio_smtp::{Connection, ConnectionConfig, command};
     /// # use new_tokio_smtp::error::GeneralError;
-    /// # use new_tokio_smtp::send_mail::MailEnvelop;
+    /// # use new_tokio_smtp::send_mail::{collect_all_results, MailEnvelop};
     /// # let config: ConnectionConfig<command::Noop> = unimplemented!();
     /// # let mail: Result<MailEnvelop, GeneralError> = unimplemented!();
     /// # // We only have this overhead as we skipped any (fallible) mail encoding process
     /// // note that the map_err is only needed as `!` isn't stable yet
-    /// let fut = Connection::connect_send_quit(config, one(mail))
-    ///     //Stream::collect is conceptually broken in futures v0.1
-    ///     .then(|res| Result::Ok::<_, ()>(res))
-    ///     .collect();
+    /// let fut = collect_all_results(Connection::connect_send_quit(config, one(mail)));
     /// # let _ = fut;
     /// ```
     ///
@@ -501,6 +1272,50 @@ impl Connection {
 
         fut
     }
+
+    /// Like `connect_send_quit` but yields a `MailOutcome` for each mail
+    ///
+    /// Logic errors of sending a mail are part of the `MailOutcome` instead of
+    /// being turned into a `GeneralError::Cmd`, so that e.g. mails which failed
+    /// with a transient error can be re-queued. Connecting failures, I/O-Errors
+    /// and the errors passed in through `mails` are still yielded as errors.
+    pub fn connect_send_quit_outcomes<A, E, I, T>(
+        config: ConnectionConfig<A, T>,
+        mails: I,
+    ) -> impl Stream<Item = MailOutcome, Error = E>
+    where
+        A: Cmd,
+        E: From<GeneralError>,
+        I: IntoIterator<Item = Result<MailEnvelop, E>>,
+        T: SetupTls,
+    {
+        let fut = Connection::connect(config)
+            .then(|res| match res {
+                Err(err) => Err(E::from(GeneralError::from(err))),
+                Ok(con) => Ok(SendAllMails::new(con, mails)
+                    .outcomes()
+                    .quit_on_completion()),
+            })
+            .flatten_stream();
+
+        fut
+    }
+}
+
+/// drives the stream to completion, collecting all items _and_ errors
+///
+/// Unlike `Stream::collect` this does not stop on the first error, which
+/// is needed for streams like `SendAllMails` which yield one result per
+/// mail.
+//FIXME[futures/v>=0.2] use Never instead of ()
+pub fn collect_all_results<S>(
+    stream: S,
+) -> impl Future<Item = Vec<Result<S::Item, S::Error>>, Error = ()>
+where
+    S: Stream,
+{
+    //Stream::collect is conceptually broken in futures v0.1
+    stream.then(Ok).collect()
 }
 
 /// Adapter to send all mails from an iterable instance through a smtp connection.
@@ -510,6 +1325,8 @@ pub struct SendAllMails<I> {
     //FIXME[rust/impl Trait in struct]
     pending:
         Option<Box<dyn Future<Item = (Connection, MailSendResult), Error = std_io::Error> + Send>>,
+    /// index of the next mail taken from `mails`
+    next_index: usize,
 }
 
 impl<I, E> SendAllMails<I>
@@ -526,9 +1343,17 @@ where
             mails: mails.into_iter(),
             con: Some(con),
             pending: None,
+            next_index: 0,
         }
     }
 
+    /// turns the adapter into a stream of `MailOutcome`'s
+    ///
+    /// See `MailOutcome` for details.
+    pub fn outcomes(self) -> MailOutcomes<I> {
+        MailOutcomes { inner: self }
+    }
+
     /// takes the connection out of the adapter
     ///
     /// - if there currently is a pending future this will always be `None`
@@ -542,7 +1367,7 @@ where
     ///
     /// returns the currently set connection, if any
     pub fn set_connection(&mut self, con: Connection) -> Option<Connection> {
-        ::std::mem::replace(&mut self.con, Some(con))
+        self.con.replace(con)
     }
 
     /// true if a mail is currently in the process of being send
@@ -570,6 +1395,15 @@ where
         })
     }
 
+    /// Sends all mails, quits the connection and resolves to the results of all mails.
+    ///
+    /// This doesn't stop on the first error, the result `Vec` contains
+    /// one entry for each mail. (This is `collect_all_results` applied
+    /// to `quit_on_completion`.)
+    pub fn collect_all(self) -> impl Future<Item = Vec<Result<(), E>>, Error = ()> {
+        collect_all_results(self.quit_on_completion())
+    }
+
     /// Calls a closure once the stream completed with the connection (if there is one).
     ///
     /// The closure can resolve to a future which is resolved, but the result of
@@ -601,8 +1435,25 @@ where
     type Item = ();
     type Error = E;
 
-    //FIXME[futures/async streams]
     fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        match try_ready!(self.poll_outcome()) {
+            None => Ok(Async::Ready(None)),
+            Some(MailOutcome { result: Ok(()), .. }) => Ok(Async::Ready(Some(()))),
+            Some(MailOutcome {
+                result: Err((_idx, err)),
+                ..
+            }) => Err(E::from(GeneralError::from(err))),
+        }
+    }
+}
+
+impl<I, E> SendAllMails<I>
+where
+    I: Iterator<Item = Result<MailEnvelop, E>>,
+    E: From<GeneralError>,
+{
+    //FIXME[futures/async streams]
+    fn poll_outcome(&mut self) -> Poll<Option<MailOutcome>, E> {
         loop {
             if let Some(mut pending) = self.pending.take() {
                 return match pending.poll() {
@@ -612,16 +1463,20 @@ where
                     }
                     Ok(Async::Ready((con, result))) => {
                         self.con = Some(con);
-                        match result {
-                            Ok(()) => Ok(Async::Ready(Some(()))),
-                            Err((_idx, err)) => Err(E::from(GeneralError::from(err))),
-                        }
+                        Ok(Async::Ready(Some(MailOutcome {
+                            index: self.next_index - 1,
+                            result,
+                        })))
                     }
                     Err(io_error) => Err(E::from(GeneralError::from(io_error))),
                 };
             }
 
-            return match self.mails.next() {
+            let next = self.mails.next();
+            if next.is_some() {
+                self.next_index += 1;
+            }
+            return match next {
                 None => Ok(Async::Ready(None)),
                 Some(Ok(mail)) => {
                     if let Some(con) = self.con.take() {
@@ -640,6 +1495,48 @@ where
     }
 }
 
+/// Stream of the `MailOutcome` of each mail, created with `SendAllMails::outcomes`
+pub struct MailOutcomes<I> {
+    inner: SendAllMails<I>,
+}
+
+impl<I, E> MailOutcomes<I>
+where
+    I: Iterator<Item = Result<MailEnvelop, E>>,
+    E: From<GeneralError>,
+{
+    /// takes the connection out of the adapter, see `SendAllMails::take_connection`
+    pub fn take_connection(&mut self) -> Option<Connection> {
+        self.inner.take_connection()
+    }
+
+    /// Quits the contained connection once the stream is completed.
+    ///
+    /// See `SendAllMails::quit_on_completion`.
+    pub fn quit_on_completion(self) -> impl Stream<Item = MailOutcome, Error = E> {
+        OnCompletion::new(self, |stream| {
+            if let Some(con) = stream.take_connection() {
+                Either::A(con.quit().then(|_| Ok(())))
+            } else {
+                Either::B(future::ok(()))
+            }
+        })
+    }
+}
+
+impl<I, E> Stream for MailOutcomes<I>
+where
+    I: Iterator<Item = Result<MailEnvelop, E>>,
+    E: From<GeneralError>,
+{
+    type Item = MailOutcome;
+    type Error = E;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        self.inner.poll_outcome()
+    }
+}
+
 /// Stream adapt resolving one function/future after the stream completes
 ///
 /// If `S` is fused calling the stream adapter after completion is fine,
@@ -753,12 +1650,129 @@ mod test {
 
     fn assert_send(_: &impl Send) {}
 
-    #[allow(unused)]
+    #[allow(unused, clippy::diverging_sub_expression)]
     fn assert_send_in_send_out() {
         let config: ConnectionConfig<command::Noop> = unimplemented!();
         let mails: Vec<Result<MailEnvelop, GeneralError>> = unimplemented!();
         assert_send(&mails);
         let fut = Connection::connect_send_quit(config, mails);
         assert_send(&fut);
+        let mails: Vec<Result<MailEnvelop, GeneralError>> = unimplemented!();
+        let config: ConnectionConfig<command::Noop> = unimplemented!();
+        let fut = Connection::connect_send_quit_outcomes(config, mails);
+        assert_send(&fut);
+    }
+
+    mod MailAddress {
+        #![allow(non_snake_case)]
+        use crate::{data_types::SyntaxError, send_mail::MailAddress};
+        use std::convert::TryFrom;
+
+        #[test]
+        fn parses_valid_addresses() {
+            for addr in &[
+                "user@host.test",
+                "first.last+tag@sub.host.test",
+                "\"quoted @ local\"@host.test",
+                "user@[127.0.0.1]",
+                "user@[IPv6:2001:db8::1]",
+            ] {
+                let parsed: MailAddress = addr.parse().unwrap();
+                assert_eq!(parsed.as_str(), *addr);
+                assert!(!parsed.needs_smtputf8());
+            }
+        }
+
+        #[test]
+        fn parses_internationalized_addresses() {
+            let addr = MailAddress::try_from("j\u{f6}rg@b\u{fc}cher.test").unwrap();
+            assert!(addr.needs_smtputf8());
+        }
+
+        #[test]
+        fn rejects_invalid_addresses() {
+            for addr in &[
+                "",
+                "no-at-sign",
+                "@host.test",
+                "user@",
+                "two..dots@host.test",
+                ".leading@host.test",
+                "user@host..test",
+                "user@-host.test",
+                "spa ce@host.test",
+                "user@[not-a-literal]",
+                "\"unterminated@host.test",
+            ] {
+                assert_eq!(
+                    addr.parse::<MailAddress>().unwrap_err(),
+                    SyntaxError::MailAddress(addr.to_string())
+                );
+            }
+        }
+    }
+
+    mod Mail {
+        #![allow(non_snake_case)]
+        use crate::send_mail::{EncodingRequirement, EncodingRequirementTooWeak, Mail};
+
+        #[test]
+        fn detects_ascii() {
+            let mail = Mail::new(EncodingRequirement::None, "Subject: hy\r\n\r\nbody\r\n");
+            assert_eq!(
+                mail.detect_encoding_requirement(),
+                EncodingRequirement::None
+            );
+        }
+
+        #[test]
+        fn detects_8bit_body() {
+            let mail = Mail::new(
+                EncodingRequirement::None,
+                &b"Subject: hy\r\n\r\nb\xf6dy\r\n"[..],
+            );
+            assert_eq!(
+                mail.detect_encoding_requirement(),
+                EncodingRequirement::Mime8bit
+            );
+        }
+
+        #[test]
+        fn detects_utf8_header() {
+            let mail = Mail::new(
+                EncodingRequirement::None,
+                "Subject: h\u{fc}te\r\n\r\nbody\r\n",
+            );
+            assert_eq!(
+                mail.detect_encoding_requirement(),
+                EncodingRequirement::Smtputf8
+            );
+        }
+
+        #[test]
+        fn new_checked_rejects_weaker_requirement() {
+            let err = Mail::new_checked(
+                EncodingRequirement::Mime8bit,
+                "Subject: h\u{fc}te\r\n\r\nbody\r\n",
+            )
+            .unwrap_err();
+            assert_eq!(
+                err,
+                EncodingRequirementTooWeak {
+                    declared: EncodingRequirement::Mime8bit,
+                    detected: EncodingRequirement::Smtputf8
+                }
+            );
+        }
+
+        #[test]
+        fn new_checked_accepts_stronger_requirement() {
+            let mail = Mail::new_checked(
+                EncodingRequirement::Smtputf8,
+                "Subject: hy\r\n\r\nb\u{f6}dy\r\n",
+            )
+            .unwrap();
+            assert!(mail.needs_smtputf8());
+        }
     }
 }
@@ -0,0 +1,132 @@
+//! xtext encoding/decoding (RFC 3461) as used by e.g. the `AUTH=`, `ENVID` and `ORCPT` parameters
+//!
+//! In xtext all bytes outside of `'!'..='~'` as well as `'+'` and `'='`
+//! are encoded as `"+XX"` where `XX` are two upper case hex digits.
+use std::error::Error;
+use std::fmt::{self, Display, Write};
+
+/// xtext encodes the input, the output is always a valid esmtp-value
+///
+/// (Use `EsmtpValue::from_encoded` to directly get an `EsmtpValue`.)
+pub fn encode(raw: &str) -> String {
+    let mut out = String::with_capacity(raw.len());
+    for bch in raw.bytes() {
+        if needs_encoding(bch) {
+            //UNWRAP_SAFE: writing to a String can't fail
+            write!(out, "+{:02X}", bch).unwrap();
+        } else {
+            out.push(bch as char);
+        }
+    }
+    out
+}
+
+/// decodes a xtext encoded string
+pub fn decode(encoded: &str) -> Result<String, XtextError> {
+    let bytes = encoded.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        let bch = bytes[idx];
+        if bch == b'+' {
+            let hex = bytes
+                .get(idx + 1..idx + 3)
+                .and_then(|hex| Some(hex_value(hex[0])? << 4 | hex_value(hex[1])?))
+                .ok_or(XtextError::InvalidHexChar { position: idx })?;
+            out.push(hex);
+            idx += 3;
+        } else if needs_encoding(bch) {
+            return Err(XtextError::InvalidChar { position: idx });
+        } else {
+            out.push(bch);
+            idx += 1;
+        }
+    }
+    String::from_utf8(out).map_err(|_| XtextError::NotUtf8)
+}
+
+fn needs_encoding(bch: u8) -> bool {
+    !(b'!'..=b'~').contains(&bch) || bch == b'+' || bch == b'='
+}
+
+/// RFC 3461 only allows upper case hex digits
+fn hex_value(bch: u8) -> Option<u8> {
+    match bch {
+        b'0'..=b'9' => Some(bch - b'0'),
+        b'A'..=b'F' => Some(bch - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// error returned when decoding invalid xtext
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum XtextError {
+    /// a byte at given position has to be encoded but wasn't
+    InvalidChar { position: usize },
+    /// the `'+'` at the given position isn't followed by two (upper case) hex digits
+    InvalidHexChar { position: usize },
+    /// the decoded bytes are not valid utf-8
+    NotUtf8,
+}
+
+impl Display for XtextError {
+    fn fmt(&self, fter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            XtextError::InvalidChar { position } => {
+                write!(fter, "unencoded char in xtext at {}", position)
+            }
+            XtextError::InvalidHexChar { position } => {
+                write!(fter, "malformed hex char in xtext at {}", position)
+            }
+            XtextError::NotUtf8 => write!(fter, "decoded xtext is not utf-8"),
+        }
+    }
+}
+
+impl Error for XtextError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn encodes_plus_equals_and_space() {
+        assert_eq!(encode("<a+b=c d@e.test>"), "<a+2Bb+3Dc+20d@e.test>");
+        assert_eq!(encode("rfc822;bob@example.com"), "rfc822;bob@example.com");
+    }
+
+    #[test]
+    fn encodes_non_ascii_as_utf8_bytes() {
+        assert_eq!(encode("\u{fc}"), "+C3+BC");
+    }
+
+    #[test]
+    fn decodes_encoded_text() {
+        assert_eq!(
+            decode("<a+2Bb+3Dc+20d@e.test>").unwrap(),
+            "<a+b=c d@e.test>"
+        );
+        assert_eq!(decode("+C3+BC").unwrap(), "\u{fc}");
+    }
+
+    #[test]
+    fn round_trips() {
+        let raw = "Joe Smith+ext=1 <joe@\u{e9}xample.test>";
+        assert_eq!(decode(&encode(raw)).unwrap(), raw);
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        assert_eq!(
+            decode("a+2"),
+            Err(XtextError::InvalidHexChar { position: 1 })
+        );
+        assert_eq!(
+            decode("+2b"),
+            Err(XtextError::InvalidHexChar { position: 0 })
+        );
+        assert_eq!(decode("a=b"), Err(XtextError::InvalidChar { position: 1 }));
+        assert_eq!(decode("a b"), Err(XtextError::InvalidChar { position: 1 }));
+        assert_eq!(decode("+FF"), Err(XtextError::NotUtf8));
+    }
+}
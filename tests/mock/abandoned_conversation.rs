@@ -0,0 +1,29 @@
+use futures::{future, Async, Future};
+
+use new_tokio_smtp::{
+    command,
+    mock::{ActionData::Lines, Actor::*, MockSocket},
+    Connection, Io,
+};
+
+#[test]
+fn dropping_a_connection_mid_command_does_not_panic() {
+    let io: Io = MockSocket::new_allow_incomplete(vec![
+        (Client, Lines(vec!["NOOP"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["QUIT"])),
+        (Server, Lines(vec!["221 Bye"])),
+    ])
+    .into();
+    let con = Connection::from(io);
+
+    let mut fut = con.send(command::Noop);
+    future::poll_fn(|| {
+        let is_ready = fut.poll().unwrap().is_ready();
+        Ok::<_, ()>(Async::Ready(is_ready))
+    })
+    .wait()
+    .unwrap();
+
+    drop(fut);
+}
@@ -0,0 +1,32 @@
+use tokio::runtime::current_thread::Runtime;
+
+use new_tokio_smtp::{command, ClientId, Connection, ConnectionConfig, Domain, EhloData};
+
+use super::serve_recording;
+
+#[test]
+fn cached_ehlo_data_is_used_without_sending_ehlo() {
+    let (addr, received_lines) = serve_recording(vec!["250 Ok", "221 Bye"]);
+
+    let config: ConnectionConfig<command::Noop> = ConnectionConfig::builder_local_unencrypted()
+        .port(addr.port())
+        .client_id(ClientId::localhost())
+        .build();
+    let cached = EhloData::from_caps(
+        Domain::from_unchecked("they.test"),
+        &[("SMTPUTF8", &[]), ("SIZE", &["1024"])],
+    )
+    .unwrap();
+
+    let mut runtime = Runtime::new().unwrap();
+    let con = runtime
+        .block_on(Connection::connect_with_cached_ehlo(config, cached))
+        .unwrap();
+    assert!(con.has_capability("SMTPUTF8"));
+    assert_eq!(con.ehlo_data().unwrap().max_size(), Some(1024));
+    assert_eq!(con.client_id().map(ClientId::as_str), Some("[127.0.0.1]"));
+    runtime.block_on(con.quit()).unwrap();
+
+    let lines = received_lines.iter().collect::<Vec<_>>();
+    assert_eq!(lines, vec!["NOOP", "QUIT"]);
+}
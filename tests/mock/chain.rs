@@ -3,7 +3,7 @@ use std::io as std_io;
 use futures::{future, Future};
 
 use new_tokio_smtp::{
-    chain::{HandleErrorInChain, OnError},
+    chain::{chain_typed, HandleErrorInChain, OnError},
     command,
     error::LogicError,
     mock::{ActionData, Actor},
@@ -26,9 +26,9 @@ fn runs_the_cmd_chain() {
         (Server, Lines(vec!["250 3itus <testitus3@test.test>"])),
     ]);
     let chain = smtp_chain!(con with OnError::StopAndReset => [
-        command::Verify { query: "test1".to_owned() },
-        command::Verify { query: "test2".to_owned() },
-        command::Verify { query: "test3".to_owned() }
+        command::Verify::new("test1").unwrap(),
+        command::Verify::new("test2").unwrap(),
+        command::Verify::new("test3").unwrap()
     ])
     .and_then(|(con, res)| {
         assert!(res.is_ok());
@@ -47,9 +47,9 @@ fn stops_on_error() {
         (Server, Lines(vec!["550 only 1itus was left behind"])),
     ]);
     let chain = smtp_chain!(con with OnError::Stop => [
-        command::Verify { query: "test1".to_owned() },
-        command::Verify { query: "test2".to_owned() },
-        command::Verify { query: "test3".to_owned() }
+        command::Verify::new("test1").unwrap(),
+        command::Verify::new("test2").unwrap(),
+        command::Verify::new("test3").unwrap()
     ])
     .and_then(|(con, res)| {
         assert!(res.is_err());
@@ -70,9 +70,9 @@ fn sends_reset_on_error_if_requested() {
         (Server, Lines(vec!["250 Ok"])),
     ]);
     let chain = smtp_chain!(con with OnError::StopAndReset => [
-        command::Verify { query: "test1".to_owned() },
-        command::Verify { query: "test2".to_owned() },
-        command::Verify { query: "test3".to_owned() }
+        command::Verify::new("test1").unwrap(),
+        command::Verify::new("test2").unwrap(),
+        command::Verify::new("test3").unwrap()
     ])
     .and_then(|(con, res)| {
         assert!(res.is_err());
@@ -103,9 +103,9 @@ fn ignores_error_if_requested() {
         (Server, Lines(vec!["250 3itus <testitus3@test.test>"])),
     ]);
     let chain = smtp_chain!(con with IgnoreAllErrors => [
-        command::Verify { query: "test1".to_owned() },
-        command::Verify { query: "test2".to_owned() },
-        command::Verify { query: "test3".to_owned() }
+        command::Verify::new("test1").unwrap(),
+        command::Verify::new("test2").unwrap(),
+        command::Verify::new("test3").unwrap()
     ])
     .and_then(|(con, res)| {
         assert!(res.is_ok());
@@ -114,3 +114,25 @@ fn ignores_error_if_requested() {
 
     chain.wait().unwrap();
 }
+
+#[test]
+fn chain_typed_runs_unboxed_commands() {
+    let con = mock(vec![
+        (Client, Lines(vec!["VRFY test1"])),
+        (Server, Lines(vec!["250 1itus <testitus1@test.test>"])),
+        (Client, Lines(vec!["VRFY test2"])),
+        (Server, Lines(vec!["550 only 1itus was left behind"])),
+        (Client, Lines(vec!["RSET"])),
+        (Server, Lines(vec!["250 Ok"])),
+    ]);
+    let cmds = vec!["test1", "test2", "test3"]
+        .into_iter()
+        .map(|query| command::Verify::new(query).unwrap());
+
+    let (con, res) = chain_typed(con, cmds, OnError::StopAndReset)
+        .wait()
+        .unwrap();
+    let (idx, _err) = res.unwrap_err();
+    assert_eq!(idx, 1);
+    con.shutdown().wait().unwrap();
+}
@@ -1,6 +1,5 @@
 #![allow(non_snake_case)]
 
-
 use new_tokio_smtp::{command, ClientId};
 
 use new_tokio_smtp::mock::{ActionData, Actor};
@@ -8,7 +7,7 @@ use new_tokio_smtp::mock::{ActionData, Actor};
 use self::ActionData::*;
 use self::Actor::*;
 
-use super::{mock, mock_no_shutdown};
+use super::{mock, mock_no_shutdown, with_capabilities, with_capability};
 
 fn client_id() -> ClientId {
     ClientId::Domain("me.test".parse().unwrap())
@@ -58,10 +57,70 @@ mod Ehlo {
     }
 }
 
+mod Noop {
+    use super::*;
+    use futures::Future;
+
+    #[test]
+    fn bare() {
+        let con = mock(vec![
+            (Client, Lines(vec!["NOOP"])),
+            (Server, Lines(vec!["250 Ok"])),
+        ]);
+
+        let (con, res) = con.send(command::Noop).wait().unwrap();
+        assert!(res.is_ok());
+        con.shutdown().wait().unwrap();
+    }
+
+    #[test]
+    fn with_arg() {
+        let con = mock(vec![
+            (Client, Lines(vec!["NOOP lb-token-42"])),
+            (Server, Lines(vec!["250 lb-token-42"])),
+        ]);
+
+        let cmd = command::Noop::with_arg("lb-token-42").unwrap();
+        let (con, res) = con.send(cmd).wait().unwrap();
+        assert_eq!(res.unwrap().msg(), &["lb-token-42"]);
+        con.shutdown().wait().unwrap();
+    }
+
+    #[test]
+    fn send_same_reference_twice() {
+        let con = mock(vec![
+            (Client, Lines(vec!["NOOP"])),
+            (Server, Lines(vec!["250 Ok"])),
+            (Client, Lines(vec!["NOOP"])),
+            (Server, Lines(vec!["250 Ok"])),
+        ]);
+
+        let cmd = command::Noop;
+        let (con, res) = con.send_ref(&cmd).wait().unwrap();
+        assert!(res.is_ok());
+        let (con, res) = con.send_ref(&cmd).wait().unwrap();
+        assert!(res.is_ok());
+        con.shutdown().wait().unwrap();
+    }
+}
+
 mod Reset {
     use super::*;
     use futures::Future;
 
+    #[test]
+    fn connection_reset_sends_rset() {
+        let con = mock(vec![
+            (Client, Lines(vec!["RSET"])),
+            (Server, Lines(vec!["250 Ok"])),
+        ]);
+
+        let (con, result) = con.reset().wait().unwrap();
+        assert_eq!(result.unwrap().msg(), &["Ok"]);
+
+        con.shutdown().wait().unwrap();
+    }
+
     #[test]
     fn turns_unexpected_codes_into_failure() {
         let con = mock_no_shutdown(vec![
@@ -80,14 +139,1080 @@ mod Reset {
     }
 }
 
+mod ResetIfNeeded {
+    use super::*;
+    use futures::Future;
+    use new_tokio_smtp::{response::codes, ReversePath};
+
+    #[test]
+    fn sends_rset_if_a_transaction_is_open() {
+        let con = mock(vec![
+            (Client, Lines(vec!["MAIL FROM:<a@b.test>"])),
+            (Server, Lines(vec!["250 Ok"])),
+            (Client, Lines(vec!["RSET"])),
+            (Server, Lines(vec!["250 Reset"])),
+        ]);
+
+        let mail = command::Mail::new(ReversePath::from_unchecked("a@b.test"));
+        let (con, result) = con.send(mail).wait().unwrap();
+        result.unwrap();
+        assert!(con.is_in_transaction());
+
+        let (con, result) = con.send(command::ResetIfNeeded).wait().unwrap();
+        assert_eq!(result.unwrap().msg(), &["Reset"]);
+        assert!(!con.is_in_transaction());
+
+        con.shutdown().wait().unwrap();
+    }
+
+    #[test]
+    fn writes_nothing_if_no_transaction_is_open() {
+        let con = mock(vec![]);
+        assert!(!con.is_in_transaction());
+
+        let (con, result) = con.send(command::ResetIfNeeded).wait().unwrap();
+        assert_eq!(result.unwrap().code(), codes::OK);
+
+        con.shutdown().wait().unwrap();
+    }
+}
+
 mod Data {
-    //TODO test
+    use super::*;
+    use futures::{stream, Future};
+    use new_tokio_smtp::error::LogicError;
+    use std::io::Cursor;
+
+    fn conversation() -> Vec<(Actor, ActionData)> {
+        vec![
+            (Client, Lines(vec!["DATA"])),
+            (Server, Lines(vec!["354 go ahead"])),
+            (Client, Lines(vec!["line one", "..dotted", "last", "."])),
+            (Server, Lines(vec!["250 Ok"])),
+        ]
+    }
+
+    #[test]
+    fn counts_the_stuffed_data_size() {
+        let con = mock(conversation());
+        assert_eq!(con.last_data_bytes(), None);
+        let (con, res) = con
+            .send(command::Data::from_buf("line one\r\n.dotted\r\nlast\r\n"))
+            .wait()
+            .unwrap();
+        assert!(res.is_ok());
+        let stuffed = "line one\r\n..dotted\r\nlast\r\n.\r\n";
+        assert_eq!(con.last_data_bytes(), Some(stuffed.len()));
+        con.shutdown().wait().unwrap();
+    }
+
+    #[test]
+    fn streams_bytes_chunks() {
+        use bytes::Bytes;
+
+        let con = mock(conversation());
+        let chunks = vec![
+            Bytes::from_static(b"line one\r\n."),
+            Bytes::from_static(b"dotted\r\nlast\r\n"),
+        ];
+        let (con, res) = con
+            .send(command::Data::from_bytes_stream(stream::iter_ok(chunks)))
+            .wait()
+            .unwrap();
+        assert!(res.is_ok());
+        let stuffed = "line one\r\n..dotted\r\nlast\r\n.\r\n";
+        assert_eq!(con.last_data_bytes(), Some(stuffed.len()));
+        con.shutdown().wait().unwrap();
+    }
+
+    #[test]
+    fn pre_stuffed_writes_same_as_dot_stashing() {
+        let con = mock(conversation());
+        let (con, res) = con
+            .send(command::Data::from_buf("line one\r\n.dotted\r\nlast\r\n"))
+            .wait()
+            .unwrap();
+        assert!(res.is_ok());
+        con.shutdown().wait().unwrap();
+
+        let con = mock(conversation());
+        let (con, res) = con
+            .send(command::Data::pre_stuffed(stream::once(Ok(Cursor::new(
+                &b"line one\r\n..dotted\r\nlast"[..],
+            )))))
+            .wait()
+            .unwrap();
+        assert!(res.is_ok());
+        con.shutdown().wait().unwrap();
+    }
+
+    #[test]
+    fn accepts_configured_intermediate_code() {
+        use new_tokio_smtp::response::parser::parse_code;
+
+        let con = mock(vec![
+            (Client, Lines(vec!["DATA"])),
+            (Server, Lines(vec!["355 send it"])),
+            (Client, Lines(vec!["body", "."])),
+            (Server, Lines(vec!["250 Ok"])),
+        ]);
+        let code = parse_code(b'3', b'5', b'5').unwrap();
+        let (con, res) = con
+            .send(command::Data::from_buf("body\r\n").expecting_intermediate(code))
+            .wait()
+            .unwrap();
+        assert!(res.is_ok());
+        con.shutdown().wait().unwrap();
+    }
+
+    #[test]
+    fn rejects_other_intermediate_code_by_default() {
+        let con = mock(vec![
+            (Client, Lines(vec!["DATA"])),
+            (Server, Lines(vec!["355 send it"])),
+        ]);
+        let (con, res) = con
+            .send(command::Data::from_buf("body\r\n"))
+            .wait()
+            .unwrap();
+        match res {
+            Err(LogicError::UnexpectedCode(response)) => {
+                assert_eq!(response.code().as_byte_string(), *b"355")
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        con.shutdown().wait().unwrap();
+    }
+
+    fn normalized_conversation() -> Vec<(Actor, ActionData)> {
+        vec![
+            (Client, Lines(vec!["DATA"])),
+            (Server, Lines(vec!["354 go ahead"])),
+            (
+                Client,
+                Lines(vec!["Subject: x", "", "..dotted", "last", "."]),
+            ),
+            (Server, Lines(vec!["250 Ok"])),
+        ]
+    }
+
+    #[test]
+    fn normalizes_lf_only_body() {
+        let con = mock(normalized_conversation());
+        let (con, res) = con
+            .send(
+                command::Data::from_buf("Subject: x\n\n.dotted\nlast\n")
+                    .with_line_ending_normalization(),
+            )
+            .wait()
+            .unwrap();
+        assert!(res.is_ok());
+        con.shutdown().wait().unwrap();
+    }
+
+    #[test]
+    fn normalizes_mixed_line_endings() {
+        let con = mock(normalized_conversation());
+        let (con, res) = con
+            .send(
+                command::Data::from_buf("Subject: x\r\n\r.dotted\nlast\r")
+                    .with_line_ending_normalization(),
+            )
+            .wait()
+            .unwrap();
+        assert!(res.is_ok());
+        con.shutdown().wait().unwrap();
+    }
 }
 
 mod Mail {
-    //TODO test
+    use super::*;
+    use futures::Future;
+    use new_tokio_smtp::{error::LogicError, ReversePath};
+
+    #[test]
+    fn with_custom_param() {
+        let con = mock(vec![
+            (Client, Lines(vec!["MAIL FROM:<a@b.test> X-TRACK=abc12"])),
+            (Server, Lines(vec!["250 Ok"])),
+        ]);
+
+        let cmd = command::Mail::new(ReversePath::from_unchecked("a@b.test"))
+            .with_param("X-TRACK".parse().unwrap(), Some("abc12".parse().unwrap()));
+        let (con, res) = con.send(cmd).wait().unwrap();
+        assert!(res.is_ok());
+        con.shutdown().wait().unwrap();
+    }
+
+    #[test]
+    fn with_auth_identity() {
+        let con = mock(vec![
+            (Client, Lines(vec!["MAIL FROM:<s@b.test> AUTH=u@host.test"])),
+            (Server, Lines(vec!["250 Ok"])),
+        ]);
+        let con = with_capability(con, "AUTH");
+
+        let cmd = command::Mail::new(ReversePath::from_unchecked("s@b.test"))
+            .with_auth_identity(ReversePath::from_unchecked("u@host.test"));
+        let (con, res) = con.send(cmd).wait().unwrap();
+        assert!(res.is_ok());
+        con.shutdown().wait().unwrap();
+    }
+
+    #[test]
+    fn with_anonymous_auth() {
+        let con = mock(vec![
+            (Client, Lines(vec!["MAIL FROM:<s@b.test> AUTH=<>"])),
+            (Server, Lines(vec!["250 Ok"])),
+        ]);
+        let con = with_capability(con, "AUTH");
+
+        let cmd = command::Mail::new(ReversePath::from_unchecked("s@b.test")).with_anonymous_auth();
+        let (con, res) = con.send(cmd).wait().unwrap();
+        assert!(res.is_ok());
+        con.shutdown().wait().unwrap();
+    }
+
+    #[test]
+    fn with_deliver_by() {
+        let con = mock(vec![
+            (Client, Lines(vec!["MAIL FROM:<s@b.test> DELIVERBY=120R"])),
+            (Server, Lines(vec!["250 Ok"])),
+        ]);
+        let con = with_capability(con, "DELIVERBY");
+
+        let cmd = command::Mail::new(ReversePath::from_unchecked("s@b.test"))
+            .with_deliver_by(120, command::DeliverByMode::Return)
+            .unwrap();
+        let (con, res) = con.send(cmd).wait().unwrap();
+        assert!(res.is_ok());
+        con.shutdown().wait().unwrap();
+    }
+
+    #[test]
+    fn deliver_by_requires_deliverby_capability() {
+        let con = mock(vec![]);
+
+        let cmd = command::Mail::new(ReversePath::from_unchecked("s@b.test"))
+            .with_deliver_by(120, command::DeliverByMode::Notify)
+            .unwrap();
+        let (con, res) = con.send(cmd).wait().unwrap();
+        match res {
+            Err(LogicError::MissingCapabilities(missing)) => {
+                assert_eq!(missing.capabilities()[0].as_str(), "DELIVERBY")
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        con.shutdown().wait().unwrap();
+    }
+
+    #[test]
+    fn send_unchecked_ignores_missing_capability() {
+        let con = mock(vec![
+            (Client, Lines(vec!["MAIL FROM:<s@b.test> DELIVERBY=120N"])),
+            (Server, Lines(vec!["250 Ok"])),
+        ]);
+        let con = with_capability(con, "SIZE");
+
+        let cmd = command::Mail::new(ReversePath::from_unchecked("s@b.test"))
+            .with_deliver_by(120, command::DeliverByMode::Notify)
+            .unwrap();
+        let (con, res) = con.send_unchecked(cmd).wait().unwrap();
+        assert!(res.is_ok());
+        con.shutdown().wait().unwrap();
+    }
+
+    #[test]
+    fn deliver_by_return_respects_min_by_time() {
+        let con = mock(vec![
+            (Client, Lines(vec!["MAIL FROM:<s@b.test> DELIVERBY=120N"])),
+            (Server, Lines(vec!["250 Ok"])),
+            (Client, Lines(vec!["MAIL FROM:<s@b.test> DELIVERBY=300R"])),
+            (Server, Lines(vec!["250 Ok"])),
+        ]);
+        let con = with_capabilities(con, &[("DELIVERBY", &["240"])]);
+
+        let too_short = command::Mail::new(ReversePath::from_unchecked("s@b.test"))
+            .with_deliver_by(120, command::DeliverByMode::Return)
+            .unwrap();
+        let (con, res) = con.send(too_short).wait().unwrap();
+        match res {
+            Err(LogicError::Custom(err)) => assert_eq!(
+                err.downcast_ref::<command::InvalidDeliverBy>(),
+                Some(&command::InvalidDeliverBy::BelowMinByTime {
+                    by_time: 120,
+                    min_by_time: 240
+                })
+            ),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let notify = command::Mail::new(ReversePath::from_unchecked("s@b.test"))
+            .with_deliver_by(120, command::DeliverByMode::Notify)
+            .unwrap();
+        let (con, res) = con.send(notify).wait().unwrap();
+        assert!(res.is_ok());
+
+        let long_enough = command::Mail::new(ReversePath::from_unchecked("s@b.test"))
+            .with_deliver_by(300, command::DeliverByMode::Return)
+            .unwrap();
+        let (con, res) = con.send(long_enough).wait().unwrap();
+        assert!(res.is_ok());
+        con.shutdown().wait().unwrap();
+    }
+
+    #[test]
+    fn deliver_by_return_needs_positive_by_time() {
+        for &seconds in &[0, -10] {
+            let err = command::Mail::new(ReversePath::from_unchecked("s@b.test"))
+                .with_deliver_by(seconds, command::DeliverByMode::Return)
+                .unwrap_err();
+            assert_eq!(
+                err,
+                command::InvalidDeliverBy::NonPositiveReturnTime { seconds }
+            );
+        }
+
+        let notify = command::Mail::new(ReversePath::from_unchecked("s@b.test"))
+            .with_deliver_by(-10, command::DeliverByMode::Notify);
+        assert!(notify.is_ok());
+    }
+
+    #[test]
+    fn auth_param_requires_auth_capability() {
+        let con = mock(vec![]);
+
+        let cmd = command::Mail::new(ReversePath::from_unchecked("s@b.test")).with_anonymous_auth();
+        let (con, res) = con.send(cmd).wait().unwrap();
+        assert!(res.is_err());
+        con.shutdown().wait().unwrap();
+    }
 }
 
 mod Recipient {
-    //todo test
+    use super::*;
+    use futures::Future;
+    use new_tokio_smtp::ForwardPath;
+
+    #[test]
+    fn with_custom_param_without_value() {
+        let con = mock(vec![
+            (Client, Lines(vec!["RCPT TO:<c@d.test> X-PRIORITY"])),
+            (Server, Lines(vec!["250 Ok"])),
+        ]);
+
+        let cmd = command::Recipient::new(ForwardPath::from_unchecked("c@d.test"))
+            .with_param("X-PRIORITY".parse().unwrap(), None);
+        let (con, res) = con.send(cmd).wait().unwrap();
+        assert!(res.is_ok());
+        con.shutdown().wait().unwrap();
+    }
+}
+
+mod ThenCmd {
+    use super::*;
+    use futures::Future;
+
+    #[test]
+    fn runs_second_command_after_success() {
+        let con = mock(vec![
+            (Client, Lines(vec!["NOOP"])),
+            (Server, Lines(vec!["250 Ok"])),
+            (Client, Lines(vec!["RSET"])),
+            (Server, Lines(vec!["250 reset"])),
+        ]);
+
+        let (con, res) = con
+            .send(command::ThenCmd(command::Noop, command::Reset))
+            .wait()
+            .unwrap();
+        assert_eq!(res.unwrap().msg(), &["reset"]);
+        con.shutdown().wait().unwrap();
+    }
+
+    #[test]
+    fn short_circuits_on_logic_error() {
+        let con = mock(vec![
+            (Client, Lines(vec!["NOOP"])),
+            (Server, Lines(vec!["500 what"])),
+        ]);
+
+        let (con, res) = con
+            .send(command::ThenCmd(command::Noop, command::Reset))
+            .wait()
+            .unwrap();
+        assert!(res.is_err());
+        con.shutdown().wait().unwrap();
+    }
+}
+
+mod MapResult {
+    use super::*;
+    use futures::Future;
+    use new_tokio_smtp::{error::LogicError, response::codes};
+
+    #[test]
+    fn can_turn_error_into_success() {
+        let con = mock(vec![
+            (Client, Lines(vec!["RCPT TO:<c@d.test>"])),
+            (Server, Lines(vec!["550 no such user"])),
+        ]);
+
+        let rcpt = command::Recipient::new(new_tokio_smtp::ForwardPath::from_unchecked("c@d.test"));
+        let cmd = command::MapResult(rcpt, |result| match result {
+            Err(LogicError::Code(response)) if response.code() == codes::MAILBOX_UNAVAILABLE => {
+                Ok(response)
+            }
+            other => other,
+        });
+        let (con, res) = con.send(cmd).wait().unwrap();
+        assert_eq!(res.unwrap().msg(), &["no such user"]);
+        con.shutdown().wait().unwrap();
+    }
+}
+
+mod Inspect {
+    use super::*;
+    use futures::Future;
+    use new_tokio_smtp::io::SmtpResult;
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    };
+
+    #[test]
+    fn sees_the_result() {
+        let con = mock(vec![
+            (Client, Lines(vec!["NOOP"])),
+            (Server, Lines(vec!["250 Ok"])),
+        ]);
+
+        let called = Arc::new(AtomicBool::new(false));
+        let cmd = command::Inspect(command::Noop, {
+            let called = called.clone();
+            move |result: &SmtpResult| {
+                assert!(result.is_ok());
+                called.store(true, Ordering::SeqCst);
+            }
+        });
+        let (con, res) = con.send(cmd).wait().unwrap();
+        assert!(res.is_ok());
+        assert!(called.load(Ordering::SeqCst));
+        con.shutdown().wait().unwrap();
+    }
+}
+
+mod RequireSecure {
+    use super::*;
+    use futures::Future;
+    use new_tokio_smtp::{error::LogicError, io::MockStream, mock::MockSocket, Connection, Io};
+
+    #[test]
+    fn refuses_to_run_over_plaintext() {
+        let con = mock(vec![]);
+
+        let (con, res) = con
+            .send(command::RequireSecure(command::Noop))
+            .wait()
+            .unwrap();
+        match res {
+            Err(LogicError::Custom(err)) => {
+                assert_eq!(err.to_string(), "refusing to run over plaintext")
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        con.shutdown().wait().unwrap();
+    }
+
+    #[test]
+    fn runs_the_command_if_secure() {
+        let mut socket = MockSocket::new(vec![
+            (Client, Lines(vec!["NOOP"])),
+            (Server, Lines(vec!["250 Ok"])),
+        ]);
+        socket.set_is_secure(true);
+        let con = Connection::from(Io::from(socket));
+
+        let (con, res) = con
+            .send(command::RequireSecure(command::Noop))
+            .wait()
+            .unwrap();
+        assert!(res.is_ok());
+        con.shutdown().wait().unwrap();
+    }
+}
+
+mod WithTimeout {
+    use super::*;
+    use new_tokio_smtp::mock::MockSocket;
+    use new_tokio_smtp::{Connection, Io};
+    use std::{io::ErrorKind, time::Duration};
+    use tokio::runtime::current_thread::Runtime;
+
+    #[test]
+    fn fails_if_the_server_is_too_slow() {
+        let io: Io = MockSocket::new_allow_incomplete(vec![
+            (Client, Lines(vec!["NOOP"])),
+            (
+                ServerAfter(Duration::from_millis(500)),
+                Lines(vec!["250 Ok"]),
+            ),
+        ])
+        .into();
+        let con = Connection::from(io);
+
+        let mut runtime = Runtime::new().unwrap();
+        let cmd = command::WithTimeout(command::Noop, Duration::from_millis(20));
+        let err = runtime.block_on(con.send(cmd)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn passes_the_result_through_in_time() {
+        let con = mock(vec![
+            (Client, Lines(vec!["NOOP"])),
+            (
+                ServerAfter(Duration::from_millis(10)),
+                Lines(vec!["250 Ok"]),
+            ),
+        ]);
+
+        let mut runtime = Runtime::new().unwrap();
+        let cmd = command::WithTimeout(command::Noop, Duration::from_secs(5));
+        let (con, res) = runtime.block_on(con.send(cmd)).unwrap();
+        assert_eq!(res.unwrap().msg(), &["Ok"]);
+        runtime.block_on(con.shutdown()).unwrap();
+    }
+}
+
+mod Raw {
+    use super::*;
+    use futures::Future;
+
+    #[test]
+    fn sends_the_line_as_is() {
+        let con = mock(vec![
+            (Client, Lines(vec!["HELP FOO"])),
+            (Server, Lines(vec!["214 no help for FOO"])),
+        ]);
+
+        let cmd = command::Raw::new("HELP FOO", vec![]).unwrap();
+        let (con, res) = con.send(cmd).wait().unwrap();
+        assert_eq!(res.unwrap().msg(), &["no help for FOO"]);
+        con.shutdown().wait().unwrap();
+    }
+
+    #[test]
+    fn requires_capabilities() {
+        let con = mock(vec![]);
+
+        let cmd = command::Raw::new("XFOO", vec!["XFOO".parse().unwrap()]).unwrap();
+        let (con, res) = con.send(cmd).wait().unwrap();
+        assert!(res.is_err());
+        con.shutdown().wait().unwrap();
+    }
+}
+
+mod Verify {
+    use super::*;
+    use futures::Future;
+    use new_tokio_smtp::error::LogicError;
+
+    #[test]
+    fn ascii_query_is_always_allowed() {
+        let con = mock(vec![
+            (Client, Lines(vec!["VRFY joe"])),
+            (Server, Lines(vec!["250 Joe <joe@test.test>"])),
+        ]);
+
+        let cmd = command::Verify::new("joe").unwrap();
+        let (con, res) = con.send(cmd).wait().unwrap();
+        assert!(res.is_ok());
+        con.shutdown().wait().unwrap();
+    }
+
+    #[test]
+    fn utf8_query_requires_smtputf8() {
+        let con = mock(vec![]);
+
+        let cmd = command::Verify::new("j\u{f6}rg").unwrap();
+        let (con, res) = con.send(cmd).wait().unwrap();
+        match res {
+            Err(LogicError::MissingCapabilities(missing)) => {
+                assert_eq!(missing.capabilities()[0].as_str(), "SMTPUTF8")
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        con.shutdown().wait().unwrap();
+    }
+
+    #[test]
+    fn utf8_query_with_smtputf8() {
+        let con = mock(vec![
+            (Client, Lines(vec!["VRFY j\u{f6}rg"])),
+            (Server, Lines(vec!["252 Cannot verify"])),
+        ]);
+        let con = with_capability(con, "SMTPUTF8");
+
+        let cmd = command::Verify::new("j\u{f6}rg").unwrap();
+        let (con, res) = con.send(cmd).wait().unwrap();
+        assert!(res.is_ok());
+        con.shutdown().wait().unwrap();
+    }
+}
+
+mod Expn {
+    use super::*;
+    use futures::Future;
+    use new_tokio_smtp::error::LogicError;
+
+    #[test]
+    fn ascii_query_is_always_allowed() {
+        let con = mock(vec![
+            (Client, Lines(vec!["EXPN staff"])),
+            (
+                Server,
+                Lines(vec!["250-Joe <joe@test.test>", "250 Ann <ann@test.test>"]),
+            ),
+        ]);
+
+        let cmd = command::Expn::new("staff").unwrap();
+        let (con, res) = con.send(cmd).wait().unwrap();
+        assert_eq!(command::Expn::members(&res.unwrap()).len(), 2);
+        con.shutdown().wait().unwrap();
+    }
+
+    #[test]
+    fn utf8_query_requires_smtputf8() {
+        let con = mock(vec![]);
+
+        let cmd = command::Expn::new("mitarbeiter-\u{fc}").unwrap();
+        let (con, res) = con.send(cmd).wait().unwrap();
+        match res {
+            Err(LogicError::MissingCapabilities(missing)) => {
+                assert_eq!(missing.capabilities()[0].as_str(), "SMTPUTF8")
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        con.shutdown().wait().unwrap();
+    }
+
+    #[test]
+    fn utf8_query_with_smtputf8() {
+        let con = mock(vec![
+            (Client, Lines(vec!["EXPN mitarbeiter-\u{fc}"])),
+            (Server, Lines(vec!["250 Joe <joe@test.test>"])),
+        ]);
+        let con = with_capability(con, "SMTPUTF8");
+
+        let cmd = command::Expn::new("mitarbeiter-\u{fc}").unwrap();
+        let (con, res) = con.send(cmd).wait().unwrap();
+        assert!(res.is_ok());
+        con.shutdown().wait().unwrap();
+    }
+}
+
+mod exec_simple_cmd {
+    use super::*;
+    use futures::Future;
+    use new_tokio_smtp::{error::MissingCapabilities, Cmd, EhloData, ExecFuture, Io};
+
+    struct XTest(&'static str);
+
+    impl Cmd for XTest {
+        fn check_cmd_availability(
+            &self,
+            _caps: Option<&EhloData>,
+        ) -> Result<(), MissingCapabilities> {
+            Ok(())
+        }
+
+        fn exec(self, io: Io) -> ExecFuture {
+            io.exec_simple_cmd(&["XTEST ", self.0, " now"])
+        }
+    }
+
+    #[test]
+    fn writes_joined_parts_and_parses_one_response() {
+        let con = mock(vec![
+            (Client, Lines(vec!["XTEST it now"])),
+            (Server, Lines(vec!["250-tested", "250 it"])),
+            (Client, Lines(vec!["NOOP"])),
+            (Server, Lines(vec!["250 Ok"])),
+        ]);
+
+        let (con, res) = con.send(XTest("it")).wait().unwrap();
+        assert_eq!(res.unwrap().msg(), &["tested", "it"]);
+
+        let (con, res) = con.send(command::Noop).wait().unwrap();
+        assert_eq!(res.unwrap().msg(), &["Ok"]);
+        con.shutdown().wait().unwrap();
+    }
+}
+
+mod required_capabilities {
+    use super::*;
+    use new_tokio_smtp::{command::auth, Cmd, Domain};
+
+    fn names<C: Cmd>(cmd: &C) -> Vec<String> {
+        cmd.required_capabilities()
+            .iter()
+            .map(|cap| cap.as_str().to_owned())
+            .collect()
+    }
+
+    #[test]
+    fn starttls_requires_starttls() {
+        let cmd = command::StartTls::new(Domain::from_unchecked("they.test"));
+        assert_eq!(names(&cmd), vec!["STARTTLS"]);
+    }
+
+    #[test]
+    fn auth_commands_require_auth() {
+        let plain = auth::Plain::from_username("user", "pass").unwrap();
+        assert_eq!(names(&plain), vec!["AUTH"]);
+        let login = auth::Login::new("user", "pass");
+        assert_eq!(names(&login.boxed()), vec!["AUTH"]);
+    }
+
+    #[test]
+    fn simple_commands_require_nothing() {
+        assert!(names(&command::Noop).is_empty());
+        assert!(names(&command::ThenCmd(command::Noop, command::Reset)).is_empty());
+    }
+}
+
+mod capabilities {
+    use super::*;
+    use futures::Future;
+
+    #[test]
+    fn snapshot_outlives_moved_connection() {
+        let con = mock(vec![
+            (Client, Lines(vec!["NOOP"])),
+            (Server, Lines(vec!["250 Ok"])),
+        ]);
+        let con = with_capability(con, "SMTPUTF8");
+
+        let caps = con.capabilities().unwrap();
+        let (con, res) = con.send(command::Noop).wait().unwrap();
+        assert!(res.is_ok());
+        con.shutdown().wait().unwrap();
+
+        assert!(caps.supports_smtputf8());
+        assert!(caps.clone().has_capability("SMTPUTF8"));
+    }
+}
+
+mod state {
+    use super::*;
+    use futures::Future;
+    use new_tokio_smtp::{command::auth, Cmd, Connection, Domain, EhloData, Io, ReversePath};
+
+    #[test]
+    fn transaction_flag_follows_mail_and_data() {
+        let con = mock(vec![
+            (Client, Lines(vec!["MAIL FROM:<a@b.test>"])),
+            (Server, Lines(vec!["250 Ok"])),
+            (Client, Lines(vec!["DATA"])),
+            (Server, Lines(vec!["354 go ahead"])),
+            (Client, Lines(vec!["body", "."])),
+            (Server, Lines(vec!["250 Ok"])),
+        ]);
+        assert!(!con.state().transaction_in_progress);
+
+        let cmd = command::Mail::new(ReversePath::from_unchecked("a@b.test"));
+        let (con, res) = con.send(cmd).wait().unwrap();
+        assert!(res.is_ok());
+        assert!(con.state().transaction_in_progress);
+
+        let (con, res) = con
+            .send(command::Data::from_buf("body\r\n"))
+            .wait()
+            .unwrap();
+        assert!(res.is_ok());
+        assert!(!con.state().transaction_in_progress);
+        con.shutdown().wait().unwrap();
+    }
+
+    #[test]
+    fn reset_ends_the_transaction() {
+        let con = mock(vec![
+            (Client, Lines(vec!["MAIL FROM:<a@b.test>"])),
+            (Server, Lines(vec!["250 Ok"])),
+            (Client, Lines(vec!["RSET"])),
+            (Server, Lines(vec!["250 Ok"])),
+        ]);
+
+        let cmd = command::Mail::new(ReversePath::from_unchecked("a@b.test"));
+        let (con, _res) = con.send(cmd).wait().unwrap();
+        assert!(con.state().transaction_in_progress);
+
+        let (con, _res) = con.reset().wait().unwrap();
+        assert!(!con.state().transaction_in_progress);
+        con.shutdown().wait().unwrap();
+    }
+
+    #[test]
+    fn successful_auth_marks_the_connection_authenticated() {
+        let con = mock(vec![
+            (Client, Lines(vec!["AUTH PLAIN dXNlcgB1c2VyAHBhc3M="])),
+            (Server, Lines(vec!["235 Ok"])),
+        ]);
+        let (socket, buffer, _) = Io::from(con).split();
+        let caps = EhloData::from_caps(
+            Domain::from_unchecked("they.test"),
+            &[("AUTH", &["PLAIN"]), ("SMTPUTF8", &[])],
+        )
+        .unwrap();
+        let con = Connection::from(Io::from((socket, buffer, caps)));
+
+        let state = con.state();
+        assert!(!state.is_authenticated);
+        assert!(!state.is_secure);
+        assert!(state.has_ehlo_data);
+        assert!(state.supports_smtputf8);
+        assert!(!state.supports_pipelining);
+
+        let cmd = auth::Plain::from_username("user", "pass").unwrap();
+        let (con, res) = con.send(cmd).wait().unwrap();
+        assert!(res.is_ok());
+        assert!(con.state().is_authenticated);
+        con.shutdown().wait().unwrap();
+    }
+
+    #[test]
+    fn successful_auth_records_the_identity() {
+        let con = with_capabilities(
+            mock(vec![
+                (Client, Lines(vec!["AUTH PLAIN dXNlcgB1c2VyAHBhc3M="])),
+                (Server, Lines(vec!["235 Ok"])),
+            ]),
+            &[("AUTH", &["PLAIN", "LOGIN"])],
+        );
+        assert!(con.authenticated_as().is_none());
+
+        let cmd = auth::Plain::from_username("user", "pass").unwrap();
+        let (con, res) = con.send(cmd).wait().unwrap();
+        assert!(res.is_ok());
+
+        let identity = con.authenticated_as().expect("identity recorded");
+        assert_eq!(identity.identity(), "user");
+        assert_eq!(identity.mechanism(), "PLAIN");
+        con.shutdown().wait().unwrap();
+    }
+
+    #[test]
+    fn failed_auth_records_no_identity() {
+        let con = with_capabilities(
+            mock(vec![
+                (Client, Lines(vec!["AUTH PLAIN dXNlcgB1c2VyAHBhc3M="])),
+                (
+                    Server,
+                    Lines(vec!["535 Authentication credentials invalid"]),
+                ),
+            ]),
+            &[("AUTH", &["PLAIN", "LOGIN"])],
+        );
+
+        let cmd = auth::Plain::from_username("user", "pass").unwrap();
+        let (con, res) = con.send(cmd).wait().unwrap();
+        assert!(res.is_err());
+        assert!(con.authenticated_as().is_none());
+        con.shutdown().wait().unwrap();
+    }
+
+    #[test]
+    fn login_reports_the_decoded_username() {
+        let cmd = auth::Login::new("user", "pass");
+        let identity = cmd.auth_identity().unwrap();
+        assert_eq!(identity.identity(), "user");
+        assert_eq!(identity.mechanism(), "LOGIN");
+    }
+}
+
+mod send_batch {
+    use super::*;
+    use futures::Future;
+    use new_tokio_smtp::{Cmd, ForwardPath};
+
+    #[test]
+    fn continues_after_errors_and_returns_all_results() {
+        let con = mock(vec![
+            (Client, Lines(vec!["RCPT TO:<nobody@d.test>"])),
+            (Server, Lines(vec!["550 no such user"])),
+            (Client, Lines(vec!["NOOP"])),
+            (Server, Lines(vec!["250 Ok"])),
+        ]);
+
+        let cmds = vec![
+            command::Recipient::new(ForwardPath::from_unchecked("nobody@d.test")).boxed(),
+            command::Noop.boxed(),
+        ];
+        let (con, results) = con.send_batch(cmds).wait().unwrap();
+        assert_eq!(results.len(), 2);
+        assert!(results[0].is_err());
+        assert!(results[1].is_ok());
+        con.shutdown().wait().unwrap();
+    }
+}
+
+mod send_expecting {
+    use super::*;
+    use futures::Future;
+    use new_tokio_smtp::{error::LogicError, response::codes};
+
+    #[test]
+    fn turns_other_positive_codes_into_unexpected_code() {
+        let con = mock(vec![
+            (Client, Lines(vec!["XDATA"])),
+            (Server, Lines(vec!["250 Ok"])),
+        ]);
+
+        let cmd = command::Raw::new("XDATA", vec![]).unwrap();
+        let (con, res) = con
+            .send_expecting(cmd, codes::START_MAIL_DATA)
+            .wait()
+            .unwrap();
+        match res {
+            Err(LogicError::UnexpectedCode(response)) => {
+                assert!(response.code_matches(codes::OK))
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        con.shutdown().wait().unwrap();
+    }
+
+    #[test]
+    fn passes_the_expected_code_through() {
+        let con = mock(vec![
+            (Client, Lines(vec!["XDATA"])),
+            (Server, Lines(vec!["354 go ahead"])),
+        ]);
+
+        let cmd = command::Raw::new("XDATA", vec![]).unwrap();
+        let (con, res) = con
+            .send_expecting(cmd, codes::START_MAIL_DATA)
+            .wait()
+            .unwrap();
+        assert_eq!(res.unwrap().msg(), &["go ahead"]);
+        con.shutdown().wait().unwrap();
+    }
+}
+
+mod DataBytes {
+    use super::*;
+    use bytes::Bytes;
+    use futures::Future;
+    use new_tokio_smtp::{error::LogicError, response::codes, ForwardPath};
+
+    const BODY: &str = "line one\r\n.dotted\r\nlast";
+
+    fn conversation() -> Vec<(Actor, ActionData)> {
+        vec![
+            (Client, Lines(vec!["DATA"])),
+            (Server, Lines(vec!["354 go ahead"])),
+            (
+                Client,
+                Blob(b"line one\r\n..dotted\r\nlast\r\n.\r\n".to_vec()),
+            ),
+            (Server, Lines(vec!["250 Ok"])),
+        ]
+    }
+
+    #[test]
+    fn writes_same_bytes_as_data_from_buf() {
+        let con = mock(conversation());
+        let (con, res) = con.send(command::Data::from_buf(BODY)).wait().unwrap();
+        assert!(res.is_ok());
+        let data_bytes = con.last_data_bytes();
+        con.shutdown().wait().unwrap();
+
+        let con = mock(conversation());
+        let (con, res) = con
+            .send(command::DataBytes::new(Bytes::from_static(BODY.as_bytes())))
+            .wait()
+            .unwrap();
+        assert!(res.is_ok());
+        assert_eq!(con.last_data_bytes(), data_bytes);
+        assert!(!con.is_in_transaction());
+        con.shutdown().wait().unwrap();
+    }
+
+    #[test]
+    fn does_not_send_body_on_unexpected_code() {
+        let con = mock(vec![
+            (Client, Lines(vec!["DATA"])),
+            (Server, Lines(vec!["554 no valid recipients"])),
+        ]);
+        let (con, res) = con
+            .send(command::DataBytes::new(Bytes::from_static(BODY.as_bytes())))
+            .wait()
+            .unwrap();
+        assert!(res.is_err());
+        assert_eq!(con.last_data_bytes(), None);
+        con.shutdown().wait().unwrap();
+    }
+
+    #[test]
+    fn waits_for_the_expected_intermediate_code() {
+        let con = mock(vec![
+            (Client, Lines(vec!["DATA"])),
+            (Server, Lines(vec!["250 go ahead"])),
+            (
+                Client,
+                Blob(b"line one\r\n..dotted\r\nlast\r\n.\r\n".to_vec()),
+            ),
+            (Server, Lines(vec!["250 Ok"])),
+        ]);
+        let cmd = command::DataBytes::new(Bytes::from_static(BODY.as_bytes()))
+            .expecting_intermediate(codes::OK);
+        let (con, res) = con.send(cmd).wait().unwrap();
+        assert!(res.is_ok());
+        con.shutdown().wait().unwrap();
+    }
+
+    #[test]
+    fn reads_one_response_per_accepted_recipient_in_lmtp_mode() {
+        let con = mock(vec![
+            (Client, Lines(vec!["LHLO me.test"])),
+            (Server, Lines(vec!["250 they.test"])),
+            (Client, Lines(vec!["RCPT TO:<a@test.test>"])),
+            (Server, Lines(vec!["250 Ok"])),
+            (Client, Lines(vec!["RCPT TO:<b@test.test>"])),
+            (Server, Lines(vec!["250 Ok"])),
+            (Client, Lines(vec!["DATA"])),
+            (Server, Lines(vec!["354 go ahead"])),
+            (
+                Client,
+                Blob(b"line one\r\n..dotted\r\nlast\r\n.\r\n".to_vec()),
+            ),
+            (Server, Lines(vec!["250 a ok", "452 b is full"])),
+        ]);
+        let (con, _) = con.send(command::Lhlo::new(client_id())).wait().unwrap();
+        let (con, _) = con
+            .send(command::Recipient::new(ForwardPath::from_unchecked(
+                "a@test.test",
+            )))
+            .wait()
+            .unwrap();
+        let (con, _) = con
+            .send(command::Recipient::new(ForwardPath::from_unchecked(
+                "b@test.test",
+            )))
+            .wait()
+            .unwrap();
+        let (con, res) = con
+            .send(command::DataBytes::new(Bytes::from_static(BODY.as_bytes())))
+            .wait()
+            .unwrap();
+        match res {
+            Err(LogicError::Code(response)) => {
+                assert_eq!(response.code(), codes::INSUFFICIENT_SYSTEM)
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(!con.is_in_transaction());
+        con.shutdown().wait().unwrap();
+    }
 }
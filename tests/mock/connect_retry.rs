@@ -0,0 +1,49 @@
+use std::net::SocketAddr;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+
+use tokio::runtime::current_thread::Runtime;
+
+use new_tokio_smtp::{command, error::ConnectingFailed, ClientId, ConnectionConfig};
+
+use super::serve_connections;
+
+fn config(addr: SocketAddr) -> ConnectionConfig<command::Noop> {
+    ConnectionConfig::builder_local_unencrypted()
+        .port(addr.port())
+        .client_id(ClientId::localhost())
+        .build()
+}
+
+#[test]
+fn retries_on_io_errors() {
+    let (addr, count, _) =
+        serve_connections(vec![None, Some(vec!["250 localhost", "250 Ok", "221 Bye"])]);
+
+    let mut runtime = Runtime::new().unwrap();
+    let con = runtime
+        .block_on(config(addr).connect_with_retry(3, Duration::from_millis(5)))
+        .unwrap();
+    runtime.block_on(con.quit()).unwrap();
+
+    assert_eq!(count.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn does_not_retry_auth_failures() {
+    let (addr, count, _) = serve_connections(vec![
+        Some(vec!["250 localhost", "535 nope", "221 Bye"]),
+        Some(vec!["250 localhost", "250 Ok", "221 Bye"]),
+    ]);
+
+    let mut runtime = Runtime::new().unwrap();
+    let res = runtime.block_on(config(addr).connect_with_retry(3, Duration::from_millis(5)));
+
+    match res {
+        Err(ConnectingFailed::Auth(_)) => {}
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+    thread::sleep(Duration::from_millis(50));
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+}
@@ -0,0 +1,49 @@
+use std::error::Error;
+use std::io as std_io;
+
+use futures::Future;
+
+use new_tokio_smtp::{command, error::DataBodyInterrupted, Connection};
+
+use super::FailingStream;
+
+fn connection(accept: usize) -> Connection {
+    FailingStream::abort_after(accept, b"354 go ahead\r\n").into_connection()
+}
+
+#[test]
+fn connection_lost_during_body_is_marked() {
+    // accept "DATA\r\n" and a bit of the body
+    let con = connection(8);
+
+    let err = con
+        .send(command::Data::from_buf(&b"some mail body\r\n"[..]))
+        .wait()
+        .unwrap_err();
+
+    assert_eq!(err.kind(), std_io::ErrorKind::ConnectionAborted);
+    let inner = err
+        .get_ref()
+        .and_then(|inner| inner.downcast_ref::<DataBodyInterrupted>())
+        .expect("DataBodyInterrupted");
+    assert_eq!(inner.cause().kind(), std_io::ErrorKind::ConnectionAborted);
+    assert!(inner.source().is_some());
+    assert!(err.to_string().contains("connection lost during DATA body"));
+}
+
+#[test]
+fn connection_lost_before_data_is_not_marked() {
+    let con = connection(0);
+
+    let err = con
+        .send(command::Data::from_buf(&b"some mail body\r\n"[..]))
+        .wait()
+        .unwrap_err();
+
+    assert_eq!(err.kind(), std_io::ErrorKind::ConnectionAborted);
+    let is_marked = err
+        .get_ref()
+        .map(|inner| inner.is::<DataBodyInterrupted>())
+        .unwrap_or(false);
+    assert!(!is_marked);
+}
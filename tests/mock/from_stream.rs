@@ -0,0 +1,44 @@
+use futures::Future;
+
+use new_tokio_smtp::{
+    error::ConnectingFailed,
+    mock::{ActionData, Actor, MockSocket},
+    ClientId, Connection,
+};
+
+use self::ActionData::*;
+use self::Actor::*;
+
+fn client_id() -> ClientId {
+    ClientId::Domain("me.test".parse().unwrap())
+}
+
+#[test]
+fn reads_greeting_and_sends_ehlo() {
+    let socket = MockSocket::new(vec![
+        (Server, Lines(vec!["220 they.test ready"])),
+        (Client, Lines(vec!["EHLO me.test"])),
+        (
+            Server,
+            Lines(vec!["250-they.test greets you", "250 SMTPUTF8"]),
+        ),
+    ]);
+
+    let con = Connection::from_stream(socket, client_id()).wait().unwrap();
+    assert!(con.has_capability("SMTPUTF8"));
+    con.shutdown().wait().unwrap();
+}
+
+#[test]
+fn fails_with_setup_error_on_bad_greeting() {
+    let socket = MockSocket::new(vec![
+        (Server, Lines(vec!["554 go away"])),
+        (Client, Lines(vec!["QUIT"])),
+        (Server, Lines(vec!["221 bye"])),
+    ]);
+
+    match Connection::from_stream(socket, client_id()).wait() {
+        Err(ConnectingFailed::Setup(_)) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
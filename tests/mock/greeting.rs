@@ -0,0 +1,110 @@
+use std::net::TcpListener;
+use std::thread;
+use std::time::Duration;
+
+use futures::Future;
+use tokio::runtime::current_thread::Runtime;
+
+use new_tokio_smtp::{
+    error::ConnectingFailed,
+    mock::{ActionData, Actor, MockSocket},
+    ClientId, Connection, ConnectionConfig,
+};
+
+use self::ActionData::*;
+use self::Actor::*;
+
+#[test]
+fn multiline_greeting_is_retrievable() {
+    let socket = MockSocket::new(vec![
+        (
+            Server,
+            Lines(vec!["220-they.test ESMTP", "220 no UCE please"]),
+        ),
+        (Client, Lines(vec!["EHLO [127.0.0.1]"])),
+        (Server, Lines(vec!["250 they.test"])),
+    ]);
+
+    let con = Connection::from_stream(socket, ClientId::localhost())
+        .wait()
+        .unwrap();
+    {
+        let greeting = con.greeting().unwrap();
+        assert_eq!(greeting.code().as_byte_string(), *b"220");
+        assert_eq!(greeting.msg(), &["they.test ESMTP", "no UCE please"]);
+    }
+    con.shutdown().wait().unwrap();
+}
+
+#[test]
+fn times_out_if_server_sends_no_greeting() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    thread::spawn(move || {
+        let (_stream, _) = listener.accept().unwrap();
+        thread::sleep(Duration::from_millis(500));
+    });
+
+    let config = ConnectionConfig::builder_local_unencrypted()
+        .port(port)
+        .client_id(ClientId::localhost())
+        .greeting_timeout(Duration::from_millis(20))
+        .build();
+
+    let mut runtime = Runtime::new().unwrap();
+    match runtime.block_on(Connection::connect(config)) {
+        Err(ConnectingFailed::Io(err)) => assert_eq!(err.kind(), std::io::ErrorKind::TimedOut),
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+}
+
+fn connect_with_greeting(greeting: &'static str) -> Result<Connection, ConnectingFailed> {
+    let socket = MockSocket::new(vec![
+        (Server, Lines(vec![greeting])),
+        (Client, Lines(vec!["QUIT"])),
+        (Server, Lines(vec!["221 bye"])),
+    ]);
+    Connection::from_stream(socket, ClientId::localhost()).wait()
+}
+
+#[test]
+fn service_ready_greeting_is_accepted() {
+    let socket = MockSocket::new(vec![
+        (Server, Lines(vec!["220 they.test ready"])),
+        (Client, Lines(vec!["EHLO [127.0.0.1]"])),
+        (Server, Lines(vec!["250 they.test"])),
+    ]);
+
+    let con = Connection::from_stream(socket, ClientId::localhost())
+        .wait()
+        .unwrap();
+    assert!(con.greeting().unwrap().is_service_ready());
+    con.shutdown().wait().unwrap();
+}
+
+#[test]
+fn service_unavailable_greeting_is_transient() {
+    // the server is closing the connection, so no QUIT is send
+    let socket = MockSocket::new(vec![(Server, Lines(vec!["421 try again later"]))]);
+
+    match Connection::from_stream(socket, ClientId::localhost()).wait() {
+        Err(ConnectingFailed::Setup(err)) => assert!(err.is_transient()),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn no_smtp_service_greeting_is_permanent() {
+    match connect_with_greeting("554 No SMTP service here") {
+        Err(ConnectingFailed::Setup(err)) => assert!(!err.is_transient()),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn other_positive_greeting_is_rejected() {
+    match connect_with_greeting("250 hy") {
+        Err(ConnectingFailed::Setup(err)) => assert!(!err.is_transient()),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
@@ -0,0 +1,72 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use futures::{future::Either, Future};
+use tokio::{runtime::current_thread::Runtime, timer::Delay};
+
+use new_tokio_smtp::{
+    command,
+    mock::{ActionData, Actor},
+};
+
+use self::ActionData::*;
+use self::Actor::*;
+
+use super::mock;
+
+#[test]
+fn quits_after_being_idle() {
+    let con = mock(vec![
+        (Client, Lines(vec!["QUIT"])),
+        (Server, Lines(vec!["221 Bye"])),
+    ]);
+
+    let idle = con.with_idle_timeout(Duration::from_millis(10));
+    let mut runtime = Runtime::new().unwrap();
+    runtime.block_on(idle).unwrap();
+}
+
+#[test]
+fn send_resets_the_timer() {
+    let con = mock(vec![
+        (Client, Lines(vec!["NOOP"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["NOOP"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["QUIT"])),
+        (Server, Lines(vec!["221 Bye"])),
+    ]);
+
+    let mut runtime = Runtime::new().unwrap();
+    let idle = con.with_idle_timeout(Duration::from_millis(200));
+    thread::sleep(Duration::from_millis(120));
+    let (mut idle, result) = runtime.block_on(idle.send(command::Noop)).unwrap();
+    assert!(result.is_ok());
+
+    // drive the timer past the initial deadline but not past the reset one
+    let wait = Delay::new(Instant::now() + Duration::from_millis(120));
+    let closed = runtime
+        .block_on(
+            (&mut idle)
+                .select2(wait)
+                .map(|either| match either {
+                    Either::A(_) => true,
+                    Either::B(_) => false,
+                })
+                .map_err(|_| ()),
+        )
+        .unwrap();
+    assert!(!closed);
+    assert!(!idle.is_closed());
+
+    let (mut idle, result) = runtime.block_on(idle.send(command::Noop)).unwrap();
+    assert!(result.is_ok());
+
+    runtime.block_on(&mut idle).unwrap();
+    assert!(idle.is_closed());
+
+    let err = runtime.block_on(idle.send(command::Noop)).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::NotConnected);
+}
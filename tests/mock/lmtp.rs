@@ -0,0 +1,186 @@
+use futures::Future;
+use tokio::runtime::current_thread::Runtime;
+use vec1::vec1;
+
+use new_tokio_smtp::mock::{ActionData::*, Actor::*};
+use new_tokio_smtp::{
+    chain::OnError,
+    command,
+    error::LogicError,
+    response::codes,
+    send_mail::{EncodingRequirement, Mail, MailAddress, MailEnvelop},
+    ClientId, ConnectionConfig, ForwardPath, ReversePath,
+};
+
+use super::{mock, serve_recording};
+
+fn client_id() -> ClientId {
+    ClientId::Domain("me.test".parse().unwrap())
+}
+
+fn path(addr: &str) -> ReversePath {
+    MailAddress::from_unchecked(addr).into()
+}
+
+fn forward(addr: &str) -> ForwardPath {
+    MailAddress::from_unchecked(addr).into()
+}
+
+#[test]
+fn lhlo_sets_ehlo_data() {
+    let con = mock(vec![
+        (Client, Lines(vec!["LHLO me.test"])),
+        (Server, Lines(vec!["250-they.test", "250 PIPELINING"])),
+    ]);
+    let (con, res) = con.send(command::Lhlo::new(client_id())).wait().unwrap();
+    assert!(res.is_ok());
+    assert_eq!(con.ehlo_data().unwrap().domain(), "they.test");
+    assert!(con.has_capability("PIPELINING"));
+    assert_eq!(con.client_id().unwrap().as_str(), "me.test");
+    con.shutdown().wait().unwrap();
+}
+
+#[test]
+fn connect_option_sends_lhlo_instead_of_ehlo() {
+    let (addr, received_lines) =
+        serve_recording(vec!["250-they.test\r\n250 PIPELINING", "250 Ok", "221 Bye"]);
+
+    let config = ConnectionConfig::builder_local_unencrypted()
+        .port(addr.port())
+        .client_id(ClientId::localhost())
+        .lmtp(true)
+        .build();
+
+    let mut runtime = Runtime::new().unwrap();
+    let con = runtime.block_on(config.connect()).unwrap();
+    assert!(con.has_capability("PIPELINING"));
+    assert!(con.is_lmtp());
+    runtime.block_on(con.quit()).unwrap();
+
+    let lines = received_lines.iter().collect::<Vec<_>>();
+    assert_eq!(lines, vec!["LHLO [127.0.0.1]", "NOOP", "QUIT"]);
+}
+
+#[test]
+fn data_returns_one_result_per_accepted_recipient() {
+    let con = mock(vec![
+        (Client, Lines(vec!["LHLO me.test"])),
+        (Server, Lines(vec!["250 they.test"])),
+        (Client, Lines(vec!["MAIL FROM:<x@y.test>"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["RCPT TO:<a@y.test>"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["RCPT TO:<b@y.test>"])),
+        (Server, Lines(vec!["550 no such user"])),
+        (Client, Lines(vec!["RCPT TO:<c@y.test>"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["DATA"])),
+        (Server, Lines(vec!["354 go ahead"])),
+        (Client, Lines(vec!["body", "."])),
+        (Server, Lines(vec!["250 2.1.5 a ok", "452 4.2.2 c is full"])),
+    ]);
+    let (con, _) = con.send(command::Lhlo::new(client_id())).wait().unwrap();
+    assert!(con.is_lmtp());
+    let (con, _) = con
+        .send(command::Mail::new(path("x@y.test")))
+        .wait()
+        .unwrap();
+    let (con, _) = con
+        .send(command::Recipient::new(forward("a@y.test")))
+        .wait()
+        .unwrap();
+    let (con, _) = con
+        .send(command::Recipient::new(forward("b@y.test")))
+        .wait()
+        .unwrap();
+    let (con, _) = con
+        .send(command::Recipient::new(forward("c@y.test")))
+        .wait()
+        .unwrap();
+    let (con, results) = con
+        .send_lmtp_data(command::Data::from_buf("body\r\n"))
+        .wait()
+        .unwrap();
+
+    assert_eq!(results.len(), 2);
+    assert!(results[0].is_ok());
+    assert!(results[1].is_err());
+    assert!(!con.is_in_transaction());
+    con.shutdown().wait().unwrap();
+}
+
+#[test]
+fn rejected_data_returns_only_its_result() {
+    let con = mock(vec![
+        (Client, Lines(vec!["DATA"])),
+        (Server, Lines(vec!["503 no valid recipients"])),
+    ]);
+    let (con, results) = con
+        .send_lmtp_data(command::Data::from_buf("body\r\n"))
+        .wait()
+        .unwrap();
+
+    assert_eq!(results.len(), 1);
+    assert!(results[0].is_err());
+    con.shutdown().wait().unwrap();
+}
+
+#[test]
+fn send_mail_reads_all_data_responses_in_lmtp_mode() {
+    let con = mock(vec![
+        (Client, Lines(vec!["LHLO me.test"])),
+        (Server, Lines(vec!["250 they.test"])),
+        (Client, Lines(vec!["MAIL FROM:<x@y.test>"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["RCPT TO:<a@y.test>"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["RCPT TO:<b@y.test>"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["DATA"])),
+        (Server, Lines(vec!["354 go ahead"])),
+        (Client, Lines(vec!["body", "."])),
+        // separate actions, as the mock only lets the client read ahead
+        // into a following server action, not into the `NOOP`
+        (Server, Lines(vec!["250 2.1.5 a ok"])),
+        (Server, Lines(vec!["452 4.2.2 b is full"])),
+        (Client, Lines(vec!["NOOP"])),
+        (Server, Lines(vec!["250 Ok"])),
+    ]);
+    let (con, _) = con.send(command::Lhlo::new(client_id())).wait().unwrap();
+    let envelop = MailEnvelop::new(
+        MailAddress::from_unchecked("x@y.test"),
+        vec1![
+            MailAddress::from_unchecked("a@y.test"),
+            MailAddress::from_unchecked("b@y.test")
+        ],
+        Mail::new(EncodingRequirement::None, "body\r\n"),
+    );
+    let (con, result) = con.send_mail_with(envelop, OnError::Stop).wait().unwrap();
+    match result {
+        Err((_, LogicError::Code(response))) => {
+            assert_eq!(response.code(), codes::INSUFFICIENT_SYSTEM)
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    // the second response was not mistaken for the response to NOOP
+    let (con, result) = con.send(command::Noop).wait().unwrap();
+    assert!(result.is_ok());
+    con.shutdown().wait().unwrap();
+}
+
+#[test]
+fn rehlo_sends_lhlo_in_lmtp_mode() {
+    let con = mock(vec![
+        (Client, Lines(vec!["LHLO me.test"])),
+        (Server, Lines(vec!["250 they.test"])),
+        (Client, Lines(vec!["LHLO me.test"])),
+        (Server, Lines(vec!["250-they.test", "250 PIPELINING"])),
+    ]);
+    let (con, _) = con.send(command::Lhlo::new(client_id())).wait().unwrap();
+    let (con, res) = con.rehlo().wait().unwrap();
+    assert!(res.is_ok());
+    assert!(con.is_lmtp());
+    assert!(con.has_capability("PIPELINING"));
+    con.shutdown().wait().unwrap();
+}
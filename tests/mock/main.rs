@@ -1,14 +1,47 @@
+mod abandoned_conversation;
+mod cached_ehlo;
 mod chain;
 mod command;
+mod connect_retry;
+mod data_interrupted;
+mod from_stream;
+mod greeting;
+mod idle_timeout;
 mod issue_05;
+mod lmtp;
+mod named_futures;
+mod opportunistic_tls;
+mod output_buffer;
+mod pipeline_guard;
+mod quit_graceful;
+mod quit_on_drop;
+mod raw_response;
+mod rehlo;
+mod response_limits;
+mod scripted_latency;
 #[cfg(feature = "send-mail")]
 mod send_mail;
+mod service_closing;
+mod streamed_response;
+mod take_socket;
+mod trace_hook;
+mod zero_write;
 
 use std::collections::HashMap;
-use std::str::FromStr;
+use std::io::{self as std_io, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    mpsc, Arc, Mutex,
+};
+use std::thread;
 
+use futures::{Async, Poll};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use new_tokio_smtp::io::{MockStream, Socket};
 use new_tokio_smtp::mock::{ActionData, Actor, MockSocket};
-use new_tokio_smtp::{Capability, Connection, Domain, EhloData, EsmtpKeyword, Io};
+use new_tokio_smtp::{Connection, Domain, EhloData, Io};
 
 pub fn mock(conv: Vec<(Actor, ActionData)>) -> Connection {
     let io: Io = MockSocket::new(conv).into();
@@ -21,17 +54,177 @@ pub fn mock_no_shutdown(conv: Vec<(Actor, ActionData)>) -> Connection {
 }
 
 pub fn with_capability(con: Connection, cap: &str) -> Connection {
-    let capability = Capability::from(EsmtpKeyword::from_str(cap).unwrap());
+    with_capabilities(con, &[(cap, &[])])
+}
 
-    let (socket, buffer, opt_ehlo_data) = Io::from(con).split();
+/// adds the capabilities (with their parameters) to the ehlo data of the connection
+pub fn with_capabilities(con: Connection, caps: &[(&str, &[&str])]) -> Connection {
+    let added = EhloData::from_caps(Domain::from_unchecked("uhmail.test"), caps).unwrap();
+    let (_, added): (Domain, HashMap<_, _>) = added.into();
 
-    let (domain, mut ehlo_map) = opt_ehlo_data
+    let mut io = Io::from(con);
+
+    let (domain, mut ehlo_map) = io
+        .ehlo_data()
+        .cloned()
         .map(|ehlo_data| ehlo_data.into())
         .unwrap_or_else(|| (Domain::from_unchecked("uhmail.test"), HashMap::new()));
 
-    ehlo_map.insert(capability, Vec::new());
+    ehlo_map.extend(added);
+
+    io.set_ehlo_data(EhloData::from((domain, ehlo_map)));
+    Connection::from(io)
+}
+
+/// a minimal smtp server for one connection, recording the lines send by the client
+///
+/// It sends `"220 hy"` as greeting and answers each line with the next reply,
+/// replies can contain multiple lines (separated by `"\r\n"`). The recorded
+/// lines are available through the receiver once the server is done.
+pub fn serve_recording(replies: Vec<&'static str>) -> (SocketAddr, mpsc::Receiver<String>) {
+    let (addr, _, received_lines) = serve_connections(vec![Some(replies)]);
+    (addr, received_lines)
+}
+
+/// like `serve_recording`, but the n-th connection is handled by the n-th script
+///
+/// A `None` script closes the connection directly (without a greeting).
+/// The returned counter is the number of accepted connections.
+pub fn serve_connections(
+    scripts: Vec<Option<Vec<&'static str>>>,
+) -> (SocketAddr, Arc<AtomicUsize>, mpsc::Receiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let count = Arc::new(AtomicUsize::new(0));
+    let counter = count.clone();
+    let (line_sender, received_lines) = mpsc::channel();
+    thread::spawn(move || {
+        for script in scripts {
+            let (mut stream, _) = listener.accept().unwrap();
+            counter.fetch_add(1, Ordering::SeqCst);
+            let replies = match script {
+                Some(replies) => replies,
+                None => continue,
+            };
+            stream.write_all(b"220 hy\r\n").unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            for reply in replies {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 {
+                    break;
+                }
+                // the receiver isn't required to be kept around
+                let _ = line_sender.send(line.trim_end().to_owned());
+                stream.write_all(reply.as_bytes()).unwrap();
+                stream.write_all(b"\r\n").unwrap();
+            }
+        }
+    });
+    (addr, count, received_lines)
+}
+
+/// a mock stream for the failure cases the scripted `MockSocket` can't express
+///
+/// Reads are answered with the given reply and then block forever. Up to
+/// `accept` written bytes are accepted (and recorded), after that writes
+/// either fail with `ConnectionAborted` or return `Ok(0)`.
+#[derive(Debug)]
+pub struct FailingStream {
+    reply: &'static [u8],
+    accept: usize,
+    when_full: WhenFull,
+    written: Arc<Mutex<Vec<u8>>>,
+    was_shutdown: Arc<AtomicBool>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum WhenFull {
+    Abort,
+    WriteZero,
+}
+
+impl FailingStream {
+    fn new(accept: usize, when_full: WhenFull, reply: &'static [u8]) -> Self {
+        FailingStream {
+            reply,
+            accept,
+            when_full,
+            written: Default::default(),
+            was_shutdown: Default::default(),
+        }
+    }
+
+    /// a stream accepting all writes but never answering them
+    pub fn silent() -> Self {
+        FailingStream::new(usize::MAX, WhenFull::Abort, b"")
+    }
+
+    /// a stream sending `reply` and aborting the connection after `accept` bytes were written
+    pub fn abort_after(accept: usize, reply: &'static [u8]) -> Self {
+        FailingStream::new(accept, WhenFull::Abort, reply)
+    }
+
+    /// a stream which never accepts any data
+    pub fn zero_write() -> Self {
+        FailingStream::new(0, WhenFull::WriteZero, b"")
+    }
+
+    /// returns a handle to the bytes written so far
+    pub fn written(&self) -> Arc<Mutex<Vec<u8>>> {
+        self.written.clone()
+    }
+
+    /// returns a flag which is set once the stream was shut down
+    pub fn was_shutdown(&self) -> Arc<AtomicBool> {
+        self.was_shutdown.clone()
+    }
+
+    pub fn into_connection(self) -> Connection {
+        let io: Io = Socket::Mock(Box::new(self)).into();
+        Connection::from(io)
+    }
+}
+
+impl MockStream for FailingStream {
+    fn set_is_secure(&mut self, _secure: bool) {}
+}
+
+impl Read for FailingStream {
+    fn read(&mut self, buf: &mut [u8]) -> std_io::Result<usize> {
+        if self.reply.is_empty() {
+            return Err(std_io::ErrorKind::WouldBlock.into());
+        }
+        let len = self.reply.len().min(buf.len());
+        buf[..len].copy_from_slice(&self.reply[..len]);
+        self.reply = &self.reply[len..];
+        Ok(len)
+    }
+}
+
+impl Write for FailingStream {
+    fn write(&mut self, buf: &[u8]) -> std_io::Result<usize> {
+        if self.accept == 0 {
+            return match self.when_full {
+                WhenFull::Abort => Err(std_io::ErrorKind::ConnectionAborted.into()),
+                WhenFull::WriteZero => Ok(0),
+            };
+        }
+        let len = self.accept.min(buf.len());
+        self.accept -= len;
+        self.written.lock().unwrap().extend_from_slice(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> std_io::Result<()> {
+        Ok(())
+    }
+}
 
-    let ehlo_data = EhloData::from((domain, ehlo_map));
+impl AsyncRead for FailingStream {}
 
-    Connection::from(Io::from((socket, buffer, ehlo_data)))
+impl AsyncWrite for FailingStream {
+    fn shutdown(&mut self) -> Poll<(), std_io::Error> {
+        self.was_shutdown.store(true, Ordering::SeqCst);
+        Ok(Async::Ready(()))
+    }
 }
@@ -0,0 +1,75 @@
+use futures::Future;
+
+use new_tokio_smtp::mock::{ActionData, Actor};
+#[cfg(feature = "send-mail")]
+use new_tokio_smtp::send_mail::MailSendFuture;
+use new_tokio_smtp::{command, QuitFuture, SendFuture};
+
+use self::ActionData::*;
+use self::Actor::*;
+
+use super::mock;
+
+/// the futures have to be storable in a struct without naming a `impl Future`
+struct PendingSend {
+    fut: SendFuture,
+}
+
+struct PendingQuit {
+    fut: QuitFuture,
+}
+
+#[test]
+fn send_and_quit_futures_can_be_stored() {
+    let con = mock(vec![
+        (Client, Lines(vec!["NOOP"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["QUIT"])),
+        (Server, Lines(vec!["221 Bye"])),
+    ]);
+
+    let pending = PendingSend {
+        fut: Box::new(con.send(command::Noop)),
+    };
+    let (con, result) = pending.fut.wait().unwrap();
+    result.unwrap();
+
+    let pending = PendingQuit {
+        fut: Box::new(con.quit()),
+    };
+    pending.fut.wait().unwrap();
+}
+
+#[cfg(feature = "send-mail")]
+#[test]
+fn send_mail_future_can_be_stored() {
+    use new_tokio_smtp::send_mail::{EncodingRequirement, Mail, MailAddress, MailEnvelop};
+    use vec1::vec1;
+
+    struct PendingMail {
+        fut: MailSendFuture,
+    }
+
+    let con = mock(vec![
+        (Client, Lines(vec!["MAIL FROM:<t1@test.test>"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["RCPT TO:<t2@test.test>"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["DATA"])),
+        (Server, Lines(vec!["354 ..."])),
+        (Client, Blob(b"the data\r\n.\r\n".to_vec())),
+        (Server, Lines(vec!["250 Ok"])),
+    ]);
+    let envelop = MailEnvelop::new(
+        MailAddress::from_unchecked("t1@test.test"),
+        vec1![MailAddress::from_unchecked("t2@test.test")],
+        Mail::new(EncodingRequirement::None, Vec::from("the data\r\n")),
+    );
+
+    let pending = PendingMail {
+        fut: Box::new(con.send_mail(envelop)),
+    };
+    let (con, result) = pending.fut.wait().unwrap();
+    result.unwrap();
+    con.shutdown().wait().unwrap();
+}
@@ -0,0 +1,101 @@
+use std::net::SocketAddr;
+
+use tokio::runtime::current_thread::Runtime;
+
+use new_tokio_smtp::{
+    command, error::ConnectingFailed, ClientId, Connection, ConnectionConfig, DefaultTlsSetup,
+    Domain, Security, SyntaxErrorHandling, TlsConfig,
+};
+
+use super::serve_recording;
+
+fn opportunistic_config(addr: SocketAddr) -> ConnectionConfig<command::Noop> {
+    ConnectionConfig {
+        addr,
+        auth_cmd: command::Noop,
+        security: Security::Opportunistic(TlsConfig {
+            domain: Domain::from_unchecked("localhost"),
+            setup: DefaultTlsSetup,
+        }),
+        client_id: ClientId::localhost(),
+        syntax_error_handling: SyntaxErrorHandling::default(),
+        greeting_timeout: None,
+        read_idle_timeout: None,
+        tcp_options: Default::default(),
+        lmtp: false,
+    }
+}
+
+#[test]
+fn continues_unencrypted_without_starttls() {
+    let (addr, received_lines) =
+        serve_recording(vec!["250-they.test\r\n250 SMTPUTF8", "250 Ok", "221 Bye"]);
+
+    let mut runtime = Runtime::new().unwrap();
+    let con = runtime
+        .block_on(Connection::connect(opportunistic_config(addr)))
+        .unwrap();
+    assert!(!con.state().is_secure);
+    assert!(con.has_capability("SMTPUTF8"));
+    runtime.block_on(con.quit()).unwrap();
+
+    let lines = received_lines.iter().collect::<Vec<_>>();
+    assert_eq!(lines, vec!["EHLO [127.0.0.1]", "NOOP", "QUIT"]);
+}
+
+#[cfg(feature = "native-tls")]
+#[test]
+fn uses_starttls_if_supported() {
+    // the server stops after accepting STARTTLS, so the handshake fails
+    let (addr, received_lines) =
+        serve_recording(vec!["250-they.test\r\n250 STARTTLS", "220 Go ahead"]);
+
+    let mut runtime = Runtime::new().unwrap();
+    let res = runtime.block_on(Connection::connect(opportunistic_config(addr)));
+    match res {
+        Err(ConnectingFailed::Io(_)) => {}
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+
+    let lines = received_lines.iter().collect::<Vec<_>>();
+    assert_eq!(lines, vec!["EHLO [127.0.0.1]", "STARTTLS"]);
+}
+
+#[cfg(not(feature = "native-tls"))]
+#[test]
+fn fails_without_sending_starttls_if_tls_is_disabled() {
+    let (addr, received_lines) = serve_recording(vec!["250-they.test\r\n250 STARTTLS"]);
+
+    let mut runtime = Runtime::new().unwrap();
+    let res = runtime.block_on(Connection::connect(opportunistic_config(addr)));
+    match res {
+        Err(ConnectingFailed::Io(_)) => {}
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+
+    let lines = received_lines.iter().collect::<Vec<_>>();
+    assert_eq!(lines, vec!["EHLO [127.0.0.1]"]);
+}
+
+#[test]
+fn builder_auth_is_not_send_unencrypted() {
+    let (addr, received_lines) = serve_recording(vec!["250-they.test\r\n250 SMTPUTF8"]);
+
+    let config = ConnectionConfig::builder_with_addr(addr, Domain::from_unchecked("localhost"))
+        .use_opportunistic_tls()
+        .client_id(ClientId::localhost())
+        .auth(command::Noop)
+        .build();
+
+    let mut runtime = Runtime::new().unwrap();
+    let res = runtime.block_on(Connection::connect(config));
+    match res {
+        Err(ConnectingFailed::Auth(err)) => {
+            assert_eq!(err.to_string(), "refusing to run over plaintext")
+        }
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+
+    let lines = received_lines.iter().collect::<Vec<_>>();
+    assert_eq!(lines, vec!["EHLO [127.0.0.1]"]);
+}
@@ -0,0 +1,65 @@
+use futures::Future;
+
+use new_tokio_smtp::{
+    command,
+    mock::{ActionData, Actor},
+    Connection, Io,
+};
+
+use self::ActionData::*;
+use self::Actor::*;
+
+use super::mock_no_shutdown;
+
+const THRESHOLD: usize = 16 * 1024;
+
+fn send_large_mail(threshold: Option<usize>) -> Connection {
+    let body = vec![b'a'; 256 * 1024];
+    let mut expected = body.clone();
+    expected.extend_from_slice(b"\r\n.\r\n");
+
+    let mut con = mock_no_shutdown(vec![
+        (Client, Lines(vec!["DATA"])),
+        (Server, Lines(vec!["354 go ahead"])),
+        (Client, Blob(expected)),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["NOOP"])),
+        (Server, Lines(vec!["250 Ok"])),
+    ]);
+    con.set_output_shrink_threshold(threshold);
+
+    let (con, result) = con.send(command::Data::from_buf(body)).wait().unwrap();
+    result.unwrap();
+    con
+}
+
+/// the capacity available for a small write
+///
+/// (this makes a not shrunk buffer reclaim the space of already flushed bytes)
+fn output_capacity(con: Connection) -> (Connection, usize) {
+    let mut io = Io::from(con);
+    let capacity = io.out_buffer(1024).capacity();
+    (Connection::from(io), capacity)
+}
+
+#[test]
+fn output_buffer_is_shrunk_after_large_data() {
+    let con = send_large_mail(Some(THRESHOLD));
+
+    let (con, result) = con.send(command::Noop).wait().unwrap();
+    result.unwrap();
+
+    let (_con, capacity) = output_capacity(con);
+    assert!(capacity <= THRESHOLD, "capacity: {}", capacity);
+}
+
+#[test]
+fn output_buffer_is_kept_if_disabled() {
+    let con = send_large_mail(None);
+
+    let (con, result) = con.send(command::Noop).wait().unwrap();
+    result.unwrap();
+
+    let (_con, capacity) = output_capacity(con);
+    assert!(capacity > THRESHOLD, "capacity: {}", capacity);
+}
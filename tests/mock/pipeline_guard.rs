@@ -0,0 +1,29 @@
+use futures::Future;
+
+use super::{mock, with_capability};
+
+#[test]
+fn errors_without_pipelining() {
+    let con = with_capability(mock(vec![]), "8BITMIME");
+
+    let err = con.pipeline_guard().unwrap_err();
+    assert_eq!(err.capabilities().len(), 1);
+    assert_eq!(err.capabilities()[0].as_str(), "PIPELINING");
+    con.shutdown().wait().unwrap();
+}
+
+#[test]
+fn errors_without_ehlo_data() {
+    let con = mock(vec![]);
+
+    assert!(con.pipeline_guard().is_err());
+    con.shutdown().wait().unwrap();
+}
+
+#[test]
+fn succeeds_with_pipelining() {
+    let con = with_capability(mock(vec![]), "PIPELINING");
+
+    assert!(con.pipeline_guard().is_ok());
+    con.shutdown().wait().unwrap();
+}
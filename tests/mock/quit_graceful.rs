@@ -0,0 +1,39 @@
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use tokio::runtime::current_thread::Runtime;
+
+use new_tokio_smtp::mock::{ActionData::Lines, Actor::*};
+
+use super::{mock, FailingStream};
+
+#[test]
+fn sends_quit_and_shuts_down() {
+    let con = mock(vec![
+        (Client, Lines(vec!["QUIT"])),
+        (Server, Lines(vec!["221 Bye"])),
+    ]);
+
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .block_on(con.quit_graceful(Duration::from_secs(5)))
+        .unwrap();
+}
+
+#[test]
+fn shuts_down_if_quit_response_never_arrives() {
+    let socket = FailingStream::silent();
+    let written = socket.written();
+    let was_shutdown = socket.was_shutdown();
+    let con = socket.into_connection();
+
+    let mut runtime = Runtime::new().unwrap();
+    let start = Instant::now();
+    runtime
+        .block_on(con.quit_graceful(Duration::from_millis(50)))
+        .unwrap();
+
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert!(was_shutdown.load(Ordering::SeqCst));
+    assert_eq!(&*written.lock().unwrap(), b"QUIT\r\n");
+}
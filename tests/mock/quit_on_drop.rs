@@ -0,0 +1,37 @@
+use futures::{future, Future};
+use tokio::runtime::current_thread::Runtime;
+
+use new_tokio_smtp::mock::{ActionData, Actor};
+
+use self::ActionData::*;
+use self::Actor::*;
+
+use super::mock;
+
+#[test]
+fn dropping_the_guard_sends_quit() {
+    let con = mock(vec![
+        (Client, Lines(vec!["QUIT"])),
+        (Server, Lines(vec!["221 Bye"])),
+    ]);
+
+    let guard = con.quit_on_drop();
+    assert!(!guard.has_capability("SMTPUTF8"));
+
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .block_on(future::lazy(move || -> Result<(), ()> {
+            drop(guard);
+            Ok(())
+        }))
+        .unwrap();
+    runtime.run().unwrap();
+}
+
+#[test]
+fn into_inner_opts_out() {
+    let con = mock(vec![]);
+
+    let con = con.quit_on_drop().into_inner();
+    con.shutdown().wait().unwrap();
+}
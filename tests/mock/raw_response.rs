@@ -0,0 +1,43 @@
+use futures::Future;
+
+use new_tokio_smtp::{
+    command,
+    mock::{ActionData, Actor},
+};
+
+use self::ActionData::*;
+use self::Actor::*;
+
+use super::mock;
+
+#[test]
+fn retains_the_exact_bytes_of_a_response() {
+    let mut con = mock(vec![
+        (Client, Lines(vec!["NOOP"])),
+        (
+            Server,
+            Blob(b"250-first line\r\n250-  spaced \r\n250 last\r\n".to_vec()),
+        ),
+    ]);
+    con.set_retain_raw_responses(true);
+
+    let (con, res) = con.send(command::Noop).wait().unwrap();
+    let response = res.unwrap();
+    assert_eq!(
+        response.raw(),
+        Some(&b"250-first line\r\n250-  spaced \r\n250 last\r\n"[..])
+    );
+    con.shutdown().wait().unwrap();
+}
+
+#[test]
+fn raw_bytes_are_not_retained_by_default() {
+    let con = mock(vec![
+        (Client, Lines(vec!["NOOP"])),
+        (Server, Lines(vec!["250 Ok"])),
+    ]);
+
+    let (con, res) = con.send(command::Noop).wait().unwrap();
+    assert_eq!(res.unwrap().raw(), None);
+    con.shutdown().wait().unwrap();
+}
@@ -0,0 +1,59 @@
+use futures::Future;
+
+use new_tokio_smtp::{
+    error::LogicError,
+    mock::{ActionData, Actor, MockSocket},
+    ClientId, Connection, Io,
+};
+
+use self::ActionData::*;
+use self::Actor::*;
+
+fn client_id(domain: &str) -> ClientId {
+    ClientId::Domain(domain.parse().unwrap())
+}
+
+#[test]
+fn rehlo_uses_changed_identity() {
+    let socket = MockSocket::new(vec![
+        (Server, Lines(vec!["220 they.test ready"])),
+        (Client, Lines(vec!["EHLO me.test"])),
+        (Server, Lines(vec!["250 they.test greets you"])),
+        (Client, Lines(vec!["EHLO other.test"])),
+        (
+            Server,
+            Lines(vec!["250-they.test greets you again", "250 SMTPUTF8"]),
+        ),
+    ]);
+
+    let con = Connection::from_stream(socket, client_id("me.test"))
+        .wait()
+        .unwrap();
+    assert_eq!(con.client_id().map(ClientId::as_str), Some("me.test"));
+    assert!(!con.has_capability("SMTPUTF8"));
+
+    let (con, result) = con
+        .with_identity(client_id("other.test"))
+        .rehlo()
+        .wait()
+        .unwrap();
+
+    result.unwrap();
+    assert_eq!(con.client_id().map(ClientId::as_str), Some("other.test"));
+    assert!(con.has_capability("SMTPUTF8"));
+    con.shutdown().wait().unwrap();
+}
+
+#[test]
+fn rehlo_without_identity_sends_nothing() {
+    let io: Io = MockSocket::new(vec![]).into();
+    let con = Connection::from(io);
+
+    let (con, result) = con.rehlo().wait().unwrap();
+
+    match result {
+        Err(LogicError::Custom(_)) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+    con.shutdown().wait().unwrap();
+}
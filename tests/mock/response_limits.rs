@@ -0,0 +1,33 @@
+use std::io::ErrorKind;
+
+use futures::Future;
+
+use new_tokio_smtp::{
+    command,
+    mock::{ActionData, Actor},
+};
+
+use self::ActionData::*;
+use self::Actor::*;
+
+use super::mock_no_shutdown;
+
+#[test]
+fn rejects_too_many_continuation_lines() {
+    let mut lines = vec!["250-more"; 200];
+    lines.push("250 done");
+    let con = mock_no_shutdown(vec![(Client, Lines(vec!["NOOP"])), (Server, Lines(lines))]);
+
+    let err = con.send(command::Noop).wait().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+}
+
+#[test]
+fn rejects_overlong_unterminated_lines() {
+    let mut line = b"250 ".to_vec();
+    line.resize(2048, b'a');
+    let con = mock_no_shutdown(vec![(Client, Lines(vec!["NOOP"])), (Server, Blob(line))]);
+
+    let err = con.send(command::Noop).wait().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+}
@@ -0,0 +1,92 @@
+use std::{
+    io as std_io,
+    time::{Duration, Instant},
+};
+
+use tokio::{runtime::current_thread::Runtime, timer::Timeout};
+
+use new_tokio_smtp::{
+    command,
+    mock::{ActionData, Actor, MockSocket},
+    Connection, Io,
+};
+
+use self::ActionData::*;
+use self::Actor::*;
+
+use super::mock;
+
+#[test]
+fn command_times_out_if_server_is_slower() {
+    let io: Io = MockSocket::new_allow_incomplete(vec![
+        (Client, Lines(vec!["NOOP"])),
+        (
+            ServerAfter(Duration::from_millis(500)),
+            Lines(vec!["250 Ok"]),
+        ),
+    ])
+    .into();
+    let con = Connection::from(io);
+
+    let mut runtime = Runtime::new().unwrap();
+    let fut = Timeout::new(con.send(command::Noop), Duration::from_millis(20));
+    let err = runtime.block_on(fut).unwrap_err();
+    assert!(err.is_elapsed());
+}
+
+#[test]
+fn response_is_read_after_delay() {
+    let con = mock(vec![
+        (Client, Lines(vec!["NOOP"])),
+        (
+            ServerAfter(Duration::from_millis(30)),
+            Lines(vec!["250 Ok"]),
+        ),
+    ]);
+
+    let mut runtime = Runtime::new().unwrap();
+    let start = Instant::now();
+    let fut = Timeout::new(con.send(command::Noop), Duration::from_secs(5));
+    let (con, result) = runtime.block_on(fut).unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(30));
+    assert!(result.is_ok());
+    runtime.block_on(con.shutdown()).unwrap();
+}
+
+#[test]
+fn stalled_response_fails_with_read_idle_timeout() {
+    let io: Io = MockSocket::new_allow_incomplete(vec![
+        (Client, Lines(vec!["NOOP"])),
+        (Server, Blob(b"250-still ".to_vec())),
+        (
+            ServerAfter(Duration::from_millis(500)),
+            Blob(b"working\r\n250 Ok\r\n".to_vec()),
+        ),
+    ])
+    .into();
+    let mut con = Connection::from(io);
+    con.set_read_idle_timeout(Some(Duration::from_millis(20)));
+
+    let mut runtime = Runtime::new().unwrap();
+    let start = Instant::now();
+    let err = runtime.block_on(con.send(command::Noop)).unwrap_err();
+    assert_eq!(err.kind(), std_io::ErrorKind::TimedOut);
+    assert!(start.elapsed() < Duration::from_millis(500));
+}
+
+#[test]
+fn read_idle_timeout_does_not_limit_waiting_for_the_response() {
+    let mut con = mock(vec![
+        (Client, Lines(vec!["NOOP"])),
+        (
+            ServerAfter(Duration::from_millis(50)),
+            Lines(vec!["250 Ok"]),
+        ),
+    ]);
+    con.set_read_idle_timeout(Some(Duration::from_millis(10)));
+
+    let mut runtime = Runtime::new().unwrap();
+    let (con, result) = runtime.block_on(con.send(command::Noop)).unwrap();
+    assert!(result.is_ok());
+    runtime.block_on(con.shutdown()).unwrap();
+}
@@ -1,15 +1,24 @@
-use futures::Future;
+use std::io::{self as std_io, Cursor};
+
+use futures::{future, stream, Future};
 use vec1::vec1;
 
 use new_tokio_smtp::{
+    chain::{HandleErrorInChain, OnError},
+    error::{GeneralError, LogicError, PreflightReason},
     mock::{ActionData, Actor},
-    send_mail::{EncodingRequirement, Mail, MailAddress, MailEnvelop},
+    response::codes,
+    send_mail::{
+        collect_all_results, send_mail, EncodingRequirement, EnvelopData, Mail, MailAddress,
+        MailEnvelop, OnUnsupported8bit, SendAllMails,
+    },
+    Connection, ForwardPath, ReversePath,
 };
 
 use self::ActionData::*;
 use self::Actor::*;
 
-use super::{mock, with_capability};
+use super::{mock, with_capabilities, with_capability};
 
 #[test]
 fn creates_the_right_chain() {
@@ -78,3 +87,591 @@ fn uses_smtputf8_for_internationalized_mail_addresses() {
         .wait()
         .unwrap();
 }
+
+#[test]
+fn send_mail_with_stop_does_not_reset() {
+    let con = mock(vec![
+        (Client, Lines(vec!["MAIL FROM:<t1@test.test>"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["RCPT TO:<t2@test.test>"])),
+        (Server, Lines(vec!["550 No such user"])),
+        (Client, Lines(vec!["QUIT"])),
+        (Server, Lines(vec!["250 Ok"])),
+    ]);
+
+    let envelop = MailEnvelop::new(
+        MailAddress::from_unchecked("t1@test.test"),
+        vec1![MailAddress::from_unchecked("t2@test.test")],
+        Mail::new(EncodingRequirement::None, Vec::from("the data\r\n")),
+    );
+
+    let (con, result) = con.send_mail_with(envelop, OnError::Stop).wait().unwrap();
+
+    match result {
+        Err((1, LogicError::Code(response))) => {
+            assert!(response.code_matches(codes::MAILBOX_UNAVAILABLE))
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    con.quit().wait().unwrap();
+}
+
+/// continues after failing `RCPT TO:` commands, i.e. the commands with index `1..=recipients`
+struct SkipRejectedRecipients {
+    recipients: usize,
+}
+
+impl HandleErrorInChain for SkipRejectedRecipients {
+    type Fut = future::FutureResult<(Connection, bool), std_io::Error>;
+
+    fn handle_error(&self, con: Connection, msg_idx: usize, _error: &LogicError) -> Self::Fut {
+        let stop = msg_idx == 0 || msg_idx > self.recipients;
+        future::ok((con, stop))
+    }
+}
+
+#[test]
+fn summary_counts_accepted_and_rejected_recipients() {
+    let con = mock(vec![
+        (Client, Lines(vec!["MAIL FROM:<t1@test.test>"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["RCPT TO:<t2@test.test>"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["RCPT TO:<t3@test.test>"])),
+        (Server, Lines(vec!["550 No such user"])),
+        (Client, Lines(vec!["RCPT TO:<t4@test.test>"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["DATA"])),
+        (Server, Lines(vec!["354 ..."])),
+        (Client, Blob(Vec::from("the data\r\n.\r\n".to_owned()))),
+        (Server, Lines(vec!["250 queued as 12"])),
+        (Client, Lines(vec!["QUIT"])),
+        (Server, Lines(vec!["250 Ok"])),
+    ]);
+
+    let envelop = MailEnvelop::new(
+        MailAddress::from_unchecked("t1@test.test"),
+        vec1![
+            MailAddress::from_unchecked("t2@test.test"),
+            MailAddress::from_unchecked("t3@test.test"),
+            MailAddress::from_unchecked("t4@test.test")
+        ],
+        Mail::new(EncodingRequirement::None, Vec::from("the data\r\n")),
+    );
+
+    let (con, result) = con
+        .send_mail_with_summary(envelop, SkipRejectedRecipients { recipients: 3 })
+        .wait()
+        .unwrap();
+    let summary = result.unwrap();
+
+    assert_eq!(summary.accepted, 2);
+    assert_eq!(summary.rejected.len(), 1);
+    let (addr, err) = &summary.rejected[0];
+    assert_eq!(addr.as_str(), "t3@test.test");
+    match err {
+        LogicError::Code(response) => {
+            assert!(response.code_matches(codes::MAILBOX_UNAVAILABLE))
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+    assert_eq!(summary.data_response.msg(), &["queued as 12".to_owned()]);
+
+    con.quit().wait().unwrap();
+}
+
+#[test]
+fn send_mail_keep_sends_mails_sequentially() {
+    let con = mock(vec![
+        (Client, Lines(vec!["MAIL FROM:<t1@test.test>"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["RCPT TO:<t2@test.test>"])),
+        (Server, Lines(vec!["550 No such user"])),
+        (Client, Lines(vec!["RSET"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["MAIL FROM:<t1@test.test>"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["RCPT TO:<t3@test.test>"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["DATA"])),
+        (Server, Lines(vec!["354 ..."])),
+        (Client, Blob(Vec::from("the data\r\n.\r\n".to_owned()))),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["QUIT"])),
+        (Server, Lines(vec!["250 Ok"])),
+    ]);
+
+    let envelop = |to| {
+        MailEnvelop::new(
+            MailAddress::from_unchecked("t1@test.test"),
+            vec1![MailAddress::from_unchecked(to)],
+            Mail::new(EncodingRequirement::None, Vec::from("the data\r\n")),
+        )
+    };
+
+    let (con, result) = con.send_mail_keep(envelop("t2@test.test")).wait().unwrap();
+    assert!(result.is_err());
+    assert!(con.is_in_transaction());
+
+    let (con, result) = con.send_mail_keep(envelop("t3@test.test")).wait().unwrap();
+    assert!(result.is_ok());
+    assert!(!con.is_in_transaction());
+
+    con.quit().wait().unwrap();
+}
+
+#[test]
+fn preflight_checks_envelop_without_data() {
+    let con = mock(vec![
+        (Client, Lines(vec!["MAIL FROM:<t1@test.test> SIZE=1234"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["RCPT TO:<r1@test.test>"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["RCPT TO:<r2@test.test>"])),
+        (Server, Lines(vec!["550 No such user"])),
+        (Client, Lines(vec!["RSET"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["QUIT"])),
+        (Server, Lines(vec!["250 Ok"])),
+    ]);
+    let con = with_capabilities(con, &[("SIZE", &["10240"])]);
+
+    let envelop = EnvelopData {
+        from: Some(MailAddress::from_unchecked("t1@test.test")),
+        to: vec1![
+            MailAddress::from_unchecked("r1@test.test"),
+            MailAddress::from_unchecked("r2@test.test"),
+        ],
+    };
+
+    let (con, result) = con.preflight(envelop, 1234).wait().unwrap();
+
+    assert!(result.mail.is_ok());
+    assert!(!result.is_accepted());
+    let accepted: Vec<_> = result.accepted().map(MailAddress::as_str).collect();
+    assert_eq!(accepted, vec!["r1@test.test"]);
+    match &result.recipients[1] {
+        (addr, Err(LogicError::Code(response))) => {
+            assert_eq!(addr.as_str(), "r2@test.test");
+            assert!(response.code_matches(codes::MAILBOX_UNAVAILABLE));
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+    assert!(!con.is_in_transaction());
+
+    con.quit().wait().unwrap();
+}
+
+#[test]
+fn splits_recipients_over_multiple_transactions() {
+    let con = mock(vec![
+        (Client, Lines(vec!["MAIL FROM:<t1@test.test>"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["RCPT TO:<r1@test.test>"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["RCPT TO:<r2@test.test>"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["DATA"])),
+        (Server, Lines(vec!["354 ..."])),
+        (Client, Blob(Vec::from("the data\r\n.\r\n".to_owned()))),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["MAIL FROM:<t1@test.test>"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["RCPT TO:<r3@test.test>"])),
+        (Server, Lines(vec!["550 No such user"])),
+        (Client, Lines(vec!["RSET"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["QUIT"])),
+        (Server, Lines(vec!["250 Ok"])),
+    ]);
+
+    let envelop = MailEnvelop::new(
+        MailAddress::from_unchecked("t1@test.test"),
+        vec1![
+            MailAddress::from_unchecked("r1@test.test"),
+            MailAddress::from_unchecked("r2@test.test"),
+            MailAddress::from_unchecked("r3@test.test"),
+        ],
+        Mail::new(EncodingRequirement::None, Vec::from("the data\r\n")),
+    );
+
+    let (con, results) = con
+        .send_mail_with_recipient_limit(envelop, Some(2))
+        .wait()
+        .unwrap();
+
+    assert_eq!(results.len(), 2);
+    assert!(results[0].is_ok());
+    match &results[1] {
+        Err((3, LogicError::Code(response))) => {
+            assert!(response.code_matches(codes::MAILBOX_UNAVAILABLE))
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    con.quit().wait().unwrap();
+}
+
+#[test]
+fn streams_the_body_through_the_chain() {
+    let con = mock(vec![
+        (Client, Lines(vec!["MAIL FROM:<t1@test.test>"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["RCPT TO:<t2@test.test>"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["DATA"])),
+        (Server, Lines(vec!["354 ..."])),
+        (
+            Client,
+            Blob(Vec::from("the data\r\n..stashed\r\n.\r\n".to_owned())),
+        ),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["QUIT"])),
+        (Server, Lines(vec!["250 Ok"])),
+    ]);
+
+    let envelop = EnvelopData {
+        from: Some(MailAddress::from_unchecked("t1@test.test")),
+        to: vec1![MailAddress::from_unchecked("t2@test.test")],
+    };
+    let body = stream::iter_ok(vec![
+        Cursor::new(&b"the data\r\n"[..]),
+        Cursor::new(&b".stashed\r\n"[..]),
+    ]);
+
+    let (con, result) = con
+        .send_mail_streaming(envelop, EncodingRequirement::None, body)
+        .wait()
+        .unwrap();
+    assert!(result.is_ok());
+    con.quit().wait().unwrap();
+}
+
+fn assert_preflight_rejected(con: Connection, envelop: MailEnvelop, expected: PreflightReason) {
+    let (con, res) = con.send_mail(envelop).wait().unwrap();
+    match res {
+        Err((0, LogicError::PreflightRejected(reason))) => assert_eq!(reason, expected),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    con.shutdown().wait().unwrap();
+}
+
+#[test]
+fn rejects_internationalized_addresses_without_smtputf8() {
+    let envelop = MailEnvelop::new(
+        MailAddress::from_unchecked("tü1@test.test"),
+        vec1![MailAddress::from_unchecked("t2@test.test")],
+        Mail::new(EncodingRequirement::None, Vec::from("the data\r\n")),
+    );
+
+    assert_preflight_rejected(mock(vec![]), envelop, PreflightReason::Smtputf8Unsupported);
+}
+
+#[test]
+fn rejects_8bit_mail_without_8bitmime() {
+    let envelop = MailEnvelop::new(
+        MailAddress::from_unchecked("t1@test.test"),
+        vec1![MailAddress::from_unchecked("t2@test.test")],
+        Mail::new(EncodingRequirement::Mime8bit, Vec::from("the data\r\n")),
+    );
+
+    assert_preflight_rejected(mock(vec![]), envelop, PreflightReason::Mime8bitUnsupported);
+}
+
+#[test]
+fn fail_on_unsupported_8bit_rejects_8bit_mail() {
+    let envelop = MailEnvelop::new(
+        MailAddress::from_unchecked("t1@test.test"),
+        vec1![MailAddress::from_unchecked("t2@test.test")],
+        Mail::new(EncodingRequirement::Mime8bit, Vec::from("d\u{e4}ta\r\n")),
+    );
+
+    let (con, res) = mock(vec![])
+        .send_mail_with_8bit_handling(envelop, OnUnsupported8bit::Fail)
+        .wait()
+        .unwrap();
+    match res {
+        Err((0, LogicError::PreflightRejected(PreflightReason::Mime8bitUnsupported))) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+    con.shutdown().wait().unwrap();
+}
+
+#[test]
+fn downgrade_on_unsupported_8bit_sends_8bit_mail_anyway() {
+    let con = mock(vec![
+        (Client, Lines(vec!["MAIL FROM:<t1@test.test>"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["RCPT TO:<t2@test.test>"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["DATA"])),
+        (Server, Lines(vec!["354 ..."])),
+        (Client, Blob(Vec::from("d\u{e4}ta\r\n.\r\n".to_owned()))),
+        (Server, Lines(vec!["250 Ok"])),
+    ]);
+    let envelop = MailEnvelop::new(
+        MailAddress::from_unchecked("t1@test.test"),
+        vec1![MailAddress::from_unchecked("t2@test.test")],
+        Mail::new(EncodingRequirement::Mime8bit, Vec::from("d\u{e4}ta\r\n")),
+    );
+
+    let (con, res) = con
+        .send_mail_with_8bit_handling(envelop, OnUnsupported8bit::Downgrade)
+        .wait()
+        .unwrap();
+    assert!(res.is_ok());
+    con.shutdown().wait().unwrap();
+}
+
+#[test]
+fn send_mail_paths_can_send_to_postmaster() {
+    let con = mock(vec![
+        (Client, Lines(vec!["MAIL FROM:<>"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["RCPT TO:<Postmaster>"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["DATA"])),
+        (Server, Lines(vec!["354 ..."])),
+        (Client, Blob(b"the data\r\n.\r\n".to_vec())),
+        (Server, Lines(vec!["250 Ok"])),
+    ]);
+
+    let mail = Mail::new(EncodingRequirement::None, Vec::from("the data\r\n"));
+    let (con, res) = con
+        .send_mail_paths(
+            ReversePath::empty(),
+            vec1![ForwardPath::from_unchecked("Postmaster")],
+            mail,
+        )
+        .wait()
+        .unwrap();
+    assert!(res.is_ok());
+    con.shutdown().wait().unwrap();
+}
+
+#[test]
+fn send_mail_paths_detects_smtputf8_from_paths() {
+    let mail = Mail::new(EncodingRequirement::None, Vec::from("the data\r\n"));
+    let (con, res) = mock(vec![])
+        .send_mail_paths(
+            ReversePath::from_unchecked("t1@test.test"),
+            vec1![ForwardPath::from_unchecked("t\u{e4}@test.test")],
+            mail,
+        )
+        .wait()
+        .unwrap();
+    match res {
+        Err((0, LogicError::PreflightRejected(PreflightReason::Smtputf8Unsupported))) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+    con.shutdown().wait().unwrap();
+}
+
+#[test]
+fn streaming_uses_explicit_encoding_requirement_for_preflight() {
+    let envelop = EnvelopData {
+        from: Some(MailAddress::from_unchecked("t1@test.test")),
+        to: vec1![MailAddress::from_unchecked("t2@test.test")],
+    };
+    let body = stream::iter_ok(vec![Cursor::new(&b"the data\r\n"[..])]);
+
+    let (con, res) = mock(vec![])
+        .send_mail_streaming(envelop, EncodingRequirement::Smtputf8, body)
+        .wait()
+        .unwrap();
+    match res {
+        Err((0, LogicError::PreflightRejected(reason))) => {
+            assert_eq!(reason, PreflightReason::Smtputf8Unsupported)
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+    con.shutdown().wait().unwrap();
+}
+
+#[test]
+fn rejects_mail_larger_than_advertised_size() {
+    let con = with_capabilities(mock(vec![]), &[("SIZE", &["8"])]);
+    let envelop = MailEnvelop::new(
+        MailAddress::from_unchecked("t1@test.test"),
+        vec1![MailAddress::from_unchecked("t2@test.test")],
+        Mail::new(EncodingRequirement::None, Vec::from("the data\r\n")),
+    );
+
+    assert_preflight_rejected(
+        con,
+        envelop,
+        PreflightReason::MessageTooLarge {
+            limit: 8,
+            actual: 10,
+        },
+    );
+}
+
+#[test]
+fn size_zero_means_no_limit() {
+    let con = mock(vec![
+        (Client, Lines(vec!["MAIL FROM:<t1@test.test> SIZE=10"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["RCPT TO:<t2@test.test>"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["DATA"])),
+        (Server, Lines(vec!["354 ..."])),
+        (Client, Blob(Vec::from("the data\r\n.\r\n".to_owned()))),
+        (Server, Lines(vec!["250 Ok"])),
+    ]);
+    let con = with_capabilities(con, &[("SIZE", &["0"])]);
+    let envelop = MailEnvelop::new(
+        MailAddress::from_unchecked("t1@test.test"),
+        vec1![MailAddress::from_unchecked("t2@test.test")],
+        Mail::new(EncodingRequirement::None, Vec::from("the data\r\n")),
+    );
+
+    let (con, res) = con.send_mail(envelop).wait().unwrap();
+    assert!(res.is_ok());
+    con.shutdown().wait().unwrap();
+}
+
+#[test]
+fn accepts_mail_of_exactly_the_advertised_size() {
+    let con = mock(vec![
+        (Client, Lines(vec!["MAIL FROM:<t1@test.test> SIZE=10"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["RCPT TO:<t2@test.test>"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["DATA"])),
+        (Server, Lines(vec!["354 ..."])),
+        (Client, Blob(Vec::from("the data\r\n.\r\n".to_owned()))),
+        (Server, Lines(vec!["250 Ok"])),
+    ]);
+    let con = with_capabilities(con, &[("SIZE", &["10"])]);
+    let envelop = MailEnvelop::new(
+        MailAddress::from_unchecked("t1@test.test"),
+        vec1![MailAddress::from_unchecked("t2@test.test")],
+        Mail::new(EncodingRequirement::None, Vec::from("the data\r\n")),
+    );
+
+    let (con, res) = con.send_mail(envelop).wait().unwrap();
+    assert!(res.is_ok());
+    con.shutdown().wait().unwrap();
+}
+
+#[test]
+fn collect_all_gathers_every_result() {
+    let con = mock(vec![
+        (Client, Lines(vec!["MAIL FROM:<t1@test.test>"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["RCPT TO:<t2@test.test>"])),
+        (Server, Lines(vec!["550 no such user"])),
+        (Client, Lines(vec!["RSET"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["MAIL FROM:<t1@test.test>"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["RCPT TO:<t3@test.test>"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["DATA"])),
+        (Server, Lines(vec!["354 ..."])),
+        (Client, Blob(Vec::from("the data\r\n.\r\n".to_owned()))),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["QUIT"])),
+        (Server, Lines(vec!["221 Bye"])),
+    ]);
+
+    let mails = vec!["t2@test.test", "t3@test.test"].into_iter().map(|to| {
+        Ok::<_, GeneralError>(MailEnvelop::new(
+            MailAddress::from_unchecked("t1@test.test"),
+            vec1![MailAddress::from_unchecked(to)],
+            Mail::new(EncodingRequirement::None, Vec::from("the data\r\n")),
+        ))
+    });
+
+    let results = SendAllMails::new(con, mails).collect_all().wait().unwrap();
+    assert_eq!(results.len(), 2);
+    match &results[0] {
+        Err(GeneralError::Cmd(LogicError::Code(_))) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+    assert!(results[1].is_ok());
+}
+
+#[test]
+fn resets_left_over_transaction_before_next_mail() {
+    let con = mock(vec![
+        (Client, Lines(vec!["MAIL FROM:<t1@test.test>"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["RCPT TO:<t2@test.test>"])),
+        (Server, Lines(vec!["550 no such user"])),
+        (Client, Lines(vec!["RSET"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["MAIL FROM:<t1@test.test>"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["RCPT TO:<t3@test.test>"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["DATA"])),
+        (Server, Lines(vec!["354 ..."])),
+        (Client, Blob(Vec::from("the data\r\n.\r\n".to_owned()))),
+        (Server, Lines(vec!["250 Ok"])),
+    ]);
+    let envelop = |to| {
+        MailEnvelop::new(
+            MailAddress::from_unchecked("t1@test.test"),
+            vec1![MailAddress::from_unchecked(to)],
+            Mail::new(EncodingRequirement::None, Vec::from("the data\r\n")),
+        )
+    };
+
+    // `Stop` doesn't send `RSET` so the transaction is left open
+    let (con, res) = send_mail(con, envelop("t2@test.test"), OnError::Stop)
+        .wait()
+        .unwrap();
+    assert!(res.is_err());
+    assert!(con.is_in_transaction());
+
+    let (con, res) = send_mail(con, envelop("t3@test.test"), OnError::Stop)
+        .wait()
+        .unwrap();
+    assert!(res.is_ok());
+    assert!(!con.is_in_transaction());
+    con.shutdown().wait().unwrap();
+}
+
+#[test]
+fn outcomes_distinguish_transient_and_permanent_failures() {
+    let con = mock(vec![
+        (Client, Lines(vec!["MAIL FROM:<t1@test.test>"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["RCPT TO:<t2@test.test>"])),
+        (Server, Lines(vec!["450 mailbox busy"])),
+        (Client, Lines(vec!["RSET"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["MAIL FROM:<t1@test.test>"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["RCPT TO:<t3@test.test>"])),
+        (Server, Lines(vec!["550 no such user"])),
+        (Client, Lines(vec!["RSET"])),
+        (Server, Lines(vec!["250 Ok"])),
+        (Client, Lines(vec!["QUIT"])),
+        (Server, Lines(vec!["221 Bye"])),
+    ]);
+
+    let mails = vec!["t2@test.test", "t3@test.test"].into_iter().map(|to| {
+        Ok::<_, GeneralError>(MailEnvelop::new(
+            MailAddress::from_unchecked("t1@test.test"),
+            vec1![MailAddress::from_unchecked(to)],
+            Mail::new(EncodingRequirement::None, Vec::from("the data\r\n")),
+        ))
+    });
+
+    let stream = SendAllMails::new(con, mails)
+        .outcomes()
+        .quit_on_completion();
+    let outcomes = collect_all_results(stream).wait().unwrap();
+    let outcomes = outcomes.into_iter().map(Result::unwrap).collect::<Vec<_>>();
+
+    assert_eq!(outcomes.len(), 2);
+    assert_eq!(outcomes[0].index, 0);
+    assert!(outcomes[0].is_transient_failure());
+    assert_eq!(outcomes[1].index, 1);
+    assert!(outcomes[1].result.is_err());
+    assert!(!outcomes[1].is_transient_failure());
+}
@@ -0,0 +1,43 @@
+use std::io::ErrorKind;
+
+use futures::Future;
+
+use new_tokio_smtp::{
+    command,
+    error::LogicError,
+    mock::{ActionData, Actor},
+};
+
+use self::ActionData::*;
+use self::Actor::*;
+
+use super::{mock, mock_no_shutdown};
+
+#[test]
+fn send_after_421_fails_with_not_connected() {
+    let con = mock_no_shutdown(vec![
+        (Client, Lines(vec!["NOOP"])),
+        (Server, Lines(vec!["421 Service closing"])),
+    ]);
+
+    let (con, result) = con.send(command::Noop).wait().unwrap();
+    match result {
+        Err(LogicError::Code(response)) => assert!(response.is_shutdown()),
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    let err = con.send(command::Noop).wait().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotConnected);
+}
+
+#[test]
+fn quit_after_421_only_shuts_down() {
+    let con = mock(vec![
+        (Client, Lines(vec!["NOOP"])),
+        (Server, Lines(vec!["421 Service closing"])),
+    ]);
+
+    let (con, result) = con.send(command::Noop).wait().unwrap();
+    assert!(result.is_err());
+    con.quit().wait().unwrap();
+}
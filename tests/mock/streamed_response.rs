@@ -0,0 +1,69 @@
+use futures::{Future, Stream};
+
+use new_tokio_smtp::{
+    command,
+    mock::{ActionData, Actor},
+    response::codes,
+    Connection, SyntaxErrorHandling,
+};
+
+use self::ActionData::*;
+use self::Actor::*;
+
+use super::{mock, mock_no_shutdown};
+
+fn big_reply(lines: usize) -> Vec<u8> {
+    let mut reply = Vec::new();
+    for idx in 0..lines {
+        reply.extend_from_slice(format!("250-member{}@list.test\r\n", idx).as_bytes());
+    }
+    reply.extend_from_slice(b"250 end of list\r\n");
+    reply
+}
+
+#[test]
+fn streams_a_long_response_line_by_line() {
+    let con = mock(vec![
+        (Client, Lines(vec!["EXPN big-list"])),
+        (Server, Blob(big_reply(50))),
+        (Client, Lines(vec!["NOOP"])),
+        (Server, Lines(vec!["250 Ok"])),
+    ]);
+
+    let mut io = con.into_inner();
+    io.write_line_from_parts(&["EXPN big-list"]);
+    let io = io.flush().wait().unwrap();
+
+    let mut lines = io.parse_response_streamed();
+    let mut count = 0;
+    for line in (&mut lines).wait() {
+        let line = line.unwrap();
+        assert_eq!(line.code, codes::OK);
+        if count < 50 {
+            assert!(!line.last_line);
+            assert_eq!(line.msg, format!("member{}@list.test", count));
+        } else {
+            assert!(line.last_line);
+            assert_eq!(line.msg, "end of list");
+        }
+        count += 1;
+    }
+    assert_eq!(count, 51);
+
+    // the next response is not touched
+    let con = Connection::from(lines.into_io().unwrap());
+    let (con, res) = con.send(command::Noop).wait().unwrap();
+    assert!(res.is_ok());
+    con.shutdown().wait().unwrap();
+}
+
+#[test]
+fn strict_handling_rejects_mismatching_codes() {
+    let con = mock_no_shutdown(vec![(Server, Blob(b"250-first\r\n550 last\r\n".to_vec()))]);
+
+    let mut io = con.into_inner();
+    io.set_syntax_error_handling(SyntaxErrorHandling::Strict);
+    let mut lines = io.parse_response_streamed().wait();
+    assert!(lines.next().unwrap().is_ok());
+    assert!(lines.next().unwrap().is_err());
+}
@@ -0,0 +1,87 @@
+use bytes::BufMut;
+use futures::Future;
+
+use new_tokio_smtp::{
+    command::{self, auth::AuthIdentity},
+    io::Buffers,
+    mock::{ActionData, Actor, MockSocket},
+    response::codes,
+    ClientId, Connection, Domain, Io, Response, SetupWarning,
+};
+
+use self::ActionData::*;
+use self::Actor::*;
+
+use super::{mock, with_capability};
+
+#[test]
+fn round_trip_keeps_ehlo_data() {
+    let con = mock(vec![
+        (Client, Lines(vec!["NOOP"])),
+        (Server, Lines(vec!["250 Ok"])),
+    ]);
+    let con = with_capability(con, "X-TEST");
+
+    let (socket, token) = con.take_socket().unwrap();
+    let con = Connection::restore_socket(token, socket);
+    assert!(con.has_capability("X-TEST"));
+
+    let (con, res) = con.send(command::Noop).wait().unwrap();
+    assert!(res.is_ok());
+    con.shutdown().wait().unwrap();
+}
+
+#[test]
+fn fails_with_buffered_data() {
+    let socket = MockSocket::new(vec![]).into();
+    let mut buffers = Buffers::new();
+    buffers.input.reserve(16);
+    buffers.input.put("250 Ok\r\n");
+    let con = Connection::from(Io::from((socket, buffers)));
+
+    let err = con.take_socket().unwrap_err();
+    let con = err.into_connection();
+    con.shutdown().wait().unwrap();
+}
+
+#[test]
+fn round_trip_keeps_the_connection_id() {
+    let con = mock(vec![]);
+    let other = mock(vec![]);
+    assert_ne!(con.id(), other.id());
+    assert_eq!(con.peer_addr(), None);
+
+    let id = con.id();
+    let (socket, token) = con.take_socket().unwrap();
+    let con = Connection::restore_socket(token, socket);
+    assert_eq!(con.id(), id);
+
+    con.shutdown().wait().unwrap();
+    other.shutdown().wait().unwrap();
+}
+
+#[test]
+fn meta_survives_into_parts_from_parts() {
+    let con = with_capability(mock(vec![]), "X-TEST");
+    let mut io = con.into_inner();
+    io.set_client_id(ClientId::Domain(Domain::from_unchecked("me.test")));
+    io.set_greeting(Response::new(codes::READY, vec!["hy".to_owned()]));
+    io.set_in_transaction(true);
+    io.set_authenticated_as(AuthIdentity::new("tim", "PLAIN"));
+    io.add_setup_warning(SetupWarning::LoopbackClientIdForMx);
+    let id = io.id();
+
+    let (io, meta) = Connection::from(io).into_parts();
+    let (socket, _, _) = io.split();
+    let con = Connection::from_parts(Io::from(socket), meta);
+
+    assert!(con.has_capability("X-TEST"));
+    assert_eq!(con.client_id().map(ClientId::as_str), Some("me.test"));
+    assert_eq!(con.greeting().unwrap().msg(), &["hy"]);
+    assert!(con.is_in_transaction());
+    assert!(con.state().is_authenticated);
+    assert_eq!(con.authenticated_as().unwrap().identity(), "tim");
+    assert_eq!(con.warnings(), &[SetupWarning::LoopbackClientIdForMx]);
+    assert_eq!(con.id(), id);
+    con.shutdown().wait().unwrap();
+}
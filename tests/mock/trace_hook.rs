@@ -0,0 +1,44 @@
+use std::sync::{Arc, Mutex};
+
+use futures::Future;
+
+use new_tokio_smtp::{
+    command,
+    io::Direction,
+    mock::{ActionData, Actor},
+};
+
+use self::ActionData::*;
+use self::Actor::*;
+
+use super::mock;
+
+#[test]
+fn hook_sees_send_and_received_lines() {
+    let mut con = mock(vec![
+        (Client, Lines(vec!["NOOP"])),
+        (Server, Lines(vec!["250-Ok", "250 still Ok"])),
+    ]);
+
+    let traced = Arc::new(Mutex::new(Vec::new()));
+    con.set_trace_hook({
+        let traced = traced.clone();
+        Arc::new(move |direction, line: &[u8]| {
+            traced.lock().unwrap().push((direction, line.to_owned()))
+        })
+    });
+
+    let (con, result) = con.send(command::Noop).wait().unwrap();
+    assert!(result.is_ok());
+    con.shutdown().wait().unwrap();
+
+    let traced = traced.lock().unwrap();
+    assert_eq!(
+        *traced,
+        vec![
+            (Direction::Sent, b"NOOP".to_vec()),
+            (Direction::Received, b"250-Ok".to_vec()),
+            (Direction::Received, b"250 still Ok".to_vec()),
+        ]
+    );
+}
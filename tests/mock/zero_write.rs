@@ -0,0 +1,15 @@
+use std::io as std_io;
+
+use futures::Future;
+
+use new_tokio_smtp::command;
+
+use super::FailingStream;
+
+#[test]
+fn zero_length_write_is_an_error_not_a_panic() {
+    let con = FailingStream::zero_write().into_connection();
+
+    let err = con.send(command::Noop).wait().unwrap_err();
+    assert_eq!(err.kind(), std_io::ErrorKind::WriteZero);
+}